@@ -3,6 +3,12 @@ pub struct NullBitmask {
     bits: Vec<bool>,
 }
 
+impl Default for NullBitmask {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl NullBitmask {
     pub fn new() -> Self { Self { bits: Vec::new() } }
     pub fn push(&mut self, is_valid: bool) { self.bits.push(is_valid); }
@@ -11,6 +17,14 @@ impl NullBitmask {
         if idx < self.bits.len() { self.bits[idx] = is_valid; }
     }
     pub fn len(&self) -> usize { self.bits.len() }
+    pub fn is_empty(&self) -> bool { self.bits.is_empty() }
+    pub fn capacity(&self) -> usize { self.bits.capacity() }
+    pub fn shrink_to_fit(&mut self) { self.bits.shrink_to_fit(); }
+    /// Keep only the entries whose position is `true` in `keep`.
+    pub fn retain_mask(&mut self, keep: &[bool]) {
+        let mut i = 0;
+        self.bits.retain(|_| { let k = keep.get(i).copied().unwrap_or(true); i += 1; k });
+    }
     pub fn count_valid(&self) -> usize { self.bits.iter().filter(|&&b| b).count() }
     pub fn count_null(&self) -> usize { self.bits.iter().filter(|&&b| !b).count() }
 }
@@ -1,4 +1,5 @@
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct IntervalValue {
@@ -13,6 +14,60 @@ impl IntervalValue {
         Self { years, months, days, micros }
     }
     pub fn zero() -> Self { Self { years: 0, months: 0, days: 0, micros: 0 } }
+
+    /// Fold whole years out of `months`: 14 months is 1 year 2 months.
+    pub fn normalized(self) -> Self {
+        let total = self.years as i64 * 12 + self.months as i64;
+        Self { years: (total / 12) as i32, months: (total % 12) as i32, ..self }
+    }
+
+    pub fn negated(self) -> Self {
+        Self::new(-self.years, -self.months, -self.days, -self.micros)
+    }
+
+    /// Field-wise sum, or `None` if a field overflows.
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(Self::new(
+            self.years.checked_add(other.years)?,
+            self.months.checked_add(other.months)?,
+            self.days.checked_add(other.days)?,
+            self.micros.checked_add(other.micros)?,
+        ))
+    }
+
+    /// Field-wise difference, or `None` if a field overflows.
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        Some(Self::new(
+            self.years.checked_sub(other.years)?,
+            self.months.checked_sub(other.months)?,
+            self.days.checked_sub(other.days)?,
+            self.micros.checked_sub(other.micros)?,
+        ))
+    }
+
+    /// The years and months as a month count.
+    pub fn total_months(&self) -> i64 {
+        self.years as i64 * 12 + self.months as i64
+    }
+
+    /// Order by length, counting a month as 30 days and a day as 24 hours
+    /// (as PostgreSQL does), so `1 month` and `30 days` are equal.
+    pub fn cmp_length(&self, other: &Self) -> std::cmp::Ordering {
+        self.length_micros().cmp(&other.length_micros())
+    }
+
+    fn length_micros(&self) -> i128 {
+        (self.total_months() as i128 * 30 + self.days as i128) * 86_400_000_000 + self.micros as i128
+    }
+}
+
+/// The canonical interval text, `<y> years <m> months <d> days <us> micros`,
+/// with every field present. `parse_interval` reads it back exactly, so it is
+/// also the form intervals are stored and exported in.
+impl fmt::Display for IntervalValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} years {} months {} days {} micros", self.years, self.months, self.days, self.micros)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,6 +80,10 @@ pub enum ScalarValue {
     Timestamp(i64),  // microseconds since epoch
     Time(i64),       // microseconds since midnight
     Interval(IntervalValue),
+    /// A composite value from a `ROW(...)` or `(a, b, ...)` constructor.
+    Row(Vec<ScalarValue>),
+    /// An ordered list of values, as built by `ARRAY_AGG`; elements may be NULL.
+    Array(Vec<ScalarValue>),
     Null,
 }
 
@@ -44,15 +103,109 @@ impl fmt::Display for ScalarValue {
             ScalarValue::Date(d) => write!(f, "{}", epoch_days_to_date_string(*d)),
             ScalarValue::Timestamp(ts) => write!(f, "{}", epoch_micros_to_ts_string(*ts)),
             ScalarValue::Time(t) => write!(f, "{}", micros_to_time_string(*t)),
-            ScalarValue::Interval(iv) => {
-                write!(f, "{} years {} months {} days {} micros",
-                    iv.years, iv.months, iv.days, iv.micros)
+            ScalarValue::Interval(iv) => write!(f, "{}", iv),
+            ScalarValue::Row(values) => {
+                write!(f, "(")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, ")")
+            }
+            ScalarValue::Array(values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
             }
             ScalarValue::Null => write!(f, "NULL"),
         }
     }
 }
 
+/// A `ScalarValue` usable as a hash key (grouping, DISTINCT, set operations).
+///
+/// Equality is value-based rather than representation-based: `Int64(1)` and
+/// `Float64(1.0)` are equal, `-0.0` equals `0.0`, every NaN equals every other
+/// NaN, and NULL equals NULL, as GROUP BY and DISTINCT require. Values of
+/// different types (`1` vs `'1'`) are never equal.
+#[derive(Debug, Clone)]
+pub struct HashableScalar(pub ScalarValue);
+
+#[derive(PartialEq, Eq, Hash)]
+enum CanonicalScalar<'a> {
+    Null,
+    Boolean(bool),
+    Int(i64),
+    Float(u64),
+    Utf8(&'a str),
+    Date(i64),
+    Timestamp(i64),
+    Time(i64),
+    Interval(i128),
+    Row(Vec<CanonicalScalar<'a>>),
+    Array(Vec<CanonicalScalar<'a>>),
+}
+
+impl HashableScalar {
+    pub fn new(v: ScalarValue) -> Self {
+        Self(v)
+    }
+
+    pub fn into_inner(self) -> ScalarValue {
+        self.0
+    }
+
+    fn canonical(&self) -> CanonicalScalar<'_> {
+        canonical(&self.0)
+    }
+}
+
+fn canonical(v: &ScalarValue) -> CanonicalScalar<'_> {
+    match v {
+        ScalarValue::Null => CanonicalScalar::Null,
+        ScalarValue::Boolean(b) => CanonicalScalar::Boolean(*b),
+        ScalarValue::Int64(i) => CanonicalScalar::Int(*i),
+        ScalarValue::Float64(f) if f.is_nan() => CanonicalScalar::Float(f64::NAN.to_bits()),
+        // Integral floats (including -0.0) key the same as the integer.
+        ScalarValue::Float64(f) if f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < i64::MAX as f64 => {
+            CanonicalScalar::Int(*f as i64)
+        }
+        ScalarValue::Float64(f) => CanonicalScalar::Float(f.to_bits()),
+        ScalarValue::Utf8(s) => CanonicalScalar::Utf8(s),
+        ScalarValue::Date(d) => CanonicalScalar::Date(*d),
+        ScalarValue::Timestamp(t) => CanonicalScalar::Timestamp(*t),
+        ScalarValue::Time(t) => CanonicalScalar::Time(*t),
+        // Key by length so hashing agrees with `cmp_length`: `1 month` and
+        // `30 days` join and group together.
+        ScalarValue::Interval(iv) => CanonicalScalar::Interval(iv.length_micros()),
+        ScalarValue::Row(values) => CanonicalScalar::Row(values.iter().map(canonical).collect()),
+        ScalarValue::Array(values) => CanonicalScalar::Array(values.iter().map(canonical).collect()),
+    }
+}
+
+impl PartialEq for HashableScalar {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical() == other.canonical()
+    }
+}
+
+impl Eq for HashableScalar {}
+
+impl Hash for HashableScalar {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical().hash(state);
+    }
+}
+
+impl From<ScalarValue> for HashableScalar {
+    fn from(v: ScalarValue) -> Self {
+        Self(v)
+    }
+}
+
 pub fn is_leap_year(year: i32) -> bool {
     (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
 }
@@ -110,6 +263,30 @@ pub fn ymd_to_epoch_days(year: i32, month: u32, day: u32) -> i64 {
     days
 }
 
+/// Move a date by `months`, clamping the day to the target month's length
+/// (Jan 31 + 1 month is Feb 28, or Feb 29 in a leap year). `None` if the
+/// year leaves the supported range.
+pub fn add_months_to_days(days: i64, months: i64) -> Option<i64> {
+    let (y, m, d) = epoch_days_to_ymd(days);
+    let total = (y as i64 * 12 + (m as i64 - 1)).checked_add(months)?;
+    let year = i32::try_from(total.div_euclid(12)).ok()?;
+    let month = total.rem_euclid(12) as u32 + 1;
+    Some(ymd_to_epoch_days(year, month, d.min(days_in_month(year, month))))
+}
+
+/// Add the months and days of an interval to a date, or `None` on overflow.
+pub fn add_interval_to_days(days: i64, iv: &IntervalValue) -> Option<i64> {
+    add_months_to_days(days, iv.total_months())?.checked_add(iv.days as i64)
+}
+
+/// Add an interval to a timestamp: months first, then days, then micros.
+/// `None` if the result is out of range.
+pub fn add_interval_to_micros(micros: i64, iv: &IntervalValue) -> Option<i64> {
+    const DAY_US: i64 = 86_400_000_000;
+    let days = add_interval_to_days(micros.div_euclid(DAY_US), iv)?;
+    days.checked_mul(DAY_US)?.checked_add(micros.rem_euclid(DAY_US))?.checked_add(iv.micros)
+}
+
 pub fn epoch_days_to_date_string(days: i64) -> String {
     let (y, m, d) = epoch_days_to_ymd(days);
     format!("{:04}-{:02}-{:02}", y, m, d)
@@ -150,7 +327,7 @@ pub fn date_string_to_epoch_days(s: &str) -> Option<i64> {
     let year: i32 = parts[0].parse().ok()?;
     let month: u32 = parts[1].parse().ok()?;
     let day: u32 = parts[2].parse().ok()?;
-    if month < 1 || month > 12 || day < 1 || day > 31 { return None; }
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) { return None; }
     Some(ymd_to_epoch_days(year, month, day))
 }
 
@@ -163,9 +340,57 @@ pub fn timestamp_string_to_epoch_micros(s: &str) -> Option<i64> {
     } else {
         (s, "00:00:00")
     };
+    let (time_part, offset) = split_utc_offset(time_part)?;
     let days = date_string_to_epoch_days(date_part)?;
     let time_micros = time_string_to_micros(time_part)?;
-    Some(days * 86_400_000_000 + time_micros)
+    Some(days * 86_400_000_000 + time_micros - offset)
+}
+
+/// Split a trailing UTC offset (`Z`, `+HH`, `+HH:MM`, `-HHMM`, ` UTC`) off a
+/// time string, returning the remaining time and the offset in microseconds.
+fn split_utc_offset(time: &str) -> Option<(&str, i64)> {
+    let t = time.trim_end();
+    if let Some(rest) = t.strip_suffix(['Z', 'z']) {
+        return Some((rest.trim_end(), 0));
+    }
+    if let Some(pos) = t.rfind(['+', '-']) {
+        return Some((t[..pos].trim_end(), utc_offset_micros(&t[pos..])?));
+    }
+    if let Some(pos) = t.rfind(' ') {
+        return Some((t[..pos].trim_end(), utc_offset_micros(&t[pos + 1..])?));
+    }
+    Some((t, 0))
+}
+
+/// Parse a fixed UTC offset such as `UTC`, `Z`, `+05:30`, `-08`, `+0200` or
+/// `UTC+3` into microseconds east of UTC. Named zones other than UTC/GMT are
+/// not supported.
+pub fn utc_offset_micros(zone: &str) -> Option<i64> {
+    let z = zone.trim();
+    let upper = z.to_uppercase();
+    let rest = upper.strip_prefix("UTC").or_else(|| upper.strip_prefix("GMT")).unwrap_or(&upper);
+    if rest.is_empty() || rest == "Z" {
+        return Some(0);
+    }
+    let sign = match rest.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = &rest[1..];
+    let (h, m) = match digits.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if digits.len() > 2 => digits.split_at(digits.len() - 2),
+        None => (digits, "0"),
+    };
+    if h.is_empty() || !h.bytes().all(|b| b.is_ascii_digit()) || !m.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let (h, m): (i64, i64) = (h.parse().ok()?, m.parse().ok()?);
+    if h > 18 || m >= 60 {
+        return None;
+    }
+    Some(sign * (h * 3600 + m * 60) * 1_000_000)
 }
 
 pub fn time_string_to_micros(s: &str) -> Option<i64> {
@@ -185,3 +410,164 @@ pub fn time_string_to_micros(s: &str) -> Option<i64> {
     } else { (0, 0) };
     Some((h * 3600 + m * 60 + secs) * 1_000_000 + micros)
 }
+
+/// Parse the canonical interval text produced by `IntervalValue`'s Display.
+/// Every field must be present, in order, as an integer; use
+/// `interval_string_to_value` for the looser SQL literal forms.
+pub fn parse_interval(s: &str) -> Option<IntervalValue> {
+    let tokens: Vec<&str> = s.split_whitespace().collect();
+    match tokens.as_slice() {
+        [y, "years", m, "months", d, "days", us, "micros"] => Some(IntervalValue::new(
+            y.parse().ok()?, m.parse().ok()?, d.parse().ok()?, us.parse().ok()?,
+        )),
+        _ => None,
+    }
+}
+
+/// Parse an interval literal. `qualifier` is the unit written after the
+/// string (`DAY`, `YEAR TO MONTH`, ...) and may be empty. Accepts
+/// multi-field strings (`1 year 2 months 04:05:06`), `Y-M` and `D H:M:S`
+/// forms for range qualifiers, and ISO-8601 durations (`P1Y2M3DT4H5M6S`).
+pub fn interval_string_to_value(s: &str, qualifier: &str) -> Option<IntervalValue> {
+    let s = s.trim();
+    let q = qualifier.trim().to_uppercase();
+    let (negative, body) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    if body.starts_with(['P', 'p']) && q.is_empty() {
+        return parse_iso_duration(&body[1..]).map(|iv| if negative { iv.negated() } else { iv });
+    }
+    if let Some((from, to)) = q.split_once(" TO ") {
+        let iv = parse_interval_range(body, from.trim(), to.trim())?;
+        return Some(if negative { iv.negated() } else { iv });
+    }
+    if !q.is_empty() {
+        if let Ok(n) = s.parse::<f64>() {
+            return interval_from_unit(n, &q);
+        }
+    }
+    parse_interval_fields(s)
+}
+
+/// `n` units of `unit`; fractional parts spill into the next smaller field.
+fn interval_from_unit(n: f64, unit: &str) -> Option<IntervalValue> {
+    const DAY_US: f64 = 86_400_000_000.0;
+    let unit = unit.to_uppercase();
+    let unit = unit.trim_end_matches('S');
+    Some(match unit {
+        "YEAR" => IntervalValue::new(n.trunc() as i32, (n.fract() * 12.0).round() as i32, 0, 0),
+        "MONTH" => IntervalValue::new(0, n.trunc() as i32, (n.fract() * 30.0).round() as i32, 0),
+        "WEEK" => {
+            let days = n * 7.0;
+            IntervalValue::new(0, 0, days.trunc() as i32, (days.fract() * DAY_US).round() as i64)
+        }
+        "DAY" => IntervalValue::new(0, 0, n.trunc() as i32, (n.fract() * DAY_US).round() as i64),
+        "HOUR" => IntervalValue::new(0, 0, 0, (n * 3_600_000_000.0).round() as i64),
+        "MINUTE" => IntervalValue::new(0, 0, 0, (n * 60_000_000.0).round() as i64),
+        "SECOND" => IntervalValue::new(0, 0, 0, (n * 1_000_000.0).round() as i64),
+        "MILLISECOND" => IntervalValue::new(0, 0, 0, (n * 1_000.0).round() as i64),
+        "MICROSECOND" | "MICRO" => IntervalValue::new(0, 0, 0, n.round() as i64),
+        _ => return None,
+    })
+}
+
+/// `[-]H:M[:S[.f]]` where the first component is `first_unit`.
+fn parse_clock_fields(s: &str, first_unit: &str) -> Option<IntervalValue> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let units: &[&str] = match first_unit {
+        "HOUR" => &["HOUR", "MINUTE", "SECOND"],
+        "MINUTE" => &["MINUTE", "SECOND"],
+        "SECOND" => &["SECOND"],
+        _ => return None,
+    };
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() > units.len() { return None; }
+    let mut iv = IntervalValue::zero();
+    for (part, unit) in parts.iter().zip(units) {
+        let n: f64 = part.parse().ok()?;
+        iv = iv.checked_add(&interval_from_unit(n, unit)?)?;
+    }
+    Some(if negative { iv.negated() } else { iv })
+}
+
+fn parse_interval_range(s: &str, from: &str, to: &str) -> Option<IntervalValue> {
+    match (from, to) {
+        ("YEAR", "MONTH") => {
+            let (y, m) = s.split_once('-')?;
+            Some(IntervalValue::new(y.trim().parse().ok()?, m.trim().parse().ok()?, 0, 0))
+        }
+        ("DAY", "HOUR" | "MINUTE" | "SECOND") => {
+            let mut it = s.split_whitespace();
+            let days: i32 = it.next()?.parse().ok()?;
+            let clock = match it.next() {
+                Some(c) => parse_clock_fields(c, "HOUR")?,
+                None => IntervalValue::zero(),
+            };
+            if it.next().is_some() { return None; }
+            IntervalValue::new(0, 0, days, 0).checked_add(&clock)
+        }
+        ("HOUR" | "MINUTE", "MINUTE" | "SECOND") => parse_clock_fields(s, from),
+        _ => None,
+    }
+}
+
+/// Whitespace-separated `<number> <unit>` pairs plus an optional clock field.
+fn parse_interval_fields(s: &str) -> Option<IntervalValue> {
+    let tokens: Vec<&str> = s.split_whitespace().collect();
+    if tokens.is_empty() { return None; }
+    let mut iv = IntervalValue::zero();
+    let mut i = 0;
+    while i < tokens.len() {
+        let tok = tokens[i];
+        if tok.contains(':') {
+            iv = iv.checked_add(&parse_clock_fields(tok, "HOUR")?)?;
+            i += 1;
+            continue;
+        }
+        if let Some((y, m)) = tok.split_once('-').filter(|(y, _)| !y.is_empty()) {
+            iv = iv.checked_add(&IntervalValue::new(y.parse().ok()?, m.parse().ok()?, 0, 0))?;
+            i += 1;
+            continue;
+        }
+        let n: f64 = tok.parse().ok()?;
+        let unit = tokens.get(i + 1)?;
+        iv = iv.checked_add(&interval_from_unit(n, unit)?)?;
+        i += 2;
+    }
+    Some(iv)
+}
+
+/// ISO-8601 duration body after the leading `P`.
+fn parse_iso_duration(s: &str) -> Option<IntervalValue> {
+    if s.is_empty() { return None; }
+    let mut iv = IntervalValue::zero();
+    let mut in_time = false;
+    let mut num = String::new();
+    for c in s.chars() {
+        match c.to_ascii_uppercase() {
+            'T' if !in_time && num.is_empty() => in_time = true,
+            d if d.is_ascii_digit() || d == '.' || d == ',' => num.push(if d == ',' { '.' } else { d }),
+            designator => {
+                let n: f64 = num.parse().ok()?;
+                num.clear();
+                let unit = match (designator, in_time) {
+                    ('Y', false) => "YEAR",
+                    ('M', false) => "MONTH",
+                    ('W', false) => "WEEK",
+                    ('D', false) => "DAY",
+                    ('H', true) => "HOUR",
+                    ('M', true) => "MINUTE",
+                    ('S', true) => "SECOND",
+                    _ => return None,
+                };
+                iv = iv.checked_add(&interval_from_unit(n, unit)?)?;
+            }
+        }
+    }
+    if !num.is_empty() { return None; }
+    Some(iv)
+}
@@ -1,20 +1,55 @@
-use crate::column::ScalarValue;
+use crate::column::{date_string_to_epoch_days, timestamp_string_to_epoch_micros, ScalarValue};
 use crate::datastore::DataStore;
 use crate::error::{PivotError, Result};
 use crate::schema::{ColumnDef, DataType, Schema};
+use crate::sql::QueryResult;
 
 pub struct CsvReader {
     pub delimiter: char,
     pub has_header: bool,
+    /// Read empty fields as NULL rather than `Utf8("")` (default: true).
+    pub empty_as_null: bool,
+    /// Field values read as NULL, e.g. `\N` or `NA` (default: none).
+    pub null_tokens: Vec<String>,
+    /// Match `null_tokens` exactly rather than ignoring ASCII case (default: true).
+    pub null_tokens_case_sensitive: bool,
+    /// Narrow each column to the type its values parse as rather than
+    /// reading everything as `Utf8` (default: false).
+    pub infer_types: bool,
+}
+
+impl Default for CsvReader {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CsvReader {
-    pub fn new() -> Self { Self { delimiter: ',', has_header: true } }
+    pub fn new() -> Self {
+        Self {
+            delimiter: ',',
+            has_header: true,
+            empty_as_null: true,
+            null_tokens: Vec::new(),
+            null_tokens_case_sensitive: true,
+            infer_types: false,
+        }
+    }
     pub fn with_delimiter(mut self, delimiter: char) -> Self { self.delimiter = delimiter; self }
     pub fn with_header(mut self, has_header: bool) -> Self { self.has_header = has_header; self }
+    pub fn with_empty_as_null(mut self, empty_as_null: bool) -> Self { self.empty_as_null = empty_as_null; self }
+    pub fn with_null_tokens(mut self, tokens: &[&str]) -> Self {
+        self.null_tokens = tokens.iter().map(|t| t.to_string()).collect();
+        self
+    }
+    pub fn with_null_tokens_case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.null_tokens_case_sensitive = case_sensitive;
+        self
+    }
+    pub fn with_type_inference(mut self, infer_types: bool) -> Self { self.infer_types = infer_types; self }
 
     pub fn read_str(&self, data: &str) -> Result<DataStore> {
-        let mut lines = data.lines();
+        let mut lines = self.records(data)?.into_iter();
         let headers: Vec<String> = if self.has_header {
             if let Some(line) = lines.next() {
                 self.split_line(line)
@@ -51,42 +86,91 @@ impl CsvReader {
         let mut store = DataStore::new(schema);
         for row in all_rows {
             let values: Vec<ScalarValue> = row.into_iter()
-                .map(|s| if s.is_empty() { ScalarValue::Null } else { ScalarValue::Utf8(s) })
+                .map(|s| if self.is_null_field(&s) { ScalarValue::Null } else { ScalarValue::Utf8(s) })
                 .collect();
             let mut padded = values;
             padded.resize(col_count, ScalarValue::Null);
             store.append_row(padded)?;
         }
-        Ok(store)
+        if self.infer_types { infer_column_types(store) } else { Ok(store) }
     }
 
+    fn is_null_field(&self, field: &str) -> bool {
+        (field.is_empty() && self.empty_as_null)
+            || self.null_tokens.iter().any(|t| if self.null_tokens_case_sensitive {
+                t == field
+            } else {
+                t.eq_ignore_ascii_case(field)
+            })
+    }
+
+    /// Split a record into fields. A `"` opens a quoted field only at the
+    /// start of a field; elsewhere it is an ordinary character.
     fn split_line(&self, line: &str) -> Vec<String> {
         let mut result = Vec::new();
         let mut current = String::new();
         let mut in_quotes = false;
+        let mut field_start = true;
         let mut chars = line.chars().peekable();
         while let Some(c) = chars.next() {
-            if c == '"' {
-                if in_quotes {
-                    if chars.peek() == Some(&'"') {
-                        chars.next();
-                        current.push('"');
-                    } else {
-                        in_quotes = false;
-                    }
+            if in_quotes && c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    current.push('"');
                 } else {
-                    in_quotes = true;
+                    in_quotes = false;
                 }
+            } else if c == '"' && field_start {
+                in_quotes = true;
             } else if c == self.delimiter && !in_quotes {
-                result.push(current.clone());
-                current.clear();
+                result.push(std::mem::take(&mut current));
+                field_start = true;
+                continue;
             } else {
                 current.push(c);
             }
+            field_start = false;
         }
         result.push(current);
         result
     }
+
+    /// Split `data` into records at line breaks outside quoted fields, so a
+    /// quoted field may span lines. A `\r` before the break is dropped, and
+    /// a quoted field still open at the end of the input is an error.
+    fn records<'a>(&self, data: &'a str) -> Result<Vec<&'a str>> {
+        let mut records = Vec::new();
+        let mut start = 0;
+        let mut in_quotes = false;
+        let mut field_start = true;
+        let mut chars = data.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                // `""` inside a quoted field is an escaped quote.
+                '"' if in_quotes => in_quotes = chars.next_if(|&(_, c)| c == '"').is_some(),
+                '"' if field_start => in_quotes = true,
+                '\n' if !in_quotes => {
+                    records.push(data[start..i].strip_suffix('\r').unwrap_or(&data[start..i]));
+                    start = i + 1;
+                    field_start = true;
+                    continue;
+                }
+                c if c == self.delimiter && !in_quotes => {
+                    field_start = true;
+                    continue;
+                }
+                _ => {}
+            }
+            field_start = false;
+        }
+        if in_quotes {
+            return Err(PivotError::IoError("Unterminated quoted field in CSV".to_string()));
+        }
+        if start < data.len() {
+            records.push(&data[start..]);
+        }
+        Ok(records)
+    }
 }
 
 pub struct CsvWriter {
@@ -94,31 +178,114 @@ pub struct CsvWriter {
     pub write_header: bool,
 }
 
+impl Default for CsvWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CsvWriter {
     pub fn new() -> Self { Self { delimiter: ',', write_header: true } }
 
     pub fn write_str(&self, store: &DataStore) -> Result<String> {
+        let rows = (0..store.row_count()).map(|row| store.get_row(row)).collect::<Result<Vec<_>>>()?;
+        Ok(self.write_rows(&store.schema().column_names(), &rows))
+    }
+
+    /// Write a query's output, with its column names as the header.
+    pub fn write_query_result(&self, result: &QueryResult) -> Result<String> {
+        Ok(self.write_rows(&result.columns, &result.rows))
+    }
+
+    fn write_rows(&self, headers: &[String], rows: &[Vec<ScalarValue>]) -> String {
+        let sep = self.delimiter.to_string();
         let mut out = String::new();
         if self.write_header {
-            let headers: Vec<String> = store.schema().column_names();
-            out.push_str(&headers.join(&self.delimiter.to_string()));
+            let fields: Vec<String> = headers.iter().map(|h| self.quote(h)).collect();
+            out.push_str(&fields.join(&sep));
             out.push('\n');
         }
-        for row in 0..store.row_count() {
-            let values: Vec<String> = (0..store.schema().column_count())
-                .map(|col| {
-                    store.get_value_by_index(row, col)
-                        .ok()
-                        .map(|v| match v {
-                            ScalarValue::Null => String::new(),
-                            other => format!("{}", other),
-                        })
-                        .unwrap_or_default()
+        for row in rows {
+            let fields: Vec<String> = row.iter()
+                .map(|v| match v {
+                    ScalarValue::Null => String::new(),
+                    // Quoted so that `with_empty_as_null(false)` reads it back as ''
+                    ScalarValue::Utf8(s) if s.is_empty() => "\"\"".to_string(),
+                    other => self.quote(&other.to_string()),
                 })
                 .collect();
-            out.push_str(&values.join(&self.delimiter.to_string()));
+            out.push_str(&fields.join(&sep));
             out.push('\n');
         }
-        Ok(out)
+        out
+    }
+
+    /// Quote a field the way `CsvReader` unquotes it: wrapped in `"` with
+    /// inner quotes doubled, when it holds the delimiter, a quote or a line break.
+    fn quote(&self, field: &str) -> String {
+        if field.contains([self.delimiter, '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+}
+
+/// Narrow each all-text column of a freshly read CSV store to the first of
+/// `Int64`, `Float64`, `Boolean`, `Date` or `Timestamp` that every non-null
+/// value parses as. Columns that fit none of them stay `Utf8`, which includes
+/// any column holding an empty string kept by `with_empty_as_null(false)`.
+fn infer_column_types(store: DataStore) -> Result<DataStore> {
+    let schema = store.schema();
+    let col_count = schema.column_count();
+    let mut columns: Vec<Vec<ScalarValue>> = vec![Vec::with_capacity(store.row_count()); col_count];
+    for row in 0..store.row_count() {
+        for (ci, col) in columns.iter_mut().enumerate() {
+            col.push(store.get_value_by_index(row, ci)?);
+        }
+    }
+    let mut defs = Vec::with_capacity(col_count);
+    for (ci, col) in columns.iter_mut().enumerate() {
+        let def = &schema.columns[ci];
+        let dt = infer_type(col);
+        for v in col.iter_mut() {
+            if let ScalarValue::Utf8(s) = v {
+                *v = parse_as(s, &dt).unwrap_or(ScalarValue::Null);
+            }
+        }
+        defs.push(ColumnDef::new(&def.name, dt, def.nullable));
+    }
+    let mut typed = DataStore::new(Schema::new(defs));
+    for row in 0..store.row_count() {
+        typed.append_row(columns.iter().map(|c| c[row].clone()).collect())?;
+    }
+    Ok(typed)
+}
+
+fn infer_type(values: &[ScalarValue]) -> DataType {
+    let texts: Vec<&str> = values.iter()
+        .filter_map(|v| if let ScalarValue::Utf8(s) = v { Some(s.as_str()) } else { None })
+        .collect();
+    if texts.is_empty() { return DataType::Utf8; }
+    [DataType::Int64, DataType::Float64, DataType::Boolean, DataType::Date, DataType::Timestamp]
+        .into_iter()
+        .find(|dt| texts.iter().all(|s| parse_as(s, dt).is_some()))
+        .unwrap_or(DataType::Utf8)
+}
+
+fn parse_as(s: &str, dt: &DataType) -> Option<ScalarValue> {
+    let t = s.trim();
+    match dt {
+        DataType::Int64 => t.parse().ok().map(ScalarValue::Int64),
+        DataType::Float64 => t.parse().ok().map(ScalarValue::Float64),
+        DataType::Boolean => match t.to_ascii_lowercase().as_str() {
+            "true" => Some(ScalarValue::Boolean(true)),
+            "false" => Some(ScalarValue::Boolean(false)),
+            _ => None,
+        },
+        DataType::Date if t.len() == 10 => date_string_to_epoch_days(t).map(ScalarValue::Date),
+        DataType::Timestamp if t.len() > 10 => timestamp_string_to_epoch_micros(t).map(ScalarValue::Timestamp),
+        DataType::Utf8 => Some(ScalarValue::Utf8(s.to_string())),
+        _ => None,
     }
 }
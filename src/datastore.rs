@@ -1,8 +1,9 @@
 use crate::bitmap::NullBitmask;
-use crate::column::{ScalarValue, date_string_to_epoch_days,
+use crate::column::{HashableScalar, ScalarValue, date_string_to_epoch_days, parse_interval,
     timestamp_string_to_epoch_micros, time_string_to_micros};
 use crate::error::{PivotError, Result};
 use crate::schema::{ColumnDef, DataType, Schema};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone)]
 pub struct ColumnStorage {
@@ -23,13 +24,50 @@ impl ColumnStorage {
             nullmask: NullBitmask::new(),
         }
     }
+
+    fn retain_mask(&mut self, keep: &[bool]) {
+        retain_by_mask(&mut self.booleans, keep);
+        retain_by_mask(&mut self.int64s, keep);
+        retain_by_mask(&mut self.float64s, keep);
+        retain_by_mask(&mut self.utf8s, keep);
+        self.nullmask.retain_mask(keep);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.booleans.shrink_to_fit();
+        self.int64s.shrink_to_fit();
+        self.float64s.shrink_to_fit();
+        self.utf8s.shrink_to_fit();
+        self.nullmask.shrink_to_fit();
+    }
+
+    fn capacity(&self) -> usize {
+        self.booleans.capacity()
+            .max(self.int64s.capacity())
+            .max(self.float64s.capacity())
+            .max(self.utf8s.capacity())
+            .max(self.nullmask.capacity())
+    }
 }
 
+fn retain_by_mask<T>(v: &mut Vec<T>, keep: &[bool]) {
+    let mut i = 0;
+    v.retain(|_| { let k = keep.get(i).copied().unwrap_or(true); i += 1; k });
+}
+
+/// The values held by each UNIQUE column of a table, so a batch of appends
+/// checks against a set instead of rescanning the table for every row.
+/// Valid until the table changes other than through `append_row_with`.
+#[derive(Debug)]
+pub struct UniqueValues(Vec<Option<HashSet<HashableScalar>>>);
+
 #[derive(Debug, Clone)]
 pub struct DataStore {
     schema: Schema,
     columns: Vec<ColumnStorage>,
     row_count: usize,
+    /// Primary key value → row index; empty without a primary key.
+    key_index: HashMap<HashableScalar, usize>,
 }
 
 impl DataStore {
@@ -39,13 +77,32 @@ impl DataStore {
         for _ in 0..col_count {
             columns.push(ColumnStorage::new());
         }
-        Self { schema, columns, row_count: 0 }
+        Self { schema, columns, row_count: 0, key_index: HashMap::new() }
     }
 
     pub fn schema(&self) -> &Schema { &self.schema }
     pub fn row_count(&self) -> usize { self.row_count }
 
     pub fn append_row(&mut self, values: Vec<ScalarValue>) -> Result<()> {
+        let mut unique = self.unique_values()?;
+        self.append_row_with(values, &mut unique)
+    }
+
+    /// The current values of the UNIQUE columns, for `append_row_with`.
+    pub fn unique_values(&self) -> Result<UniqueValues> {
+        self.schema.columns.iter().enumerate()
+            .map(|(i, col_def)| col_def.unique.then(|| (0..self.row_count)
+                .map(|row| self.get_value_by_index(row, i))
+                .filter(|v| !matches!(v, Ok(ScalarValue::Null)))
+                .map(|v| v.map(HashableScalar::new))
+                .collect::<Result<HashSet<_>>>()).transpose())
+            .collect::<Result<Vec<_>>>()
+            .map(UniqueValues)
+    }
+
+    /// Append a row, checking UNIQUE columns against `unique` and adding
+    /// the row's values to it.
+    pub fn append_row_with(&mut self, values: Vec<ScalarValue>, unique: &mut UniqueValues) -> Result<()> {
         if values.len() != self.schema.column_count() {
             return Err(PivotError::SchemaError(format!(
                 "Expected {} values, got {}", self.schema.column_count(), values.len()
@@ -59,21 +116,123 @@ impl DataStore {
                 )));
             }
         }
-        for (i, val) in values.into_iter().enumerate() {
-            let col_def = &self.schema.columns[i];
-            let coerced = self.coerce_value(val, &col_def.data_type.clone())?;
-            self.push_to_column(i, coerced);
+        let coerced = values.into_iter().enumerate()
+            .map(|(i, val)| self.coerce_value(val, &self.schema.columns[i].data_type))
+            .collect::<Result<Vec<_>>>()?;
+        let key = self.schema.primary_key_index().map(|pk| HashableScalar::new(coerced[pk].clone()));
+        if let Some(key) = &key {
+            self.check_key_free(key)?;
+        }
+        let unique_keys: Vec<Option<HashableScalar>> = coerced.iter().zip(&unique.0)
+            .map(|(val, seen)| seen.as_ref().filter(|_| !matches!(val, ScalarValue::Null))
+                .map(|_| HashableScalar::new(val.clone())))
+            .collect();
+        for (i, key) in unique_keys.iter().enumerate() {
+            if let (Some(key), Some(seen)) = (key, &unique.0[i]) {
+                if seen.contains(key) {
+                    return Err(duplicate_unique(&key.0, &self.schema.columns[i].name));
+                }
+            }
+        }
+        for (i, val) in coerced.into_iter().enumerate() {
+            self.push_to_column(i, val);
+        }
+        for (seen, key) in unique.0.iter_mut().zip(unique_keys) {
+            if let (Some(seen), Some(key)) = (seen, key) { seen.insert(key); }
+        }
+        if let Some(key) = key {
+            self.key_index.insert(key, self.row_count);
         }
         self.row_count += 1;
         Ok(())
     }
 
+    /// The row whose primary key equals `key`, if the table has a primary
+    /// key and such a row exists.
+    pub fn get_row_by_key(&self, key: &ScalarValue) -> Option<Vec<ScalarValue>> {
+        let row = *self.key_index.get(&HashableScalar::new(key.clone()))?;
+        self.get_row(row).ok()
+    }
+
+    fn check_key_free(&self, key: &HashableScalar) -> Result<()> {
+        if self.key_index.contains_key(key) {
+            let pk = self.schema.primary_key_index().map(|i| self.schema.columns[i].name.as_str()).unwrap_or("");
+            return Err(PivotError::SchemaError(format!(
+                "Duplicate primary key {} for column '{}'", key.0, pk
+            )));
+        }
+        Ok(())
+    }
+
+    /// For a UNIQUE column, fail if a row other than `skip_row` already holds
+    /// `val`. NULLs never conflict.
+    fn check_unique_free(&self, col_idx: usize, val: &ScalarValue, skip_row: Option<usize>) -> Result<()> {
+        let col_def = &self.schema.columns[col_idx];
+        if !col_def.unique || matches!(val, ScalarValue::Null) { return Ok(()); }
+        let key = HashableScalar::new(val.clone());
+        for row in (0..self.row_count).filter(|&r| Some(r) != skip_row) {
+            if HashableScalar::new(self.get_value_by_index(row, col_idx)?) == key {
+                return Err(duplicate_unique(val, &col_def.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail if the values of a UNIQUE or primary key column repeat.
+    fn check_unique_column(&self, col_idx: usize, values: &[ScalarValue]) -> Result<()> {
+        let col_def = &self.schema.columns[col_idx];
+        if !col_def.unique && !col_def.primary_key { return Ok(()); }
+        let mut seen = HashSet::new();
+        let dup = values.iter()
+            .filter(|v| !matches!(v, ScalarValue::Null))
+            .find(|v| !seen.insert(HashableScalar::new((*v).clone())));
+        match dup {
+            None => Ok(()),
+            Some(val) if col_def.primary_key => Err(PivotError::SchemaError(format!(
+                "Duplicate primary key {} for column '{}'", val, col_def.name
+            ))),
+            Some(val) => Err(duplicate_unique(val, &col_def.name)),
+        }
+    }
+
+    /// Recompute `key_index` from the stored rows, e.g. after rows moved.
+    fn rebuild_key_index(&mut self) -> Result<()> {
+        self.key_index.clear();
+        let Some(pk) = self.schema.primary_key_index() else { return Ok(()); };
+        for row in 0..self.row_count {
+            let key = HashableScalar::new(self.get_value_by_index(row, pk)?);
+            self.check_key_free(&key)?;
+            self.key_index.insert(key, row);
+        }
+        Ok(())
+    }
+
+    /// Convert `val` for storage in a `target` column. The conversions
+    /// allowed here are the ones `Schema::is_compatible_for_insert` accepts
+    /// for typed sources; anything else is a `TypeError`.
     fn coerce_value(&self, val: ScalarValue, target: &DataType) -> Result<ScalarValue> {
         match (&val, target) {
             (ScalarValue::Null, _) => Ok(ScalarValue::Null),
             (ScalarValue::Int64(i), DataType::Float64) => Ok(ScalarValue::Float64(*i as f64)),
-            (ScalarValue::Float64(_), DataType::Float64) => Ok(val),
-            (ScalarValue::Int64(_), DataType::Int64) => Ok(val),
+            (ScalarValue::Float64(_), DataType::Float64)
+            | (ScalarValue::Int64(_), DataType::Int64)
+            | (ScalarValue::Boolean(_), DataType::Boolean)
+            | (ScalarValue::Utf8(_), DataType::Utf8)
+            | (ScalarValue::Date(_), DataType::Date)
+            | (ScalarValue::Timestamp(_), DataType::Timestamp)
+            | (ScalarValue::Time(_), DataType::Time)
+            | (ScalarValue::Interval(_), DataType::Interval) => Ok(val),
+            (ScalarValue::Utf8(s), DataType::Int64) => s.trim().parse()
+                .map(ScalarValue::Int64)
+                .map_err(|_| PivotError::TypeError(format!("Cannot parse integer: {}", s))),
+            (ScalarValue::Utf8(s), DataType::Float64 | DataType::Decimal { .. }) => s.trim().parse()
+                .map(ScalarValue::Float64)
+                .map_err(|_| PivotError::TypeError(format!("Cannot parse number: {}", s))),
+            (ScalarValue::Utf8(s), DataType::Boolean) => match s.trim().to_lowercase().as_str() {
+                "true" => Ok(ScalarValue::Boolean(true)),
+                "false" => Ok(ScalarValue::Boolean(false)),
+                _ => Err(PivotError::TypeError(format!("Cannot parse boolean: {}", s))),
+            },
             (ScalarValue::Utf8(s), DataType::Date) => {
                 date_string_to_epoch_days(s)
                     .map(ScalarValue::Date)
@@ -89,13 +248,26 @@ impl DataStore {
                     .map(ScalarValue::Time)
                     .ok_or_else(|| PivotError::TypeError(format!("Cannot parse time: {}", s)))
             }
+            (ScalarValue::Utf8(s), DataType::Interval) => {
+                parse_interval(s)
+                    .map(ScalarValue::Interval)
+                    .ok_or_else(|| PivotError::TypeError(format!("Cannot parse interval: {}", s)))
+            }
+            // Composite values have no column type of their own; keep their text.
+            (ScalarValue::Row(_) | ScalarValue::Array(_), DataType::Utf8) => Ok(ScalarValue::Utf8(val.to_string())),
+            (ScalarValue::Row(_), _) => {
+                Err(PivotError::TypeError(format!("Cannot store row value {} in a {} column", val, target)))
+            }
+            (ScalarValue::Array(_), _) => {
+                Err(PivotError::TypeError(format!("Cannot store array value {} in a {} column", val, target)))
+            }
             (ScalarValue::Int64(i), DataType::Date) => Ok(ScalarValue::Date(*i)),
             (ScalarValue::Int64(i), DataType::Timestamp) => Ok(ScalarValue::Timestamp(*i)),
             (ScalarValue::Int64(i), DataType::Time) => Ok(ScalarValue::Time(*i)),
             (ScalarValue::Float64(v), DataType::Int64) => Ok(ScalarValue::Int64(*v as i64)),
             (ScalarValue::Int64(i), DataType::Decimal { .. }) => Ok(ScalarValue::Float64(*i as f64)),
             (ScalarValue::Float64(_), DataType::Decimal { .. }) => Ok(val),
-            _ => Ok(val),
+            _ => Err(PivotError::TypeError(format!("Cannot store value {} in a {} column", val, target))),
         }
     }
 
@@ -134,7 +306,11 @@ impl DataStore {
                 col.nullmask.push(true);
             }
             ScalarValue::Interval(iv) => {
-                col.utf8s.push(format!("{}:{}:{}:{}", iv.years, iv.months, iv.days, iv.micros));
+                col.utf8s.push(iv.to_string());
+                col.nullmask.push(true);
+            }
+            v @ (ScalarValue::Row(_) | ScalarValue::Array(_)) => {
+                col.utf8s.push(v.to_string());
                 col.nullmask.push(true);
             }
         }
@@ -164,19 +340,10 @@ impl DataStore {
             DataType::Timestamp => ScalarValue::Timestamp(*col.int64s.get(row).unwrap_or(&0)),
             DataType::Time => ScalarValue::Time(*col.int64s.get(row).unwrap_or(&0)),
             DataType::Interval => {
-                let s = col.utf8s.get(row).cloned().unwrap_or_default();
-                let parts: Vec<&str> = s.splitn(4, ':').collect();
-                if parts.len() == 4 {
-                    use crate::column::IntervalValue;
-                    ScalarValue::Interval(IntervalValue::new(
-                        parts[0].parse().unwrap_or(0),
-                        parts[1].parse().unwrap_or(0),
-                        parts[2].parse().unwrap_or(0),
-                        parts[3].parse().unwrap_or(0),
-                    ))
-                } else {
-                    ScalarValue::Null
-                }
+                col.utf8s.get(row)
+                    .and_then(|s| parse_interval(s))
+                    .map(ScalarValue::Interval)
+                    .unwrap_or(ScalarValue::Null)
             }
         })
     }
@@ -199,6 +366,66 @@ impl DataStore {
         }
         let data_type = self.schema.columns[col_idx].data_type.clone();
         let coerced = self.coerce_value(val, &data_type)?;
+        if matches!(coerced, ScalarValue::Null) && !self.schema.columns[col_idx].nullable {
+            return Err(PivotError::NullError(format!(
+                "Column '{}' is NOT NULL", self.schema.columns[col_idx].name
+            )));
+        }
+        self.check_unique_free(col_idx, &coerced, Some(row))?;
+        if self.schema.columns[col_idx].primary_key {
+            let old_key = HashableScalar::new(self.get_value_by_index(row, col_idx)?);
+            let new_key = HashableScalar::new(coerced.clone());
+            if new_key != old_key {
+                self.check_key_free(&new_key)?;
+                self.key_index.remove(&old_key);
+                self.key_index.insert(new_key, row);
+            }
+        }
+        self.write_value(row, col_idx, coerced);
+        Ok(())
+    }
+
+    /// Apply `(row, column, value)` writes as one change: constraints are
+    /// checked against the final state, and on error nothing is written. So
+    /// `SET id = id + 1` may shift keys through each other.
+    pub fn update_cells(&mut self, updates: Vec<(usize, usize, ScalarValue)>) -> Result<()> {
+        let mut coerced = Vec::with_capacity(updates.len());
+        for (row, col_idx, val) in updates {
+            if row >= self.row_count {
+                return Err(PivotError::IndexOutOfBounds(format!("Row {} out of bounds", row)));
+            }
+            let col_def = &self.schema.columns[col_idx];
+            let val = self.coerce_value(val, &col_def.data_type)?;
+            if matches!(val, ScalarValue::Null) && !col_def.nullable {
+                return Err(PivotError::NullError(format!("Column '{}' is NOT NULL", col_def.name)));
+            }
+            coerced.push((row, col_idx, val));
+        }
+        let mut touched: Vec<usize> = coerced.iter().map(|(_, c, _)| *c).collect();
+        touched.sort_unstable();
+        touched.dedup();
+        for &col_idx in &touched {
+            let col_def = &self.schema.columns[col_idx];
+            if !col_def.unique && !col_def.primary_key { continue; }
+            let mut values = (0..self.row_count)
+                .map(|r| self.get_value_by_index(r, col_idx))
+                .collect::<Result<Vec<_>>>()?;
+            for (row, c, val) in &coerced {
+                if *c == col_idx { values[*row] = val.clone(); }
+            }
+            self.check_unique_column(col_idx, &values)?;
+        }
+        for (row, col_idx, val) in coerced {
+            self.write_value(row, col_idx, val);
+        }
+        if self.schema.primary_key_index().is_some_and(|pk| touched.contains(&pk)) {
+            self.rebuild_key_index()?;
+        }
+        Ok(())
+    }
+
+    /// Store an already coerced and checked value.
+    fn write_value(&mut self, row: usize, col_idx: usize, coerced: ScalarValue) {
         let col = &mut self.columns[col_idx];
         match coerced {
             ScalarValue::Null => { col.nullmask.set(row, false); }
@@ -223,12 +450,16 @@ impl DataStore {
                 col.nullmask.set(row, true);
             }
             ScalarValue::Interval(iv) => {
-                let s = format!("{}:{}:{}:{}", iv.years, iv.months, iv.days, iv.micros);
+                let s = iv.to_string();
+                if row < col.utf8s.len() { col.utf8s[row] = s; } else { col.utf8s.push(s); }
+                col.nullmask.set(row, true);
+            }
+            v @ (ScalarValue::Row(_) | ScalarValue::Array(_)) => {
+                let s = v.to_string();
                 if row < col.utf8s.len() { col.utf8s[row] = s; } else { col.utf8s.push(s); }
                 col.nullmask.set(row, true);
             }
         }
-        Ok(())
     }
 
     pub fn add_column(&mut self, def: ColumnDef) -> Result<()> {
@@ -255,15 +486,98 @@ impl DataStore {
     pub fn drop_column(&mut self, name: &str) -> Result<()> {
         let idx = self.schema.find_column_index(name)
             .ok_or_else(|| PivotError::ColumnNotFound(name.to_string()))?;
-        self.schema.columns.remove(idx);
+        let def = self.schema.columns.remove(idx);
         self.columns.remove(idx);
+        if def.primary_key { self.key_index.clear(); }
+        Ok(())
+    }
+
+    /// Remove the given rows in place. Backing vectors keep their capacity
+    /// until `compact()` is called. Returns the number of rows removed.
+    pub fn delete_rows(&mut self, rows: &[usize]) -> usize {
+        let mut keep = vec![true; self.row_count];
+        let mut removed = 0;
+        for &r in rows {
+            if r < self.row_count && keep[r] {
+                keep[r] = false;
+                removed += 1;
+            }
+        }
+        if removed == 0 { return 0; }
+        for col in &mut self.columns {
+            col.retain_mask(&keep);
+        }
+        self.row_count -= removed;
+        if !self.key_index.is_empty() {
+            // Surviving keys were unique before, so this cannot fail.
+            let _ = self.rebuild_key_index();
+        }
+        removed
+    }
+
+    /// Shrink backing vectors to fit the live rows.
+    pub fn compact(&mut self) {
+        for col in &mut self.columns {
+            col.shrink_to_fit();
+        }
+    }
+
+    /// Number of row slots currently allocated by the widest backing vector.
+    pub fn capacity(&self) -> usize {
+        self.columns.iter().map(|c| c.capacity()).max().unwrap_or(0)
+    }
+
+    /// Change a column's type, replacing its storage with `values` (one per
+    /// row, already converted to `data_type`).
+    pub fn retype_column(&mut self, col_idx: usize, data_type: DataType, values: Vec<ScalarValue>) -> Result<()> {
+        if values.len() != self.row_count {
+            return Err(PivotError::SchemaError(format!(
+                "Expected {} values, got {}", self.row_count, values.len()
+            )));
+        }
+        let col_def = &self.schema.columns[col_idx];
+        if !col_def.nullable && values.iter().any(|v| matches!(v, ScalarValue::Null)) {
+            return Err(PivotError::NullError(format!("Column '{}' is NOT NULL", col_def.name)));
+        }
+        let old_type = std::mem::replace(&mut self.schema.columns[col_idx].data_type, data_type.clone());
+        let old_storage = std::mem::replace(&mut self.columns[col_idx], ColumnStorage::new());
+        let mut converted = Ok(());
+        for val in values {
+            match self.coerce_value(val, &data_type) {
+                Ok(v) => self.push_to_column(col_idx, v),
+                Err(e) => { converted = Err(e); break; }
+            }
+        }
+        if converted.is_ok() && self.schema.columns[col_idx].primary_key {
+            // Converted keys may collide (e.g. 1.2 and 1.4 as INTEGER).
+            converted = self.rebuild_key_index();
+        }
+        if converted.is_ok() && self.schema.columns[col_idx].unique {
+            converted = (0..self.row_count)
+                .map(|r| self.get_value_by_index(r, col_idx))
+                .collect::<Result<Vec<_>>>()
+                .and_then(|values| self.check_unique_column(col_idx, &values));
+        }
+        if let Err(e) = converted {
+            self.schema.columns[col_idx].data_type = old_type;
+            self.columns[col_idx] = old_storage;
+            self.rebuild_key_index()?;
+            return Err(e);
+        }
         Ok(())
     }
 
     pub fn rename_column(&mut self, old_name: &str, new_name: &str) -> Result<()> {
         let idx = self.schema.find_column_index(old_name)
             .ok_or_else(|| PivotError::ColumnNotFound(old_name.to_string()))?;
+        if self.schema.find_column_index(new_name).is_some_and(|other| other != idx) {
+            return Err(PivotError::SchemaError(format!("Column '{}' already exists", new_name)));
+        }
         self.schema.columns[idx].name = new_name.to_string();
         Ok(())
     }
 }
+
+fn duplicate_unique(val: &ScalarValue, column: &str) -> PivotError {
+    PivotError::SchemaError(format!("Duplicate value {} for UNIQUE column '{}'", val, column))
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
 use crate::sql::{SqlEngine, QueryResult};
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_int};
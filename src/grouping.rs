@@ -1,4 +1,4 @@
-use crate::column::ScalarValue;
+use crate::column::{HashableScalar, ScalarValue};
 use crate::datastore::DataStore;
 use crate::error::Result;
 use std::collections::HashMap;
@@ -9,25 +9,22 @@ pub struct GroupResult {
 }
 
 pub fn group_by(store: &DataStore, col_names: &[&str]) -> Result<Vec<GroupResult>> {
-    let mut map: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
-    let mut key_order: Vec<Vec<String>> = Vec::new();
+    let mut map: HashMap<Vec<HashableScalar>, Vec<usize>> = HashMap::new();
+    let mut key_order: Vec<Vec<HashableScalar>> = Vec::new();
     for row in 0..store.row_count() {
-        let key_strs: Vec<String> = col_names.iter().map(|name| {
-            store.get_value(row, name).ok()
-                .map(|v| format!("{}", v))
-                .unwrap_or_default()
-        }).collect();
-        let entry = map.entry(key_strs.clone()).or_insert_with(Vec::new);
+        let key: Vec<HashableScalar> = col_names.iter()
+            .map(|name| store.get_value(row, name).map(HashableScalar))
+            .collect::<Result<_>>()?;
+        let entry = map.entry(key.clone()).or_default();
         if entry.is_empty() {
-            key_order.push(key_strs);
+            key_order.push(key);
         }
         entry.push(row);
     }
     let mut results = Vec::new();
-    for key_strs in &key_order {
-        let indices = map.remove(key_strs).unwrap_or_default();
-        let key: Vec<ScalarValue> = key_strs.iter()
-            .map(|s| ScalarValue::Utf8(s.clone())).collect();
+    for key in key_order {
+        let indices = map.remove(&key).unwrap_or_default();
+        let key: Vec<ScalarValue> = key.into_iter().map(HashableScalar::into_inner).collect();
         results.push(GroupResult { key, row_indices: indices });
     }
     Ok(results)
@@ -0,0 +1,286 @@
+use crate::column::ScalarValue;
+use crate::datastore::DataStore;
+use crate::error::{PivotError, Result};
+use crate::schema::{ColumnDef, DataType, Schema};
+
+/// Reads JSON records into a `DataStore`. The input is either an array of
+/// objects or newline-delimited objects; keys become columns in first-seen
+/// order. Column types follow the values: all integers give `Int64`, any
+/// mix of numbers `Float64`, all booleans `Boolean`, anything else `Utf8`.
+/// Nested arrays and objects are kept as their JSON text.
+pub struct JsonReader;
+
+impl Default for JsonReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// A nested array or object, as written.
+    Raw(String),
+}
+
+impl JsonReader {
+    pub fn new() -> Self { Self }
+
+    pub fn read_str(&self, data: &str) -> Result<DataStore> {
+        let records = parse_records(data)?;
+
+        let mut names: Vec<String> = Vec::new();
+        for record in &records {
+            for (key, _) in record {
+                if !names.contains(key) { names.push(key.clone()); }
+            }
+        }
+        let mut columns: Vec<Vec<JsonValue>> = vec![Vec::with_capacity(records.len()); names.len()];
+        for record in records {
+            for (ci, name) in names.iter().enumerate() {
+                let value = record.iter().rev()
+                    .find(|(k, _)| k == name)
+                    .map(|(_, v)| v.clone())
+                    .unwrap_or(JsonValue::Null);
+                columns[ci].push(value);
+            }
+        }
+
+        let types: Vec<DataType> = columns.iter().map(|values| column_type(values)).collect();
+        let schema = Schema::new(names.iter().zip(&types)
+            .map(|(name, dt)| ColumnDef::new(name, dt.clone(), true))
+            .collect());
+        let mut store = DataStore::new(schema);
+        let row_count = columns.first().map(|c| c.len()).unwrap_or(0);
+        for row in 0..row_count {
+            let values = columns.iter().zip(&types)
+                .map(|(col, dt)| to_scalar(&col[row], dt))
+                .collect();
+            store.append_row(values)?;
+        }
+        Ok(store)
+    }
+}
+
+fn column_type(values: &[JsonValue]) -> DataType {
+    let mut ty: Option<DataType> = None;
+    for v in values {
+        let vt = match v {
+            JsonValue::Null => continue,
+            JsonValue::Bool(_) => DataType::Boolean,
+            JsonValue::Int(_) => DataType::Int64,
+            JsonValue::Float(_) => DataType::Float64,
+            JsonValue::Str(_) | JsonValue::Raw(_) => return DataType::Utf8,
+        };
+        ty = Some(match (ty, vt) {
+            (None, vt) => vt,
+            (Some(a), b) if a == b => a,
+            (Some(DataType::Int64), DataType::Float64) | (Some(DataType::Float64), DataType::Int64) => DataType::Float64,
+            _ => return DataType::Utf8,
+        });
+    }
+    ty.unwrap_or(DataType::Utf8)
+}
+
+fn to_scalar(v: &JsonValue, dt: &DataType) -> ScalarValue {
+    match (v, dt) {
+        (JsonValue::Null, _) => ScalarValue::Null,
+        (JsonValue::Int(i), DataType::Float64) => ScalarValue::Float64(*i as f64),
+        (JsonValue::Int(i), DataType::Int64) => ScalarValue::Int64(*i),
+        (JsonValue::Float(f), DataType::Float64) => ScalarValue::Float64(*f),
+        (JsonValue::Bool(b), DataType::Boolean) => ScalarValue::Boolean(*b),
+        (JsonValue::Bool(b), _) => ScalarValue::Utf8(b.to_string()),
+        (JsonValue::Int(i), _) => ScalarValue::Utf8(i.to_string()),
+        (JsonValue::Float(f), _) => ScalarValue::Utf8(f.to_string()),
+        (JsonValue::Str(s), _) | (JsonValue::Raw(s), _) => ScalarValue::Utf8(s.clone()),
+    }
+}
+
+type Record = Vec<(String, JsonValue)>;
+
+fn parse_records(data: &str) -> Result<Vec<Record>> {
+    let mut p = JsonParser { src: data, pos: 0 };
+    p.skip_ws();
+    let mut records = Vec::new();
+    if p.peek() == Some(b'[') {
+        p.pos += 1;
+        p.skip_ws();
+        if p.peek() == Some(b']') {
+            p.pos += 1;
+        } else {
+            loop {
+                records.push(p.parse_object()?);
+                p.skip_ws();
+                match p.next() {
+                    Some(b',') => p.skip_ws(),
+                    Some(b']') => break,
+                    _ => return Err(p.error("expected ',' or ']'")),
+                }
+            }
+        }
+    } else {
+        // Newline-delimited: one object after another.
+        while p.peek().is_some() {
+            records.push(p.parse_object()?);
+            p.skip_ws();
+        }
+    }
+    p.skip_ws();
+    if p.peek().is_some() {
+        return Err(p.error("unexpected trailing data"));
+    }
+    Ok(records)
+}
+
+struct JsonParser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn skip_ws(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, msg: &str) -> PivotError {
+        PivotError::IoError(format!("Invalid JSON at byte {}: {}", self.pos, msg))
+    }
+
+    fn expect(&mut self, b: u8) -> Result<()> {
+        self.skip_ws();
+        if self.next() == Some(b) { Ok(()) } else { Err(self.error(&format!("expected '{}'", b as char))) }
+    }
+
+    fn parse_object(&mut self) -> Result<Record> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(fields);
+        }
+        loop {
+            self.skip_ws();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected object key"));
+            }
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            let value = self.parse_value()?;
+            fields.push((key, value));
+            self.skip_ws();
+            match self.next() {
+                Some(b',') => continue,
+                Some(b'}') => return Ok(fields),
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue> {
+        self.skip_ws();
+        match self.peek() {
+            Some(b'"') => Ok(JsonValue::Str(self.parse_string()?)),
+            Some(b'{') | Some(b'[') => {
+                let start = self.pos;
+                self.skip_nested()?;
+                Ok(JsonValue::Raw(self.src[start..self.pos].to_string()))
+            }
+            Some(b't') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some(b'n') => self.parse_literal("null", JsonValue::Null),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn parse_literal(&mut self, word: &str, value: JsonValue) -> Result<JsonValue> {
+        if self.src[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error(&format!("expected '{}'", word)))
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.pos += 1;
+        }
+        let text = &self.src[start..self.pos];
+        if let Ok(i) = text.parse::<i64>() {
+            return Ok(JsonValue::Int(i));
+        }
+        text.parse::<f64>().map(JsonValue::Float).map_err(|_| self.error("invalid number"))
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        self.pos += 1; // opening quote
+        let mut out = String::new();
+        loop {
+            let rest = &self.src[self.pos..];
+            let c = rest.chars().next().ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let esc = self.next().ok_or_else(|| self.error("unterminated escape"))?;
+                    match esc {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => {
+                            let hex = self.src.get(self.pos..self.pos + 4)
+                                .ok_or_else(|| self.error("short \\u escape"))?;
+                            let code = u32::from_str_radix(hex, 16).map_err(|_| self.error("bad \\u escape"))?;
+                            self.pos += 4;
+                            out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        _ => return Err(self.error("unknown escape")),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Step over a nested array or object, honouring strings.
+    fn skip_nested(&mut self) -> Result<()> {
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unterminated array or object")),
+                Some(b'"') => { self.parse_string()?; }
+                Some(b'{' | b'[') => { depth += 1; self.pos += 1; }
+                Some(b'}' | b']') => {
+                    depth -= 1;
+                    self.pos += 1;
+                    if depth == 0 { return Ok(()); }
+                }
+                Some(_) => self.pos += 1,
+            }
+        }
+    }
+}
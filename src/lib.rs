@@ -9,5 +9,6 @@ pub mod pivot;
 pub mod filter;
 pub mod sort;
 pub mod csv;
+pub mod json;
 pub mod ffi;
 pub mod sql;
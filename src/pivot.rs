@@ -29,8 +29,8 @@ pub fn pivot_table(
     let mut key_order: Vec<Vec<String>> = Vec::new();
     for row in 0..store.row_count() {
         let key: Vec<String> = row_keys.iter()
-            .map(|k| format!("{}", store.get_value(row, k).unwrap_or(ScalarValue::Null)))
-            .collect();
+            .map(|k| Ok(format!("{}", store.get_value(row, k)?)))
+            .collect::<Result<_>>()?;
         let pv = format!("{}", store.get_value(row, col_key)?);
         let val = store.get_value(row, value_col)?;
         let entry = groups.entry(key.clone()).or_insert_with(|| {
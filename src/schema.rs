@@ -1,3 +1,4 @@
+use crate::column::ScalarValue;
 use crate::error::{PivotError, Result};
 
 #[derive(Debug, Clone, PartialEq)]
@@ -34,11 +35,39 @@ pub struct ColumnDef {
     pub name: String,
     pub data_type: DataType,
     pub nullable: bool,
+    /// Value used when an INSERT leaves the column out.
+    pub default: Option<ScalarValue>,
+    /// SERIAL / AUTOINCREMENT / GENERATED AS IDENTITY: omitted or NULL values
+    /// are filled from a per-table counter.
+    pub identity: bool,
+    /// GENERATED ALWAYS AS IDENTITY: INSERT may not supply a value.
+    pub generated_always: bool,
+    /// PRIMARY KEY: values are unique and indexed for `DataStore::get_row_by_key`.
+    pub primary_key: bool,
+    /// UNIQUE: no two rows share a non-NULL value.
+    pub unique: bool,
 }
 
 impl ColumnDef {
     pub fn new(name: &str, data_type: DataType, nullable: bool) -> Self {
-        Self { name: name.to_string(), data_type, nullable }
+        Self { name: name.to_string(), data_type, nullable, default: None, identity: false, generated_always: false, primary_key: false, unique: false }
+    }
+
+    pub fn with_default(mut self, value: ScalarValue) -> Self {
+        self.default = Some(value);
+        self
+    }
+
+    /// Mark as the primary key, which also makes the column NOT NULL.
+    pub fn with_primary_key(mut self) -> Self {
+        self.primary_key = true;
+        self.nullable = false;
+        self
+    }
+
+    pub fn with_unique(mut self) -> Self {
+        self.unique = true;
+        self
     }
 }
 
@@ -57,9 +86,68 @@ impl Schema {
         self.columns.iter().find(|c| c.name.eq_ignore_ascii_case(name))
     }
     pub fn has_column(&self, name: &str) -> bool { self.find_column_index(name).is_some() }
+    /// Index of the primary key column, if one is declared.
+    pub fn primary_key_index(&self) -> Option<usize> {
+        self.columns.iter().position(|c| c.primary_key)
+    }
     pub fn column_names(&self) -> Vec<String> {
         self.columns.iter().map(|c| c.name.clone()).collect()
     }
+    /// Column-level changes that turn `self` into `other`. Columns are matched
+    /// by name (case-insensitively); changes are reported in column order,
+    /// removals first.
+    pub fn diff(&self, other: &Schema) -> Vec<SchemaChange> {
+        let mut changes = Vec::new();
+        for col in &self.columns {
+            if !other.has_column(&col.name) {
+                changes.push(SchemaChange::ColumnRemoved { name: col.name.clone() });
+            }
+        }
+        for col in &other.columns {
+            match self.find_column(&col.name) {
+                None => changes.push(SchemaChange::ColumnAdded {
+                    name: col.name.clone(), data_type: col.data_type.clone(), nullable: col.nullable,
+                }),
+                Some(old) => {
+                    if old.data_type != col.data_type {
+                        changes.push(SchemaChange::TypeChanged {
+                            name: col.name.clone(), from: old.data_type.clone(), to: col.data_type.clone(),
+                        });
+                    }
+                    if old.nullable != col.nullable {
+                        changes.push(SchemaChange::NullabilityChanged {
+                            name: col.name.clone(), nullable: col.nullable,
+                        });
+                    }
+                }
+            }
+        }
+        changes
+    }
+
+    /// Check that rows shaped like `source` can be inserted positionally into
+    /// these columns: no more source columns than targets, and each source
+    /// type convertible to its target. `Utf8` sources are accepted for any
+    /// target since strings are parsed on insert. These are the conversions
+    /// applied to each value on insert.
+    pub fn is_compatible_for_insert(&self, source: &Schema) -> Result<()> {
+        if source.column_count() > self.column_count() {
+            return Err(PivotError::SchemaError(format!(
+                "INSERT has {} source columns but only {} target columns",
+                source.column_count(), self.column_count()
+            )));
+        }
+        for (src, dst) in source.columns.iter().zip(&self.columns) {
+            if !insert_convertible(&src.data_type, &dst.data_type) {
+                return Err(PivotError::TypeError(format!(
+                    "Cannot insert {} value '{}' into {} column '{}'",
+                    src.data_type, src.name, dst.data_type, dst.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
     pub fn validate_row_count(&self, count: usize) -> Result<()> {
         if count != self.columns.len() {
             Err(PivotError::SchemaError(format!(
@@ -70,3 +158,21 @@ impl Schema {
         }
     }
 }
+
+/// One difference reported by `Schema::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    ColumnAdded { name: String, data_type: DataType, nullable: bool },
+    ColumnRemoved { name: String },
+    TypeChanged { name: String, from: DataType, to: DataType },
+    NullabilityChanged { name: String, nullable: bool },
+}
+
+fn insert_convertible(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+    let numeric = |t: &DataType| matches!(t, Int64 | Float64 | Decimal { .. });
+    from == to
+        || matches!(from, Utf8)
+        || (numeric(from) && numeric(to))
+        || (matches!(from, Int64) && matches!(to, Date | Timestamp | Time))
+}
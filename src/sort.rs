@@ -42,6 +42,7 @@ pub fn compare_scalar(a: &ScalarValue, b: &ScalarValue) -> std::cmp::Ordering {
         (ScalarValue::Date(x), ScalarValue::Date(y)) => x.cmp(y),
         (ScalarValue::Timestamp(x), ScalarValue::Timestamp(y)) => x.cmp(y),
         (ScalarValue::Time(x), ScalarValue::Time(y)) => x.cmp(y),
+        (ScalarValue::Interval(x), ScalarValue::Interval(y)) => x.cmp_length(y),
         _ => Ordering::Equal,
     }
 }
@@ -1,5 +1,6 @@
 use crate::schema::DataType;
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum Statement {
     Select(SelectStatement),
@@ -8,16 +9,25 @@ pub enum Statement {
     Delete(DeleteStatement),
     CreateTable(CreateTableStatement),
     DropTable(DropTableStatement),
+    CreateView(CreateViewStatement),
+    DropView(DropViewStatement),
     With(WithStatement),
     Begin,
     Commit,
     Rollback,
     Explain(Box<Statement>),
+    Vacuum(Option<TableName>),
+    Analyze(Option<TableName>),
+    AlterTable(AlterTableStatement),
     SetOp(SetOpStatement),
+    /// Standalone `VALUES (...), (...)` query.
+    Values(Vec<Vec<Expr>>),
 }
 
 #[derive(Debug, Clone)]
 pub struct WithStatement {
+    /// `WITH RECURSIVE`: CTEs may read their own rows.
+    pub recursive: bool,
     pub ctes: Vec<Cte>,
     pub body: Box<Statement>,
 }
@@ -25,6 +35,8 @@ pub struct WithStatement {
 #[derive(Debug, Clone)]
 pub struct Cte {
     pub name: String,
+    /// Column names from `name (a, b) AS ...`; empty keeps the query's own.
+    pub columns: Vec<String>,
     pub query: Box<Statement>,
 }
 
@@ -36,12 +48,21 @@ pub struct SelectStatement {
     pub joins: Vec<Join>,
     pub where_clause: Option<Expr>,
     pub group_by: Vec<Expr>,
+    /// Grouping sets from ROLLUP, CUBE or GROUPING SETS, as indices into
+    /// `group_by`. `None` for a plain GROUP BY, which is the single set of
+    /// every key.
+    pub grouping_sets: Option<GroupingSets>,
     pub having: Option<Expr>,
+    /// Named windows from the `WINDOW w AS (...)` clause, in order.
+    pub windows: Vec<(String, WindowSpec)>,
     pub order_by: Vec<OrderByItem>,
     pub limit: Option<Expr>,
     pub offset: Option<Expr>,
 }
 
+/// Each grouping set lists the `group_by` keys it groups on.
+pub type GroupingSets = Vec<Vec<usize>>;
+
 #[derive(Debug, Clone)]
 pub enum SelectItem {
     Wildcard,
@@ -51,8 +72,40 @@ pub enum SelectItem {
 
 #[derive(Debug, Clone)]
 pub enum TableRef {
-    Table { name: String, alias: Option<String> },
+    Table { name: TableName, alias: Option<String> },
     Subquery { query: Box<Statement>, alias: String },
+    /// A table-valued function such as `read_csv('...')`.
+    Function { name: String, args: Vec<Expr>, alias: Option<String> },
+    /// `source PIVOT (agg FOR column IN (...))`.
+    Pivot(Box<PivotTable>),
+    /// `source UNPIVOT (value FOR name IN (...))`.
+    Unpivot(Box<UnpivotTable>),
+}
+
+/// Groups `source` on every column but `column` and those `aggregate`
+/// reads, with one aggregate column per listed value of `column`.
+#[derive(Debug, Clone)]
+pub struct PivotTable {
+    pub source: TableRef,
+    pub aggregate: Expr,
+    pub column: ColumnRef,
+    /// Each value with the name of its output column, if given.
+    pub values: Vec<(Expr, Option<String>)>,
+    pub alias: Option<String>,
+}
+
+/// Turns each listed column of `source` into a row holding the column's
+/// name in `name` and its value in `value`; the other columns repeat.
+#[derive(Debug, Clone)]
+pub struct UnpivotTable {
+    pub source: TableRef,
+    pub value: String,
+    pub name: String,
+    /// Each column with the text to put in `name`, if not its own name.
+    pub columns: Vec<(ColumnRef, Option<String>)>,
+    /// `INCLUDE NULLS`: keep rows whose value is NULL.
+    pub include_nulls: bool,
+    pub alias: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -75,6 +128,8 @@ pub enum JoinType {
 pub enum JoinCondition {
     On(Expr),
     Using(Vec<String>),
+    /// `NATURAL JOIN`: equality on every column name both inputs share.
+    Natural,
     None,
 }
 
@@ -87,6 +142,7 @@ pub struct OrderByItem {
 
 #[derive(Debug, Clone)]
 pub struct WindowSpec {
+    /// Named window this spec refers to or extends (`OVER w`, `OVER (w ...)`).
     pub name: Option<String>,
     pub partition_by: Vec<Expr>,
     pub order_by: Vec<OrderByItem>,
@@ -100,8 +156,10 @@ pub struct WindowFrame {
     pub end: Option<WindowFrameBound>,
 }
 
+/// ROWS counts rows, RANGE measures ORDER BY values and GROUPS counts peer
+/// groups (runs of rows with equal ORDER BY keys).
 #[derive(Debug, Clone)]
-pub enum WindowFrameKind { Rows, Range }
+pub enum WindowFrameKind { Rows, Range, Groups }
 
 #[derive(Debug, Clone)]
 pub enum WindowFrameBound {
@@ -114,9 +172,11 @@ pub enum WindowFrameBound {
 
 #[derive(Debug, Clone)]
 pub struct InsertStatement {
-    pub table: String,
+    pub table: TableName,
     pub columns: Option<Vec<String>>,
     pub values: InsertValues,
+    /// `RETURNING ...`: projected over each inserted row, defaults included.
+    pub returning: Option<Vec<SelectItem>>,
 }
 
 #[derive(Debug, Clone)]
@@ -127,10 +187,12 @@ pub enum InsertValues {
 
 #[derive(Debug, Clone)]
 pub struct UpdateStatement {
-    pub table: String,
+    pub table: TableName,
     pub alias: Option<String>,
     pub assignments: Vec<Assignment>,
     pub where_clause: Option<Expr>,
+    /// `RETURNING ...`: projected over each row's post-update values.
+    pub returning: Option<Vec<SelectItem>>,
 }
 
 #[derive(Debug, Clone)]
@@ -141,13 +203,15 @@ pub struct Assignment {
 
 #[derive(Debug, Clone)]
 pub struct DeleteStatement {
-    pub table: String,
+    pub table: TableName,
     pub where_clause: Option<Expr>,
+    /// `RETURNING ...`: projected over each deleted row as it was.
+    pub returning: Option<Vec<SelectItem>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct CreateTableStatement {
-    pub name: String,
+    pub name: TableName,
     pub if_not_exists: bool,
     pub columns: Vec<ColumnDefAst>,
 }
@@ -159,11 +223,44 @@ pub struct ColumnDefAst {
     pub nullable: bool,
     pub default: Option<Expr>,
     pub primary_key: bool,
+    pub unique: bool,
+    pub identity: bool,
+    pub generated_always: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlterTableStatement {
+    pub table: TableName,
+    pub operation: AlterTableOperation,
+}
+
+#[derive(Debug, Clone)]
+pub enum AlterTableOperation {
+    /// `ALTER COLUMN c [SET DATA] TYPE t`: existing values are cast to `t`.
+    AlterColumnType { column: String, data_type: DataType },
+    /// `ADD [COLUMN] def`: existing rows get the default, or NULL.
+    AddColumn(ColumnDefAst),
+    DropColumn { column: String, if_exists: bool },
+    RenameColumn { from: String, to: String },
 }
 
 #[derive(Debug, Clone)]
 pub struct DropTableStatement {
-    pub name: String,
+    pub name: TableName,
+    pub if_exists: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateViewStatement {
+    pub name: TableName,
+    pub or_replace: bool,
+    pub if_not_exists: bool,
+    pub query: Box<Statement>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DropViewStatement {
+    pub name: TableName,
     pub if_exists: bool,
 }
 
@@ -184,7 +281,17 @@ pub enum Expr {
     Column(ColumnRef),
     BinaryOp { left: Box<Expr>, op: BinOp, right: Box<Expr> },
     UnaryOp { op: UnaryOp, expr: Box<Expr> },
-    Function { name: String, args: Vec<Expr>, distinct: bool, over: Option<WindowSpec> },
+    Function {
+        name: String,
+        args: Vec<Expr>,
+        distinct: bool,
+        /// Ordering from `WITHIN GROUP (ORDER BY ...)` for ordered-set functions,
+        /// or from an `ORDER BY` inside the call, as in `STRING_AGG(x, ',' ORDER BY y)`.
+        within_group: Vec<OrderByItem>,
+        /// `FILTER (WHERE ...)`: only rows matching it feed the aggregate.
+        filter: Option<Box<Expr>>,
+        over: Option<WindowSpec>,
+    },
     Cast { expr: Box<Expr>, data_type: DataType },
     TryCast { expr: Box<Expr>, data_type: DataType },
     Case {
@@ -195,18 +302,55 @@ pub enum Expr {
     IsNull { expr: Box<Expr>, negated: bool },
     InList { expr: Box<Expr>, list: Vec<Expr>, negated: bool },
     InSubquery { expr: Box<Expr>, query: Box<Statement>, negated: bool },
-    Between { expr: Box<Expr>, low: Box<Expr>, high: Box<Expr>, negated: bool },
-    Like { expr: Box<Expr>, pattern: Box<Expr>, negated: bool, case_insensitive: bool },
+    /// `symmetric` (BETWEEN SYMMETRIC) accepts the bounds in either order.
+    Between { expr: Box<Expr>, low: Box<Expr>, high: Box<Expr>, negated: bool, symmetric: bool },
+    /// `escape` is the optional `ESCAPE` character making the next pattern
+    /// character literal.
+    Like { expr: Box<Expr>, pattern: Box<Expr>, negated: bool, case_insensitive: bool, escape: Option<Box<Expr>> },
+    SimilarTo { expr: Box<Expr>, pattern: Box<Expr>, negated: bool },
+    /// `expr COLLATE name`; the collation name is upper-cased.
+    Collate { expr: Box<Expr>, collation: String },
     Subquery(Box<Statement>),
+    /// `ROW(a, b)` or `(a, b)`: a composite value compared element-wise.
+    Row(Vec<Expr>),
     Exists { query: Box<Statement>, negated: bool },
     Wildcard,
+    /// `table.*` as a function argument, as in `COUNT(t.*)`.
+    QualifiedWildcard(String),
     TypeCast { expr: Box<Expr>, data_type: DataType },
 }
 
+/// A table or view name as written in the query.
+#[derive(Debug, Clone)]
+pub struct TableName {
+    pub name: String,
+    /// Written as a quoted identifier; see `Fold::matches_ref`.
+    pub quoted: bool,
+}
+
+/// Whether `name` (any case) is one of the built-in aggregate functions.
+pub(crate) fn is_aggregate_name(name: &str) -> bool {
+    matches!(name.to_uppercase().as_str(),
+        "COUNT" | "SUM" | "AVG" | "MIN" | "MAX"
+        | "STRING_AGG" | "GROUP_CONCAT" | "LISTAGG"
+        | "ARRAY_AGG" | "STDDEV" | "STDEV" | "STDDEV_SAMP" | "STDDEV_POP"
+        | "VARIANCE" | "VAR_SAMP" | "VAR_POP" | "ANY_VALUE" | "GROUPING"
+        | "PERCENTILE_CONT" | "PERCENTILE_DISC" | "MEDIAN"
+    )
+}
+
+impl std::fmt::Display for TableName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ColumnRef {
     pub table: Option<String>,
     pub name: String,
+    /// Written as a quoted identifier; see `Fold::matches_ref`.
+    pub quoted: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -222,6 +366,8 @@ pub enum LiteralValue {
 #[derive(Debug, Clone, PartialEq)]
 pub enum BinOp {
     Add, Sub, Mul, Div, Mod,
+    /// `a DIV b`: division truncated toward zero, always INTEGER.
+    IntDiv,
     Eq, NotEq, Lt, LtEq, Gt, GtEq,
     And, Or,
     Concat,
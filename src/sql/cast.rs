@@ -1,4 +1,5 @@
-use crate::column::ScalarValue;
+use crate::column::{ScalarValue, interval_string_to_value, parse_interval};
+use crate::error::{PivotError, Result};
 use crate::schema::DataType;
 
 /// Cast a ScalarValue to a target DataType, returning Null on failure.
@@ -11,16 +12,53 @@ pub fn try_cast_value(v: ScalarValue, target: &DataType) -> ScalarValue {
     if matches!(v, ScalarValue::Null) { return ScalarValue::Null; }
     match target {
         DataType::Int64 => to_int64(v),
-        DataType::Float64 | DataType::Decimal { .. } => to_float64(v),
+        DataType::Float64 => to_float64(v),
+        DataType::Decimal { precision, scale } => to_decimal(v, *precision, *scale),
         DataType::Utf8 => to_utf8(v),
         DataType::Boolean => to_boolean(v),
         DataType::Date => to_date(v),
         DataType::Timestamp => to_timestamp(v),
         DataType::Time => to_time(v),
-        DataType::Interval => ScalarValue::Null,
+        DataType::Interval => to_interval(v),
     }
 }
 
+/// Cast like `cast_value`, but a value that does not fit a DECIMAL's
+/// precision is an error rather than NULL.
+pub fn cast_value_strict(v: ScalarValue, target: &DataType) -> Result<ScalarValue> {
+    if let DataType::Decimal { precision, scale } = target {
+        return match round_to_scale(to_float64(v), *scale) {
+            ScalarValue::Float64(f) if !fits_precision(f, *precision, *scale) => {
+                Err(PivotError::TypeError(format!("Value {} exceeds precision of {}", f, target)))
+            }
+            other => Ok(other),
+        };
+    }
+    Ok(cast_value(v, target))
+}
+
+fn to_decimal(v: ScalarValue, precision: u8, scale: u8) -> ScalarValue {
+    match round_to_scale(to_float64(v), scale) {
+        ScalarValue::Float64(f) if !fits_precision(f, precision, scale) => ScalarValue::Null,
+        other => other,
+    }
+}
+
+/// Round half away from zero to `scale` fractional digits.
+fn round_to_scale(v: ScalarValue, scale: u8) -> ScalarValue {
+    match v {
+        ScalarValue::Float64(f) => {
+            let factor = 10f64.powi(scale as i32);
+            ScalarValue::Float64((f * factor).round() / factor)
+        }
+        other => other,
+    }
+}
+
+fn fits_precision(f: f64, precision: u8, scale: u8) -> bool {
+    f.abs() < 10f64.powi(precision.saturating_sub(scale) as i32)
+}
+
 fn to_int64(v: ScalarValue) -> ScalarValue {
     match v {
         ScalarValue::Int64(i) => ScalarValue::Int64(i),
@@ -54,6 +92,17 @@ fn to_utf8(v: ScalarValue) -> ScalarValue {
     ScalarValue::Utf8(format!("{}", v))
 }
 
+fn to_interval(v: ScalarValue) -> ScalarValue {
+    match v {
+        ScalarValue::Interval(iv) => ScalarValue::Interval(iv),
+        ScalarValue::Utf8(s) => parse_interval(&s)
+            .or_else(|| interval_string_to_value(&s, ""))
+            .map(ScalarValue::Interval)
+            .unwrap_or(ScalarValue::Null),
+        _ => ScalarValue::Null,
+    }
+}
+
 fn to_boolean(v: ScalarValue) -> ScalarValue {
     match v {
         ScalarValue::Boolean(b) => ScalarValue::Boolean(b),
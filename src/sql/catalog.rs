@@ -1,48 +1,310 @@
+use crate::column::{HashableScalar, ScalarValue};
 use crate::datastore::DataStore;
-use crate::schema::{ColumnDef, DataType, Schema};
-use std::collections::HashMap;
+use crate::error::{PivotError, Result};
+use crate::schema::Schema;
+use crate::sql::ast::{Statement, TableName};
+use crate::sort::compare_scalar;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 
+/// How unquoted SQL identifiers are normalized when they are compared.
+/// Names are stored as written; quoted identifiers (`"Name"` or `` `Name` ``)
+/// are taken verbatim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fold {
+    /// Fold to upper case (the default, matching the SQL standard).
+    #[default]
+    Upper,
+    /// Fold to lower case, as PostgreSQL does.
+    Lower,
+    /// Keep identifiers exactly as written; lookups are case-sensitive.
+    Preserve,
+}
+
+impl Fold {
+    pub fn apply(&self, s: &str) -> String {
+        match self {
+            Fold::Upper => s.to_uppercase(),
+            Fold::Lower => s.to_lowercase(),
+            Fold::Preserve => s.to_string(),
+        }
+    }
+
+    /// Whether two unquoted identifiers name the same object under this policy.
+    pub fn matches(&self, a: &str, b: &str) -> bool {
+        match self {
+            Fold::Upper | Fold::Lower => self.apply(a) == self.apply(b),
+            Fold::Preserve => a == b,
+        }
+    }
+
+    /// Whether a reference to `name` names the object stored as `stored`. A
+    /// quoted reference must spell the stored name exactly, as written or
+    /// folded; an unquoted one need only match under the policy.
+    pub fn matches_ref(&self, stored: &str, name: &str, quoted: bool) -> bool {
+        if quoted { name == stored || name == self.apply(stored) } else { self.matches(stored, name) }
+    }
+}
+
+/// Statistics gathered by `ANALYZE`. They describe the table as it was when
+/// collected and are not updated by later writes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStats {
+    pub row_count: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub name: String,
+    pub null_count: usize,
+    /// Number of distinct non-NULL values.
+    pub distinct_count: usize,
+    /// Smallest and largest non-NULL values; NULL for an all-NULL column.
+    pub min: ScalarValue,
+    pub max: ScalarValue,
+}
+
+impl TableStats {
+    pub fn collect(store: &DataStore) -> Result<Self> {
+        let schema = store.schema();
+        let mut columns = Vec::with_capacity(schema.column_count());
+        for (ci, def) in schema.columns.iter().enumerate() {
+            let mut stats = ColumnStats {
+                name: def.name.clone(),
+                null_count: 0,
+                distinct_count: 0,
+                min: ScalarValue::Null,
+                max: ScalarValue::Null,
+            };
+            let mut seen: HashSet<HashableScalar> = HashSet::new();
+            for row in 0..store.row_count() {
+                let v = store.get_value_by_index(row, ci)?;
+                if matches!(v, ScalarValue::Null) {
+                    stats.null_count += 1;
+                    continue;
+                }
+                if matches!(stats.min, ScalarValue::Null) || compare_scalar(&v, &stats.min) == Ordering::Less {
+                    stats.min = v.clone();
+                }
+                if matches!(stats.max, ScalarValue::Null) || compare_scalar(&v, &stats.max) == Ordering::Greater {
+                    stats.max = v.clone();
+                }
+                seen.insert(HashableScalar::new(v));
+            }
+            stats.distinct_count = seen.len();
+            columns.push(stats);
+        }
+        Ok(Self { row_count: store.row_count(), columns })
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnStats> {
+        self.columns.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Clone)]
 pub struct Catalog {
     tables: HashMap<String, DataStore>,
+    identifier_case: Fold,
+    /// Last value issued per (table, column index) for identity columns.
+    identities: HashMap<(String, usize), i64>,
+    /// Statistics from the last `ANALYZE` of each table.
+    stats: HashMap<String, TableStats>,
+    /// View definitions, run afresh each time a query reads them.
+    views: HashMap<String, Statement>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Catalog {
     pub fn new() -> Self {
-        Self { tables: HashMap::new() }
+        Self {
+            tables: HashMap::new(),
+            identifier_case: Fold::default(),
+            identities: HashMap::new(),
+            stats: HashMap::new(),
+            views: HashMap::new(),
+        }
     }
 
-    pub fn create_table(&mut self, name: &str, schema: Schema) -> bool {
-        let key = name.to_uppercase();
-        if self.tables.contains_key(&key) {
-            return false;
+    pub fn identifier_case(&self) -> Fold {
+        self.identifier_case
+    }
+
+    pub fn set_identifier_case(&mut self, fold: Fold) {
+        self.identifier_case = fold;
+    }
+
+    /// The name under which `map` stores what a reference to `name` names:
+    /// its exact spelling if stored, else the first stored name it matches.
+    fn resolve<V>(&self, map: &HashMap<String, V>, name: &str, quoted: bool) -> Option<String> {
+        if map.contains_key(name) {
+            return Some(name.to_string());
         }
-        self.tables.insert(key, DataStore::new(schema));
-        true
+        map.keys().filter(|k| self.identifier_case.matches_ref(k, name, quoted)).min().cloned()
+    }
+
+    fn key(&self, name: &str) -> Option<String> {
+        self.resolve(&self.tables, name, false)
+    }
+
+    /// Stored name of the table `name` refers to.
+    pub(crate) fn table_key(&self, name: &TableName) -> Option<String> {
+        self.resolve(&self.tables, &name.name, name.quoted)
+    }
+
+    /// Stored name of the view `name` refers to.
+    pub(crate) fn view_key(&self, name: &TableName) -> Option<String> {
+        self.resolve(&self.views, &name.name, name.quoted)
+    }
+
+    pub fn create_table(&mut self, name: &str, schema: Schema) -> bool {
+        self.key(name).is_none() && self.create_table_verbatim(name, schema)
     }
 
     pub fn create_table_if_not_exists(&mut self, name: &str, schema: Schema) -> bool {
-        let key = name.to_uppercase();
-        if !self.tables.contains_key(&key) {
-            self.tables.insert(key, DataStore::new(schema));
+        self.create_table(name, schema)
+    }
+
+    /// Register a table under exactly `name`, unless a table is already
+    /// stored under that spelling.
+    pub(crate) fn create_table_verbatim(&mut self, name: &str, schema: Schema) -> bool {
+        if let std::collections::hash_map::Entry::Vacant(e) = self.tables.entry(name.to_string()) {
+            e.insert(DataStore::new(schema));
             return true;
         }
         false
     }
 
     pub fn drop_table(&mut self, name: &str) -> bool {
-        self.tables.remove(&name.to_uppercase()).is_some()
+        self.key(name).is_some_and(|key| self.drop_table_verbatim(&key))
     }
 
     pub fn get_table(&self, name: &str) -> Option<&DataStore> {
-        self.tables.get(&name.to_uppercase())
+        self.tables.get(&self.key(name)?)
     }
 
     pub fn get_table_mut(&mut self, name: &str) -> Option<&mut DataStore> {
-        self.tables.get_mut(&name.to_uppercase())
+        let key = self.key(name)?;
+        self.tables.get_mut(&key)
     }
 
     pub fn table_exists(&self, name: &str) -> bool {
-        self.tables.contains_key(&name.to_uppercase())
+        self.key(name).is_some()
+    }
+
+    pub(crate) fn get_table_verbatim(&self, name: &str) -> Option<&DataStore> {
+        self.tables.get(name)
+    }
+
+    pub(crate) fn get_table_verbatim_mut(&mut self, name: &str) -> Option<&mut DataStore> {
+        self.tables.get_mut(name)
+    }
+
+    pub(crate) fn drop_table_verbatim(&mut self, name: &str) -> bool {
+        self.identities.retain(|(table, _), _| table != name);
+        self.stats.remove(name);
+        self.tables.remove(name).is_some()
+    }
+
+    /// Store `store` as table `name`, replacing any table registered under
+    /// it. Identity sequences continue from the largest value loaded.
+    pub(crate) fn insert_table_verbatim(&mut self, name: &str, store: DataStore) -> Result<()> {
+        self.drop_table_verbatim(name);
+        for (idx, def) in store.schema().columns.iter().enumerate() {
+            if !def.identity { continue; }
+            for row in 0..store.row_count() {
+                if let ScalarValue::Int64(v) = store.get_value_by_index(row, idx)? {
+                    self.observe_identity(name, idx, v);
+                }
+            }
+        }
+        self.tables.insert(name.to_string(), store);
+        Ok(())
+    }
+
+    pub(crate) fn get_view_verbatim(&self, name: &str) -> Option<&Statement> {
+        self.views.get(name)
+    }
+
+    /// Store the query of view `name`, replacing any earlier definition.
+    pub(crate) fn create_view_verbatim(&mut self, name: &str, query: Statement) {
+        self.views.insert(name.to_string(), query);
+    }
+
+    pub(crate) fn drop_view_verbatim(&mut self, name: &str) -> bool {
+        self.views.remove(name).is_some()
+    }
+
+    /// Drop a column of the table registered as `name`, keeping identity
+    /// sequences attached to the columns after it. The table's statistics
+    /// are discarded until its next `ANALYZE`.
+    pub(crate) fn drop_column_verbatim(&mut self, name: &str, column: &str) -> Result<()> {
+        let store = self.tables.get_mut(name)
+            .ok_or_else(|| PivotError::SqlError(format!("Table '{}' not found", name)))?;
+        let idx = store.schema().find_column_index(column)
+            .ok_or_else(|| PivotError::ColumnNotFound(column.to_string()))?;
+        if store.schema().column_count() == 1 {
+            return Err(PivotError::SchemaError(format!(
+                "Cannot drop '{}', the only column of table '{}'", column, name
+            )));
+        }
+        store.drop_column(column)?;
+        self.identities = std::mem::take(&mut self.identities).into_iter()
+            .filter(|((table, col), _)| !(table == name && *col == idx))
+            .map(|((table, col), v)| {
+                let col = if table == name && col > idx { col - 1 } else { col };
+                ((table, col), v)
+            })
+            .collect();
+        self.stats.remove(name);
+        Ok(())
+    }
+
+    pub(crate) fn rename_column_verbatim(&mut self, name: &str, from: &str, to: &str) -> Result<()> {
+        let store = self.tables.get_mut(name)
+            .ok_or_else(|| PivotError::SqlError(format!("Table '{}' not found", name)))?;
+        store.rename_column(from, to)?;
+        self.stats.remove(name);
+        Ok(())
+    }
+
+    /// Next value for an identity column of the table registered as `name`;
+    /// sequences start at 1.
+    pub(crate) fn next_identity(&mut self, name: &str, col_idx: usize) -> i64 {
+        let last = self.identities.entry((name.to_string(), col_idx)).or_insert(0);
+        *last += 1;
+        *last
+    }
+
+    /// Record an explicitly inserted identity value so later generated values
+    /// don't collide with it.
+    pub(crate) fn observe_identity(&mut self, name: &str, col_idx: usize, value: i64) {
+        let last = self.identities.entry((name.to_string(), col_idx)).or_insert(0);
+        *last = (*last).max(value);
+    }
+
+    /// Statistics from the last `ANALYZE` of the table, if any.
+    pub fn table_stats(&self, name: &str) -> Option<&TableStats> {
+        self.stats.get(&self.key(name)?)
+    }
+
+    /// Collect statistics for the table registered as `name`.
+    pub(crate) fn analyze_verbatim(&mut self, name: &str) -> Result<()> {
+        if let Some(store) = self.tables.get(name) {
+            let stats = TableStats::collect(store)?;
+            self.stats.insert(name.to_string(), stats);
+        }
+        Ok(())
+    }
+
+    pub fn tables_mut(&mut self) -> impl Iterator<Item = &mut DataStore> {
+        self.tables.values_mut()
     }
 
     pub fn table_names(&self) -> Vec<String> {
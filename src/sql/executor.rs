@@ -1,14 +1,25 @@
-use crate::column::ScalarValue;
+use crate::column::{
+    HashableScalar, IntervalValue, ScalarValue, add_interval_to_days, add_interval_to_micros,
+    interval_string_to_value,
+};
+use crate::csv::CsvReader;
+use crate::datastore::DataStore;
 use crate::error::{PivotError, Result};
+use crate::json::JsonReader;
 use crate::schema::{ColumnDef, DataType, Schema};
 use crate::sql::ast::*;
-use crate::sql::catalog::Catalog;
+use crate::sql::catalog::{Catalog, Fold};
 use crate::sql::cast;
 use crate::sql::functions_scalar;
 use crate::sql::functions_datetime;
 use crate::sql::lexer::Lexer;
-use crate::sql::parser::Parser;
-use std::collections::HashMap;
+use crate::sql::parser::{Parser, DEFAULT_MAX_DEPTH};
+use crate::sql::regex::Regex;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 // ─── Public types ─────────────────────────────────────────────────────────────
 
@@ -25,12 +36,157 @@ impl QueryResult {
         Self { columns: Vec::new(), rows: Vec::new(), affected_rows: 0, message: None }
     }
     pub fn row_count(&self) -> usize { self.rows.len() }
+    pub fn column_count(&self) -> usize { self.columns.len() }
     pub fn with_message(msg: String) -> Self {
         Self { columns: Vec::new(), rows: Vec::new(), affected_rows: 0, message: Some(msg) }
     }
     pub fn affected(n: usize) -> Self {
         Self { columns: Vec::new(), rows: Vec::new(), affected_rows: n, message: None }
     }
+    /// Position of the first column named `name` (case-insensitive).
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.eq_ignore_ascii_case(name))
+    }
+    /// The value at `row`, `col`, if both are in range.
+    pub fn get(&self, row: usize, col: usize) -> Option<&ScalarValue> {
+        self.rows.get(row)?.get(col)
+    }
+    /// The INTEGER at `row`, `col`; `None` for NULL or another type.
+    pub fn get_i64(&self, row: usize, col: usize) -> Option<i64> {
+        match self.get(row, col)? {
+            ScalarValue::Int64(i) => Some(*i),
+            _ => None,
+        }
+    }
+    /// The number at `row`, `col`, with INTEGERs widened to `f64`.
+    pub fn get_f64(&self, row: usize, col: usize) -> Option<f64> {
+        match self.get(row, col)? {
+            ScalarValue::Float64(f) => Some(*f),
+            ScalarValue::Int64(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+    /// The VARCHAR at `row`, `col`; `None` for NULL or another type.
+    pub fn get_str(&self, row: usize, col: usize) -> Option<&str> {
+        match self.get(row, col)? {
+            ScalarValue::Utf8(s) => Some(s),
+            _ => None,
+        }
+    }
+    /// The BOOLEAN at `row`, `col`; `None` for NULL or another type.
+    pub fn get_bool(&self, row: usize, col: usize) -> Option<bool> {
+        match self.get(row, col)? {
+            ScalarValue::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+    /// Borrow each row in order.
+    pub fn iter(&self) -> std::slice::Iter<'_, Vec<ScalarValue>> {
+        self.rows.iter()
+    }
+    /// Each row as a map from column name to value. When two columns share a
+    /// name the later one wins.
+    pub fn rows_as_maps(&self) -> impl Iterator<Item = HashMap<String, ScalarValue>> + '_ {
+        self.rows.iter().map(move |row| {
+            self.columns.iter().cloned().zip(row.iter().cloned()).collect()
+        })
+    }
+    /// Render as an ASCII table: numbers right-aligned, other values left.
+    /// A result without columns renders as its message or affected count.
+    pub fn to_table_string(&self) -> String {
+        self.to_table_string_truncated(usize::MAX)
+    }
+    /// Like `to_table_string`, showing at most `max_rows` rows and a
+    /// "... N more rows" footer for the rest.
+    pub fn to_table_string_truncated(&self, max_rows: usize) -> String {
+        if self.columns.is_empty() {
+            return self.message.clone()
+                .unwrap_or_else(|| format!("{} row{} affected", self.affected_rows, plural(self.affected_rows)));
+        }
+        let shown = &self.rows[..self.rows.len().min(max_rows)];
+        let cells: Vec<Vec<(String, bool)>> = shown.iter().map(|row| {
+            (0..self.columns.len()).map(|i| match row.get(i) {
+                None | Some(ScalarValue::Null) => ("NULL".to_string(), false),
+                Some(v @ (ScalarValue::Int64(_) | ScalarValue::Float64(_))) => (v.to_string(), true),
+                Some(v) => (v.to_string(), false),
+            }).collect()
+        }).collect();
+        let widths: Vec<usize> = self.columns.iter().enumerate().map(|(i, name)| {
+            cells.iter().map(|row| row[i].0.chars().count())
+                .fold(name.chars().count(), usize::max)
+        }).collect();
+
+        let separator = widths.iter().fold("+".to_string(), |line, w| line + &"-".repeat(w + 2) + "+");
+        let line = |values: Vec<(&str, bool)>| {
+            values.iter().zip(&widths).fold("|".to_string(), |line, ((text, right), &w)| {
+                let pad = " ".repeat(w - text.chars().count());
+                if *right { format!("{} {}{} |", line, pad, text) } else { format!("{} {}{} |", line, text, pad) }
+            })
+        };
+        let mut out = vec![
+            separator.clone(),
+            line(self.columns.iter().map(|c| (c.as_str(), false)).collect()),
+            separator.clone(),
+        ];
+        out.extend(cells.iter().map(|row| line(row.iter().map(|(t, r)| (t.as_str(), *r)).collect())));
+        out.push(separator);
+        let hidden = self.rows.len() - shown.len();
+        if hidden > 0 {
+            out.push(format!("... {} more row{}", hidden, plural(hidden)));
+        }
+        out.join("\n")
+    }
+}
+
+fn plural(n: usize) -> &'static str { if n == 1 { "" } else { "s" } }
+
+/// A user-defined scalar function, called with its evaluated arguments.
+pub type ScalarFunction = Box<dyn Fn(&[ScalarValue]) -> Result<ScalarValue> + Send + Sync>;
+
+impl IntoIterator for QueryResult {
+    type Item = Vec<ScalarValue>;
+    type IntoIter = std::vec::IntoIter<Vec<ScalarValue>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a QueryResult {
+    type Item = &'a Vec<ScalarValue>;
+    type IntoIter = std::slice::Iter<'a, Vec<ScalarValue>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.iter()
+    }
+}
+
+/// Work done by the executor while running a statement. Only collected
+/// with the `metrics` feature (on by default); otherwise every count is 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecMetrics {
+    /// Rows read from tables, table functions and CTEs.
+    pub rows_scanned: u64,
+    /// Rows copied into intermediate results (joins, filters, set operations).
+    pub rows_cloned: u64,
+    /// Join conditions evaluated.
+    pub join_comparisons: u64,
+    /// Rows produced by join steps, intermediate ones included; a gauge of
+    /// how well the join order worked.
+    pub join_rows: u64,
+    /// Comparisons made by ORDER BY sorts.
+    pub sort_comparisons: u64,
+}
+
+/// What to do when a result has two columns with the same name, as in
+/// `SELECT id, id FROM t` or `SELECT *` over a join.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateColumns {
+    /// Leave the names as they are (the default).
+    #[default]
+    Keep,
+    /// Rename later repeats `name:1`, `name:2`, ...
+    Suffix,
+    /// Fail the query.
+    Error,
 }
 
 // ─── Internal row-set type ────────────────────────────────────────────────────
@@ -39,6 +195,7 @@ impl QueryResult {
 struct Col {
     table: Option<String>,
     name: String,
+    #[allow(dead_code)]
     dtype: DataType,
 }
 
@@ -62,36 +219,6 @@ impl RowSet {
         rs
     }
 
-    fn col_names(&self) -> Vec<String> {
-        self.cols.iter().map(|c| c.display_name()).collect()
-    }
-
-    // Find column index, handling table qualification
-    fn find_col(&self, table: Option<&str>, name: &str) -> Option<usize> {
-        if let Some(t) = table {
-            // Qualified lookup
-            if let Some(idx) = self.cols.iter().position(|c|
-                c.table.as_deref().map(|s| s.eq_ignore_ascii_case(t)).unwrap_or(false)
-                && c.name.eq_ignore_ascii_case(name)
-            ) {
-                return Some(idx);
-            }
-            // Also try name = "table.col"
-            let qualified = format!("{}.{}", t, name);
-            if let Some(idx) = self.cols.iter().position(|c|
-                c.name.eq_ignore_ascii_case(&qualified)
-            ) {
-                return Some(idx);
-            }
-        }
-        // Unqualified: find unique match
-        let matches: Vec<usize> = self.cols.iter().enumerate()
-            .filter(|(_, c)| c.name.eq_ignore_ascii_case(name))
-            .map(|(i, _)| i)
-            .collect();
-        if matches.len() == 1 { Some(matches[0]) } else { matches.into_iter().next() }
-    }
-
     fn into_query_result(self) -> QueryResult {
         QueryResult {
             columns: self.cols.iter().map(|c| c.display_name()).collect(),
@@ -113,22 +240,294 @@ impl ExecCtx {
     fn new() -> Self { Self { ctes: HashMap::new() } }
 }
 
+/// A row of an enclosing query while one of its subqueries runs.
+struct OuterRow {
+    cols: Vec<Col>,
+    row: Vec<ScalarValue>,
+    /// Whether the subquery read one of the row's columns.
+    read: Cell<bool>,
+}
+
+/// Engine state visible while evaluating expressions.
+struct EvalCtx<'a> {
+    engine: &'a SqlEngine,
+    /// The CTEs of the statement being evaluated, for its subqueries.
+    exec: &'a ExecCtx,
+}
+
 // ─── SQL Engine ───────────────────────────────────────────────────────────────
 
 pub struct SqlEngine {
     pub catalog: Catalog,
+    /// Break window ORDER BY ties on the input row position so peer rows get
+    /// reproducible numbering (default: true).
+    stable_window_order: bool,
+    /// Raise errors for lossy conversions (e.g. DECIMAL precision overflow)
+    /// instead of producing NULL (default: false).
+    strict: bool,
+    /// Numeric aggregates over no values return 0 and string aggregates ''
+    /// instead of NULL (default: false).
+    empty_aggregate_as_zero: bool,
+    /// Named text sources that `read_csv` / `read_json` can read by name.
+    sources: HashMap<String, String>,
+    /// Handling of repeated output column names (default: keep).
+    duplicate_columns: DuplicateColumns,
+    /// Limit on statement/expression nesting, enforced by the parser and on
+    /// nested query execution (default: `parser::DEFAULT_MAX_DEPTH`).
+    max_depth: usize,
+    /// Queries currently executing inside one another.
+    query_depth: Cell<usize>,
+    /// INTEGER / INTEGER truncates to an INTEGER rather than giving a DOUBLE
+    /// (default: true).
+    integer_division: bool,
+    /// Rounds a recursive CTE may take before it is reported as
+    /// non-terminating (default: 1000).
+    max_recursion: usize,
+    /// Join chains of inner joins smallest input first (default: true).
+    reorder_joins: bool,
+    metrics: Cell<ExecMetrics>,
+    /// Rows of the enclosing queries while a subquery runs, innermost last;
+    /// columns a subquery can't resolve itself are looked up here.
+    outer_rows: RefCell<Vec<OuterRow>>,
+    /// Results of uncorrelated subqueries, one map per running SELECT, keyed
+    /// by the address of the subquery's statement.
+    subquery_results: RefCell<Vec<HashMap<usize, Arc<RowSet>>>>,
+    /// The catalog as of BEGIN while a transaction is open; ROLLBACK
+    /// restores it.
+    transaction: Option<Catalog>,
+    /// Fixed clock for CURRENT_DATE / NOW, in UTC microseconds since the
+    /// epoch (default: the system clock).
+    now: Option<i64>,
+    /// The clock as read when `execute` began, so every CURRENT_DATE / NOW
+    /// in its statements sees the same instant.
+    statement_time: i64,
+    /// Functions from `register_function`, by upper-cased name.
+    functions: HashMap<String, ScalarFunction>,
+    /// Calls to unknown functions are errors rather than NULL (default: false).
+    strict_functions: bool,
+}
+
+impl Default for SqlEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SqlEngine {
     pub fn new() -> Self {
-        Self { catalog: Catalog::new() }
+        Self {
+            catalog: Catalog::new(),
+            stable_window_order: true,
+            strict: false,
+            empty_aggregate_as_zero: false,
+            sources: HashMap::new(),
+            duplicate_columns: DuplicateColumns::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            query_depth: Cell::new(0),
+            max_recursion: 1000,
+            integer_division: true,
+            reorder_joins: true,
+            metrics: Cell::new(ExecMetrics::default()),
+            outer_rows: RefCell::new(Vec::new()),
+            subquery_results: RefCell::new(Vec::new()),
+            transaction: None,
+            now: None,
+            statement_time: 0,
+            functions: HashMap::new(),
+            strict_functions: false,
+        }
+    }
+
+    pub fn set_identifier_case(&mut self, fold: Fold) {
+        self.catalog.set_identifier_case(fold);
+    }
+
+    /// Stored name of the table `name` refers to.
+    fn table_key(&self, name: &TableName) -> Result<String> {
+        self.catalog.table_key(name)
+            .ok_or_else(|| PivotError::SqlError(format!("Table '{}' not found", name)))
+    }
+
+    /// How unquoted identifiers are compared; the catalog holds the policy.
+    fn fold(&self) -> Fold {
+        self.catalog.identifier_case()
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn set_strict_functions(&mut self, strict: bool) {
+        self.strict_functions = strict;
+    }
+
+    pub fn set_empty_aggregate_as_zero(&mut self, enabled: bool) {
+        self.empty_aggregate_as_zero = enabled;
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn set_integer_division(&mut self, enabled: bool) {
+        self.integer_division = enabled;
+    }
+
+    pub fn set_max_recursion(&mut self, rounds: usize) {
+        self.max_recursion = rounds;
+    }
+
+    pub fn set_duplicate_columns(&mut self, mode: DuplicateColumns) {
+        self.duplicate_columns = mode;
+    }
+
+    pub fn set_join_reordering(&mut self, enabled: bool) {
+        self.reorder_joins = enabled;
+    }
+
+    /// Whether a BEGIN is awaiting its COMMIT or ROLLBACK.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    /// Executor counters for the last statement (see `ExecMetrics`).
+    pub fn last_metrics(&self) -> ExecMetrics {
+        self.metrics.get()
+    }
+
+    #[inline]
+    fn record(&self, update: impl FnOnce(&mut ExecMetrics)) {
+        #[cfg(feature = "metrics")]
+        {
+            let mut m = self.metrics.get();
+            update(&mut m);
+            self.metrics.set(m);
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = update;
+    }
+
+    /// Turn a statement's rows into its result, applying `duplicate_columns`.
+    fn output_result(&self, rs: RowSet) -> Result<QueryResult> {
+        let mut result = rs.into_query_result();
+        if self.duplicate_columns == DuplicateColumns::Keep { return Ok(result); }
+        let mut seen: HashSet<String> = HashSet::new();
+        for i in 0..result.columns.len() {
+            let name = result.columns[i].clone();
+            if seen.insert(name.to_uppercase()) { continue; }
+            if self.duplicate_columns == DuplicateColumns::Error {
+                return Err(PivotError::SqlError(format!("Duplicate output column '{}'", name)));
+            }
+            let renamed = (1..)
+                .map(|n| format!("{}:{}", name, n))
+                .find(|c| !seen.contains(&c.to_uppercase()) && !result.columns.iter().any(|o| o.eq_ignore_ascii_case(c)))
+                .unwrap();
+            seen.insert(renamed.to_uppercase());
+            result.columns[i] = renamed;
+        }
+        Ok(result)
+    }
+
+    /// Make `data` readable as `read_csv('name')` / `read_json('name')`.
+    pub fn register_source(&mut self, name: &str, data: &str) {
+        self.sources.insert(name.to_string(), data.to_string());
+    }
+
+    /// Make `store` queryable as table `name`. An existing table of that
+    /// name is an error unless `replace` is set; a view of that name always is.
+    pub fn register_table(&mut self, name: &str, store: DataStore, replace: bool) -> Result<()> {
+        let name = TableName { name: name.to_string(), quoted: false };
+        if self.catalog.view_key(&name).is_some() {
+            return Err(PivotError::SqlError(format!("A view named '{}' already exists", name)));
+        }
+        let key = match self.catalog.table_key(&name) {
+            Some(_) if !replace => return Err(PivotError::SqlError(format!("Table '{}' already exists", name))),
+            Some(key) => key,
+            None => name.name,
+        };
+        self.catalog.insert_table_verbatim(&key, store)
+    }
+
+    /// Read `csv` with `reader` and register it as table `name`, as
+    /// `register_table` does.
+    pub fn load_csv(&mut self, name: &str, csv: &str, reader: &CsvReader, replace: bool) -> Result<()> {
+        let store = reader.read_str(csv)?;
+        self.register_table(name, store, replace)
+    }
+
+    /// Result of an aggregate that saw no non-NULL input: NULL, or `zero`
+    /// under `empty_aggregate_as_zero`.
+    fn empty_aggregate(&self, zero: ScalarValue) -> ScalarValue {
+        if self.empty_aggregate_as_zero { zero } else { ScalarValue::Null }
+    }
+
+    /// Run subquery `stmt` for the outer row `row`. A subquery that reads no
+    /// column of `row` gives the same rows for every row of the enclosing
+    /// SELECT, so its result is kept and reused until that SELECT finishes.
+    fn exec_subquery(&self, stmt: &Statement, cols: &[Col], row: &[ScalarValue], ctx: &ExecCtx) -> Result<Arc<RowSet>> {
+        let key = stmt as *const Statement as usize;
+        if let Some(rs) = self.subquery_results.borrow().last().and_then(|m| m.get(&key)) {
+            return Ok(Arc::clone(rs));
+        }
+        self.outer_rows.borrow_mut().push(OuterRow { cols: cols.to_vec(), row: row.to_vec(), read: Cell::new(false) });
+        let result = self.exec_query(stmt, ctx);
+        let outer = self.outer_rows.borrow_mut().pop().unwrap();
+        let rs = Arc::new(result?);
+        if !outer.read.get() {
+            if let Some(results) = self.subquery_results.borrow_mut().last_mut() {
+                results.insert(key, Arc::clone(&rs));
+            }
+        }
+        Ok(rs)
+    }
+
+    /// Resolve a column against the enclosing rows, innermost first.
+    fn outer_value(&self, col_ref: &ColumnRef) -> Option<ScalarValue> {
+        let fold = self.fold();
+        self.outer_rows.borrow().iter().rev().find_map(|outer| {
+            if col_ref.table.as_deref().is_some_and(|t| !has_table(&outer.cols, t, fold)) { return None; }
+            let idx = find_col_idx(&outer.cols, col_ref, fold)?;
+            outer.read.set(true);
+            Some(outer.row[idx].clone())
+        })
+    }
+
+    fn eval_ctx<'a>(&'a self, exec: &'a ExecCtx) -> EvalCtx<'a> {
+        EvalCtx { engine: self, exec }
+    }
+
+    /// Pin CURRENT_DATE, NOW() and friends to `now` (UTC microseconds since
+    /// the epoch), or go back to the system clock with `None`.
+    pub fn set_now(&mut self, now: Option<i64>) {
+        self.now = now;
+    }
+
+    /// Make `f` callable as scalar function `name` (any case). It takes
+    /// precedence over a built-in of the same name and replaces an earlier
+    /// registration.
+    pub fn register_function(&mut self, name: &str, f: ScalarFunction) {
+        self.functions.insert(name.to_uppercase(), f);
+    }
+
+    fn now_micros(&self) -> i64 {
+        self.statement_time
+    }
+
+    pub fn set_stable_window_order(&mut self, stable: bool) {
+        self.stable_window_order = stable;
     }
 
     pub fn execute(&mut self, sql: &str) -> Result<QueryResult> {
         let tokens = Lexer::new(sql).tokenize()?;
-        let stmts = Parser::new(tokens).parse()?;
+        let stmts = Parser::new(tokens)
+            .with_max_depth(self.max_depth)
+            .parse()?;
+        self.statement_time = self.now.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as i64).unwrap_or(0)
+        });
         let mut last = QueryResult::empty();
         for stmt in stmts {
+            self.metrics.set(ExecMetrics::default());
             last = self.exec_stmt(stmt)?;
         }
         Ok(last)
@@ -138,46 +537,73 @@ impl SqlEngine {
         match stmt {
             Statement::Select(s) => {
                 let ctx = ExecCtx::new();
-                Ok(self.exec_select(&s, &ctx)?.into_query_result())
+                let rs = self.exec_select(&s, &ctx)?;
+                self.output_result(rs)
             }
             Statement::With(w) => {
                 let mut ctx = ExecCtx::new();
                 for cte in &w.ctes {
-                    let rs = self.exec_stmt_ctx(&*cte.query, &ctx)?;
+                    let rs = if w.recursive { self.exec_recursive_cte(cte, &ctx)? }
+                        else { name_cte_columns(cte, self.exec_stmt_ctx(&cte.query, &ctx)?)? };
                     ctx.ctes.insert(cte.name.to_uppercase(), rs);
                 }
-                Ok(self.exec_stmt_ctx(&*w.body, &ctx)?.into_query_result())
+                let rs = self.exec_stmt_ctx(&w.body, &ctx)?;
+                self.output_result(rs)
             }
             Statement::SetOp(s) => {
                 let ctx = ExecCtx::new();
-                Ok(self.exec_set_op(&s, &ctx)?.into_query_result())
+                let rs = self.exec_set_op(&s, &ctx)?;
+                self.output_result(rs)
             }
             Statement::Insert(i) => self.exec_insert(i),
             Statement::Update(u) => self.exec_update(u),
             Statement::Delete(d) => self.exec_delete(d),
             Statement::CreateTable(c) => self.exec_create_table(c),
             Statement::DropTable(d) => self.exec_drop_table(d),
-            Statement::Begin | Statement::Commit | Statement::Rollback => {
+            Statement::CreateView(c) => self.exec_create_view(c),
+            Statement::DropView(d) => self.exec_drop_view(d),
+            Statement::Begin => {
+                if self.transaction.is_some() {
+                    return Err(PivotError::SqlError("A transaction is already in progress".to_string()));
+                }
+                self.transaction = Some(self.catalog.clone());
+                Ok(QueryResult::with_message("OK".to_string()))
+            }
+            Statement::Commit => {
+                self.transaction = None;
+                Ok(QueryResult::with_message("OK".to_string()))
+            }
+            Statement::Rollback => {
+                if let Some(snapshot) = self.transaction.take() {
+                    self.catalog = snapshot;
+                }
                 Ok(QueryResult::with_message("OK".to_string()))
             }
             Statement::Explain(inner) => {
                 Ok(QueryResult::with_message(format!("Plan: {:?}", inner)))
             }
+            Statement::Vacuum(table) => self.exec_vacuum(table),
+            Statement::Analyze(table) => self.exec_analyze(table),
+            Statement::AlterTable(a) => self.exec_alter_table(a),
+            Statement::Values(rows) => {
+                let rs = self.exec_values(&rows, &ExecCtx::new())?;
+                self.output_result(rs)
+            }
         }
     }
 
     fn exec_stmt_ctx(&mut self, stmt: &Statement, ctx: &ExecCtx) -> Result<RowSet> {
         match stmt {
-            Statement::Select(s) => self.exec_select(s, ctx),
+            Statement::Select(_) | Statement::SetOp(_) | Statement::Values(_) => self.exec_query(stmt, ctx),
             Statement::With(w) => {
                 let mut new_ctx = ctx.clone();
                 for cte in &w.ctes {
-                    let rs = self.exec_stmt_ctx(&*cte.query, &new_ctx)?;
+                    let rs = if w.recursive { self.exec_recursive_cte(cte, &new_ctx)? }
+                        else { name_cte_columns(cte, self.exec_stmt_ctx(&cte.query, &new_ctx)?)? };
                     new_ctx.ctes.insert(cte.name.to_uppercase(), rs);
                 }
-                self.exec_stmt_ctx(&*w.body, &new_ctx)
+                self.exec_stmt_ctx(&w.body, &new_ctx)
             }
-            Statement::SetOp(s) => self.exec_set_op(s, ctx),
             other => {
                 let result = self.exec_stmt(other.clone())?;
                 Ok(RowSet {
@@ -190,9 +616,121 @@ impl SqlEngine {
         }
     }
 
+    /// Run a read-only query: the form subqueries are executed in.
+    fn exec_query(&self, stmt: &Statement, ctx: &ExecCtx) -> Result<RowSet> {
+        let depth = self.query_depth.get();
+        if depth >= self.max_depth {
+            return Err(PivotError::SqlError(format!(
+                "Query nesting exceeds the maximum depth of {}", self.max_depth
+            )));
+        }
+        self.query_depth.set(depth + 1);
+        let result = self.exec_query_inner(stmt, ctx);
+        self.query_depth.set(depth);
+        result
+    }
+
+    fn exec_query_inner(&self, stmt: &Statement, ctx: &ExecCtx) -> Result<RowSet> {
+        match stmt {
+            Statement::Select(s) => self.exec_select(s, ctx),
+            Statement::With(w) => {
+                let mut new_ctx = ctx.clone();
+                for cte in &w.ctes {
+                    let rs = if w.recursive { self.exec_recursive_cte(cte, &new_ctx)? }
+                        else { name_cte_columns(cte, self.exec_query(&cte.query, &new_ctx)?)? };
+                    new_ctx.ctes.insert(cte.name.to_uppercase(), rs);
+                }
+                self.exec_query(&w.body, &new_ctx)
+            }
+            Statement::SetOp(s) => self.exec_set_op(s, ctx),
+            Statement::Values(rows) => self.exec_values(rows, ctx),
+            _ => Err(PivotError::SqlError("Only SELECT, VALUES or WITH queries can be nested".to_string())),
+        }
+    }
+
+    /// Rows of a CTE in a `WITH RECURSIVE`. A body `anchor UNION [ALL] step`
+    /// whose `step` reads the CTE runs to a fixed point: each round `step`
+    /// sees only the rows the previous round added, and rounds stop once
+    /// none are (under UNION, rows already produced don't count as added).
+    /// Other bodies run once, as in a plain WITH.
+    fn exec_recursive_cte(&self, cte: &Cte, ctx: &ExecCtx) -> Result<RowSet> {
+        let (all, anchor, step) = match cte.query.as_ref() {
+            Statement::SetOp(SetOpStatement { op: SetOp::Union, all, left, right })
+                if statement_reads_table(right, &cte.name) => (*all, left, right),
+            query => return name_cte_columns(cte, self.exec_query(query, ctx)?),
+        };
+        let mut result = name_cte_columns(cte, self.exec_query(anchor, ctx)?)?;
+        let mut seen = HashSet::new();
+        if !all {
+            result = dedup_rowset(result);
+            seen.extend(result.rows.iter().map(|row| row_key(row)));
+        }
+        let mut added = result.clone();
+        let mut rounds = 0;
+        while !added.rows.is_empty() {
+            if rounds == self.max_recursion {
+                return Err(PivotError::SqlError(format!(
+                    "Recursive CTE '{}' did not finish within {} rounds", cte.name, self.max_recursion
+                )));
+            }
+            rounds += 1;
+            let mut step_ctx = ctx.clone();
+            step_ctx.ctes.insert(cte.name.to_uppercase(), added);
+            let mut rows = self.exec_query(step, &step_ctx)?;
+            if rows.cols.len() != result.cols.len() {
+                return Err(PivotError::SqlError(format!(
+                    "Recursive term of '{}' returns {} columns, expected {}",
+                    cte.name, rows.cols.len(), result.cols.len()
+                )));
+            }
+            if !all {
+                rows.rows.retain(|row| seen.insert(row_key(row)));
+            }
+            result.rows.extend(rows.rows.iter().cloned());
+            added = RowSet { cols: result.cols.clone(), rows: rows.rows };
+        }
+        Ok(result)
+    }
+
+    // ─── VALUES ───────────────────────────────────────────────────────────────
+
+    fn exec_values(&self, rows: &[Vec<Expr>], ctx: &ExecCtx) -> Result<RowSet> {
+        let width = rows.first().map(|r| r.len()).unwrap_or(0);
+        let cols = (1..=width).map(|i| Col {
+            table: None, name: format!("column{}", i), dtype: DataType::Utf8
+        }).collect();
+        let mut rs = RowSet::new(cols);
+        for row in rows {
+            if row.len() != width {
+                return Err(PivotError::SqlError(format!(
+                    "VALUES rows must all have {} columns, found one with {}", width, row.len()
+                )));
+            }
+            let values = row.iter()
+                .map(|e| eval_expr(e, &[], &[], &self.eval_ctx(ctx)))
+                .collect::<Result<Vec<_>>>()?;
+            rs.rows.push(values);
+        }
+        Ok(rs)
+    }
+
     // ─── SELECT ───────────────────────────────────────────────────────────────
 
-    fn exec_select(&mut self, stmt: &SelectStatement, ctx: &ExecCtx) -> Result<RowSet> {
+    fn exec_select(&self, stmt: &SelectStatement, ctx: &ExecCtx) -> Result<RowSet> {
+        self.subquery_results.borrow_mut().push(HashMap::new());
+        let result = self.exec_select_inner(stmt, ctx);
+        self.subquery_results.borrow_mut().pop();
+        result
+    }
+
+    fn exec_select_inner(&self, stmt: &SelectStatement, ctx: &ExecCtx) -> Result<RowSet> {
+        validate_window_placement(stmt)?;
+        let resolved;
+        let stmt = if stmt.windows.is_empty() { stmt } else {
+            resolved = resolve_named_windows(stmt)?;
+            &resolved
+        };
+
         // 1. FROM
         let base = if let Some(table_ref) = &stmt.from {
             self.resolve_table_ref(table_ref, ctx)?
@@ -200,82 +738,485 @@ impl SqlEngine {
             RowSet::empty_single_row()
         };
 
-        // 2. JOINs
-        let joined = self.apply_joins(base, &stmt.joins, ctx)?;
+        // 2. JOINs. When nothing between the join and LIMIT can drop, merge
+        // or reorder rows, the join only needs to produce LIMIT + OFFSET.
+        let streams_to_limit = stmt.where_clause.is_none() && stmt.group_by.is_empty()
+            && stmt.grouping_sets.is_none()
+            && stmt.having.is_none() && stmt.order_by.is_empty() && !stmt.distinct
+            && !select_items_have_aggregate(&stmt.columns)
+            && !stmt.columns.iter().any(|item| matches!(item, SelectItem::Expr { expr, .. } if expr_has_window(expr)));
+        let row_limit = if streams_to_limit {
+            let (offset, limit) = self.limit_offset_values(stmt.limit.as_ref(), stmt.offset.as_ref(), ctx)?;
+            limit.map(|n| n.saturating_add(offset))
+        } else { None };
+        let joined = self.apply_joins(base, &stmt.joins, ctx, row_limit)?;
 
         // 3. WHERE
-        let filtered = self.apply_where(joined, stmt.where_clause.as_ref())?;
+        let filtered = self.apply_where(joined, stmt.where_clause.as_ref(), ctx)?;
+        let aliased;
+        let stmt = match self.resolve_select_aliases(stmt, &filtered.cols)? {
+            Some(s) => { aliased = s; &aliased }
+            None => stmt,
+        };
 
         // 4. GROUP BY or direct projection
-        let has_agg = select_items_have_aggregate(&stmt.columns);
-        let projected = if !stmt.group_by.is_empty() || has_agg {
-            // HAVING is evaluated inside exec_group_by with group context
-            self.exec_group_by(filtered, stmt)?
+        let has_agg = select_items_have_aggregate(&stmt.columns)
+            || stmt.order_by.iter().any(|item| expr_has_aggregate(&item.expr));
+        let grouped = !stmt.group_by.is_empty() || stmt.grouping_sets.is_some() || has_agg;
+        // Window functions may likewise read input columns the SELECT list
+        // drops, as in `RANK() OVER (PARTITION BY dept ...)`. Over a grouped
+        // query they see each group's row plus the aggregates they read,
+        // which are replaced by columns computed per group.
+        let has_window = stmt.columns.iter().any(|item| matches!(item, SelectItem::Expr { expr, .. } if expr_has_window(expr)))
+            || stmt.order_by.iter().any(|item| expr_has_window(&item.expr));
+        let mut window_cols = filtered.cols.clone();
+        let mut window_aggs = Vec::new();
+        let window_stmt = (grouped && has_window).then(|| {
+            let mut extract = |expr: &Expr| if expr_has_window(expr) {
+                let (expr, aggs) = extract_window_aggregates(expr, &mut window_cols);
+                window_aggs.extend(aggs);
+                expr
+            } else { expr.clone() };
+            let columns = stmt.columns.iter().map(|item| match item {
+                SelectItem::Expr { expr, alias } => SelectItem::Expr { expr: extract(expr), alias: alias.clone() },
+                other => other.clone(),
+            }).collect();
+            let order_by = stmt.order_by.iter()
+                .map(|item| OrderByItem { expr: extract(&item.expr), ..item.clone() })
+                .collect();
+            SelectStatement { columns, order_by, ..stmt.clone() }
+        });
+        let (projected, source_keys, window_source) = if grouped {
+            // HAVING is evaluated inside exec_group_by with group context,
+            // as are the ORDER BY keys of each group.
+            let mut window_rows = Vec::new();
+            let window_groups = window_stmt.is_some().then_some((window_aggs.as_slice(), &mut window_rows));
+            let (rs, group_keys) = self.exec_group_by(filtered, stmt, window_groups, ctx)?;
+            let window_source = window_stmt.is_some().then_some(RowSet { cols: window_cols, rows: window_rows });
+            (rs, Some(SourceKeys::Groups(group_keys)), window_source)
         } else {
-            let rs = self.project_select(filtered, &stmt.columns, stmt.distinct)?;
+            // Windows run before DISTINCT and HAVING drop rows.
+            let window_source = has_window.then(|| filtered.clone());
+            let rs = self.project_select(&filtered, &stmt.columns, stmt.distinct && !has_window, ctx)?;
+            // ORDER BY may name input columns the SELECT list drops; while the
+            // projection is row-for-row, keep the input rows as a fallback.
+            let source_keys = (!stmt.distinct && stmt.having.is_none() && !stmt.order_by.is_empty())
+                .then_some(SourceKeys::Rows(filtered));
+            let rs = self.apply_window_funcs(rs, &stmt.columns, window_source.as_ref(), ctx)?;
+            let rs = if stmt.distinct && has_window { dedup_rowset(rs) } else { rs };
             // For non-aggregate queries, HAVING is unusual but apply it
-            self.apply_having(rs, stmt.having.as_ref())?
+            (self.apply_having(rs, stmt.having.as_ref(), ctx)?, source_keys, window_source)
         };
+        let stmt = window_stmt.as_ref().unwrap_or(stmt);
 
         // 5. Window functions
-        let windowed = self.apply_window_funcs(projected, &stmt.columns)?;
+        let windowed = if grouped {
+            self.apply_window_funcs(projected, &stmt.columns, window_source.as_ref(), ctx)?
+        } else { projected };
 
         // 6. ORDER BY
-        let sorted = self.apply_order_by(windowed, &stmt.order_by)?;
+        let sorted = self.apply_order_by(windowed, &stmt.order_by, source_keys, window_source.as_ref(), ctx)?;
 
         // 7. LIMIT / OFFSET
-        self.apply_limit_offset(sorted, stmt.limit.as_ref(), stmt.offset.as_ref())
+        self.apply_limit_offset(sorted, stmt.limit.as_ref(), stmt.offset.as_ref(), ctx)
     }
 
     // ─── FROM / table resolution ──────────────────────────────────────────────
 
-    fn resolve_table_ref(&mut self, table_ref: &TableRef, ctx: &ExecCtx) -> Result<RowSet> {
+    fn resolve_table_ref(&self, table_ref: &TableRef, ctx: &ExecCtx) -> Result<RowSet> {
         match table_ref {
             TableRef::Table { name, alias } => {
-                let upper = name.to_uppercase();
+                let upper = name.name.to_uppercase();
                 // Check CTEs first
                 if let Some(rs) = ctx.ctes.get(&upper) {
-                    let effective_alias = alias.as_ref().map(|a| a.as_str()).unwrap_or(name.as_str());
+                    let effective_alias = alias.as_ref().map(|a| a.as_str()).unwrap_or(name.name.as_str());
+                    let n = rs.rows.len() as u64;
+                    self.record(|m| { m.rows_scanned += n; m.rows_cloned += n; });
                     return Ok(tag_rowset(rs.clone(), effective_alias));
                 }
-                // Then catalog
-                let store = self.catalog.get_table(&upper)
-                    .ok_or_else(|| PivotError::SqlError(format!("Table '{}' not found", name)))?;
-                let effective_alias = alias.as_ref().map(|a| a.as_str()).unwrap_or(name.as_str());
-                let cols: Vec<Col> = store.schema().columns.iter().map(|c| Col {
-                    table: Some(effective_alias.to_string()),
-                    name: c.name.clone(),
-                    dtype: c.data_type.clone(),
-                }).collect();
-                let mut rs = RowSet::new(cols);
-                for row in 0..store.row_count() {
-                    rs.rows.push(store.get_row(row)?);
+                let effective_alias = alias.as_deref().unwrap_or(name.name.as_str());
+                // Then views, which don't see the CTEs of the query reading them
+                if let Some(query) = self.catalog.view_key(name).and_then(|key| self.catalog.get_view_verbatim(&key)) {
+                    let rs = self.exec_query(query, &ExecCtx::new())?;
+                    return Ok(tag_rowset(rs, effective_alias));
                 }
-                Ok(rs)
+                // Then catalog
+                let store = self.catalog.get_table_verbatim(&self.table_key(name)?).unwrap();
+                self.record(|m| m.rows_scanned += store.row_count() as u64);
+                store_rowset(store, effective_alias)
             }
             TableRef::Subquery { query, alias } => {
-                let mut rs = self.exec_stmt_ctx(query, ctx)?;
+                let mut rs = self.exec_query(query, ctx)?;
                 // Tag with alias
                 for col in &mut rs.cols {
                     col.table = Some(alias.clone());
                 }
                 Ok(rs)
             }
+            TableRef::Function { name, args, alias } => {
+                let store = self.exec_table_function(name, args, ctx)?;
+                self.record(|m| m.rows_scanned += store.row_count() as u64);
+                store_rowset(&store, alias.as_deref().unwrap_or(name))
+            }
+            TableRef::Pivot(pivot) => self.exec_pivot(pivot, ctx),
+            TableRef::Unpivot(unpivot) => self.exec_unpivot(unpivot, ctx),
+        }
+    }
+
+    /// Melt the listed columns into (name, value) rows, one per source row
+    /// and column in order, as `pivot::unpivot_table` does but keeping the
+    /// values' types, cast to their common type when the columns differ.
+    /// NULL values are skipped unless `INCLUDE NULLS`.
+    fn exec_unpivot(&self, unpivot: &UnpivotTable, ctx: &ExecCtx) -> Result<RowSet> {
+        let source = self.resolve_table_ref(&unpivot.source, ctx)?;
+        let fold = self.fold();
+        let melted = unpivot.columns.iter()
+            .map(|(c, label)| {
+                let idx = find_col_idx(&source.cols, c, fold)
+                    .ok_or_else(|| PivotError::ColumnNotFound(c.name.clone()))?;
+                Ok((idx, label.clone().unwrap_or_else(|| source.cols[idx].name.clone())))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let kept: Vec<usize> = (0..source.cols.len()).filter(|i| !melted.iter().any(|(m, _)| m == i)).collect();
+        for (i, out) in [&unpivot.name, &unpivot.value].into_iter().enumerate() {
+            let taken = kept.iter().any(|&k| fold.matches(&source.cols[k].name, out))
+                || (i == 1 && fold.matches(&unpivot.name, out));
+            if taken {
+                return Err(PivotError::SqlError(format!(
+                    "UNPIVOT output column '{}' is already a column of the result", out)));
+            }
+        }
+        let table = unpivot.alias.clone()
+            .or_else(|| source.cols.first().and_then(|c| c.table.clone()));
+        let value_type = melted.iter().map(|(i, _)| source.cols[*i].dtype.clone())
+            .reduce(|a, b| common_type(&a, &b))
+            .unwrap_or(DataType::Utf8);
+        let mut cols: Vec<Col> = kept.iter()
+            .map(|&i| Col { table: table.clone(), ..source.cols[i].clone() })
+            .collect();
+        cols.push(Col { table: table.clone(), name: unpivot.name.clone(), dtype: DataType::Utf8 });
+        cols.push(Col { table, name: unpivot.value.clone(), dtype: value_type.clone() });
+
+        let mut rs = RowSet::new(cols);
+        for row in &source.rows {
+            for (idx, label) in &melted {
+                if matches!(row[*idx], ScalarValue::Null) && !unpivot.include_nulls { continue; }
+                let mut out: Vec<ScalarValue> = kept.iter().map(|&i| row[i].clone()).collect();
+                out.push(ScalarValue::Utf8(label.clone()));
+                out.push(if source.cols[*idx].dtype == value_type { row[*idx].clone() }
+                    else { cast::cast_value(row[*idx].clone(), &value_type) });
+                rs.rows.push(out);
+            }
+        }
+        Ok(rs)
+    }
+
+    /// Run `PIVOT` as a SELECT over the source grouped on its remaining
+    /// columns, with `agg(CASE WHEN column = value THEN arg END)` for each
+    /// listed value.
+    fn exec_pivot(&self, pivot: &PivotTable, ctx: &ExecCtx) -> Result<RowSet> {
+        let source = self.resolve_table_ref(&pivot.source, ctx)?;
+        let fold = self.fold();
+        let (func, args, distinct, within_group, filter) = match &pivot.aggregate {
+            Expr::Function { name, args, distinct, within_group, filter, over: None }
+                if is_aggregate_call(&pivot.aggregate) && name != "GROUPING" => (name, args, *distinct, within_group, filter),
+            other => return Err(PivotError::SqlError(format!(
+                "PIVOT needs an aggregate function, got {}", expr_display_name(other)
+            ))),
+        };
+        let pivot_idx = find_col_idx(&source.cols, &pivot.column, fold)
+            .ok_or_else(|| PivotError::ColumnNotFound(pivot.column.name.clone()))?;
+        let mut read = Vec::new();
+        if !args.iter().all(|a| matches!(a, Expr::Wildcard) || collect_column_refs(a, &mut read)) {
+            return Err(PivotError::SqlError(format!(
+                "PIVOT cannot aggregate {}", expr_display_name(&pivot.aggregate)
+            )));
+        }
+        let read: HashSet<usize> = read.iter().filter_map(|c| find_col_idx(&source.cols, c, fold)).collect();
+        let column_ref = |c: &Col| Expr::Column(ColumnRef { table: None, name: c.name.clone(), quoted: true });
+
+        let group_by: Vec<Expr> = source.cols.iter().enumerate()
+            .filter(|(i, _)| *i != pivot_idx && !read.contains(i))
+            .map(|(_, c)| column_ref(c))
+            .collect();
+        let mut columns: Vec<SelectItem> = group_by.iter()
+            .map(|e| SelectItem::Expr { expr: e.clone(), alias: None })
+            .collect();
+        let pivot_col = column_ref(&source.cols[pivot_idx]);
+        for (value, name) in &pivot.values {
+            let when = Expr::BinaryOp { left: Box::new(pivot_col.clone()), op: BinOp::Eq, right: Box::new(value.clone()) };
+            let args = args.iter().map(|a| {
+                let then = if matches!(a, Expr::Wildcard) { Expr::Literal(LiteralValue::Integer(1)) } else { a.clone() };
+                Expr::Case { operand: None, when_clauses: vec![(when.clone(), then)], else_clause: None }
+            }).collect();
+            let alias = match (name, value) {
+                (Some(n), _) => n.clone(),
+                (None, Expr::Literal(LiteralValue::String(s))) => s.clone(),
+                (None, v) => expr_display_name(v),
+            };
+            columns.push(SelectItem::Expr {
+                expr: Expr::Function {
+                    name: func.clone(),
+                    args,
+                    distinct,
+                    within_group: within_group.clone(),
+                    filter: filter.clone(),
+                    over: None,
+                },
+                alias: Some(alias),
+            });
+        }
+
+        // The aggregate may qualify its columns with the source's alias.
+        let alias = source.cols.first().and_then(|c| c.table.clone()).unwrap_or_default();
+        const SOURCE: &str = "PIVOT SOURCE";
+        let mut pivot_ctx = ctx.clone();
+        pivot_ctx.ctes.insert(SOURCE.to_string(), source);
+        let select = SelectStatement {
+            distinct: false,
+            columns,
+            from: Some(TableRef::Table { name: TableName { name: SOURCE.to_string(), quoted: true }, alias: Some(alias.clone()) }),
+            joins: Vec::new(),
+            where_clause: None,
+            group_by,
+            grouping_sets: None,
+            having: None,
+            windows: Vec::new(),
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+        };
+        let rs = self.exec_query(&Statement::Select(select), &pivot_ctx)?;
+        Ok(tag_rowset(rs, pivot.alias.as_deref().unwrap_or(&alias)))
+    }
+
+    /// Run `read_csv(data [, delimiter [, header]])` or `read_json(data)`.
+    /// `data` names a source added with `register_source`, or is the text
+    /// itself.
+    fn exec_table_function(&self, name: &str, args: &[Expr], ctx: &ExecCtx) -> Result<DataStore> {
+        let eval = self.eval_ctx(ctx);
+        let values = args.iter()
+            .map(|a| eval_expr(a, &[], &[], &eval))
+            .collect::<Result<Vec<_>>>()?;
+        let text_arg = |i: usize, what: &str| -> Result<Option<&str>> {
+            match values.get(i) {
+                None | Some(ScalarValue::Null) => Ok(None),
+                Some(ScalarValue::Utf8(s)) => Ok(Some(s.as_str())),
+                Some(v) => Err(PivotError::TypeError(format!("{}() {} must be a string, got {}", name, what, v))),
+            }
+        };
+        let data = text_arg(0, "data")?
+            .ok_or_else(|| PivotError::SqlError(format!("{}() requires a data argument", name)))?;
+        let data = self.sources.get(data).map(|s| s.as_str()).unwrap_or(data);
+        match name {
+            "read_csv" => {
+                if values.len() > 3 {
+                    return Err(PivotError::SqlError("read_csv() takes at most 3 arguments".to_string()));
+                }
+                let mut reader = CsvReader::new().with_type_inference(true);
+                if let Some(delim) = text_arg(1, "delimiter")? {
+                    let mut chars = delim.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => reader = reader.with_delimiter(c),
+                        _ => return Err(PivotError::SqlError(format!(
+                            "read_csv() delimiter must be a single character, got '{}'", delim))),
+                    }
+                }
+                match values.get(2) {
+                    None | Some(ScalarValue::Null) => {}
+                    Some(ScalarValue::Boolean(h)) => reader = reader.with_header(*h),
+                    Some(v) => return Err(PivotError::TypeError(format!(
+                        "read_csv() header must be a boolean, got {}", v))),
+                }
+                reader.read_str(data)
+            }
+            "read_json" => {
+                if values.len() > 1 {
+                    return Err(PivotError::SqlError("read_json() takes 1 argument".to_string()));
+                }
+                JsonReader::new().read_str(data)
+            }
+            _ => Err(PivotError::SqlError(format!("Unknown table function '{}'", name))),
         }
     }
 
     // ─── JOINs ────────────────────────────────────────────────────────────────
 
-    fn apply_joins(&mut self, base: RowSet, joins: &[Join], ctx: &ExecCtx) -> Result<RowSet> {
-        let mut result = base;
+    /// Join `base` with each of `joins` in turn. `row_limit` caps the output
+    /// of the last join; earlier joins still run in full since later ones
+    /// may discard their rows.
+    fn apply_joins(&self, base: RowSet, joins: &[Join], ctx: &ExecCtx, row_limit: Option<usize>) -> Result<RowSet> {
+        let mut inputs = vec![base];
+        for join in joins {
+            inputs.push(self.resolve_table_ref(&join.table, ctx)?);
+        }
+        if row_limit.is_none() && self.reorder_joins {
+            if let Some((order, conjuncts)) = self.join_order(&inputs, joins) {
+                return self.apply_joins_in_order(inputs, &order, &conjuncts, ctx);
+            }
+        }
+        let mut inputs = inputs.into_iter();
+        let mut result = inputs.next().unwrap();
+        for (i, (join, right)) in joins.iter().zip(inputs).enumerate() {
+            let limit = if i + 1 == joins.len() { row_limit } else { None };
+            result = self.apply_join(result, right, join, limit, ctx)?;
+        }
+        Ok(result)
+    }
+
+    /// A cheaper order for a chain of inner and cross joins: start from the
+    /// smallest input, then repeatedly add the smallest input that an ON
+    /// condition connects to what is joined so far. Returns the order with
+    /// the ON conjuncts and the inputs each one reads, or None to keep the
+    /// written order (outer joins, USING, conditions whose columns cannot be
+    /// pinned to a single input, or no better order).
+    #[allow(clippy::type_complexity)]
+    fn join_order<'a>(&self, inputs: &[RowSet], joins: &'a [Join]) -> Option<(Vec<usize>, Vec<(HashSet<usize>, &'a Expr)>)> {
+        if joins.len() < 2 { return None; }
+        let mut conjuncts = Vec::new();
         for join in joins {
-            result = self.apply_join(result, join, ctx)?;
+            match (&join.join_type, &join.condition) {
+                (JoinType::Inner | JoinType::Cross, JoinCondition::None) => {}
+                (JoinType::Inner, JoinCondition::On(expr)) => {
+                    let mut parts = Vec::new();
+                    split_conjuncts(expr, &mut parts);
+                    for part in parts {
+                        let mut refs = Vec::new();
+                        if !collect_column_refs(part, &mut refs) { return None; }
+                        let mut deps = HashSet::new();
+                        for col in refs {
+                            deps.insert(self.owning_input(inputs, col)?);
+                        }
+                        conjuncts.push((deps, part));
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        let sizes: Vec<usize> = inputs.iter().map(|rs| rs.rows.len()).collect();
+        let smallest = |candidates: &[usize]| candidates.iter().copied().min_by_key(|&i| (sizes[i], i));
+        let mut order = vec![smallest(&(0..inputs.len()).collect::<Vec<_>>())?];
+        while order.len() < inputs.len() {
+            let placed: HashSet<usize> = order.iter().copied().collect();
+            let remaining: Vec<usize> = (0..inputs.len()).filter(|i| !placed.contains(i)).collect();
+            let connected: Vec<usize> = remaining.iter().copied().filter(|&i| conjuncts.iter()
+                .any(|(deps, _)| deps.contains(&i) && deps.iter().all(|d| *d == i || placed.contains(d))))
+                .collect();
+            order.push(smallest(&connected).or_else(|| smallest(&remaining))?);
+        }
+        if order.iter().enumerate().all(|(pos, &i)| pos == i) { return None; }
+        Some((order, conjuncts))
+    }
+
+    /// The input whose columns `col` names, if exactly one does.
+    fn owning_input(&self, inputs: &[RowSet], col: &ColumnRef) -> Option<usize> {
+        let fold = self.fold();
+        let mut owners = inputs.iter().enumerate().filter(|(_, rs)| match &col.table {
+            Some(t) => rs.cols.iter().any(|c| c.table.as_deref().is_some_and(|s| fold.matches(s, t)))
+                && find_col_idx(&rs.cols, col, fold).is_some(),
+            None => find_col_idx(&rs.cols, col, fold).is_some(),
+        }).map(|(i, _)| i);
+        match (owners.next(), owners.next()) {
+            (Some(i), None) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// Join `inputs` in `order`, applying each conjunct as soon as every
+    /// input it reads is present. As in `apply_join`, a step whose ready
+    /// conjuncts equate columns only pairs rows whose keys hash alike. The
+    /// rows and columns come out as the written order would have produced
+    /// them.
+    fn apply_joins_in_order(&self, inputs: Vec<RowSet>, order: &[usize], conjuncts: &[(HashSet<usize>, &Expr)], ctx: &ExecCtx) -> Result<RowSet> {
+        let mut cols: Vec<Col> = inputs[order[0]].cols.clone();
+        let mut rows: Vec<Vec<ScalarValue>> = inputs[order[0]].rows.clone();
+        // The source row of every input joined so far, for each row.
+        let mut sources: Vec<Vec<usize>> = (0..rows.len()).map(|i| vec![i]).collect();
+        let mut applied = vec![false; conjuncts.len()];
+        let mut placed: HashSet<usize> = HashSet::from([order[0]]);
+        for &next in &order[1..] {
+            placed.insert(next);
+            let left_len = cols.len();
+            cols.extend(inputs[next].cols.iter().cloned());
+            let ready: Vec<&Expr> = conjuncts.iter().zip(applied.iter_mut())
+                .filter(|((deps, _), done)| !**done && deps.is_subset(&placed))
+                .map(|((_, expr), done)| { *done = true; *expr })
+                .collect();
+            let right = &inputs[next].rows;
+            let equi_keys: Vec<(usize, usize)> = ready.iter()
+                .flat_map(|cond| self.equi_join_keys(cond, &cols, left_len))
+                .collect();
+            let probe = (!equi_keys.is_empty()).then(|| HashProbe::build(&rows, right, &equi_keys));
+            let mut joined = Vec::new();
+            let mut joined_sources = Vec::new();
+            for (li, (row, row_sources)) in rows.iter().zip(&sources).enumerate() {
+                let (hits, scan) = match &probe {
+                    Some(probe) => (probe.candidates(li, row), 0..0),
+                    None => (&[][..], 0..right.len()),
+                };
+                for ri in hits.iter().copied().chain(scan) {
+                    let mut combined = row.clone();
+                    combined.extend_from_slice(&right[ri]);
+                    self.record(|m| { m.rows_cloned += 1; m.join_comparisons += 1; });
+                    let mut keep = true;
+                    for cond in &ready {
+                        if !is_truthy(&eval_expr(cond, &combined, &cols, &self.eval_ctx(ctx))?) {
+                            keep = false;
+                            break;
+                        }
+                    }
+                    if keep {
+                        let mut row_sources = row_sources.clone();
+                        row_sources.push(ri);
+                        joined.push(combined);
+                        joined_sources.push(row_sources);
+                    }
+                }
+            }
+            let n = joined.len() as u64;
+            self.record(|m| m.join_rows += n);
+            rows = joined;
+            sources = joined_sources;
+        }
+        let mut rows: Vec<(Vec<usize>, Vec<ScalarValue>)> = sources.into_iter().zip(rows).collect();
+
+        // Back to the written order: rows sort by their source rows taken in
+        // input order (the nested-loop order), columns regroup by input.
+        let mut position = vec![0; order.len()];
+        let mut offset = vec![0; order.len()];
+        let mut width = 0;
+        for (pos, &i) in order.iter().enumerate() {
+            position[i] = pos;
+            offset[i] = width;
+            width += inputs[i].cols.len();
         }
+        rows.sort_by(|(a, _), (b, _)| (0..order.len()).map(|i| a[position[i]])
+            .cmp((0..order.len()).map(|i| b[position[i]])));
+        let mut result = RowSet::new((0..order.len())
+            .flat_map(|i| cols[offset[i]..offset[i] + inputs[i].cols.len()].iter().cloned())
+            .collect());
+        result.rows = rows.into_iter().map(|(_, row)| (0..order.len())
+            .flat_map(|i| row[offset[i]..offset[i] + inputs[i].cols.len()].iter().cloned())
+            .collect())
+            .collect();
         Ok(result)
     }
 
-    fn apply_join(&mut self, left: RowSet, join: &Join, ctx: &ExecCtx) -> Result<RowSet> {
-        let right = self.resolve_table_ref(&join.table, ctx)?;
+    /// Join two inputs. Equi-joins only pair rows whose keys hash alike,
+    /// anything else is a nested loop; either way each pair is checked
+    /// against the full condition. With a `limit`, stop once that many rows
+    /// are out; RIGHT and FULL joins ignore it because their unmatched right
+    /// rows are only known after every left row has been seen.
+    fn apply_join(&self, left: RowSet, right: RowSet, join: &Join, limit: Option<usize>, ctx: &ExecCtx) -> Result<RowSet> {
+        // NATURAL joins match the (left, right) positions of each shared
+        // column name; with none shared they pair every row like CROSS JOIN.
+        let natural_keys = matches!(join.condition, JoinCondition::Natural).then(|| {
+            left.cols.iter().enumerate().filter_map(|(li, lc)| right.cols.iter()
+                .position(|rc| self.fold().matches(&rc.name, &lc.name))
+                .map(|ri| (li, ri)))
+                .collect::<Vec<_>>()
+        });
 
         // Build combined schema
         let mut combined_cols: Vec<Col> = left.cols.clone();
@@ -289,10 +1230,13 @@ impl SqlEngine {
 
         match &join.join_type {
             JoinType::Cross => {
-                for lr in &left.rows {
+                let limit = limit.unwrap_or(usize::MAX);
+                'outer: for lr in &left.rows {
                     for rr in &right.rows {
+                        if result.rows.len() >= limit { break 'outer; }
                         let mut combined = lr.clone();
                         combined.extend_from_slice(rr);
+                        self.record(|m| m.rows_cloned += 1);
                         result.rows.push(combined);
                     }
                 }
@@ -300,27 +1244,44 @@ impl SqlEngine {
             JoinType::Inner | JoinType::Left | JoinType::Right | JoinType::Full => {
                 let is_left = matches!(join.join_type, JoinType::Left | JoinType::Full);
                 let is_right = matches!(join.join_type, JoinType::Right | JoinType::Full);
+                let limit = if is_right { usize::MAX } else { limit.unwrap_or(usize::MAX) };
 
                 let mut right_matched = vec![false; right.rows.len()];
+                let equi_keys = match (&natural_keys, &join.condition) {
+                    (Some(keys), _) => keys.clone(),
+                    (None, JoinCondition::On(expr)) => self.equi_join_keys(expr, &combined_cols, left_len),
+                    _ => Vec::new(),
+                };
+                let probe = (!equi_keys.is_empty()).then(|| HashProbe::build(&left.rows, &right.rows, &equi_keys));
 
-                for lr in &left.rows {
+                'outer: for (li, lr) in left.rows.iter().enumerate() {
+                    if result.rows.len() >= limit { break; }
                     let mut found = false;
-                    for (ri, rr) in right.rows.iter().enumerate() {
+                    let (hits, scan) = match &probe {
+                        Some(probe) => (probe.candidates(li, lr), 0..0),
+                        None => (&[][..], 0..right.rows.len()),
+                    };
+                    for ri in hits.iter().copied().chain(scan) {
+                        let rr = &right.rows[ri];
                         let mut combined = lr.clone();
                         combined.extend_from_slice(rr);
-                        let matches = self.eval_join_condition(
-                            &join.condition, &combined, &combined_cols
-                        )?;
+                        self.record(|m| { m.rows_cloned += 1; m.join_comparisons += 1; });
+                        let matches = match &natural_keys {
+                            Some(keys) => keys.iter().all(|&(li, ri)| scalar_eq(&lr[li], &rr[ri])),
+                            None => self.eval_join_condition(&join.condition, &combined, &combined_cols, ctx)?,
+                        };
                         if matches {
                             result.rows.push(combined);
                             right_matched[ri] = true;
                             found = true;
+                            if result.rows.len() >= limit { break 'outer; }
                         }
                     }
                     if !found && is_left {
                         // Left row with nulls for right
+                        self.record(|m| m.rows_cloned += 1);
                         let mut combined = lr.clone();
-                        combined.extend(std::iter::repeat(ScalarValue::Null).take(right_len));
+                        combined.extend(std::iter::repeat_n(ScalarValue::Null, right_len));
                         result.rows.push(combined);
                     }
                 }
@@ -328,8 +1289,7 @@ impl SqlEngine {
                 if is_right {
                     for (ri, rr) in right.rows.iter().enumerate() {
                         if !right_matched[ri] {
-                            let mut combined: Vec<ScalarValue> = std::iter::repeat(ScalarValue::Null)
-                                .take(left_len).collect();
+                            let mut combined: Vec<ScalarValue> = std::iter::repeat_n(ScalarValue::Null, left_len).collect();
                             combined.extend_from_slice(rr);
                             result.rows.push(combined);
                         }
@@ -337,7 +1297,35 @@ impl SqlEngine {
                 }
             }
         }
-        Ok(result)
+        let n = result.rows.len() as u64;
+        self.record(|m| m.join_rows += n);
+        match natural_keys {
+            Some(keys) => Ok(merge_natural_keys(result, left_len, &keys)),
+            None => Ok(result),
+        }
+    }
+
+    /// `(left, right)` column positions of the ON conjuncts that equate a
+    /// left column with a right one. Other conjuncts don't narrow the pairs
+    /// tried; they are still checked along with the rest of the condition.
+    fn equi_join_keys(&self, expr: &Expr, cols: &[Col], left_len: usize) -> Vec<(usize, usize)> {
+        let fold = self.fold();
+        // Resolve as eval_expr would; qualifiers naming an outer query's
+        // table don't give a key.
+        let side = |e: &Expr| match e {
+            Expr::Column(c) if c.table.as_deref().is_none_or(|t| has_table(cols, t, fold)) => find_col_idx(cols, c, fold),
+            _ => None,
+        };
+        let mut parts = Vec::new();
+        split_conjuncts(expr, &mut parts);
+        parts.into_iter().filter_map(|part| match part {
+            Expr::BinaryOp { left, op: BinOp::Eq, right } => match (side(left)?, side(right)?) {
+                (l, r) if l < left_len && r >= left_len => Some((l, r - left_len)),
+                (r, l) if l < left_len && r >= left_len => Some((l, r - left_len)),
+                _ => None,
+            },
+            _ => None,
+        }).collect()
     }
 
     fn eval_join_condition(
@@ -345,11 +1333,15 @@ impl SqlEngine {
         cond: &JoinCondition,
         row: &[ScalarValue],
         cols: &[Col],
+        ctx: &ExecCtx,
     ) -> Result<bool> {
         match cond {
             JoinCondition::None => Ok(true),
+            JoinCondition::Natural => Err(PivotError::SqlError(
+                "NATURAL join columns are matched by apply_join".to_string()
+            )),
             JoinCondition::On(expr) => {
-                let v = eval_expr(expr, row, cols, None, &HashMap::new())?;
+                let v = eval_expr(expr, row, cols, &self.eval_ctx(ctx))?;
                 Ok(is_truthy(&v))
             }
             JoinCondition::Using(col_names) => {
@@ -372,15 +1364,16 @@ impl SqlEngine {
 
     // ─── WHERE ────────────────────────────────────────────────────────────────
 
-    fn apply_where(&self, rs: RowSet, where_clause: Option<&Expr>) -> Result<RowSet> {
+    fn apply_where(&self, rs: RowSet, where_clause: Option<&Expr>, ctx: &ExecCtx) -> Result<RowSet> {
         let expr = match where_clause {
             None => return Ok(rs),
             Some(e) => e,
         };
         let mut result = RowSet::new(rs.cols.clone());
         for row in &rs.rows {
-            let v = eval_expr(expr, row, &rs.cols, None, &HashMap::new())?;
+            let v = eval_expr(expr, row, &rs.cols, &self.eval_ctx(ctx))?;
             if is_truthy(&v) {
+                self.record(|m| m.rows_cloned += 1);
                 result.rows.push(row.clone());
             }
         }
@@ -389,7 +1382,18 @@ impl SqlEngine {
 
     // ─── GROUP BY ─────────────────────────────────────────────────────────────
 
-    fn exec_group_by(&self, rs: RowSet, stmt: &SelectStatement) -> Result<RowSet> {
+    /// Group and project `rs`. Alongside the output rows this returns each
+    /// group's ORDER BY keys, evaluated over the group's input rows so they
+    /// can use aggregates and columns the SELECT list leaves out.
+    /// Group and aggregate `rs`. With `window_groups`, also push each output
+    /// row's group, as `window_group_row` gives it, for window functions.
+    fn exec_group_by(
+        &self,
+        rs: RowSet,
+        stmt: &SelectStatement,
+        mut window_groups: Option<(&[Expr], &mut Vec<Vec<ScalarValue>>)>,
+        ctx: &ExecCtx,
+    ) -> Result<(RowSet, Vec<Vec<Result<ScalarValue>>>)> {
         // Determine output columns from SELECT items
         let mut out_cols: Vec<Col> = Vec::new();
         let mut out_exprs: Vec<(Expr, Option<String>)> = Vec::new();
@@ -402,6 +1406,7 @@ impl SqlEngine {
                         out_exprs.push((Expr::Column(ColumnRef {
                             table: col.table.clone(),
                             name: col.name.clone(),
+                            quoted: true,
                         }), None));
                     }
                 }
@@ -410,56 +1415,205 @@ impl SqlEngine {
                     out_cols.push(Col { table: None, name: col_name.clone(), dtype: DataType::Utf8 });
                     out_exprs.push((expr.clone(), alias.clone()));
                 }
-                SelectItem::TableWildcard(_) => {}
+                SelectItem::TableWildcard(tname) => {
+                    let cols = table_columns(&rs.cols, tname, self.fold())?;
+                    for col in cols {
+                        out_cols.push(col.clone());
+                        out_exprs.push((Expr::Column(ColumnRef {
+                            table: col.table.clone(),
+                            name: col.name.clone(),
+                            quoted: true,
+                        }), None));
+                    }
+                }
             }
         }
 
-        if stmt.group_by.is_empty() {
-            // No GROUP BY but has aggregates: entire table is one group
-            let group_rows: Vec<usize> = (0..rs.rows.len()).collect();
-            let result_row = self.eval_agg_row(&out_exprs, &rs.rows, &group_rows, &rs.cols,
-                                               &[ScalarValue::Null], &[])?;
-            let mut result = RowSet::new(out_cols);
-            result.rows.push(result_row);
-            return Ok(result);
+        if self.strict {
+            for item in &stmt.columns {
+                if let SelectItem::Expr { expr, alias } = item {
+                    let by_alias = alias.as_ref().is_some_and(|a| stmt.group_by.iter()
+                        .any(|g| matches!(g, Expr::Column(c) if c.table.is_none() && c.name.eq_ignore_ascii_case(a))));
+                    if !by_alias {
+                        self.check_grouped(expr, &stmt.group_by, &rs.cols)?;
+                    }
+                }
+            }
+            if let Some(having) = &stmt.having {
+                self.check_grouped(having, &stmt.group_by, &rs.cols)?;
+            }
         }
 
-        // Group rows by GROUP BY key
-        let mut group_map: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
-        let mut group_order: Vec<Vec<String>> = Vec::new();
-
-        for (row_idx, row) in rs.rows.iter().enumerate() {
-            let key: Vec<String> = stmt.group_by.iter().map(|expr| {
-                eval_expr(expr, row, &rs.cols, None, &HashMap::new())
-                    .ok()
-                    .map(|v| scalar_to_key(&v))
-                    .unwrap_or_default()
-            }).collect();
-
-            let entry = group_map.entry(key.clone()).or_insert_with(Vec::new);
-            if entry.is_empty() {
-                group_order.push(key);
+        if stmt.group_by.is_empty() && stmt.grouping_sets.is_none() {
+            // No GROUP BY but has aggregates: entire table is one group
+            let group_rows: Vec<usize> = (0..rs.rows.len()).collect();
+            let result_row = self.eval_agg_row(&out_exprs, &rs.rows, &group_rows, &rs.cols,
+                                               &[], ctx)?;
+            let mut result = RowSet::new(out_cols);
+            result.rows.push(result_row);
+            if let Some((aggs, rows)) = window_groups.as_mut() {
+                rows.push(self.window_group_row(aggs, &rs.rows, &group_rows, &rs.cols, &[], ctx)?);
             }
-            entry.push(row_idx);
+            let keys = self.group_order_keys(stmt, &rs, &group_rows, &[], ctx);
+            return Ok((result, vec![keys]));
         }
 
+        // A plain GROUP BY is the single grouping set of every key. Sets are
+        // emitted in order, so a ROLLUP lists detail rows before subtotals.
+        let sets = stmt.grouping_sets.clone()
+            .unwrap_or_else(|| vec![(0..stmt.group_by.len()).collect()]);
         let mut result = RowSet::new(out_cols);
-        for key in &group_order {
-            let indices = &group_map[key];
-            // Evaluate HAVING using group context before projecting
-            if let Some(ref having_expr) = stmt.having {
-                let passes = self.eval_expr_agg(
-                    having_expr, &rs.rows, indices, &rs.cols, &stmt.group_by
+        let mut order_keys = Vec::new();
+        for set in &sets {
+            let rolled_up: Vec<Expr> = (0..stmt.group_by.len())
+                .filter(|k| !set.contains(k))
+                .map(|k| stmt.group_by[k].clone())
+                .collect();
+
+            // Group rows by this set's keys
+            let mut group_map: HashMap<Vec<HashableScalar>, Vec<usize>> = HashMap::new();
+            let mut group_order: Vec<Vec<HashableScalar>> = Vec::new();
+            if set.is_empty() && rs.rows.is_empty() {
+                // The grand total has a row even when there is no input.
+                group_map.insert(Vec::new(), Vec::new());
+                group_order.push(Vec::new());
+            }
+            for (row_idx, row) in rs.rows.iter().enumerate() {
+                let key: Vec<HashableScalar> = set.iter().map(|&k| {
+                    let expr = &stmt.group_by[k];
+                    let v = eval_expr(expr, row, &rs.cols, &self.eval_ctx(ctx))?;
+                    Ok(HashableScalar(collate_value(v, expr_collation(expr))))
+                }).collect::<Result<_>>()?;
+
+                let entry = group_map.entry(key.clone()).or_default();
+                if entry.is_empty() {
+                    group_order.push(key);
+                }
+                entry.push(row_idx);
+            }
+
+            for key in &group_order {
+                let indices = &group_map[key];
+                // Evaluate HAVING using group context before projecting
+                if let Some(ref having_expr) = stmt.having {
+                    let passes = self.eval_expr_agg(
+                        having_expr, &rs.rows, indices, &rs.cols, &rolled_up, ctx
+                    )?;
+                    if !is_truthy(&passes) { continue; }
+                }
+                let result_row = self.eval_agg_row(
+                    &out_exprs, &rs.rows, indices, &rs.cols, &rolled_up, ctx
                 )?;
-                if !is_truthy(&passes) { continue; }
+                result.rows.push(result_row);
+                if let Some((aggs, rows)) = window_groups.as_mut() {
+                    rows.push(self.window_group_row(aggs, &rs.rows, indices, &rs.cols, &rolled_up, ctx)?);
+                }
+                order_keys.push(self.group_order_keys(stmt, &rs, indices, &rolled_up, ctx));
             }
-            let result_row = self.eval_agg_row(
-                &out_exprs, &rs.rows, indices, &rs.cols,
-                &[], &stmt.group_by
-            )?;
-            result.rows.push(result_row);
         }
-        Ok(result)
+        Ok((result, order_keys))
+    }
+
+    fn group_order_keys(&self, stmt: &SelectStatement, rs: &RowSet, indices: &[usize], rolled_up: &[Expr], ctx: &ExecCtx) -> Vec<Result<ScalarValue>> {
+        stmt.order_by.iter()
+            .map(|item| self.eval_expr_agg(&item.expr, &rs.rows, indices, &rs.cols, rolled_up, ctx))
+            .collect()
+    }
+
+    /// The group's row (see `group_row`, all NULL for an empty group)
+    /// followed by the values of the aggregates `aggs` over the group.
+    fn window_group_row(
+        &self,
+        aggs: &[Expr],
+        all_rows: &[Vec<ScalarValue>],
+        group_indices: &[usize],
+        cols: &[Col],
+        rolled_up: &[Expr],
+        ctx: &ExecCtx,
+    ) -> Result<Vec<ScalarValue>> {
+        let mut row = self.group_row(all_rows, group_indices, cols, rolled_up)
+            .map(Cow::into_owned)
+            .unwrap_or_else(|| vec![ScalarValue::Null; cols.len()]);
+        for agg in aggs {
+            row.push(self.eval_expr_agg(agg, all_rows, group_indices, cols, rolled_up, ctx)?);
+        }
+        Ok(row)
+    }
+
+    /// The row a non-aggregate expression of a group is evaluated on: the
+    /// group's first, with any rolled-up key columns set to NULL.
+    fn group_row<'r>(
+        &self,
+        all_rows: &'r [Vec<ScalarValue>],
+        group_indices: &[usize],
+        cols: &[Col],
+        rolled_up: &[Expr],
+    ) -> Option<Cow<'r, [ScalarValue]>> {
+        let row = &all_rows[*group_indices.first()?];
+        if rolled_up.is_empty() { return Some(Cow::Borrowed(row)); }
+        let mut row = row.clone();
+        for key in rolled_up {
+            if let Expr::Column(c) = key {
+                if let Some(idx) = find_col_idx(cols, c, self.fold()) { row[idx] = ScalarValue::Null; }
+            }
+        }
+        Some(Cow::Owned(row))
+    }
+
+    /// Copy of `stmt` with SELECT aliases in GROUP BY and HAVING replaced by
+    /// the expressions they name, or None if there is nothing to replace. In
+    /// GROUP BY an input column of the same name wins, as in standard SQL;
+    /// in HAVING the alias does, as in ORDER BY. Aliases of window
+    /// functions are left alone since neither clause may use them.
+    fn resolve_select_aliases(&self, stmt: &SelectStatement, cols: &[Col]) -> Result<Option<SelectStatement>> {
+        if stmt.group_by.is_empty() && stmt.having.is_none() { return Ok(None); }
+        let aliases: Vec<(&str, &Expr)> = stmt.columns.iter().filter_map(|item| match item {
+            SelectItem::Expr { expr, alias: Some(a) } if !expr_has_window(expr) => Some((a.as_str(), expr)),
+            _ => None,
+        }).collect();
+        if aliases.is_empty() { return Ok(None); }
+        let fold = self.fold();
+        let mut out = stmt.clone();
+        let mut changed = false;
+        for key in &mut out.group_by {
+            if substitute_aliases(key, &aliases, fold, &|c| find_col_idx(cols, c, fold).is_none()) {
+                if expr_has_aggregate(key) {
+                    return Err(PivotError::SqlError(format!(
+                        "GROUP BY cannot use the aggregate {}", expr_display_name(key)
+                    )));
+                }
+                changed = true;
+            }
+        }
+        if let Some(having) = &mut out.having {
+            changed |= substitute_aliases(having, &aliases, fold, &|_| true);
+        }
+        Ok(changed.then_some(out))
+    }
+
+    /// `eval_binary_op` under the engine's settings: with integer division
+    /// on, INTEGER / INTEGER truncates toward zero.
+    fn binary_op(&self, op: &BinOp, l: ScalarValue, r: ScalarValue) -> Result<ScalarValue> {
+        if let (BinOp::Div, true, ScalarValue::Int64(a), ScalarValue::Int64(b)) = (op, self.integer_division, &l, &r) {
+            if *b == 0 { return Err(PivotError::SqlError("Division by zero".to_string())); }
+            return a.checked_div(*b).map(ScalarValue::Int64).ok_or_else(|| PivotError::SqlError(format!(
+                "{} / {} is out of range for INTEGER", a, b
+            )));
+        }
+        eval_binary_op(op, l, r)
+    }
+
+    /// Strict mode: reject input columns used in a grouped query outside
+    /// both the GROUP BY keys and any aggregate, since their value would come
+    /// from an arbitrary row of the group.
+    fn check_grouped(&self, expr: &Expr, group_by: &[Expr], cols: &[Col]) -> Result<()> {
+        match ungrouped_column(expr, group_by, cols, self.fold()) {
+            None => Ok(()),
+            Some(name) => Err(PivotError::SqlError(format!(
+                "Column '{0}' must appear in GROUP BY or be used in an aggregate function; \
+                 use ANY_VALUE({0}) to take any value from the group", name
+            ))),
+        }
     }
 
     fn eval_agg_row(
@@ -468,40 +1622,97 @@ impl SqlEngine {
         all_rows: &[Vec<ScalarValue>],
         group_indices: &[usize],
         cols: &[Col],
-        _key_vals: &[ScalarValue],
-        group_exprs: &[Expr],
+        rolled_up: &[Expr],
+        ctx: &ExecCtx,
     ) -> Result<Vec<ScalarValue>> {
         let mut result = Vec::new();
         for (expr, _alias) in out_exprs {
-            let val = self.eval_expr_agg(expr, all_rows, group_indices, cols, group_exprs)?;
+            let val = self.eval_expr_agg(expr, all_rows, group_indices, cols, rolled_up, ctx)?;
             result.push(val);
         }
         Ok(result)
     }
 
+    #[allow(clippy::only_used_in_recursion)]
     fn eval_expr_agg(
         &self,
         expr: &Expr,
         all_rows: &[Vec<ScalarValue>],
         group_indices: &[usize],
         cols: &[Col],
-        group_exprs: &[Expr],
+        rolled_up: &[Expr],
+        ctx: &ExecCtx,
     ) -> Result<ScalarValue> {
+        // A key its grouping set rolls up reads as NULL in that set's rows.
+        if !rolled_up.is_empty() && !expr_has_aggregate(expr)
+            && rolled_up.iter().any(|k| same_group_key(k, expr, cols, self.fold())) {
+            return Ok(ScalarValue::Null);
+        }
         match expr {
-            Expr::Function { name, args, distinct, over: None } => {
+            Expr::Function { name, args, distinct, within_group, filter, over: None } => {
                 let agg_name = name.to_uppercase();
+                let filtered_indices: Vec<usize>;
+                let group_indices = match filter {
+                    Some(predicate) if is_aggregate_call(expr) => {
+                        let mut kept = Vec::new();
+                        for &idx in group_indices {
+                            if is_truthy(&eval_expr(predicate, &all_rows[idx], cols, &self.eval_ctx(ctx))?) {
+                                kept.push(idx);
+                            }
+                        }
+                        filtered_indices = kept;
+                        &filtered_indices
+                    }
+                    _ => group_indices,
+                };
+                // DISTINCT keeps the first row for each non-NULL argument value,
+                // so NULLs are excluded just as in the plain aggregates.
+                let distinct_indices: Vec<usize>;
+                let group_indices = if *distinct && !args.is_empty() && is_aggregate_call(expr) {
+                    let mut seen: HashSet<HashableScalar> = HashSet::new();
+                    let mut kept = Vec::new();
+                    for &idx in group_indices {
+                        let v = eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))?;
+                        if !matches!(v, ScalarValue::Null)
+                            && seen.insert(HashableScalar(collate_value(v, expr_collation(&args[0])))) {
+                            kept.push(idx);
+                        }
+                    }
+                    distinct_indices = kept;
+                    &distinct_indices
+                } else { group_indices };
+                // STRING_AGG(x, ',' ORDER BY y) and ARRAY_AGG(x ORDER BY y)
+                // combine their inputs in that order.
+                let ordered_indices: Vec<usize>;
+                let group_indices = if !within_group.is_empty()
+                    && matches!(agg_name.as_str(), "STRING_AGG" | "GROUP_CONCAT" | "LISTAGG" | "ARRAY_AGG")
+                {
+                    let mut keyed = group_indices.iter()
+                        .map(|&idx| {
+                            let keys = within_group.iter()
+                                .map(|o| eval_expr(&o.expr, &all_rows[idx], cols, &self.eval_ctx(ctx)))
+                                .collect::<Result<Vec<_>>>()?;
+                            Ok((idx, keys))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    keyed.sort_by(|(_, a), (_, b)| order_keys_cmp(within_group, a, b));
+                    ordered_indices = keyed.into_iter().map(|(idx, _)| idx).collect();
+                    &ordered_indices
+                } else { group_indices };
                 match agg_name.as_str() {
                     "COUNT" => {
-                        if args.len() == 1 && matches!(&args[0], Expr::Wildcard) {
-                            return Ok(ScalarValue::Int64(group_indices.len() as i64));
+                        match args.as_slice() {
+                            [Expr::Wildcard] => return Ok(ScalarValue::Int64(group_indices.len() as i64)),
+                            [Expr::QualifiedWildcard(tname)] => {
+                                table_columns(cols, tname, self.fold())?;
+                                return Ok(ScalarValue::Int64(group_indices.len() as i64));
+                            }
+                            _ => {}
                         }
                         let mut n = 0i64;
                         for &idx in group_indices {
-                            let v = eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())?;
+                            let v = eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))?;
                             if !matches!(v, ScalarValue::Null) {
-                                if *distinct {
-                                    // Simplified: count all
-                                }
                                 n += 1;
                             }
                         }
@@ -513,13 +1724,13 @@ impl SqlEngine {
                         let mut is_float = false;
                         let mut has = false;
                         for &idx in group_indices {
-                            match eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())? {
+                            match eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))? {
                                 ScalarValue::Int64(i) => { total_i += i; has = true; }
                                 ScalarValue::Float64(f) => { total_f += f; is_float = true; has = true; }
                                 _ => {}
                             }
                         }
-                        if !has { Ok(ScalarValue::Null) }
+                        if !has { Ok(self.empty_aggregate(ScalarValue::Int64(0))) }
                         else if is_float { Ok(ScalarValue::Float64(total_f + total_i as f64)) }
                         else { Ok(ScalarValue::Int64(total_i)) }
                     }
@@ -527,98 +1738,153 @@ impl SqlEngine {
                         let mut total = 0.0f64;
                         let mut n = 0i64;
                         for &idx in group_indices {
-                            match eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())? {
+                            match eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))? {
                                 ScalarValue::Int64(i) => { total += i as f64; n += 1; }
                                 ScalarValue::Float64(f) => { total += f; n += 1; }
                                 _ => {}
                             }
                         }
-                        if n == 0 { Ok(ScalarValue::Null) }
+                        if n == 0 { Ok(self.empty_aggregate(ScalarValue::Float64(0.0))) }
                         else { Ok(ScalarValue::Float64(total / n as f64)) }
                     }
                     "MIN" => {
+                        let collation = expr_collation(&args[0]);
                         let mut best: Option<ScalarValue> = None;
                         for &idx in group_indices {
-                            let v = eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())?;
+                            let v = eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))?;
                             if matches!(v, ScalarValue::Null) { continue; }
                             best = Some(match best {
                                 None => v,
-                                Some(cur) => if scalar_cmp(&v, &cur) == std::cmp::Ordering::Less { v } else { cur },
+                                Some(cur) => if collated_cmp(&v, &cur, collation) == std::cmp::Ordering::Less { v } else { cur },
                             });
                         }
                         Ok(best.unwrap_or(ScalarValue::Null))
                     }
                     "MAX" => {
+                        let collation = expr_collation(&args[0]);
                         let mut best: Option<ScalarValue> = None;
                         for &idx in group_indices {
-                            let v = eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())?;
+                            let v = eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))?;
                             if matches!(v, ScalarValue::Null) { continue; }
                             best = Some(match best {
                                 None => v,
-                                Some(cur) => if scalar_cmp(&v, &cur) == std::cmp::Ordering::Greater { v } else { cur },
+                                Some(cur) => if collated_cmp(&v, &cur, collation) == std::cmp::Ordering::Greater { v } else { cur },
                             });
                         }
                         Ok(best.unwrap_or(ScalarValue::Null))
                     }
                     "STRING_AGG" | "GROUP_CONCAT" | "LISTAGG" => {
                         let sep = if args.len() > 1 {
-                            match eval_expr(&args[1], &all_rows.get(0).map(|r| r.as_slice()).unwrap_or(&[]),
-                                           cols, None, &HashMap::new())? {
+                            match eval_expr(&args[1], all_rows.first().map(|r| r.as_slice()).unwrap_or(&[]),
+                                           cols, &self.eval_ctx(ctx))? {
                                 ScalarValue::Utf8(s) => s,
                                 _ => ",".to_string(),
                             }
                         } else { ",".to_string() };
                         let mut parts: Vec<String> = Vec::new();
                         for &idx in group_indices {
-                            let v = eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())?;
+                            let v = eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))?;
                             if !matches!(v, ScalarValue::Null) {
                                 parts.push(format!("{}", v));
                             }
                         }
+                        if parts.is_empty() { return Ok(self.empty_aggregate(ScalarValue::Utf8(String::new()))); }
                         Ok(ScalarValue::Utf8(parts.join(&sep)))
                     }
+                    "GROUPING" => {
+                        // Bit mask over the arguments, first argument highest:
+                        // a bit is set when that key is rolled up in this row.
+                        if args.is_empty() {
+                            return Err(PivotError::SqlError("GROUPING requires at least one argument".to_string()));
+                        }
+                        let mut bits = 0i64;
+                        for arg in args {
+                            let rolled = rolled_up.iter().any(|k| same_group_key(k, arg, cols, self.fold()));
+                            bits = (bits << 1) | rolled as i64;
+                        }
+                        Ok(ScalarValue::Int64(bits))
+                    }
+                    "ANY_VALUE" => {
+                        for &idx in group_indices {
+                            let v = eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))?;
+                            if !matches!(v, ScalarValue::Null) { return Ok(v); }
+                        }
+                        Ok(ScalarValue::Null)
+                    }
                     "ARRAY_AGG" => {
-                        let mut parts: Vec<String> = Vec::new();
+                        let mut values = Vec::with_capacity(group_indices.len());
                         for &idx in group_indices {
-                            let v = eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())?;
-                            parts.push(format!("{}", v));
+                            values.push(eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))?);
                         }
-                        Ok(ScalarValue::Utf8(format!("[{}]", parts.join(", "))))
+                        Ok(ScalarValue::Array(values))
                     }
                     "STDDEV" | "STDEV" | "STDDEV_SAMP" | "STDDEV_POP" => {
                         let mut vals: Vec<f64> = Vec::new();
                         for &idx in group_indices {
-                            match eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())? {
+                            match eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))? {
                                 ScalarValue::Int64(i) => vals.push(i as f64),
                                 ScalarValue::Float64(f) => vals.push(f),
                                 _ => {}
                             }
                         }
-                        if vals.is_empty() { return Ok(ScalarValue::Null); }
+                        if vals.is_empty() { return Ok(self.empty_aggregate(ScalarValue::Float64(0.0))); }
                         let mean = vals.iter().sum::<f64>() / vals.len() as f64;
                         let var = vals.iter().map(|v| (v - mean).powi(2)).sum::<f64>()
                             / (if agg_name == "STDDEV_POP" { vals.len() } else { vals.len().max(2) - 1 }) as f64;
                         Ok(ScalarValue::Float64(var.sqrt()))
                     }
+                    "PERCENTILE_CONT" | "PERCENTILE_DISC" | "MEDIAN" => {
+                        // MEDIAN(x) is PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY x)
+                        let (fraction, key, ascending) = if agg_name == "MEDIAN" {
+                            let arg = args.first().ok_or_else(|| PivotError::SqlError(
+                                "MEDIAN requires an argument".to_string()))?;
+                            (0.5, arg, true)
+                        } else {
+                            let first = all_rows.first().map(|r| r.as_slice()).unwrap_or(&[]);
+                            let fraction = match args.first().map(|a| eval_expr(a, first, cols, &self.eval_ctx(ctx))).transpose()? {
+                                Some(ScalarValue::Float64(f)) => f,
+                                Some(ScalarValue::Int64(i)) => i as f64,
+                                _ => return Err(PivotError::SqlError(format!(
+                                    "{} requires a numeric fraction argument", agg_name))),
+                            };
+                            let ob = within_group.first().ok_or_else(|| PivotError::SqlError(format!(
+                                "{} requires WITHIN GROUP (ORDER BY ...)", agg_name)))?;
+                            (fraction, &ob.expr, ob.ascending)
+                        };
+                        let mut values = Vec::new();
+                        for &idx in group_indices {
+                            let v = eval_expr(key, &all_rows[idx], cols, &self.eval_ctx(ctx))?;
+                            if !matches!(v, ScalarValue::Null) { values.push(v); }
+                        }
+                        percentile_value(values, fraction, agg_name != "PERCENTILE_DISC", ascending)
+                    }
                     "VARIANCE" | "VAR_SAMP" | "VAR_POP" => {
                         let mut vals: Vec<f64> = Vec::new();
                         for &idx in group_indices {
-                            match eval_expr(&args[0], &all_rows[idx], cols, None, &HashMap::new())? {
+                            match eval_expr(&args[0], &all_rows[idx], cols, &self.eval_ctx(ctx))? {
                                 ScalarValue::Int64(i) => vals.push(i as f64),
                                 ScalarValue::Float64(f) => vals.push(f),
                                 _ => {}
                             }
                         }
-                        if vals.is_empty() { return Ok(ScalarValue::Null); }
+                        if vals.is_empty() { return Ok(self.empty_aggregate(ScalarValue::Float64(0.0))); }
                         let mean = vals.iter().sum::<f64>() / vals.len() as f64;
                         let var = vals.iter().map(|v| (v - mean).powi(2)).sum::<f64>()
                             / (vals.len().max(2) - 1) as f64;
                         Ok(ScalarValue::Float64(var))
                     }
+                    _ if expr_has_aggregate(expr) => {
+                        // A scalar function over aggregates, as in ROUND(SUM(x), 2):
+                        // evaluate each argument over the group, then the function once.
+                        let values = args.iter()
+                            .map(|a| self.eval_expr_agg(a, all_rows, group_indices, cols, rolled_up, ctx))
+                            .collect::<Result<Vec<_>>>()?;
+                        call_scalar_function(&agg_name, values, &self.eval_ctx(ctx))
+                    }
                     _ => {
                         // Not an aggregate - evaluate against first row of group
                         if let Some(&first_idx) = group_indices.first() {
-                            eval_expr(expr, &all_rows[first_idx], cols, None, &HashMap::new())
+                            eval_expr(expr, &all_rows[first_idx], cols, &self.eval_ctx(ctx))
                         } else {
                             Ok(ScalarValue::Null)
                         }
@@ -626,32 +1892,36 @@ impl SqlEngine {
                 }
             }
             Expr::BinaryOp { left, op, right } => {
-                let l = self.eval_expr_agg(left, all_rows, group_indices, cols, group_exprs)?;
-                let r = self.eval_expr_agg(right, all_rows, group_indices, cols, group_exprs)?;
-                eval_binary_op(op, l, r)
+                let l = self.eval_expr_agg(left, all_rows, group_indices, cols, rolled_up, ctx)?;
+                let r = self.eval_expr_agg(right, all_rows, group_indices, cols, rolled_up, ctx)?;
+                self.binary_op(op, l, r)
             }
             Expr::UnaryOp { op, expr: inner } => {
-                let v = self.eval_expr_agg(inner, all_rows, group_indices, cols, group_exprs)?;
+                let v = self.eval_expr_agg(inner, all_rows, group_indices, cols, rolled_up, ctx)?;
                 eval_unary_op(op, v)
             }
+            Expr::Row(items) => items.iter()
+                .map(|item| self.eval_expr_agg(item, all_rows, group_indices, cols, rolled_up, ctx))
+                .collect::<Result<Vec<_>>>()
+                .map(ScalarValue::Row),
             Expr::Cast { expr: inner, data_type } => {
-                let v = self.eval_expr_agg(inner, all_rows, group_indices, cols, group_exprs)?;
-                Ok(cast::cast_value(v, data_type))
+                let v = self.eval_expr_agg(inner, all_rows, group_indices, cols, rolled_up, ctx)?;
+                if self.strict { cast::cast_value_strict(v, data_type) }
+                else { Ok(cast::cast_value(v, data_type)) }
             }
-            Expr::Case { operand, when_clauses, else_clause } => {
-                // Use first row for case evaluation
-                if let Some(&first_idx) = group_indices.first() {
-                    eval_expr(expr, &all_rows[first_idx], cols, None, &HashMap::new())
-                } else {
-                    Ok(ScalarValue::Null)
-                }
+            _ if expr_has_aggregate(expr) => {
+                // Aggregates inside anything else, as in SUM(x) IS NULL: compute
+                // each over the group, then the rest on the group's row.
+                let mut agg_cols = cols.to_vec();
+                let (expr, aggs) = extract_exprs(expr, &is_aggregate_call, &mut agg_cols);
+                let row = self.window_group_row(&aggs, all_rows, group_indices, cols, rolled_up, ctx)?;
+                eval_expr(&expr, &row, &agg_cols, &self.eval_ctx(ctx))
             }
             // For non-aggregate expressions, evaluate against first row in group
             _ => {
-                if let Some(&first_idx) = group_indices.first() {
-                    eval_expr(expr, &all_rows[first_idx], cols, None, &HashMap::new())
-                } else {
-                    Ok(ScalarValue::Null)
+                match self.group_row(all_rows, group_indices, cols, rolled_up) {
+                    Some(row) => eval_expr(expr, &row, cols, &self.eval_ctx(ctx)),
+                    None => Ok(ScalarValue::Null),
                 }
             }
         }
@@ -659,15 +1929,16 @@ impl SqlEngine {
 
     // ─── HAVING ───────────────────────────────────────────────────────────────
 
-    fn apply_having(&self, rs: RowSet, having: Option<&Expr>) -> Result<RowSet> {
+    fn apply_having(&self, rs: RowSet, having: Option<&Expr>, ctx: &ExecCtx) -> Result<RowSet> {
         let expr = match having {
             None => return Ok(rs),
             Some(e) => e,
         };
         let mut result = RowSet::new(rs.cols.clone());
         for row in &rs.rows {
-            let v = eval_expr(expr, row, &rs.cols, None, &HashMap::new())?;
+            let v = eval_expr(expr, row, &rs.cols, &self.eval_ctx(ctx))?;
             if is_truthy(&v) {
+                self.record(|m| m.rows_cloned += 1);
                 result.rows.push(row.clone());
             }
         }
@@ -678,13 +1949,20 @@ impl SqlEngine {
 
     fn project_select(
         &self,
-        rs: RowSet,
+        rs: &RowSet,
         items: &[SelectItem],
         distinct: bool,
+        ctx: &ExecCtx,
     ) -> Result<RowSet> {
         // Determine output columns
         let mut out_cols: Vec<Col> = Vec::new();
         let mut out_item_indices: Vec<(usize, Option<String>)> = Vec::new(); // (item_idx, alias)
+        let fold = self.fold();
+        for item in items {
+            if let SelectItem::TableWildcard(tname) = item {
+                table_columns(&rs.cols, tname, fold)?;
+            }
+        }
 
         for (item_idx, item) in items.iter().enumerate() {
             match item {
@@ -696,7 +1974,7 @@ impl SqlEngine {
                 }
                 SelectItem::TableWildcard(tname) => {
                     for (ci, col) in rs.cols.iter().enumerate() {
-                        if col.table.as_deref().map(|t| t.eq_ignore_ascii_case(tname)).unwrap_or(false) {
+                        if col_in_table(col, tname, fold) {
                             out_cols.push(col.clone());
                             out_item_indices.push((item_idx * 1000 + ci, None));
                         }
@@ -714,8 +1992,6 @@ impl SqlEngine {
         let mut result_rows: Vec<Vec<ScalarValue>> = Vec::new();
         for row in &rs.rows {
             let mut out_row = Vec::new();
-            let mut item_idx_iter = items.iter().enumerate().peekable();
-            let mut col_out_idx = 0;
 
             for item in items {
                 match item {
@@ -726,13 +2002,13 @@ impl SqlEngine {
                     }
                     SelectItem::TableWildcard(tname) => {
                         for (ci, col) in rs.cols.iter().enumerate() {
-                            if col.table.as_deref().map(|t| t.eq_ignore_ascii_case(tname)).unwrap_or(false) {
+                            if col_in_table(col, tname, fold) {
                                 out_row.push(row[ci].clone());
                             }
                         }
                     }
                     SelectItem::Expr { expr, .. } => {
-                        let v = eval_expr(expr, row, &rs.cols, None, &HashMap::new())?;
+                        let v = eval_expr(expr, row, &rs.cols, &self.eval_ctx(ctx))?;
                         out_row.push(v);
                     }
                 }
@@ -747,8 +2023,6 @@ impl SqlEngine {
 
         // Rebuild out_cols properly
         let mut proper_cols: Vec<Col> = Vec::new();
-        let dummy_row: Vec<ScalarValue> = rs.cols.iter().map(|_| ScalarValue::Null).collect();
-        let sample_row = rs.rows.first().unwrap_or(&dummy_row);
 
         for item in items {
             match item {
@@ -759,14 +2033,14 @@ impl SqlEngine {
                 }
                 SelectItem::TableWildcard(tname) => {
                     for col in &rs.cols {
-                        if col.table.as_deref().map(|t| t.eq_ignore_ascii_case(tname)).unwrap_or(false) {
+                        if col_in_table(col, tname, fold) {
                             proper_cols.push(col.clone());
                         }
                     }
                 }
                 SelectItem::Expr { expr, alias } => {
                     let name = alias.clone().unwrap_or_else(|| expr_display_name(expr));
-                    proper_cols.push(Col { table: None, name, dtype: DataType::Utf8 });
+                    proper_cols.push(Col { table: None, name, dtype: projected_type(expr, &rs.cols, fold) });
                 }
             }
         }
@@ -782,7 +2056,9 @@ impl SqlEngine {
 
     // ─── Window functions ─────────────────────────────────────────────────────
 
-    fn apply_window_funcs(&self, mut rs: RowSet, items: &[SelectItem]) -> Result<RowSet> {
+    /// `source`, when given, is the projection's input row for row; window
+    /// expressions see its columns after the projected ones.
+    fn apply_window_funcs(&self, mut rs: RowSet, items: &[SelectItem], source: Option<&RowSet>, ctx: &ExecCtx) -> Result<RowSet> {
         // Find window function columns by index in the result
         let mut window_col_indices: Vec<(usize, Expr)> = Vec::new();
         let mut col_idx = 0;
@@ -813,75 +2089,90 @@ impl SqlEngine {
         // We need to recompute window function values
         // The rs currently has placeholder values; we'll overwrite them
         // But first we need the "input" to window functions - which is rs itself
-        let n_rows = rs.rows.len();
 
-        for (col_idx, expr) in &window_col_indices {
-            let values = self.compute_window_col(&rs, expr)?;
+        let input = window_input(&rs, source);
+        let columns = window_col_indices.iter()
+            .map(|(col_idx, expr)| Ok((*col_idx, self.compute_window_col(input.as_ref().unwrap_or(&rs), expr, ctx)?)))
+            .collect::<Result<Vec<_>>>()?;
+        for (col_idx, values) in columns {
             for (row_idx, val) in values.into_iter().enumerate() {
-                if row_idx < rs.rows.len() && *col_idx < rs.rows[row_idx].len() {
-                    rs.rows[row_idx][*col_idx] = val;
+                if row_idx < rs.rows.len() && col_idx < rs.rows[row_idx].len() {
+                    rs.rows[row_idx][col_idx] = val;
                 }
             }
         }
         Ok(rs)
     }
 
-    fn compute_window_col(&self, rs: &RowSet, expr: &Expr) -> Result<Vec<ScalarValue>> {
+    fn compute_window_col(&self, rs: &RowSet, expr: &Expr, ctx: &ExecCtx) -> Result<Vec<ScalarValue>> {
         match expr {
-            Expr::Function { name, args, over: Some(spec), .. } => {
-                self.compute_window_func(name, args, spec, rs)
+            Expr::Function { over: Some(spec), .. } => {
+                self.compute_window_func(expr, spec, rs, ctx)
             }
             Expr::BinaryOp { left, op, right } => {
-                let left_vals = self.compute_window_col(rs, left)?;
-                let right_vals = self.compute_window_col(rs, right)?;
-                left_vals.into_iter().zip(right_vals).map(|(l, r)| eval_binary_op(op, l, r)).collect()
+                let left_vals = self.compute_window_col(rs, left, ctx)?;
+                let right_vals = self.compute_window_col(rs, right, ctx)?;
+                left_vals.into_iter().zip(right_vals).map(|(l, r)| self.binary_op(op, l, r)).collect()
+            }
+            _ if expr_has_window(expr) => {
+                // Window calls inside anything else, as in CAST(RANK() OVER w
+                // AS VARCHAR): compute each call's column, then the rest per row.
+                let mut cols = rs.cols.clone();
+                let (expr, calls) = extract_exprs(expr, &|e| matches!(e, Expr::Function { over: Some(_), .. }), &mut cols);
+                let values = calls.iter()
+                    .map(|call| self.compute_window_col(rs, call, ctx))
+                    .collect::<Result<Vec<_>>>()?;
+                rs.rows.iter().enumerate().map(|(i, row)| {
+                    let mut row = row.clone();
+                    row.extend(values.iter().map(|v| v[i].clone()));
+                    eval_expr(&expr, &row, &cols, &self.eval_ctx(ctx))
+                }).collect()
             }
             _ => {
                 // Not a window function - evaluate normally
                 rs.rows.iter().map(|row| {
-                    eval_expr(expr, row, &rs.cols, None, &HashMap::new())
+                    eval_expr(expr, row, &rs.cols, &self.eval_ctx(ctx))
                 }).collect()
             }
         }
     }
 
-    fn compute_window_func(
-        &self,
-        func_name: &str,
-        args: &[Expr],
-        spec: &WindowSpec,
-        rs: &RowSet,
-    ) -> Result<Vec<ScalarValue>> {
+    /// Evaluate window function call `func` over `spec` for every row of `rs`.
+    fn compute_window_func(&self, func: &Expr, spec: &WindowSpec, rs: &RowSet, ctx: &ExecCtx) -> Result<Vec<ScalarValue>> {
+        let Expr::Function { name: func_name, args, distinct, within_group, filter, .. } = func else {
+            return Err(PivotError::SqlError(format!("Expected a window function, got {:?}", func)));
+        };
+        if let Some(name) = &spec.name {
+            return Err(PivotError::SqlError(format!("Window '{}' is not defined", name)));
+        }
         let n = rs.rows.len();
         if n == 0 { return Ok(Vec::new()); }
 
         let fname = func_name.to_uppercase();
 
         // Get partition key for each row
-        let partition_keys: Vec<Vec<String>> = rs.rows.iter().map(|row| {
-            spec.partition_by.iter().map(|e| {
-                eval_expr(e, row, &rs.cols, None, &HashMap::new())
-                    .ok()
-                    .map(|v| scalar_to_key(&v))
-                    .unwrap_or_default()
-            }).collect()
-        }).collect();
-
-        // Get ORDER BY sort key for each row
+        let partition_keys: Vec<Vec<HashableScalar>> = rs.rows.iter().map(|row| {
+            spec.partition_by.iter()
+                .map(|e| eval_expr(e, row, &rs.cols, &self.eval_ctx(ctx)).map(HashableScalar))
+                .collect::<Result<Vec<_>>>()
+        }).collect::<Result<_>>()?;
+
+        // Get ORDER BY sort key for each row, up front so errors surface
+        // before sorting.
         let order_keys: Vec<Vec<ScalarValue>> = rs.rows.iter().map(|row| {
-            spec.order_by.iter().map(|ob| {
-                eval_expr(&ob.expr, row, &rs.cols, None, &HashMap::new())
-                    .unwrap_or(ScalarValue::Null)
-            }).collect()
-        }).collect();
+            spec.order_by.iter()
+                .map(|ob| eval_expr(&ob.expr, row, &rs.cols, &self.eval_ctx(ctx)))
+                .collect::<Result<Vec<_>>>()
+        }).collect::<Result<_>>()?;
 
+        let bounds = self.frame_bounds(spec, ctx)?;
         let mut result = vec![ScalarValue::Null; n];
 
         // Group rows by partition
-        let mut partitions: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
-        let mut part_order: Vec<Vec<String>> = Vec::new();
+        let mut partitions: HashMap<Vec<HashableScalar>, Vec<usize>> = HashMap::new();
+        let mut part_order: Vec<Vec<HashableScalar>> = Vec::new();
         for (i, key) in partition_keys.iter().enumerate() {
-            let entry = partitions.entry(key.clone()).or_insert_with(Vec::new);
+            let entry = partitions.entry(key.clone()).or_default();
             if entry.is_empty() { part_order.push(key.clone()); }
             entry.push(i);
         }
@@ -891,17 +2182,15 @@ impl SqlEngine {
 
             // Sort partition by order_by
             let mut sorted: Vec<usize> = part_indices.clone();
-            sorted.sort_by(|&a, &b| {
-                for (i, ob) in spec.order_by.iter().enumerate() {
-                    let va = order_keys[a].get(i).cloned().unwrap_or(ScalarValue::Null);
-                    let vb = order_keys[b].get(i).cloned().unwrap_or(ScalarValue::Null);
-                    let ord = scalar_cmp(&va, &vb);
-                    if ord != std::cmp::Ordering::Equal {
-                        return if ob.ascending { ord } else { ord.reverse() };
-                    }
-                }
-                std::cmp::Ordering::Equal
-            });
+            let cmp_keys = |a: &usize, b: &usize| order_keys_cmp(&spec.order_by, &order_keys[*a], &order_keys[*b]);
+            if self.stable_window_order {
+                sorted.sort_by(|a, b| cmp_keys(a, b).then(a.cmp(b)));
+            } else {
+                sorted.sort_unstable_by(cmp_keys);
+            }
+            let peer_groups = if matches!(bounds.kind, WindowFrameKind::Groups) {
+                peer_group_ids(&sorted, &order_keys)
+            } else { Vec::new() };
 
             match fname.as_str() {
                 "ROW_NUMBER" => {
@@ -911,12 +2200,11 @@ impl SqlEngine {
                 }
                 "RANK" => {
                     let mut rank = 1usize;
-                    let mut prev_key: Option<Vec<String>> = None;
-                    let mut prev_start = 1usize;
+                    let mut prev_key: Option<Vec<HashableScalar>> = None;
                     for (i, &idx) in sorted.iter().enumerate() {
-                        let cur_key: Vec<String> = spec.order_by.iter().enumerate()
+                        let cur_key: Vec<HashableScalar> = spec.order_by.iter().enumerate()
                             .map(|(ki, _)| order_keys[idx].get(ki)
-                                .map(|v| scalar_to_key(v)).unwrap_or_default())
+                                .cloned().map(HashableScalar).unwrap_or(HashableScalar(ScalarValue::Null)))
                             .collect();
                         if Some(&cur_key) != prev_key.as_ref() {
                             rank = i + 1;
@@ -927,11 +2215,11 @@ impl SqlEngine {
                 }
                 "DENSE_RANK" => {
                     let mut rank = 0usize;
-                    let mut prev_key: Option<Vec<String>> = None;
+                    let mut prev_key: Option<Vec<HashableScalar>> = None;
                     for &idx in &sorted {
-                        let cur_key: Vec<String> = spec.order_by.iter().enumerate()
+                        let cur_key: Vec<HashableScalar> = spec.order_by.iter().enumerate()
                             .map(|(ki, _)| order_keys[idx].get(ki)
-                                .map(|v| scalar_to_key(v)).unwrap_or_default())
+                                .cloned().map(HashableScalar).unwrap_or(HashableScalar(ScalarValue::Null)))
                             .collect();
                         if Some(&cur_key) != prev_key.as_ref() {
                             rank += 1;
@@ -942,25 +2230,31 @@ impl SqlEngine {
                 }
                 "NTILE" => {
                     let n_buckets = if !args.is_empty() {
-                        match eval_expr(&args[0], &rs.rows[sorted[0]], &rs.cols, None, &HashMap::new()) {
-                            Ok(ScalarValue::Int64(n)) => n as usize,
+                        match eval_expr(&args[0], &rs.rows[sorted[0]], &rs.cols, &self.eval_ctx(ctx))? {
+                            ScalarValue::Int64(n) if n > 0 => n as usize,
+                            ScalarValue::Int64(n) => return Err(PivotError::SqlError(format!(
+                                "NTILE bucket count must be positive, got {}", n
+                            ))),
                             _ => 1,
                         }
                     } else { 1 };
-                    let part_size = sorted.len();
+                    // The first `extra` buckets hold one row more than the rest.
+                    let size = sorted.len() / n_buckets;
+                    let extra = sorted.len() % n_buckets;
+                    let large = extra * (size + 1);
                     for (i, &idx) in sorted.iter().enumerate() {
-                        let bucket = (i * n_buckets / part_size.max(1)) + 1;
-                        result[idx] = ScalarValue::Int64(bucket as i64);
+                        let bucket = if i < large { i / (size + 1) } else { extra + (i - large) / size };
+                        result[idx] = ScalarValue::Int64(bucket as i64 + 1);
                     }
                 }
                 "PERCENT_RANK" => {
                     let n_part = sorted.len();
                     let mut rank = 0usize;
-                    let mut prev_key: Option<Vec<String>> = None;
+                    let mut prev_key: Option<Vec<HashableScalar>> = None;
                     for (i, &idx) in sorted.iter().enumerate() {
-                        let cur_key: Vec<String> = spec.order_by.iter().enumerate()
+                        let cur_key: Vec<HashableScalar> = spec.order_by.iter().enumerate()
                             .map(|(ki, _)| order_keys[idx].get(ki)
-                                .map(|v| scalar_to_key(v)).unwrap_or_default())
+                                .cloned().map(HashableScalar).unwrap_or(HashableScalar(ScalarValue::Null)))
                             .collect();
                         if Some(&cur_key) != prev_key.as_ref() {
                             rank = i;
@@ -972,18 +2266,17 @@ impl SqlEngine {
                 }
                 "CUME_DIST" => {
                     let n_part = sorted.len();
-                    let mut pos_end = 0usize;
                     let mut i = 0;
                     while i < sorted.len() {
-                        let cur_key: Vec<String> = spec.order_by.iter().enumerate()
+                        let cur_key: Vec<HashableScalar> = spec.order_by.iter().enumerate()
                             .map(|(ki, _)| order_keys[sorted[i]].get(ki)
-                                .map(|v| scalar_to_key(v)).unwrap_or_default())
+                                .cloned().map(HashableScalar).unwrap_or(HashableScalar(ScalarValue::Null)))
                             .collect();
                         let mut j = i + 1;
                         while j < sorted.len() {
-                            let next_key: Vec<String> = spec.order_by.iter().enumerate()
+                            let next_key: Vec<HashableScalar> = spec.order_by.iter().enumerate()
                                 .map(|(ki, _)| order_keys[sorted[j]].get(ki)
-                                    .map(|v| scalar_to_key(v)).unwrap_or_default())
+                                    .cloned().map(HashableScalar).unwrap_or(HashableScalar(ScalarValue::Null)))
                                 .collect();
                             if next_key == cur_key { j += 1; } else { break; }
                         }
@@ -996,14 +2289,13 @@ impl SqlEngine {
                 }
                 "LAG" | "LEAD" => {
                     let offset = if args.len() > 1 {
-                        match eval_expr(&args[1], &rs.rows[sorted[0]], &rs.cols, None, &HashMap::new()) {
-                            Ok(ScalarValue::Int64(n)) => n as usize,
+                        match eval_expr(&args[1], &rs.rows[sorted[0]], &rs.cols, &self.eval_ctx(ctx))? {
+                            ScalarValue::Int64(n) => n as usize,
                             _ => 1,
                         }
                     } else { 1 };
                     let default = if args.len() > 2 {
-                        eval_expr(&args[2], &rs.rows[sorted[0]], &rs.cols, None, &HashMap::new())
-                            .unwrap_or(ScalarValue::Null)
+                        eval_expr(&args[2], &rs.rows[sorted[0]], &rs.cols, &self.eval_ctx(ctx))?
                     } else { ScalarValue::Null };
 
                     for (i, &idx) in sorted.iter().enumerate() {
@@ -1015,25 +2307,27 @@ impl SqlEngine {
                         result[idx] = if let Some(src_idx) = source_i {
                             if args.is_empty() { ScalarValue::Null }
                             else {
-                                eval_expr(&args[0], &rs.rows[src_idx], &rs.cols, None, &HashMap::new())
-                                    .unwrap_or(ScalarValue::Null)
+                                eval_expr(&args[0], &rs.rows[src_idx], &rs.cols, &self.eval_ctx(ctx))?
                             }
                         } else { default.clone() };
                     }
                 }
                 "FIRST_VALUE" | "LAST_VALUE" => {
-                    let target_idx = if fname == "FIRST_VALUE" { sorted[0] } else { *sorted.last().unwrap() };
-                    let val = if args.is_empty() { ScalarValue::Null }
-                        else { eval_expr(&args[0], &rs.rows[target_idx], &rs.cols, None, &HashMap::new())
-                            .unwrap_or(ScalarValue::Null) };
-                    for &idx in &sorted {
-                        result[idx] = val.clone();
+                    // The first or last row of each row's frame; under the
+                    // default frame LAST_VALUE is the current row's last peer.
+                    for (pos, &idx) in sorted.iter().enumerate() {
+                        let frame = window_frame_rows(spec, &bounds, &sorted, &peer_groups, pos, &order_keys)?;
+                        let target = if fname == "FIRST_VALUE" { frame.first() } else { frame.last() };
+                        result[idx] = match (target, args.first()) {
+                            (Some(&t), Some(arg)) => eval_expr(arg, &rs.rows[t], &rs.cols, &self.eval_ctx(ctx))?,
+                            _ => ScalarValue::Null,
+                        };
                     }
                 }
                 "NTH_VALUE" => {
                     let n_arg = if args.len() > 1 {
-                        match eval_expr(&args[1], &rs.rows[sorted[0]], &rs.cols, None, &HashMap::new()) {
-                            Ok(ScalarValue::Int64(n)) => n as usize,
+                        match eval_expr(&args[1], &rs.rows[sorted[0]], &rs.cols, &self.eval_ctx(ctx))? {
+                            ScalarValue::Int64(n) => n as usize,
                             _ => 1,
                         }
                     } else { 1 };
@@ -1041,33 +2335,60 @@ impl SqlEngine {
                     for &idx in &sorted {
                         result[idx] = if let Some(t) = target {
                             if args.is_empty() { ScalarValue::Null }
-                            else { eval_expr(&args[0], &rs.rows[t], &rs.cols, None, &HashMap::new())
-                                .unwrap_or(ScalarValue::Null) }
+                            else { eval_expr(&args[0], &rs.rows[t], &rs.cols, &self.eval_ctx(ctx))? }
                         } else { ScalarValue::Null };
                     }
                 }
-                // Aggregate window functions (SUM, AVG, etc. over window)
+                "PERCENTILE_CONT" | "PERCENTILE_DISC" => {
+                    let fraction = match args.first()
+                        .map(|a| eval_expr(a, &rs.rows[sorted[0]], &rs.cols, &self.eval_ctx(ctx)))
+                        .transpose()?
+                    {
+                        Some(ScalarValue::Float64(f)) => f,
+                        Some(ScalarValue::Int64(i)) => i as f64,
+                        _ => return Err(PivotError::SqlError(format!(
+                            "{} requires a numeric fraction argument", fname))),
+                    };
+                    let ob = within_group.first().ok_or_else(|| PivotError::SqlError(format!(
+                        "{} requires WITHIN GROUP (ORDER BY ...)", fname)))?;
+                    let mut values: Vec<ScalarValue> = Vec::new();
+                    for &idx in part_indices {
+                        let v = eval_expr(&ob.expr, &rs.rows[idx], &rs.cols, &self.eval_ctx(ctx))?;
+                        if !matches!(v, ScalarValue::Null) { values.push(v); }
+                    }
+                    let val = percentile_value(values, fraction, fname == "PERCENTILE_CONT", ob.ascending)?;
+                    for &idx in part_indices {
+                        result[idx] = val.clone();
+                    }
+                }
+                // Aggregate window functions (SUM, AVG, etc. over window);
+                // DISTINCT applies within each row's frame.
                 "SUM" | "AVG" | "COUNT" | "MIN" | "MAX" => {
-                    for &idx in &sorted {
-                        // Default: entire partition (no frame spec)
-                        let part_row_indices: Vec<usize> = part_indices.clone();
+                    for (pos, &idx) in sorted.iter().enumerate() {
+                        let part_row_indices = window_frame_rows(spec, &bounds, &sorted, &peer_groups, pos, &order_keys)?;
                         let val = self.eval_expr_agg(
                             &Expr::Function {
                                 name: func_name.to_string(),
                                 args: args.to_vec(),
-                                distinct: false,
+                                distinct: *distinct,
+                                within_group: Vec::new(),
+                                filter: filter.clone(),
                                 over: None,
                             },
                             &rs.rows,
                             &part_row_indices,
                             &rs.cols,
                             &[],
+                            ctx,
                         )?;
                         result[idx] = val;
                     }
                 }
                 _ => {
-                    // Unknown window function - return NULL
+                    // Unknown window function: NULL unless strict_functions is set
+                    if self.strict_functions {
+                        return Err(PivotError::SqlError(format!("Unknown window function '{}'", fname)));
+                    }
                     for &idx in &sorted {
                         result[idx] = ScalarValue::Null;
                     }
@@ -1077,54 +2398,161 @@ impl SqlEngine {
         Ok(result)
     }
 
+    /// Evaluate a window's frame offsets. With no explicit frame the window
+    /// is `RANGE BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW`, which without
+    /// ORDER BY covers the whole partition since every row is a peer.
+    fn frame_bounds(&self, spec: &WindowSpec, ctx: &ExecCtx) -> Result<FrameBounds> {
+        let frame = match &spec.frame {
+            None => return Ok(FrameBounds { kind: WindowFrameKind::Range, start: None, end: Some(0.0) }),
+            Some(f) => f,
+        };
+        // ROWS and GROUPS offsets count, so they must be whole numbers.
+        let rows = !matches!(frame.kind, WindowFrameKind::Range);
+        let offset = |e: &Expr, sign: f64| -> Result<f64> {
+            match eval_expr(e, &[], &[], &self.eval_ctx(ctx))? {
+                ScalarValue::Int64(n) if n >= 0 => Ok(sign * n as f64),
+                ScalarValue::Float64(f) if f >= 0.0 && !rows => Ok(sign * f),
+                other => Err(PivotError::SqlError(format!(
+                    "Window frame offset must be a non-negative {}, got {}",
+                    if rows { "integer" } else { "number" }, other
+                ))),
+            }
+        };
+        let bound = |b: &WindowFrameBound| -> Result<Option<f64>> {
+            Ok(match b {
+                WindowFrameBound::UnboundedPreceding | WindowFrameBound::UnboundedFollowing => None,
+                WindowFrameBound::Preceding(e) => Some(offset(e, -1.0)?),
+                WindowFrameBound::CurrentRow => Some(0.0),
+                WindowFrameBound::Following(e) => Some(offset(e, 1.0)?),
+            })
+        };
+        if matches!(frame.start, WindowFrameBound::UnboundedFollowing) {
+            return Err(PivotError::SqlError("Window frame cannot start at UNBOUNDED FOLLOWING".to_string()));
+        }
+        if matches!(frame.end, Some(WindowFrameBound::UnboundedPreceding)) {
+            return Err(PivotError::SqlError("Window frame cannot end at UNBOUNDED PRECEDING".to_string()));
+        }
+        let start = bound(&frame.start)?;
+        let end = match &frame.end { Some(b) => bound(b)?, None => Some(0.0) };
+        let value_offsets = !rows && [start, end].iter().any(|b| b.is_some_and(|d| d != 0.0));
+        if value_offsets && spec.order_by.len() != 1 {
+            return Err(PivotError::SqlError(
+                "RANGE with an offset requires exactly one ORDER BY expression".to_string()
+            ));
+        }
+        Ok(FrameBounds { kind: frame.kind.clone(), start, end })
+    }
+
     // ─── ORDER BY ─────────────────────────────────────────────────────────────
 
-    fn apply_order_by(&self, mut rs: RowSet, items: &[OrderByItem]) -> Result<RowSet> {
+    /// Sort by ORDER BY keys evaluated against the output row. A key naming a
+    /// column the output lacks falls back to `source_keys` (the same key over
+    /// the pre-projection row); evaluation errors fail the query instead of
+    /// sorting as NULL.
+    fn apply_order_by(
+        &self,
+        mut rs: RowSet,
+        items: &[OrderByItem],
+        mut source_keys: Option<SourceKeys>,
+        window_source: Option<&RowSet>,
+        ctx: &ExecCtx,
+    ) -> Result<RowSet> {
         if items.is_empty() { return Ok(rs); }
-        rs.rows.sort_by(|a, b| {
-            for item in items {
-                let va = eval_expr(&item.expr, a, &rs.cols, None, &HashMap::new())
-                    .unwrap_or(ScalarValue::Null);
-                let vb = eval_expr(&item.expr, b, &rs.cols, None, &HashMap::new())
-                    .unwrap_or(ScalarValue::Null);
-                let ord = match (item.nulls_first, &va, &vb) {
-                    (Some(true), ScalarValue::Null, ScalarValue::Null) => std::cmp::Ordering::Equal,
-                    (Some(true), ScalarValue::Null, _) => std::cmp::Ordering::Less,
-                    (Some(true), _, ScalarValue::Null) => std::cmp::Ordering::Greater,
-                    (Some(false), ScalarValue::Null, ScalarValue::Null) => std::cmp::Ordering::Equal,
-                    (Some(false), ScalarValue::Null, _) => std::cmp::Ordering::Greater,
-                    (Some(false), _, ScalarValue::Null) => std::cmp::Ordering::Less,
-                    _ => scalar_cmp(&va, &vb),
+        // An integer literal is the position of a select column, from 1.
+        let positions = items.iter().map(|item| match &item.expr {
+            Expr::Literal(LiteralValue::Integer(n)) => match usize::try_from(*n) {
+                Ok(pos) if (1..=rs.cols.len()).contains(&pos) => Ok(Some(pos - 1)),
+                _ => Err(PivotError::SqlError(format!(
+                    "ORDER BY position {} is not in the select list of {} columns", n, rs.cols.len()
+                ))),
+            },
+            _ => Ok(None),
+        }).collect::<Result<Vec<_>>>()?;
+        // Window keys are computed over the whole result, with the same
+        // input columns as the SELECT list's windows.
+        let window_rows = items.iter().any(|item| expr_has_window(&item.expr))
+            .then(|| window_input(&rs, window_source)).flatten();
+        let mut window_keys: Vec<Option<Vec<ScalarValue>>> = Vec::with_capacity(items.len());
+        for item in items {
+            window_keys.push(if expr_has_window(&item.expr) {
+                Some(self.compute_window_col(window_rows.as_ref().unwrap_or(&rs), &item.expr, ctx)?)
+            } else { None });
+        }
+        let mut keys: Vec<Vec<ScalarValue>> = Vec::with_capacity(rs.rows.len());
+        for (ri, row) in rs.rows.iter().enumerate() {
+            let mut row_keys = Vec::with_capacity(items.len());
+            for (ki, item) in items.iter().enumerate() {
+                if let Some(values) = &window_keys[ki] {
+                    row_keys.push(values[ri].clone());
+                    continue;
+                }
+                if let Some(pos) = positions[ki] {
+                    row_keys.push(row[pos].clone());
+                    continue;
+                }
+                let mut source = || match &mut source_keys {
+                    Some(SourceKeys::Groups(keys)) => keys.get_mut(ri)
+                        .map(|keys| std::mem::replace(&mut keys[ki], Ok(ScalarValue::Null))),
+                    Some(SourceKeys::Rows(input)) => input.rows.get(ri)
+                        .map(|source| eval_expr(&item.expr, source, &input.cols, &self.eval_ctx(ctx))),
+                    None => None,
+                };
+                // Aggregates only mean something over the source rows.
+                let aggregate_key = if expr_has_aggregate(&item.expr) { source() } else { None };
+                let v = match aggregate_key {
+                    Some(key) => key?,
+                    None => match eval_expr(&item.expr, row, &rs.cols, &self.eval_ctx(ctx)) {
+                        Err(PivotError::ColumnNotFound(name)) => match source() {
+                            Some(key) => key?,
+                            None => return Err(PivotError::ColumnNotFound(name)),
+                        },
+                        other => other?,
+                    },
                 };
-                let ord = if item.ascending { ord } else { ord.reverse() };
-                if ord != std::cmp::Ordering::Equal { return ord; }
+                row_keys.push(v);
             }
-            std::cmp::Ordering::Equal
+            keys.push(row_keys);
+        }
+        let mut order: Vec<usize> = (0..rs.rows.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.record(|m| m.sort_comparisons += 1);
+            order_keys_cmp(items, &keys[a], &keys[b])
         });
+        let mut rows: Vec<Option<Vec<ScalarValue>>> = rs.rows.into_iter().map(Some).collect();
+        rs.rows = order.into_iter().map(|i| rows[i].take().unwrap()).collect();
         Ok(rs)
     }
 
     // ─── LIMIT / OFFSET ───────────────────────────────────────────────────────
 
+    /// Evaluate OFFSET (default 0) and LIMIT (default none).
+    fn limit_offset_values(&self, limit: Option<&Expr>, offset: Option<&Expr>, ctx: &ExecCtx) -> Result<(usize, Option<usize>)> {
+        // NULL, and for LIMIT a negative count, means no limit (or no
+        // offset); anything else must be a non-negative integer.
+        let count = |expr: Option<&Expr>, clause: &str| -> Result<Option<usize>> {
+            let Some(expr) = expr else { return Ok(None) };
+            match eval_expr(expr, &[], &[], &self.eval_ctx(ctx))? {
+                ScalarValue::Null => Ok(None),
+                ScalarValue::Int64(n) if n >= 0 => Ok(Some(n as usize)),
+                ScalarValue::Int64(_) if clause == "LIMIT" => Ok(None),
+                other => Err(PivotError::TypeError(format!(
+                    "{} must be a non-negative integer, got {}", clause, other
+                ))),
+            }
+        };
+        let offset_val = count(offset, "OFFSET")?.unwrap_or(0);
+        let limit_val = count(limit, "LIMIT")?;
+        Ok((offset_val, limit_val))
+    }
+
     fn apply_limit_offset(
         &self,
         mut rs: RowSet,
         limit: Option<&Expr>,
         offset: Option<&Expr>,
+        ctx: &ExecCtx,
     ) -> Result<RowSet> {
-        let offset_val = if let Some(off_expr) = offset {
-            match eval_expr(off_expr, &[], &[], None, &HashMap::new())? {
-                ScalarValue::Int64(n) => n as usize,
-                _ => 0,
-            }
-        } else { 0 };
-
-        let limit_val = if let Some(lim_expr) = limit {
-            match eval_expr(lim_expr, &[], &[], None, &HashMap::new())? {
-                ScalarValue::Int64(n) => Some(n as usize),
-                _ => None,
-            }
-        } else { None };
+        let (offset_val, limit_val) = self.limit_offset_values(limit, offset, ctx)?;
 
         if offset_val > 0 || limit_val.is_some() {
             let start = offset_val.min(rs.rows.len());
@@ -1133,6 +2561,7 @@ impl SqlEngine {
             } else {
                 rs.rows.len()
             };
+            self.record(|m| m.rows_cloned += (end - start) as u64);
             rs.rows = rs.rows[start..end].to_vec();
         }
         Ok(rs)
@@ -1140,42 +2569,20 @@ impl SqlEngine {
 
     // ─── SET operations ───────────────────────────────────────────────────────
 
-    fn exec_set_op(&mut self, stmt: &SetOpStatement, ctx: &ExecCtx) -> Result<RowSet> {
-        let left = self.exec_stmt_ctx(&*stmt.left, ctx)?;
-        let right = self.exec_stmt_ctx(&*stmt.right, ctx)?;
-
-        let mut result = RowSet::new(left.cols.clone());
-        match stmt.op {
-            SetOp::Union => {
-                result.rows.extend(left.rows.clone());
-                if stmt.all {
-                    result.rows.extend(right.rows);
-                } else {
-                    for row in right.rows {
-                        if !result.rows.contains(&row) {
-                            result.rows.push(row);
-                        }
-                    }
-                    if !stmt.all {
-                        result = dedup_rowset(result);
-                    }
-                }
-            }
-            SetOp::Intersect => {
-                for row in &left.rows {
-                    if right.rows.contains(row) {
-                        result.rows.push(row.clone());
-                    }
-                }
-                if !stmt.all { result = dedup_rowset(result); }
-            }
-            SetOp::Except => {
-                for row in &left.rows {
-                    if !right.rows.contains(row) {
-                        result.rows.push(row.clone());
-                    }
-                }
-            }
+    /// Run a chain of set operations. The parser builds them left-deep, so
+    /// the chain is walked iteratively: a long `UNION ALL` list is neither
+    /// nesting nor deep recursion.
+    fn exec_set_op(&self, stmt: &SetOpStatement, ctx: &ExecCtx) -> Result<RowSet> {
+        let mut chain = vec![stmt];
+        let mut first = stmt.left.as_ref();
+        while let Statement::SetOp(inner) = first {
+            chain.push(inner);
+            first = inner.left.as_ref();
+        }
+        let mut result = self.exec_query(first, ctx)?;
+        for stmt in chain.into_iter().rev() {
+            let right = self.exec_query(&stmt.right, ctx)?;
+            result = combine_set_op(&stmt.op, stmt.all, result, right)?;
         }
         Ok(result)
     }
@@ -1183,8 +2590,8 @@ impl SqlEngine {
     // ─── INSERT ───────────────────────────────────────────────────────────────
 
     fn exec_insert(&mut self, stmt: InsertStatement) -> Result<QueryResult> {
-        let table = self.catalog.get_table_mut(&stmt.table)
-            .ok_or_else(|| PivotError::SqlError(format!("Table '{}' not found", stmt.table)))?;
+        let key = self.table_key(&stmt.table)?;
+        let table = self.catalog.get_table_verbatim_mut(&key).unwrap();
         let schema = table.schema().clone();
         let col_indices: Vec<usize> = if let Some(ref cols) = stmt.columns {
             cols.iter().map(|c| schema.find_column_index(c)
@@ -1193,87 +2600,144 @@ impl SqlEngine {
         } else {
             (0..schema.column_count()).collect()
         };
+        if let Some(dup) = col_indices.iter().enumerate().find(|(i, c)| col_indices[..*i].contains(c)) {
+            return Err(PivotError::SqlError(format!(
+                "Column '{}' specified more than once", schema.columns[*dup.1].name
+            )));
+        }
 
+        let defaults: Vec<ScalarValue> = schema.columns.iter()
+            .map(|c| c.default.clone().unwrap_or(ScalarValue::Null))
+            .collect();
         let mut affected = 0;
+        let mut inserted: Vec<Vec<ScalarValue>> = Vec::new();
+        let mut unique = table.unique_values()?;
         match &stmt.values {
             InsertValues::Values(all_rows) => {
                 for row_exprs in all_rows {
-                    let mut values: Vec<ScalarValue> = (0..schema.column_count())
-                        .map(|_| ScalarValue::Null).collect();
+                    check_generated_always(&schema, &col_indices[..row_exprs.len().min(col_indices.len())])?;
+                    let mut values = defaults.clone();
                     for (i, expr) in row_exprs.iter().enumerate() {
                         if let Some(&col_idx) = col_indices.get(i) {
-                            let v = eval_expr(expr, &[], &[], None, &HashMap::new())?;
+                            let v = eval_expr(expr, &[], &[], &self.eval_ctx(&ExecCtx::new()))?;
                             values[col_idx] = v;
                         }
                     }
-                    let table = self.catalog.get_table_mut(&stmt.table).unwrap();
-                    table.append_row(values)?;
+                    self.fill_identities(&key, &schema, &mut values);
+                    let table = self.catalog.get_table_verbatim_mut(&key).unwrap();
+                    table.append_row_with(values, &mut unique)?;
+                    if stmt.returning.is_some() { inserted.push(table.get_row(table.row_count() - 1)?); }
                     affected += 1;
                 }
             }
             InsertValues::Select(select_stmt) => {
                 let ctx = ExecCtx::new();
-                let rs = self.exec_stmt_ctx(&*select_stmt, &ctx)?;
+                let rs = self.exec_stmt_ctx(select_stmt, &ctx)?;
+                // The i-th SELECT column goes to the i-th listed target column,
+                // whatever either is named. An explicit list must be covered
+                // exactly; without one, trailing columns take their defaults.
+                if stmt.columns.is_some() && rs.cols.len() != col_indices.len() {
+                    return Err(PivotError::SchemaError(format!(
+                        "INSERT lists {} target columns but SELECT returns {}",
+                        col_indices.len(), rs.cols.len()
+                    )));
+                }
+                let target = Schema::new(col_indices.iter().map(|&i| schema.columns[i].clone()).collect());
+                let source = Schema::new(rs.cols.iter()
+                    .map(|c| ColumnDef::new(&c.name, c.dtype.clone(), true)).collect());
+                target.is_compatible_for_insert(&source)?;
+                check_generated_always(&schema, &col_indices[..rs.cols.len().min(col_indices.len())])?;
                 for row in &rs.rows {
-                    let mut values: Vec<ScalarValue> = (0..schema.column_count())
-                        .map(|_| ScalarValue::Null).collect();
+                    let mut values = defaults.clone();
                     for (i, val) in row.iter().enumerate() {
                         if let Some(&col_idx) = col_indices.get(i) {
                             values[col_idx] = val.clone();
                         }
                     }
-                    let table = self.catalog.get_table_mut(&stmt.table).unwrap();
-                    table.append_row(values)?;
+                    self.fill_identities(&key, &schema, &mut values);
+                    let table = self.catalog.get_table_verbatim_mut(&key).unwrap();
+                    table.append_row_with(values, &mut unique)?;
+                    if stmt.returning.is_some() { inserted.push(table.get_row(table.row_count() - 1)?); }
                     affected += 1;
                 }
             }
         }
-        Ok(QueryResult::affected(affected))
+        match &stmt.returning {
+            Some(items) => {
+                let cols: Vec<Col> = schema.columns.iter().map(|c| Col {
+                    table: None, name: c.name.clone(), dtype: c.data_type.clone()
+                }).collect();
+                self.returning_result(cols, inserted, items, affected)
+            }
+            None => Ok(QueryResult::affected(affected)),
+        }
+    }
+
+    /// Give NULL identity columns the table's next value; explicit values move
+    /// the counter past themselves.
+    fn fill_identities(&mut self, table: &str, schema: &Schema, values: &mut [ScalarValue]) {
+        for (idx, col) in schema.columns.iter().enumerate() {
+            if !col.identity { continue; }
+            match values[idx] {
+                ScalarValue::Null => values[idx] = ScalarValue::Int64(self.catalog.next_identity(table, idx)),
+                ScalarValue::Int64(v) => self.catalog.observe_identity(table, idx, v),
+                _ => {}
+            }
+        }
     }
 
     // ─── UPDATE ───────────────────────────────────────────────────────────────
 
     fn exec_update(&mut self, stmt: UpdateStatement) -> Result<QueryResult> {
-        let table = self.catalog.get_table(&stmt.table)
-            .ok_or_else(|| PivotError::SqlError(format!("Table '{}' not found", stmt.table)))?;
+        let key = self.table_key(&stmt.table)?;
+        let table = self.catalog.get_table_verbatim(&key).unwrap();
         let schema = table.schema().clone();
         let row_count = table.row_count();
 
+        let cols: Vec<Col> = schema.columns.iter().map(|c| Col {
+            table: None, name: c.name.clone(), dtype: c.data_type.clone()
+        }).collect();
+
         let mut to_update: Vec<(usize, usize, ScalarValue)> = Vec::new();
+        let mut updated_rows: Vec<usize> = Vec::new();
         for row_idx in 0..row_count {
             let row = table.get_row(row_idx)?;
-            let cols: Vec<Col> = schema.columns.iter().map(|c| Col {
-                table: None, name: c.name.clone(), dtype: c.data_type.clone()
-            }).collect();
 
             let should_update = if let Some(ref where_expr) = stmt.where_clause {
-                let v = eval_expr(where_expr, &row, &cols, None, &HashMap::new())?;
+                let v = eval_expr(where_expr, &row, &cols, &self.eval_ctx(&ExecCtx::new()))?;
                 is_truthy(&v)
             } else { true };
 
             if should_update {
+                updated_rows.push(row_idx);
                 for assign in &stmt.assignments {
                     let col_idx = schema.find_column_index(&assign.column)
                         .ok_or_else(|| PivotError::ColumnNotFound(assign.column.clone()))?;
-                    let val = eval_expr(&assign.value, &row, &cols, None, &HashMap::new())?;
+                    let val = eval_expr(&assign.value, &row, &cols, &self.eval_ctx(&ExecCtx::new()))?;
                     to_update.push((row_idx, col_idx, val));
                 }
             }
         }
 
-        let table = self.catalog.get_table_mut(&stmt.table).unwrap();
+        let table = self.catalog.get_table_verbatim_mut(&key).unwrap();
         let affected = to_update.len();
-        for (row_idx, col_idx, val) in to_update {
-            table.set_value(row_idx, col_idx, val)?;
+        table.update_cells(to_update)?;
+        match stmt.returning {
+            Some(items) => {
+                let rows = updated_rows.iter()
+                    .map(|&idx| table.get_row(idx))
+                    .collect::<Result<Vec<_>>>()?;
+                self.returning_result(cols, rows, &items, affected)
+            }
+            None => Ok(QueryResult::affected(affected)),
         }
-        Ok(QueryResult::affected(affected))
     }
 
     // ─── DELETE ───────────────────────────────────────────────────────────────
 
     fn exec_delete(&mut self, stmt: DeleteStatement) -> Result<QueryResult> {
-        let table = self.catalog.get_table(&stmt.table)
-            .ok_or_else(|| PivotError::SqlError(format!("Table '{}' not found", stmt.table)))?;
+        let key = self.table_key(&stmt.table)?;
+        let table = self.catalog.get_table_verbatim(&key).unwrap();
         let schema = table.schema().clone();
         let row_count = table.row_count();
 
@@ -1281,55 +2745,214 @@ impl SqlEngine {
             table: None, name: c.name.clone(), dtype: c.data_type.clone()
         }).collect();
 
-        let mut keep_rows: Vec<Vec<ScalarValue>> = Vec::new();
-        let mut deleted = 0;
-
+        let mut to_delete: Vec<usize> = Vec::new();
         for row_idx in 0..row_count {
-            let row = table.get_row(row_idx)?;
             let delete = if let Some(ref where_expr) = stmt.where_clause {
-                let v = eval_expr(where_expr, &row, &cols, None, &HashMap::new())?;
+                let row = table.get_row(row_idx)?;
+                let v = eval_expr(where_expr, &row, &cols, &self.eval_ctx(&ExecCtx::new()))?;
                 is_truthy(&v)
             } else { true };
+            if delete { to_delete.push(row_idx); }
+        }
 
-            if delete { deleted += 1; } else { keep_rows.push(row); }
+        let deleted_rows = match stmt.returning {
+            Some(_) => to_delete.iter().map(|&idx| table.get_row(idx)).collect::<Result<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        let deleted = self.catalog.get_table_verbatim_mut(&key).unwrap().delete_rows(&to_delete);
+        match stmt.returning {
+            Some(items) => self.returning_result(cols, deleted_rows, &items, deleted),
+            None => Ok(QueryResult::affected(deleted)),
         }
+    }
 
-        // Rebuild table
-        let new_store = {
-            let mut s = crate::datastore::DataStore::new(schema);
-            for row in keep_rows { s.append_row(row)?; }
-            s
+    /// Project the rows touched by a DML statement through its RETURNING
+    /// list, keeping the affected-row count alongside the rows.
+    fn returning_result(
+        &self,
+        cols: Vec<Col>,
+        rows: Vec<Vec<ScalarValue>>,
+        items: &[SelectItem],
+        affected: usize,
+    ) -> Result<QueryResult> {
+        let mut rs = RowSet::new(cols);
+        rs.rows = rows;
+        let mut result = self.output_result(self.project_select(&rs, items, false, &ExecCtx::new())?)?;
+        result.affected_rows = affected;
+        Ok(result)
+    }
+
+    // ─── VACUUM ───────────────────────────────────────────────────────────────
+
+    fn exec_vacuum(&mut self, table: Option<TableName>) -> Result<QueryResult> {
+        match table {
+            Some(name) => {
+                let key = self.table_key(&name)?;
+                self.catalog.get_table_verbatim_mut(&key).unwrap().compact();
+            }
+            None => {
+                for store in self.catalog.tables_mut() { store.compact(); }
+            }
+        }
+        Ok(QueryResult::with_message("OK".to_string()))
+    }
+
+    fn exec_analyze(&mut self, table: Option<TableName>) -> Result<QueryResult> {
+        let names = match table {
+            Some(name) => vec![self.table_key(&name)?],
+            None => self.catalog.table_names(),
         };
-        *self.catalog.get_table_mut(&stmt.table).unwrap() = new_store;
-        Ok(QueryResult::affected(deleted))
+        for name in names {
+            self.catalog.analyze_verbatim(&name)?;
+        }
+        Ok(QueryResult::with_message("OK".to_string()))
     }
 
     // ─── CREATE TABLE ─────────────────────────────────────────────────────────
 
-    fn exec_create_table(&mut self, stmt: CreateTableStatement) -> Result<QueryResult> {
-        let schema = Schema::new(stmt.columns.iter().map(|c| ColumnDef {
+    fn column_def(&self, c: &ColumnDefAst) -> Result<ColumnDef> {
+        if c.identity && c.data_type != DataType::Int64 {
+            return Err(PivotError::SchemaError(format!(
+                "Identity column '{}' must be an integer column, not {}", c.name, c.data_type
+            )));
+        }
+        // Defaults are evaluated once, here, rather than per inserted row.
+        let default = match &c.default {
+            Some(expr) => Some(eval_expr(expr, &[], &[], &self.eval_ctx(&ExecCtx::new()))?),
+            None => None,
+        };
+        Ok(ColumnDef {
             name: c.name.clone(),
             data_type: c.data_type.clone(),
             nullable: c.nullable,
-        }).collect());
+            default,
+            identity: c.identity,
+            generated_always: c.generated_always,
+            primary_key: c.primary_key,
+            unique: c.unique,
+        })
+    }
 
-        if stmt.if_not_exists {
-            self.catalog.create_table_if_not_exists(&stmt.name, schema);
-        } else {
-            if !self.catalog.create_table(&stmt.name, schema) {
-                return Err(PivotError::SqlError(format!("Table '{}' already exists", stmt.name)));
+    fn exec_create_table(&mut self, stmt: CreateTableStatement) -> Result<QueryResult> {
+        let columns = stmt.columns.iter().map(|c| self.column_def(c)).collect::<Result<Vec<_>>>()?;
+        let schema = Schema::new(columns);
+
+        if self.catalog.view_key(&stmt.name).is_some() {
+            return Err(PivotError::SqlError(format!("A view named '{}' already exists", stmt.name)));
+        }
+        if self.catalog.table_key(&stmt.name).is_some() {
+            if stmt.if_not_exists {
+                return Ok(QueryResult::with_message(format!("Table '{}' created", stmt.name)));
             }
+            return Err(PivotError::SqlError(format!("Table '{}' already exists", stmt.name)));
         }
+        self.catalog.create_table_verbatim(&stmt.name.name, schema);
         Ok(QueryResult::with_message(format!("Table '{}' created", stmt.name)))
     }
 
+    // ─── ALTER TABLE ──────────────────────────────────────────────────────────
+
+    fn exec_alter_table(&mut self, stmt: AlterTableStatement) -> Result<QueryResult> {
+        let key = self.table_key(&stmt.table)?;
+        let table = self.catalog.get_table_verbatim(&key).unwrap();
+        match stmt.operation {
+            AlterTableOperation::AlterColumnType { column, data_type } => {
+                let col_idx = table.schema().find_column_index(&column)
+                    .ok_or_else(|| PivotError::ColumnNotFound(column.clone()))?;
+                // Values that don't convert become NULL, or fail the ALTER in
+                // strict mode; either way the table is untouched on error.
+                let mut values = Vec::with_capacity(table.row_count());
+                for row in 0..table.row_count() {
+                    let v = table.get_value_by_index(row, col_idx)?;
+                    if !self.strict {
+                        values.push(cast::cast_value(v, &data_type));
+                        continue;
+                    }
+                    let converted = cast::cast_value_strict(v.clone(), &data_type)?;
+                    if matches!(converted, ScalarValue::Null) && !matches!(v, ScalarValue::Null) {
+                        return Err(PivotError::TypeError(format!(
+                            "Cannot convert {} in column '{}' to {}", v, column, data_type
+                        )));
+                    }
+                    values.push(converted);
+                }
+                self.catalog.get_table_verbatim_mut(&key).unwrap()
+                    .retype_column(col_idx, data_type, values)?;
+            }
+            AlterTableOperation::AddColumn(def) => {
+                let def = self.column_def(&def)?;
+                if def.primary_key && table.schema().primary_key_index().is_some() {
+                    return Err(PivotError::SchemaError(format!(
+                        "Table '{}' already has a primary key", stmt.table
+                    )));
+                }
+                let fill = def.default.clone().unwrap_or(ScalarValue::Null);
+                let rows = table.row_count();
+                if rows > 0 && matches!(fill, ScalarValue::Null) && !def.nullable {
+                    return Err(PivotError::NullError(format!(
+                        "Column '{}' is NOT NULL and has no default for existing rows", def.name
+                    )));
+                }
+                let name = def.name.clone();
+                let table = self.catalog.get_table_verbatim_mut(&key).unwrap();
+                table.add_column(def)?;
+                if rows > 0 && !matches!(fill, ScalarValue::Null) {
+                    let col_idx = table.schema().column_count() - 1;
+                    let fills = (0..rows).map(|r| (r, col_idx, fill.clone())).collect();
+                    if let Err(e) = table.update_cells(fills) {
+                        table.drop_column(&name)?;
+                        return Err(e);
+                    }
+                }
+            }
+            AlterTableOperation::DropColumn { column, if_exists } => {
+                if table.schema().has_column(&column) {
+                    self.catalog.drop_column_verbatim(&key, &column)?;
+                } else if !if_exists {
+                    return Err(PivotError::ColumnNotFound(column));
+                }
+            }
+            AlterTableOperation::RenameColumn { from, to } => {
+                self.catalog.rename_column_verbatim(&key, &from, &to)?;
+            }
+        }
+        Ok(QueryResult::with_message(format!("Table '{}' altered", stmt.table)))
+    }
+
+    // ─── CREATE VIEW / DROP VIEW ──────────────────────────────────────────────
+
+    fn exec_create_view(&mut self, stmt: CreateViewStatement) -> Result<QueryResult> {
+        if self.catalog.table_key(&stmt.name).is_some() {
+            return Err(PivotError::SqlError(format!("A table named '{}' already exists", stmt.name)));
+        }
+        let existing = self.catalog.view_key(&stmt.name);
+        if existing.is_some() && !stmt.or_replace {
+            if stmt.if_not_exists {
+                return Ok(QueryResult::with_message(format!("View '{}' already exists", stmt.name)));
+            }
+            return Err(PivotError::SqlError(format!("View '{}' already exists", stmt.name)));
+        }
+        // Run the query once so a broken definition fails here, not on first use.
+        self.exec_query(&stmt.query, &ExecCtx::new())?;
+        let name = existing.unwrap_or(stmt.name.name.clone());
+        self.catalog.create_view_verbatim(&name, *stmt.query);
+        Ok(QueryResult::with_message(format!("View '{}' created", stmt.name)))
+    }
+
+    fn exec_drop_view(&mut self, stmt: DropViewStatement) -> Result<QueryResult> {
+        let dropped = self.catalog.view_key(&stmt.name).is_some_and(|key| self.catalog.drop_view_verbatim(&key));
+        if dropped || stmt.if_exists {
+            Ok(QueryResult::with_message(format!("View '{}' dropped", stmt.name)))
+        } else {
+            Err(PivotError::SqlError(format!("View '{}' not found", stmt.name)))
+        }
+    }
+
     // ─── DROP TABLE ───────────────────────────────────────────────────────────
 
     fn exec_drop_table(&mut self, stmt: DropTableStatement) -> Result<QueryResult> {
-        if stmt.if_exists {
-            self.catalog.drop_table(&stmt.name);
-            Ok(QueryResult::with_message(format!("Table '{}' dropped", stmt.name)))
-        } else if self.catalog.drop_table(&stmt.name) {
+        let dropped = self.catalog.table_key(&stmt.name).is_some_and(|key| self.catalog.drop_table_verbatim(&key));
+        if dropped || stmt.if_exists {
             Ok(QueryResult::with_message(format!("Table '{}' dropped", stmt.name)))
         } else {
             Err(PivotError::SqlError(format!("Table '{}' not found", stmt.name)))
@@ -1343,113 +2966,204 @@ fn eval_expr(
     expr: &Expr,
     row: &[ScalarValue],
     cols: &[Col],
-    group_rows: Option<&Vec<Vec<ScalarValue>>>,
-    ctes: &HashMap<String, RowSet>,
+    ctx: &EvalCtx,
 ) -> Result<ScalarValue> {
     match expr {
         Expr::Literal(lit) => Ok(eval_literal(lit)),
         Expr::Column(col_ref) => {
-            let idx = find_col_idx(cols, col_ref.table.as_deref(), &col_ref.name)
-                .ok_or_else(|| PivotError::ColumnNotFound(
+            let fold = ctx.engine.fold();
+            // Local columns win, except that a qualifier naming only an
+            // enclosing query's table (`e.dept`) must not fall back to a
+            // same-named local column.
+            let outer_qualifier = col_ref.table.as_deref().is_some_and(|t| !has_table(cols, t, fold));
+            let local = if outer_qualifier && ctx.engine.outer_value(col_ref).is_some() { None }
+                else { find_col_idx(cols, col_ref, fold) };
+            match local {
+                Some(idx) => Ok(row.get(idx).cloned().unwrap_or(ScalarValue::Null)),
+                None => ctx.engine.outer_value(col_ref).ok_or_else(|| PivotError::ColumnNotFound(
                     col_ref.table.as_ref()
                         .map(|t| format!("{}.{}", t, col_ref.name))
                         .unwrap_or_else(|| col_ref.name.clone())
-                ))?;
-            Ok(row.get(idx).cloned().unwrap_or(ScalarValue::Null))
+                )),
+            }
         }
         Expr::Wildcard => Ok(ScalarValue::Null),
+        Expr::Row(items) => items.iter()
+            .map(|item| eval_expr(item, row, cols, ctx))
+            .collect::<Result<Vec<_>>>()
+            .map(ScalarValue::Row),
+        Expr::QualifiedWildcard(tname) => Err(PivotError::SqlError(format!(
+            "{}.* is only allowed in the select list or COUNT", tname
+        ))),
         Expr::BinaryOp { left, op, right } => {
-            let l = eval_expr(left, row, cols, group_rows, ctes)?;
-            let r = eval_expr(right, row, cols, group_rows, ctes)?;
-            eval_binary_op(op, l, r)
+            let l = eval_expr(left, row, cols, ctx)?;
+            let r = eval_expr(right, row, cols, ctx)?;
+            ctx.engine.binary_op(op, l, r)
         }
         Expr::UnaryOp { op, expr: inner } => {
-            let v = eval_expr(inner, row, cols, group_rows, ctes)?;
+            let v = eval_expr(inner, row, cols, ctx)?;
             eval_unary_op(op, v)
         }
-        Expr::Cast { expr: inner, data_type } => {
-            let v = eval_expr(inner, row, cols, group_rows, ctes)?;
-            Ok(cast::cast_value(v, data_type))
-        }
-        Expr::TypeCast { expr: inner, data_type } => {
-            let v = eval_expr(inner, row, cols, group_rows, ctes)?;
-            Ok(cast::cast_value(v, data_type))
+        Expr::Cast { expr: inner, data_type } | Expr::TypeCast { expr: inner, data_type } => {
+            let v = eval_expr(inner, row, cols, ctx)?;
+            if ctx.engine.strict { cast::cast_value_strict(v, data_type) }
+            else { Ok(cast::cast_value(v, data_type)) }
         }
         Expr::TryCast { expr: inner, data_type } => {
-            let v = eval_expr(inner, row, cols, group_rows, ctes)?;
+            let v = eval_expr(inner, row, cols, ctx)?;
             Ok(cast::try_cast_value(v, data_type))
         }
         Expr::IsNull { expr: inner, negated } => {
-            let v = eval_expr(inner, row, cols, group_rows, ctes)?;
+            let v = eval_expr(inner, row, cols, ctx)?;
             let is_null = matches!(v, ScalarValue::Null);
             Ok(ScalarValue::Boolean(if *negated { !is_null } else { is_null }))
         }
         Expr::InList { expr: inner, list, negated } => {
-            let v = eval_expr(inner, row, cols, group_rows, ctes)?;
+            // Three-valued: with no match, a NULL on either side makes the
+            // answer unknown, so `2 NOT IN (1, NULL)` is NULL rather than true.
+            let v = eval_expr(inner, row, cols, ctx)?;
             let mut found = false;
+            let mut saw_null = false;
             for item in list {
-                let iv = eval_expr(item, row, cols, group_rows, ctes)?;
-                if scalar_eq(&v, &iv) { found = true; break; }
+                let iv = eval_expr(item, row, cols, ctx)?;
+                match eval_binary_op(&BinOp::Eq, v.clone(), iv)? {
+                    ScalarValue::Boolean(true) => { found = true; break; }
+                    ScalarValue::Null => saw_null = true,
+                    _ => {}
+                }
             }
+            if !found && saw_null { return Ok(ScalarValue::Null); }
             Ok(ScalarValue::Boolean(if *negated { !found } else { found }))
         }
-        Expr::Between { expr: inner, low, high, negated } => {
-            let v = eval_expr(inner, row, cols, group_rows, ctes)?;
-            let l = eval_expr(low, row, cols, group_rows, ctes)?;
-            let h = eval_expr(high, row, cols, group_rows, ctes)?;
+        Expr::Between { expr: inner, low, high, negated, symmetric } => {
+            let v = eval_expr(inner, row, cols, ctx)?;
+            let mut l = eval_expr(low, row, cols, ctx)?;
+            let mut h = eval_expr(high, row, cols, ctx)?;
+            if *symmetric && scalar_cmp(&l, &h) == std::cmp::Ordering::Greater {
+                std::mem::swap(&mut l, &mut h);
+            }
             let in_range = scalar_cmp(&v, &l) != std::cmp::Ordering::Less
                 && scalar_cmp(&v, &h) != std::cmp::Ordering::Greater;
             Ok(ScalarValue::Boolean(if *negated { !in_range } else { in_range }))
         }
-        Expr::Like { expr: inner, pattern, negated, case_insensitive } => {
-            let v = eval_expr(inner, row, cols, group_rows, ctes)?;
-            let p = eval_expr(pattern, row, cols, group_rows, ctes)?;
+        Expr::Like { expr: inner, pattern, negated, case_insensitive, escape } => {
+            let v = eval_expr(inner, row, cols, ctx)?;
+            let p = eval_expr(pattern, row, cols, ctx)?;
+            let escape = match escape {
+                None => None,
+                Some(e) => match eval_expr(e, row, cols, ctx)? {
+                    ScalarValue::Utf8(s) => {
+                        let mut chars = s.chars();
+                        match (chars.next(), chars.next()) {
+                            (None, _) => None,
+                            (Some(c), None) => Some(c),
+                            _ => return Err(PivotError::SqlError(format!(
+                                "ESCAPE must be a single character, got '{}'", s))),
+                        }
+                    }
+                    ScalarValue::Null => return Ok(ScalarValue::Null),
+                    other => return Err(PivotError::TypeError(format!(
+                        "ESCAPE must be a string, got {}", other))),
+                },
+            };
             let result = match (&v, &p) {
                 (ScalarValue::Utf8(s), ScalarValue::Utf8(pat)) => {
-                    like_match(s, pat, *case_insensitive)
+                    like_match(s, pat, *case_insensitive, escape)?
                 }
                 _ => false,
             };
             Ok(ScalarValue::Boolean(if *negated { !result } else { result }))
         }
+        Expr::Collate { expr: inner, .. } => eval_expr(inner, row, cols, ctx),
+        Expr::SimilarTo { expr: inner, pattern, negated } => {
+            let v = eval_expr(inner, row, cols, ctx)?;
+            let p = eval_expr(pattern, row, cols, ctx)?;
+            match (&v, &p) {
+                (ScalarValue::Utf8(s), ScalarValue::Utf8(pat)) => {
+                    let re = Regex::cached(&similar_to_regex(pat))?;
+                    Ok(ScalarValue::Boolean(re.is_match(s) != *negated))
+                }
+                _ => Ok(ScalarValue::Null),
+            }
+        }
         Expr::Case { operand, when_clauses, else_clause } => {
             let base = if let Some(op) = operand {
-                Some(eval_expr(op, row, cols, group_rows, ctes)?)
+                Some(eval_expr(op, row, cols, ctx)?)
             } else { None };
             for (cond, then_expr) in when_clauses {
                 let matches = if let Some(ref bv) = base {
-                    let cv = eval_expr(cond, row, cols, group_rows, ctes)?;
+                    let cv = eval_expr(cond, row, cols, ctx)?;
                     scalar_eq(bv, &cv)
                 } else {
-                    let cv = eval_expr(cond, row, cols, group_rows, ctes)?;
+                    let cv = eval_expr(cond, row, cols, ctx)?;
                     is_truthy(&cv)
                 };
                 if matches {
-                    return eval_expr(then_expr, row, cols, group_rows, ctes);
+                    return eval_expr(then_expr, row, cols, ctx);
                 }
             }
             if let Some(else_e) = else_clause {
-                eval_expr(else_e, row, cols, group_rows, ctes)
+                eval_expr(else_e, row, cols, ctx)
             } else {
                 Ok(ScalarValue::Null)
             }
         }
-        Expr::Function { name, args, distinct, over: None } => {
-            eval_scalar_function(name, args, row, cols, group_rows, ctes)
+        Expr::Function { name, args, over: None, .. } => {
+            eval_scalar_function(name, args, row, cols, ctx)
         }
-        Expr::Function { name, args, over: Some(spec), .. } => {
+        Expr::Function { name: _, args: _, over: Some(_spec), .. } => {
             // Window functions evaluated by compute_window_func, not here
             // Return NULL as placeholder (will be replaced later)
             Ok(ScalarValue::Null)
         }
         Expr::Subquery(stmt) => {
-            // Scalar subquery - we can't execute it without the catalog
-            // Return NULL as placeholder
-            Ok(ScalarValue::Null)
+            // Scalar subquery: one column, at most one row. It may refer to
+            // the current row's columns.
+            let rs = ctx.engine.exec_subquery(stmt, cols, row, ctx.exec)?;
+            if rs.cols.len() != 1 {
+                return Err(PivotError::SqlError(format!(
+                    "Scalar subquery must return one column, got {}", rs.cols.len()
+                )));
+            }
+            match rs.rows.len() {
+                0 => Ok(ScalarValue::Null),
+                1 => Ok(rs.rows[0][0].clone()),
+                n => Err(PivotError::SqlError(format!(
+                    "Scalar subquery returned {} rows, expected at most one", n
+                ))),
+            }
+        }
+        Expr::Exists { query, negated } => {
+            // Only the row count matters: a row with no columns, or with
+            // only NULLs, still exists.
+            let rs = ctx.engine.exec_subquery(query, cols, row, ctx.exec)?;
+            Ok(ScalarValue::Boolean(rs.rows.is_empty() == *negated))
+        }
+        Expr::InSubquery { expr: inner, query, negated } => {
+            // Same three-valued rules as IN (list). A row constructor on the
+            // left compares against whole subquery rows.
+            let v = eval_expr(inner, row, cols, ctx)?;
+            let rs = ctx.engine.exec_subquery(query, cols, row, ctx.exec)?;
+            let width = match &v { ScalarValue::Row(items) => items.len(), _ => 1 };
+            if rs.cols.len() != width {
+                return Err(PivotError::SqlError(format!(
+                    "IN subquery returns {} columns, expected {}", rs.cols.len(), width
+                )));
+            }
+            let mut found = false;
+            let mut saw_null = false;
+            for r in &rs.rows {
+                let item = if matches!(v, ScalarValue::Row(_)) { ScalarValue::Row(r.clone()) }
+                    else { r.first().cloned().unwrap_or(ScalarValue::Null) };
+                match eval_binary_op(&BinOp::Eq, v.clone(), item)? {
+                    ScalarValue::Boolean(true) => { found = true; break; }
+                    ScalarValue::Null => saw_null = true,
+                    _ => {}
+                }
+            }
+            if !found && saw_null { return Ok(ScalarValue::Null); }
+            Ok(ScalarValue::Boolean(if *negated { !found } else { found }))
         }
-        Expr::Exists { .. } => Ok(ScalarValue::Boolean(false)),
-        Expr::InSubquery { .. } => Ok(ScalarValue::Boolean(false)),
-        _ => Ok(ScalarValue::Null),
     }
 }
 
@@ -1461,24 +3175,19 @@ fn eval_literal(lit: &LiteralValue) -> ScalarValue {
         LiteralValue::Boolean(b) => ScalarValue::Boolean(*b),
         LiteralValue::Null => ScalarValue::Null,
         LiteralValue::Interval { value, unit } => {
-            // Parse interval - simplified
-            let n: i64 = value.parse().unwrap_or(0);
-            use crate::column::IntervalValue;
-            let iv = match unit.to_uppercase().as_str() {
-                "YEAR" | "YEARS" => IntervalValue::new(n as i32, 0, 0, 0),
-                "MONTH" | "MONTHS" => IntervalValue::new(0, n as i32, 0, 0),
-                "DAY" | "DAYS" => IntervalValue::new(0, 0, n as i32, 0),
-                "HOUR" | "HOURS" => IntervalValue::new(0, 0, 0, n * 3_600_000_000),
-                "MINUTE" | "MINUTES" => IntervalValue::new(0, 0, 0, n * 60_000_000),
-                "SECOND" | "SECONDS" => IntervalValue::new(0, 0, 0, n * 1_000_000),
-                _ => IntervalValue::zero(),
-            };
-            ScalarValue::Interval(iv)
+            interval_string_to_value(value, unit)
+                .map(ScalarValue::Interval)
+                .unwrap_or(ScalarValue::Null)
         }
     }
 }
 
 fn eval_binary_op(op: &BinOp, l: ScalarValue, r: ScalarValue) -> Result<ScalarValue> {
+    if let (ScalarValue::Row(a), ScalarValue::Row(b)) = (&l, &r) {
+        if matches!(op, BinOp::Eq | BinOp::NotEq | BinOp::Lt | BinOp::LtEq | BinOp::Gt | BinOp::GtEq) {
+            return compare_rows(op, a, b);
+        }
+    }
     // NULL propagation
     if matches!(l, ScalarValue::Null) || matches!(r, ScalarValue::Null) {
         match op {
@@ -1498,9 +3207,28 @@ fn eval_binary_op(op: &BinOp, l: ScalarValue, r: ScalarValue) -> Result<ScalarVa
         }
     }
 
+    if matches!(op, BinOp::Add | BinOp::Sub) {
+        if let Some(v) = interval_arith(&l, &r, *op == BinOp::Sub) { return v; }
+    }
+
     Ok(match op {
         BinOp::Add => numeric_op(&l, &r, |a, b| a + b, |a, b| a + b),
-        BinOp::Sub => numeric_op(&l, &r, |a, b| a - b, |a, b| a - b),
+        BinOp::Sub => match (&l, &r) {
+            // Date - Date is a day count; Timestamp/Time differences are intervals
+            (ScalarValue::Date(a), ScalarValue::Date(b)) => ScalarValue::Int64(a - b),
+            (ScalarValue::Timestamp(a), ScalarValue::Timestamp(b))
+            | (ScalarValue::Time(a), ScalarValue::Time(b)) => {
+                ScalarValue::Interval(IntervalValue::new(0, 0, 0, a - b))
+            }
+            // A date against a timestamp counts as its midnight
+            (ScalarValue::Date(d), ScalarValue::Timestamp(t)) => {
+                ScalarValue::Interval(IntervalValue::new(0, 0, 0, d * 86_400_000_000 - t))
+            }
+            (ScalarValue::Timestamp(t), ScalarValue::Date(d)) => {
+                ScalarValue::Interval(IntervalValue::new(0, 0, 0, t - d * 86_400_000_000))
+            }
+            _ => numeric_op(&l, &r, |a, b| a - b, |a, b| a - b),
+        },
         BinOp::Mul => numeric_op(&l, &r, |a, b| a * b, |a, b| a * b),
         BinOp::Div => {
             match (&l, &r) {
@@ -1511,7 +3239,36 @@ fn eval_binary_op(op: &BinOp, l: ScalarValue, r: ScalarValue) -> Result<ScalarVa
                 _ => numeric_op(&l, &r, |a, b| a / b, |a, b| a / b),
             }
         }
-        BinOp::Mod => numeric_op(&l, &r, |a, b| a % b, |a, b| a % b),
+        BinOp::Mod => match (&l, &r) {
+            (ScalarValue::Int64(_) | ScalarValue::Float64(_), ScalarValue::Int64(0)) => {
+                return Err(PivotError::SqlError("Division by zero".to_string()));
+            }
+            (ScalarValue::Int64(_) | ScalarValue::Float64(_), ScalarValue::Float64(b)) if *b == 0.0 => {
+                return Err(PivotError::SqlError("Division by zero".to_string()));
+            }
+            // The result takes the dividend's sign: -7 % 3 = -1
+            _ => numeric_op(&l, &r, |a, b| a.wrapping_rem(b), |a, b| a % b),
+        },
+        BinOp::IntDiv => {
+            let (a, b) = match (&l, &r) {
+                (ScalarValue::Int64(a), ScalarValue::Int64(b)) => {
+                    if *b == 0 { return Err(PivotError::SqlError("Division by zero".to_string())); }
+                    return a.checked_div(*b).map(ScalarValue::Int64).ok_or_else(|| PivotError::SqlError(format!(
+                        "{} DIV {} is out of range for INTEGER", a, b
+                    )));
+                }
+                (ScalarValue::Int64(a), ScalarValue::Float64(b)) => (*a as f64, *b),
+                (ScalarValue::Float64(a), ScalarValue::Int64(b)) => (*a, *b as f64),
+                (ScalarValue::Float64(a), ScalarValue::Float64(b)) => (*a, *b),
+                _ => return Ok(ScalarValue::Null),
+            };
+            if b == 0.0 { return Err(PivotError::SqlError("Division by zero".to_string())); }
+            let q = (a / b).trunc();
+            if !q.is_finite() || q < i64::MIN as f64 || q >= i64::MAX as f64 {
+                return Err(PivotError::SqlError(format!("{} DIV {} is out of range for INTEGER", a, b)));
+            }
+            ScalarValue::Int64(q as i64)
+        }
         BinOp::Eq => ScalarValue::Boolean(scalar_eq(&l, &r)),
         BinOp::NotEq => ScalarValue::Boolean(!scalar_eq(&l, &r)),
         BinOp::Lt => ScalarValue::Boolean(scalar_cmp(&l, &r) == std::cmp::Ordering::Less),
@@ -1528,6 +3285,36 @@ fn eval_binary_op(op: &BinOp, l: ScalarValue, r: ScalarValue) -> Result<ScalarVa
     })
 }
 
+/// Row comparison: `=` / `<>` hold or fail element-wise, the orderings are
+/// lexicographic and decided by the first unequal pair. A NULL element that
+/// the outcome depends on makes the result NULL.
+fn compare_rows(op: &BinOp, a: &[ScalarValue], b: &[ScalarValue]) -> Result<ScalarValue> {
+    if a.len() != b.len() {
+        return Err(PivotError::SqlError(format!(
+            "Cannot compare rows of {} and {} values", a.len(), b.len()
+        )));
+    }
+    let mut unknown = false;
+    for (x, y) in a.iter().zip(b) {
+        match eval_binary_op(&BinOp::Eq, x.clone(), y.clone())? {
+            ScalarValue::Boolean(true) => continue,
+            ScalarValue::Boolean(false) => {
+                return match op {
+                    BinOp::Eq => Ok(ScalarValue::Boolean(false)),
+                    BinOp::NotEq => Ok(ScalarValue::Boolean(true)),
+                    _ if unknown => Ok(ScalarValue::Null),
+                    _ => eval_binary_op(op, x.clone(), y.clone()),
+                };
+            }
+            _ if matches!(op, BinOp::Eq | BinOp::NotEq) => unknown = true,
+            _ => return Ok(ScalarValue::Null),
+        }
+    }
+    Ok(if unknown { ScalarValue::Null } else {
+        ScalarValue::Boolean(matches!(op, BinOp::Eq | BinOp::LtEq | BinOp::GtEq))
+    })
+}
+
 fn eval_unary_op(op: &UnaryOp, v: ScalarValue) -> Result<ScalarValue> {
     match op {
         UnaryOp::Neg => match v {
@@ -1548,75 +3335,98 @@ fn eval_scalar_function(
     args: &[Expr],
     row: &[ScalarValue],
     cols: &[Col],
-    group_rows: Option<&Vec<Vec<ScalarValue>>>,
-    ctes: &HashMap<String, RowSet>,
+    ctx: &EvalCtx,
 ) -> Result<ScalarValue> {
     let fname = name.to_uppercase();
 
-    // Evaluate arguments lazily where needed
-    let eval_arg = |i: usize| -> Result<ScalarValue> {
-        args.get(i)
-            .map(|e| eval_expr(e, row, cols, group_rows, ctes))
-            .unwrap_or(Ok(ScalarValue::Null))
-    };
+    // Built-ins that only evaluate the arguments they need
+    if !ctx.engine.functions.contains_key(&fname) {
+        let eval_arg = |i: usize| -> Result<ScalarValue> {
+            args.get(i)
+                .map(|e| eval_expr(e, row, cols, ctx))
+                .unwrap_or(Ok(ScalarValue::Null))
+        };
+        match fname.as_str() {
+            "COALESCE" | "IFNULL" | "NVL" => {
+                for arg in args {
+                    let v = eval_expr(arg, row, cols, ctx)?;
+                    if !matches!(v, ScalarValue::Null) { return Ok(v); }
+                }
+                return Ok(ScalarValue::Null);
+            }
+            "IF" | "IIF" => {
+                let cond = eval_arg(0)?;
+                return if is_truthy(&cond) { eval_arg(1) } else { eval_arg(2) };
+            }
+            _ => {}
+        }
+    }
+
+    let evaled: Vec<ScalarValue> = args.iter()
+        .map(|a| eval_expr(a, row, cols, ctx))
+        .collect::<Result<Vec<_>>>()?;
+    call_scalar_function(&fname, evaled, ctx)
+}
 
-    match fname.as_str() {
+/// Apply the scalar function `name` (upper-cased) to already evaluated arguments.
+fn call_scalar_function(fname: &str, args: Vec<ScalarValue>, ctx: &EvalCtx) -> Result<ScalarValue> {
+    if let Some(f) = ctx.engine.functions.get(fname) {
+        return f(&args);
+    }
+    let arg = |i: usize| args.get(i).cloned().unwrap_or(ScalarValue::Null);
+
+    match fname {
         "COALESCE" | "IFNULL" | "NVL" => {
-            for arg in args {
-                let v = eval_expr(arg, row, cols, group_rows, ctes)?;
-                if !matches!(v, ScalarValue::Null) { return Ok(v); }
-            }
-            Ok(ScalarValue::Null)
+            Ok(args.into_iter().find(|v| !matches!(v, ScalarValue::Null)).unwrap_or(ScalarValue::Null))
         }
-        "NULLIF" => {
-            let a = eval_arg(0)?;
-            let b = eval_arg(1)?;
-            Ok(if scalar_eq(&a, &b) { ScalarValue::Null } else { a })
+        // Same as `%`, including the error on a zero divisor
+        "MOD" => ctx.engine.binary_op(&BinOp::Mod, arg(0), arg(1)),
+        "DIV" => ctx.engine.binary_op(&BinOp::IntDiv, arg(0), arg(1)),
+        "ARRAY_CONTAINS" => {
+            let needle = arg(1);
+            Ok(match arg(0) {
+                ScalarValue::Array(items) if !matches!(needle, ScalarValue::Null) => {
+                    ScalarValue::Boolean(items.iter().any(|v| scalar_eq(v, &needle)))
+                }
+                _ => ScalarValue::Null,
+            })
         }
-        "IF" | "IIF" => {
-            let cond = eval_arg(0)?;
-            if is_truthy(&cond) { eval_arg(1) } else { eval_arg(2) }
+        "CURRENT_DATE" | "TODAY" | "GETDATE" => {
+            Ok(ScalarValue::Date(ctx.engine.now_micros().div_euclid(86_400_000_000)))
         }
-        "GREATEST" => {
-            let mut best: Option<ScalarValue> = None;
-            for arg in args {
-                let v = eval_expr(arg, row, cols, group_rows, ctes)?;
-                if matches!(v, ScalarValue::Null) { continue; }
-                best = Some(match best {
-                    None => v,
-                    Some(cur) => if scalar_cmp(&v, &cur) == std::cmp::Ordering::Greater { v } else { cur },
-                });
-            }
-            Ok(best.unwrap_or(ScalarValue::Null))
+        "CURRENT_TIMESTAMP" | "NOW" | "CURRENT_TIMESTAMP()" => Ok(ScalarValue::Timestamp(ctx.engine.now_micros())),
+        "CURRENT_TIME" => Ok(ScalarValue::Time(ctx.engine.now_micros().rem_euclid(86_400_000_000))),
+        "NULLIF" => {
+            let a = arg(0);
+            Ok(if scalar_eq(&a, &arg(1)) { ScalarValue::Null } else { a })
         }
-        "LEAST" => {
+        "IF" | "IIF" => Ok(if is_truthy(&arg(0)) { arg(1) } else { arg(2) }),
+        "GREATEST" | "LEAST" => {
+            let wanted = if fname == "GREATEST" { std::cmp::Ordering::Greater } else { std::cmp::Ordering::Less };
             let mut best: Option<ScalarValue> = None;
-            for arg in args {
-                let v = eval_expr(arg, row, cols, group_rows, ctes)?;
+            for v in args {
                 if matches!(v, ScalarValue::Null) { continue; }
                 best = Some(match best {
                     None => v,
-                    Some(cur) => if scalar_cmp(&v, &cur) == std::cmp::Ordering::Less { v } else { cur },
+                    Some(cur) => if scalar_cmp(&v, &cur) == wanted { v } else { cur },
                 });
             }
             Ok(best.unwrap_or(ScalarValue::Null))
         }
         _ => {
-            // Evaluate all args
-            let evaled: Vec<ScalarValue> = args.iter()
-                .map(|a| eval_expr(a, row, cols, group_rows, ctes))
-                .collect::<Result<Vec<_>>>()?;
-
             // Try scalar functions
-            if let Some(v) = functions_scalar::call(&fname, &evaled) {
+            if let Some(v) = functions_scalar::call(fname, &args) {
                 return Ok(v);
             }
             // Try datetime functions
-            if let Some(v) = functions_datetime::call(&fname, &evaled) {
+            if let Some(v) = functions_datetime::call(fname, &args) {
                 return Ok(v);
             }
 
-            // Unknown function - return NULL rather than error for robustness
+            // Unknown function: NULL unless strict_functions is set
+            if ctx.engine.strict_functions {
+                return Err(PivotError::SqlError(format!("Unknown function '{}'", fname)));
+            }
             Ok(ScalarValue::Null)
         }
     }
@@ -1624,25 +3434,135 @@ fn eval_scalar_function(
 
 // ─── Helper functions ─────────────────────────────────────────────────────────
 
-fn find_col_idx(cols: &[Col], table: Option<&str>, name: &str) -> Option<usize> {
-    if let Some(t) = table {
+/// ORDER BY keys for sorted rows that can't supply them themselves.
+enum SourceKeys {
+    /// Computed for each group by `exec_group_by`.
+    Groups(Vec<Vec<Result<ScalarValue>>>),
+    /// The input row behind each output row, evaluated only when needed.
+    Rows(RowSet),
+}
+
+/// Combine the rows of two set operation inputs.
+fn combine_set_op(op: &SetOp, all: bool, left: RowSet, right: RowSet) -> Result<RowSet> {
+    if left.cols.len() != right.cols.len() {
+        let op = match op { SetOp::Union => "UNION", SetOp::Intersect => "INTERSECT", SetOp::Except => "EXCEPT" };
+        return Err(PivotError::SqlError(format!(
+            "{} inputs must have the same number of columns, got {} and {}",
+            op, left.cols.len(), right.cols.len()
+        )));
+    }
+
+    // Columns are named after the left input and typed for both.
+    let cols = left.cols.iter().zip(&right.cols)
+        .map(|(l, r)| Col { dtype: common_type(&l.dtype, &r.dtype), ..l.clone() })
+        .collect();
+    let mut result = RowSet::new(cols);
+    match op {
+        SetOp::Union => {
+            result.rows = left.rows;
+            result.rows.extend(right.rows);
+            if !all { result = dedup_rowset(result); }
+        }
+        SetOp::Intersect | SetOp::Except => {
+            // Under ALL each right row cancels one equal left row, so a
+            // row kept m and n times comes out min(m, n) times for
+            // INTERSECT and max(m - n, 0) times for EXCEPT.
+            let mut right_counts: HashMap<Vec<HashableScalar>, usize> = HashMap::new();
+            for row in &right.rows {
+                *right_counts.entry(row_key(row)).or_default() += 1;
+            }
+            let intersect = matches!(op, SetOp::Intersect);
+            for row in left.rows {
+                let matched = match right_counts.get_mut(&row_key(&row)) {
+                    Some(n) if *n > 0 => {
+                        if all { *n -= 1; }
+                        true
+                    }
+                    _ => false,
+                };
+                if matched == intersect {
+                    result.rows.push(row);
+                }
+            }
+            if !all { result = dedup_rowset(result); }
+        }
+    }
+    Ok(result)
+}
+
+/// Whether two GROUP BY keys are the same key: columns that resolve to the
+/// same input column, or otherwise identical expressions.
+fn same_group_key(a: &Expr, b: &Expr, cols: &[Col], fold: Fold) -> bool {
+    match (a, b) {
+        (Expr::Column(x), Expr::Column(y)) => find_col_idx(cols, x, fold)
+            .is_some_and(|i| find_col_idx(cols, y, fold) == Some(i)),
+        _ => format!("{:?}", a) == format!("{:?}", b),
+    }
+}
+
+/// Whether any of `cols` belongs to the table or alias `table`.
+fn has_table(cols: &[Col], table: &str, fold: Fold) -> bool {
+    cols.iter().any(|c| c.table.as_deref().is_some_and(|s| fold.matches(s, table)))
+}
+
+fn find_col_idx(cols: &[Col], col_ref: &ColumnRef, fold: Fold) -> Option<usize> {
+    let name = col_ref.name.as_str();
+    let name_eq = |c: &str, n: &str| fold.matches_ref(c, n, col_ref.quoted);
+    if let Some(t) = col_ref.table.as_deref() {
         if let Some(idx) = cols.iter().position(|c|
-            c.table.as_deref().map(|s| s.eq_ignore_ascii_case(t)).unwrap_or(false)
-            && c.name.eq_ignore_ascii_case(name)
+            c.table.as_deref().map(|s| fold.matches(s, t)).unwrap_or(false)
+            && name_eq(&c.name, name)
         ) {
             return Some(idx);
         }
         // Try "table.col" format in name
         let qualified = format!("{}.{}", t, name);
-        if let Some(idx) = cols.iter().position(|c| c.name.eq_ignore_ascii_case(&qualified)) {
+        if let Some(idx) = cols.iter().position(|c| name_eq(&c.name, &qualified)) {
             return Some(idx);
         }
     }
     let matches: Vec<usize> = cols.iter().enumerate()
-        .filter(|(_, c)| c.name.eq_ignore_ascii_case(name))
+        .filter(|(_, c)| name_eq(&c.name, name))
         .map(|(i, _)| i)
         .collect();
-    if matches.len() >= 1 { Some(matches[0]) } else { None }
+    if !matches.is_empty() { Some(matches[0]) } else { None }
+}
+
+/// Static type of a projected expression where it is known: plain column
+/// references and casts. Anything else is reported as `Utf8`, meaning "not
+/// tracked" (values keep their runtime types).
+fn projected_type(expr: &Expr, cols: &[Col], fold: Fold) -> DataType {
+    match expr {
+        Expr::Column(col_ref) => find_col_idx(cols, col_ref, fold)
+            .map(|i| cols[i].dtype.clone())
+            .unwrap_or(DataType::Utf8),
+        Expr::Cast { data_type, .. } | Expr::TypeCast { data_type, .. } => data_type.clone(),
+        _ => DataType::Utf8,
+    }
+}
+
+/// The type a column holding values of both types is reported as: integers
+/// widen to floats, and other mixes are left untracked (`Utf8`).
+fn common_type(a: &DataType, b: &DataType) -> DataType {
+    match (a, b) {
+        _ if a == b => a.clone(),
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => DataType::Float64,
+        _ => DataType::Utf8,
+    }
+}
+
+fn col_in_table(col: &Col, table: &str, fold: Fold) -> bool {
+    col.table.as_deref().map(|t| fold.matches(t, table)).unwrap_or(false)
+}
+
+/// The columns `table.*` expands to; an unknown qualifier is an error rather
+/// than an empty expansion.
+fn table_columns<'a>(cols: &'a [Col], table: &str, fold: Fold) -> Result<Vec<&'a Col>> {
+    let matched: Vec<&Col> = cols.iter().filter(|c| col_in_table(c, table, fold)).collect();
+    if matched.is_empty() {
+        return Err(PivotError::SqlError(format!("Table '{}' not found for {}.*", table, table)));
+    }
+    Ok(matched)
 }
 
 fn is_truthy(v: &ScalarValue) -> bool {
@@ -1669,6 +3589,11 @@ fn scalar_eq(a: &ScalarValue, b: &ScalarValue) -> bool {
         (ScalarValue::Date(x), ScalarValue::Date(y)) => x == y,
         (ScalarValue::Timestamp(x), ScalarValue::Timestamp(y)) => x == y,
         (ScalarValue::Time(x), ScalarValue::Time(y)) => x == y,
+        (ScalarValue::Interval(x), ScalarValue::Interval(y)) => x.cmp_length(y) == std::cmp::Ordering::Equal,
+        (ScalarValue::Row(x), ScalarValue::Row(y)) => x.len() == y.len() && x.iter().zip(y).all(|(a, b)| scalar_eq(a, b)),
+        // Arrays compare whole, so NULL elements match each other.
+        (ScalarValue::Array(x), ScalarValue::Array(y)) => x.len() == y.len() && x.iter().zip(y)
+            .all(|(a, b)| matches!((a, b), (ScalarValue::Null, ScalarValue::Null)) || scalar_eq(a, b)),
         _ => false,
     }
 }
@@ -1688,17 +3613,43 @@ fn scalar_cmp(a: &ScalarValue, b: &ScalarValue) -> std::cmp::Ordering {
         (ScalarValue::Date(x), ScalarValue::Date(y)) => x.cmp(y),
         (ScalarValue::Timestamp(x), ScalarValue::Timestamp(y)) => x.cmp(y),
         (ScalarValue::Time(x), ScalarValue::Time(y)) => x.cmp(y),
+        (ScalarValue::Interval(x), ScalarValue::Interval(y)) => x.cmp_length(y),
+        (ScalarValue::Row(x), ScalarValue::Row(y)) | (ScalarValue::Array(x), ScalarValue::Array(y)) => x.iter().zip(y)
+            .map(|(a, b)| scalar_cmp(a, b))
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or_else(|| x.len().cmp(&y.len())),
         _ => Ordering::Equal,
     }
 }
 
-fn scalar_to_key(v: &ScalarValue) -> String {
-    match v {
-        ScalarValue::Null => "\x00NULL\x00".to_string(),
-        other => format!("{}", other),
+/// Collation named by a top-level `COLLATE` annotation, if any.
+fn expr_collation(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Collate { collation, .. } => Some(collation.as_str()),
+        _ => None,
     }
 }
 
+/// The value strings are compared by under `collation`: NOCASE folds case,
+/// BINARY (and no collation) leaves the value as is.
+fn collate_value(v: ScalarValue, collation: Option<&str>) -> ScalarValue {
+    match (v, collation) {
+        (ScalarValue::Utf8(s), Some("NOCASE")) => ScalarValue::Utf8(s.to_lowercase()),
+        (v, _) => v,
+    }
+}
+
+fn collated_cmp(a: &ScalarValue, b: &ScalarValue, collation: Option<&str>) -> std::cmp::Ordering {
+    match collation {
+        Some("NOCASE") => scalar_cmp(&collate_value(a.clone(), collation), &collate_value(b.clone(), collation)),
+        _ => scalar_cmp(a, b),
+    }
+}
+
+fn row_key(row: &[ScalarValue]) -> Vec<HashableScalar> {
+    row.iter().cloned().map(HashableScalar).collect()
+}
+
 fn scalar_to_string(v: &ScalarValue) -> String {
     match v {
         ScalarValue::Null => String::new(),
@@ -1720,36 +3671,293 @@ fn numeric_op(
     }
 }
 
-fn like_match(text: &str, pattern: &str, case_insensitive: bool) -> bool {
+/// Date/time plus or minus an interval, or the sum or difference of two
+/// intervals; `None` for any other operands.
+fn interval_arith(l: &ScalarValue, r: &ScalarValue, subtract: bool) -> Option<Result<ScalarValue>> {
+    const DAY_US: i64 = 86_400_000_000;
+    let shift = |iv: &IntervalValue| if subtract { IntervalValue::zero().checked_sub(iv) } else { Some(iv.clone()) };
+    let value = match (l, r) {
+        (ScalarValue::Interval(a), ScalarValue::Interval(b)) => {
+            let sum = if subtract { a.checked_sub(b) } else { a.checked_add(b) };
+            sum.map(|iv| ScalarValue::Interval(iv.normalized()))
+        }
+        // A date stays a date unless the interval has a time part.
+        (ScalarValue::Date(d), ScalarValue::Interval(iv)) => shift(iv).and_then(|iv| if iv.micros == 0 {
+            add_interval_to_days(*d, &iv).map(ScalarValue::Date)
+        } else {
+            add_interval_to_micros(d.checked_mul(DAY_US)?, &iv).map(ScalarValue::Timestamp)
+        }),
+        (ScalarValue::Timestamp(t), ScalarValue::Interval(iv)) => {
+            shift(iv).and_then(|iv| add_interval_to_micros(*t, &iv)).map(ScalarValue::Timestamp)
+        }
+        // Times wrap around midnight; only the interval's time part applies.
+        (ScalarValue::Time(t), ScalarValue::Interval(iv)) => {
+            let micros = iv.micros.rem_euclid(DAY_US);
+            let micros = if subtract { -micros } else { micros };
+            Some(ScalarValue::Time((t + micros).rem_euclid(DAY_US)))
+        }
+        (ScalarValue::Interval(_), ScalarValue::Date(_) | ScalarValue::Timestamp(_) | ScalarValue::Time(_)) if !subtract => {
+            return interval_arith(r, l, false);
+        }
+        _ => return None,
+    };
+    Some(value.ok_or_else(|| PivotError::SqlError(format!(
+        "{} {} {} is out of range", l, if subtract { "-" } else { "+" }, r
+    ))))
+}
+
+fn like_match(text: &str, pattern: &str, case_insensitive: bool, escape: Option<char>) -> Result<bool> {
     let t: Vec<char> = if case_insensitive { text.to_lowercase().chars().collect() }
                        else { text.chars().collect() };
-    let p: Vec<char> = if case_insensitive { pattern.to_lowercase().chars().collect() }
-                       else { pattern.chars().collect() };
-    like_match_chars(&t, &p)
+    let p = compile_like(pattern, escape, case_insensitive)?;
+    Ok(like_match_tokens(&t, &p))
+}
+
+#[derive(Debug, PartialEq)]
+enum LikeToken {
+    /// `%`: any run of characters, including none.
+    Any,
+    /// `_`: exactly one character.
+    One,
+    Lit(char),
+}
+
+/// Compile a LIKE pattern, collapsing runs of `%` into one `Any`.
+fn compile_like(pattern: &str, escape: Option<char>, case_insensitive: bool) -> Result<Vec<LikeToken>> {
+    let push_lit = |tokens: &mut Vec<LikeToken>, c: char| {
+        if case_insensitive {
+            tokens.extend(c.to_lowercase().map(LikeToken::Lit));
+        } else {
+            tokens.push(LikeToken::Lit(c));
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if Some(c) == escape {
+            let lit = chars.next().ok_or_else(|| PivotError::SqlError(
+                "LIKE pattern must not end with the escape character".to_string()))?;
+            push_lit(&mut tokens, lit);
+        } else if c == '%' {
+            if tokens.last() != Some(&LikeToken::Any) { tokens.push(LikeToken::Any); }
+        } else if c == '_' {
+            tokens.push(LikeToken::One);
+        } else {
+            push_lit(&mut tokens, c);
+        }
+    }
+    Ok(tokens)
 }
 
-fn like_match_chars(text: &[char], pattern: &[char]) -> bool {
-    match (text, pattern) {
-        (_, []) => text.is_empty(),
-        (_, ['%', rest @ ..]) => {
-            // % matches any sequence
-            for i in 0..=text.len() {
-                if like_match_chars(&text[i..], rest) { return true; }
+/// Greedy wildcard match: on a mismatch, retry from the most recent `%`
+/// with it absorbing one more character. Earlier `%`s never need to be
+/// revisited, so this is O(text × pattern) rather than exponential.
+fn like_match_tokens(text: &[char], pattern: &[LikeToken]) -> bool {
+    let (mut t, mut p) = (0, 0);
+    // (pattern index after the last `%`, text index it resumes from)
+    let mut retry: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(LikeToken::Any) => {
+                p += 1;
+                retry = Some((p, t));
             }
-            false
+            Some(LikeToken::One) => { t += 1; p += 1; }
+            Some(LikeToken::Lit(c)) if *c == text[t] => { t += 1; p += 1; }
+            _ => match retry {
+                Some((rp, rt)) => {
+                    p = rp;
+                    t = rt + 1;
+                    retry = Some((rp, t));
+                }
+                None => return false,
+            },
         }
-        ([], [_, ..]) => false,
-        ([t, rest_t @ ..], ['_', rest_p @ ..]) => like_match_chars(rest_t, rest_p),
-        ([t, rest_t @ ..], [p, rest_p @ ..]) => {
-            t == p && like_match_chars(rest_t, rest_p)
+    }
+    pattern[p..].iter().all(|tok| *tok == LikeToken::Any)
+}
+
+/// Translate a SQL `SIMILAR TO` pattern into an anchored regex: `%` and `_`
+/// become `.*` and `.`, `.`/`^`/`$` are literal, `\` escapes the next
+/// character, and `| * + ? {m,n} ( ) [...]` keep their regex meaning.
+fn similar_to_regex(pattern: &str) -> String {
+    let mut out = String::from("^(?:");
+    let mut in_class = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(e) if e.is_alphanumeric() => out.push(e),
+                Some(e) => { out.push('\\'); out.push(e); }
+                None => out.push_str("\\\\"),
+            },
+            '[' if !in_class => { in_class = true; out.push(c); }
+            ']' if in_class => { in_class = false; out.push(c); }
+            '%' if !in_class => out.push_str(".*"),
+            '_' if !in_class => out.push('.'),
+            '.' | '^' | '$' if !in_class => { out.push('\\'); out.push(c); }
+            other => out.push(other),
         }
     }
+    out.push_str(")$");
+    out
+}
+
+/// A window frame with its offsets evaluated. Offsets are relative to the
+/// current row, negative for PRECEDING; `None` is UNBOUNDED. They count rows
+/// in ROWS mode, ORDER BY values in RANGE mode and peer groups in GROUPS mode;
+/// in the last two the current row stands for all of its peers.
+struct FrameBounds {
+    kind: WindowFrameKind,
+    start: Option<f64>,
+    end: Option<f64>,
+}
+
+/// The peer group of each row of a sorted partition, numbered from 0.
+fn peer_group_ids(sorted: &[usize], order_keys: &[Vec<ScalarValue>]) -> Vec<usize> {
+    let mut groups = Vec::with_capacity(sorted.len());
+    let mut prev: Option<Vec<HashableScalar>> = None;
+    for &idx in sorted {
+        let key = row_key(&order_keys[idx]);
+        let g = match (&prev, groups.last()) {
+            (Some(p), Some(&g)) if *p == key => g,
+            (_, Some(&g)) => g + 1,
+            _ => 0,
+        };
+        groups.push(g);
+        prev = Some(key);
+    }
+    groups
+}
+
+/// Row indices in the frame of the row at `pos` of a sorted partition.
+/// `peer_groups` is `peer_group_ids` of the partition in GROUPS mode.
+fn window_frame_rows(
+    spec: &WindowSpec,
+    bounds: &FrameBounds,
+    sorted: &[usize],
+    peer_groups: &[usize],
+    pos: usize,
+    order_keys: &[Vec<ScalarValue>],
+) -> Result<Vec<usize>> {
+    let len = sorted.len() as i64;
+    let key_of = |idx: usize| -> Vec<HashableScalar> {
+        order_keys[idx].iter().cloned().map(HashableScalar).collect()
+    };
+    match bounds.kind {
+        WindowFrameKind::Rows => {
+            let lo = bounds.start.map_or(0, |d| (pos as i64 + d as i64).max(0));
+            let hi = bounds.end.map_or(len - 1, |d| (pos as i64 + d as i64).min(len - 1));
+            if lo > hi { return Ok(Vec::new()); }
+            return Ok(sorted[lo as usize..=hi as usize].to_vec());
+        }
+        WindowFrameKind::Groups => {
+            // Group numbers rise in sort order, so whole groups are a slice.
+            let cur = peer_groups[pos] as i64;
+            let lo = bounds.start.map_or(0, |d| peer_groups.partition_point(|&g| (g as i64) < cur + d as i64));
+            let hi = bounds.end.map_or(sorted.len(), |d| peer_groups.partition_point(|&g| (g as i64) <= cur + d as i64));
+            return Ok(sorted[lo..hi.max(lo)].to_vec());
+        }
+        WindowFrameKind::Range => {}
+    }
+    let value_offsets = [bounds.start, bounds.end].iter().any(|b| b.is_some_and(|d| d != 0.0));
+    if !value_offsets {
+        // Only UNBOUNDED and CURRENT ROW: extend over the current row's peers.
+        let cur = key_of(sorted[pos]);
+        let mut lo = if bounds.start.is_none() { 0 } else { pos };
+        while lo > 0 && key_of(sorted[lo - 1]) == cur { lo -= 1; }
+        let mut hi = if bounds.end.is_none() { sorted.len() } else { pos + 1 };
+        while hi < sorted.len() && key_of(sorted[hi]) == cur { hi += 1; }
+        return Ok(sorted[lo..hi].to_vec());
+    }
+    // Offsets are distances along the single ORDER BY key, in sort order.
+    let numeric = |v: &ScalarValue| -> Result<Option<f64>> {
+        match v {
+            ScalarValue::Null => Ok(None),
+            ScalarValue::Int64(i) => Ok(Some(*i as f64)),
+            ScalarValue::Float64(f) => Ok(Some(*f)),
+            other => Err(PivotError::TypeError(format!(
+                "RANGE with an offset requires a numeric ORDER BY, got {}", other))),
+        }
+    };
+    let direction = if spec.order_by[0].ascending { 1.0 } else { -1.0 };
+    let cur = numeric(&order_keys[sorted[pos]][0])?;
+    let mut frame = Vec::new();
+    for (j, &idx) in sorted.iter().enumerate() {
+        let inside = match (cur, numeric(&order_keys[idx][0])?) {
+            (Some(c), Some(v)) => {
+                let d = (v - c) * direction;
+                bounds.start.is_none_or(|s| d >= s) && bounds.end.is_none_or(|e| d <= e)
+            }
+            // NULLs are only peers of each other, but an unbounded side
+            // still reaches them.
+            (None, None) => true,
+            _ => (j < pos && bounds.start.is_none()) || (j > pos && bounds.end.is_none()),
+        };
+        if inside { frame.push(idx); }
+    }
+    Ok(frame)
+}
+
+/// Compare two rows' ORDER BY keys, one per item, honoring each item's
+/// direction, NULLS FIRST / LAST and collation.
+fn order_keys_cmp(items: &[OrderByItem], a: &[ScalarValue], b: &[ScalarValue]) -> std::cmp::Ordering {
+    for (item, (va, vb)) in items.iter().zip(a.iter().zip(b)) {
+        // An explicit NULLS FIRST/LAST holds whichever way the values sort.
+        let ord = match (item.nulls_first, va, vb) {
+            (Some(_), ScalarValue::Null, ScalarValue::Null) => std::cmp::Ordering::Equal,
+            (Some(first), ScalarValue::Null, _) => if first { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater },
+            (Some(first), _, ScalarValue::Null) => if first { std::cmp::Ordering::Greater } else { std::cmp::Ordering::Less },
+            _ => {
+                let ord = collated_cmp(va, vb, expr_collation(&item.expr));
+                if item.ascending { ord } else { ord.reverse() }
+            }
+        };
+        if ord != std::cmp::Ordering::Equal { return ord; }
+    }
+    std::cmp::Ordering::Equal
+}
+
+/// Percentile of `values` (NULLs already removed) at `fraction` in [0, 1].
+/// Continuous mode interpolates linearly between neighbours; discrete mode
+/// returns the first value whose cumulative distribution reaches `fraction`.
+fn percentile_value(
+    mut values: Vec<ScalarValue>,
+    fraction: f64,
+    continuous: bool,
+    ascending: bool,
+) -> Result<ScalarValue> {
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(PivotError::SqlError(format!(
+            "Percentile fraction must be between 0 and 1, got {}", fraction)));
+    }
+    if values.is_empty() { return Ok(ScalarValue::Null); }
+    values.sort_by(scalar_cmp);
+    if !ascending { values.reverse(); }
+    let n = values.len();
+    if !continuous {
+        let pos = ((fraction * n as f64).ceil() as usize).max(1) - 1;
+        return Ok(values[pos.min(n - 1)].clone());
+    }
+    let as_f64 = |v: &ScalarValue| match v {
+        ScalarValue::Int64(i) => Ok(*i as f64),
+        ScalarValue::Float64(f) => Ok(*f),
+        other => Err(PivotError::TypeError(format!(
+            "PERCENTILE_CONT requires a numeric ordering, got {}", other))),
+    };
+    let pos = fraction * (n - 1) as f64;
+    let lo = pos.floor() as usize;
+    let hi = pos.ceil() as usize;
+    let lo_v = as_f64(&values[lo])?;
+    let hi_v = as_f64(&values[hi])?;
+    Ok(ScalarValue::Float64(lo_v + (hi_v - lo_v) * (pos - lo as f64)))
 }
 
 fn expr_display_name(expr: &Expr) -> String {
     match expr {
         Expr::Column(col_ref) => col_ref.name.clone(),
         Expr::Function { name, .. } => name.to_lowercase(),
+        Expr::Row(_) => "row".to_string(),
         Expr::Literal(lit) => match lit {
             LiteralValue::Integer(n) => n.to_string(),
             LiteralValue::Float(f) => f.to_string(),
@@ -1759,10 +3967,11 @@ fn expr_display_name(expr: &Expr) -> String {
             LiteralValue::Interval { value, unit } => format!("{} {}", value, unit),
         },
         Expr::Cast { data_type, .. } => format!("cast({})", data_type),
+        Expr::Collate { expr: inner, .. } => expr_display_name(inner),
         Expr::BinaryOp { left, op, right } => {
             let op_str = match op {
                 BinOp::Add => "+", BinOp::Sub => "-", BinOp::Mul => "*",
-                BinOp::Div => "/", BinOp::Mod => "%",
+                BinOp::Div => "/", BinOp::Mod => "%", BinOp::IntDiv => " DIV ",
                 _ => "op",
             };
             format!("{}{}{}", expr_display_name(left), op_str, expr_display_name(right))
@@ -1778,37 +3987,438 @@ fn select_items_have_aggregate(items: &[SelectItem]) -> bool {
     })
 }
 
+/// Whether `expr` is itself an aggregate call (not a window function).
+fn is_aggregate_call(expr: &Expr) -> bool {
+    matches!(expr, Expr::Function { name, over: None, .. } if is_aggregate_name(name))
+}
+
 fn expr_has_aggregate(expr: &Expr) -> bool {
+    visit_expr(expr, &mut |e| match e {
+        Expr::Function { over: Some(_), .. } => Visit::Skip,
+        _ if is_aggregate_call(e) => Visit::Found(()),
+        _ => Visit::Descend,
+    }).is_some()
+}
+
+fn expr_has_window(expr: &Expr) -> bool {
+    find_window_function(expr).is_some()
+}
+
+/// What `visit_expr` does after looking at a node.
+enum Visit<T> {
+    /// Go on into the node's children.
+    Descend,
+    /// Leave the node's children out.
+    Skip,
+    /// Stop the walk with this result.
+    Found(T),
+}
+
+/// Walk `expr`, parents before children, until `f` finds something.
+fn visit_expr<'a, T>(expr: &'a Expr, f: &mut dyn FnMut(&'a Expr) -> Visit<T>) -> Option<T> {
+    match f(expr) {
+        Visit::Found(t) => Some(t),
+        Visit::Skip => None,
+        Visit::Descend => expr_children(expr).into_iter().find_map(|child| visit_expr(child, f)),
+    }
+}
+
+/// The expressions directly inside `expr`. Subqueries and OVER clauses are
+/// scopes of their own and are not entered.
+fn expr_children(expr: &Expr) -> Vec<&Expr> {
     match expr {
-        Expr::Function { name, over: None, .. } => {
-            matches!(name.to_uppercase().as_str(),
-                "COUNT" | "SUM" | "AVG" | "MIN" | "MAX"
-                | "STRING_AGG" | "GROUP_CONCAT" | "LISTAGG"
-                | "ARRAY_AGG" | "STDDEV" | "STDEV" | "STDDEV_SAMP" | "STDDEV_POP"
-                | "VARIANCE" | "VAR_SAMP" | "VAR_POP"
-            )
-        }
-        Expr::BinaryOp { left, right, .. } => expr_has_aggregate(left) || expr_has_aggregate(right),
-        Expr::UnaryOp { expr: inner, .. } => expr_has_aggregate(inner),
-        Expr::Cast { expr: inner, .. } => expr_has_aggregate(inner),
-        Expr::Case { operand, when_clauses, else_clause } => {
-            operand.as_ref().map(|e| expr_has_aggregate(e)).unwrap_or(false)
-                || when_clauses.iter().any(|(c, t)| expr_has_aggregate(c) || expr_has_aggregate(t))
-                || else_clause.as_ref().map(|e| expr_has_aggregate(e)).unwrap_or(false)
+        Expr::Literal(_) | Expr::Column(_) | Expr::Subquery(_) | Expr::Exists { .. }
+        | Expr::Wildcard | Expr::QualifiedWildcard(_) => Vec::new(),
+        Expr::Function { args, within_group, filter, .. } => args.iter()
+            .chain(within_group.iter().map(|item| &item.expr))
+            .chain(filter.as_deref())
+            .collect(),
+        Expr::BinaryOp { left, right, .. } => vec![left, right],
+        Expr::UnaryOp { expr: inner, .. }
+        | Expr::Cast { expr: inner, .. }
+        | Expr::TryCast { expr: inner, .. }
+        | Expr::TypeCast { expr: inner, .. }
+        | Expr::Collate { expr: inner, .. }
+        | Expr::IsNull { expr: inner, .. }
+        | Expr::InSubquery { expr: inner, .. } => vec![inner],
+        Expr::Case { operand, when_clauses, else_clause } => operand.as_deref().into_iter()
+            .chain(when_clauses.iter().flat_map(|(cond, then)| [cond, then]))
+            .chain(else_clause.as_deref())
+            .collect(),
+        Expr::InList { expr: inner, list, .. } => std::iter::once(&**inner).chain(list).collect(),
+        Expr::Row(items) => items.iter().collect(),
+        Expr::Between { expr: inner, low, high, .. } => vec![inner, low, high],
+        Expr::Like { expr: inner, pattern, escape, .. } => [&**inner, pattern].into_iter().chain(escape.as_deref()).collect(),
+        Expr::SimilarTo { expr: inner, pattern, .. } => vec![inner, pattern],
+    }
+}
+
+/// `expr_children`, mutably.
+fn expr_children_mut(expr: &mut Expr) -> Vec<&mut Expr> {
+    match expr {
+        Expr::Literal(_) | Expr::Column(_) | Expr::Subquery(_) | Expr::Exists { .. }
+        | Expr::Wildcard | Expr::QualifiedWildcard(_) => Vec::new(),
+        Expr::Function { args, within_group, filter, .. } => args.iter_mut()
+            .chain(within_group.iter_mut().map(|item| &mut item.expr))
+            .chain(filter.as_deref_mut())
+            .collect(),
+        Expr::BinaryOp { left, right, .. } => vec![left, right],
+        Expr::UnaryOp { expr: inner, .. }
+        | Expr::Cast { expr: inner, .. }
+        | Expr::TryCast { expr: inner, .. }
+        | Expr::TypeCast { expr: inner, .. }
+        | Expr::Collate { expr: inner, .. }
+        | Expr::IsNull { expr: inner, .. }
+        | Expr::InSubquery { expr: inner, .. } => vec![inner],
+        Expr::Case { operand, when_clauses, else_clause } => operand.as_deref_mut().into_iter()
+            .chain(when_clauses.iter_mut().flat_map(|(cond, then)| [cond, then]))
+            .chain(else_clause.as_deref_mut())
+            .collect(),
+        Expr::InList { expr: inner, list, .. } => std::iter::once(&mut **inner).chain(list).collect(),
+        Expr::Row(items) => items.iter_mut().collect(),
+        Expr::Between { expr: inner, low, high, .. } => vec![inner, low, high],
+        Expr::Like { expr: inner, pattern, escape, .. } => [&mut **inner, &mut **pattern].into_iter().chain(escape.as_deref_mut()).collect(),
+        Expr::SimilarTo { expr: inner, pattern, .. } => vec![inner, pattern],
+    }
+}
+
+/// Copy of `expr` with each outermost subexpression `pick` accepts replaced
+/// by a reference to a new column appended to `cols`. Also returns the
+/// replaced expressions, whose values the caller appends to each row.
+fn extract_exprs(expr: &Expr, pick: &dyn Fn(&Expr) -> bool, cols: &mut Vec<Col>) -> (Expr, Vec<Expr>) {
+    extract_exprs_with(expr, pick, false, cols)
+}
+
+/// `extract_exprs` for the aggregate calls a window expression reads,
+/// including those in its windows' PARTITION BY and ORDER BY.
+fn extract_window_aggregates(expr: &Expr, cols: &mut Vec<Col>) -> (Expr, Vec<Expr>) {
+    extract_exprs_with(expr, &is_aggregate_call, true, cols)
+}
+
+fn extract_exprs_with(expr: &Expr, pick: &dyn Fn(&Expr) -> bool, windows: bool, cols: &mut Vec<Col>) -> (Expr, Vec<Expr>) {
+    fn walk(expr: &mut Expr, pick: &dyn Fn(&Expr) -> bool, windows: bool, cols: &mut Vec<Col>, out: &mut Vec<Expr>) {
+        if pick(expr) {
+            let name = format!("#{}", cols.len());
+            cols.push(Col { table: None, name: name.clone(), dtype: DataType::Utf8 });
+            out.push(std::mem::replace(expr, Expr::Column(ColumnRef { table: None, name, quoted: true })));
+            return;
+        }
+        if let Expr::Function { over: Some(spec), .. } = expr {
+            if windows {
+                for key in spec.partition_by.iter_mut().chain(spec.order_by.iter_mut().map(|item| &mut item.expr)) {
+                    walk(key, pick, windows, cols, out);
+                }
+            }
+        }
+        for child in expr_children_mut(expr) {
+            walk(child, pick, windows, cols, out);
         }
-        _ => false,
     }
+    let mut expr = expr.clone();
+    let mut out = Vec::new();
+    walk(&mut expr, pick, windows, cols, &mut out);
+    (expr, out)
 }
 
-fn expr_has_window(expr: &Expr) -> bool {
+/// Window functions are computed after grouping, so they cannot feed WHERE,
+/// GROUP BY or HAVING; reject them there rather than evaluate garbage.
+fn validate_window_placement(stmt: &SelectStatement) -> Result<()> {
+    let clauses = stmt.where_clause.iter().map(|e| ("WHERE", e))
+        .chain(stmt.group_by.iter().map(|e| ("GROUP BY", e)))
+        .chain(stmt.having.iter().map(|e| ("HAVING", e)));
+    for (clause, expr) in clauses {
+        if let Some(name) = find_window_function(expr) {
+            return Err(PivotError::SqlError(format!(
+                "Window function {} is not allowed in {}", name.to_uppercase(), clause
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Copy of `stmt` with every `OVER w` / `OVER (w ...)` replaced by the full
+/// spec from the WINDOW clause. A window definition may build on an earlier
+/// one.
+fn resolve_named_windows(stmt: &SelectStatement) -> Result<SelectStatement> {
+    let mut windows: Vec<(String, WindowSpec)> = Vec::new();
+    for (name, spec) in &stmt.windows {
+        if windows.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            return Err(PivotError::SqlError(format!("Window '{}' is defined more than once", name)));
+        }
+        let spec = merge_window_spec(spec, &windows)?;
+        windows.push((name.clone(), spec));
+    }
+    let mut out = stmt.clone();
+    for item in &mut out.columns {
+        if let SelectItem::Expr { expr, .. } = item {
+            resolve_window_refs(expr, &windows)?;
+        }
+    }
+    for item in &mut out.order_by {
+        resolve_window_refs(&mut item.expr, &windows)?;
+    }
+    out.windows.clear();
+    Ok(out)
+}
+
+/// `spec` with its base window (if any) filled in. The base supplies the
+/// partitioning; `spec` may add an ORDER BY only when the base has none,
+/// and its frame replaces the base's.
+fn merge_window_spec(spec: &WindowSpec, windows: &[(String, WindowSpec)]) -> Result<WindowSpec> {
+    let Some(name) = &spec.name else { return Ok(spec.clone()) };
+    let base = windows.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, s)| s)
+        .ok_or_else(|| PivotError::SqlError(format!("Window '{}' is not defined", name)))?;
+    if !spec.partition_by.is_empty() {
+        return Err(PivotError::SqlError(format!("Cannot override PARTITION BY of window '{}'", name)));
+    }
+    if !spec.order_by.is_empty() && !base.order_by.is_empty() {
+        return Err(PivotError::SqlError(format!("Cannot override ORDER BY of window '{}'", name)));
+    }
+    Ok(WindowSpec {
+        name: None,
+        partition_by: base.partition_by.clone(),
+        order_by: if spec.order_by.is_empty() { base.order_by.clone() } else { spec.order_by.clone() },
+        frame: spec.frame.clone().or_else(|| base.frame.clone()),
+    })
+}
+
+fn resolve_window_refs(expr: &mut Expr, windows: &[(String, WindowSpec)]) -> Result<()> {
+    if let Expr::Function { over: Some(spec), .. } = expr {
+        *spec = merge_window_spec(spec, windows)?;
+    }
+    expr_children_mut(expr).into_iter().try_for_each(|e| resolve_window_refs(e, windows))
+}
+
+/// The first windowed function call anywhere in `expr`, not looking inside
+/// subqueries (which are validated on their own).
+fn find_window_function(expr: &Expr) -> Option<&str> {
+    visit_expr(expr, &mut |e| match e {
+        Expr::Function { name, over: Some(_), .. } => Visit::Found(name.as_str()),
+        _ => Visit::Descend,
+    })
+}
+
+/// Flatten a tree of ANDs into its conjuncts.
+fn split_conjuncts<'a>(expr: &'a Expr, out: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::BinaryOp { left, op: BinOp::And, right } => {
+            split_conjuncts(left, out);
+            split_conjuncts(right, out);
+        }
+        _ => out.push(expr),
+    }
+}
+
+/// Every column `expr` reads, or false if it contains anything whose inputs
+/// can't be listed that way (subqueries, wildcards, window functions).
+fn collect_column_refs<'a>(expr: &'a Expr, out: &mut Vec<&'a ColumnRef>) -> bool {
+    visit_expr(expr, &mut |e| match e {
+        Expr::Column(c) => { out.push(c); Visit::Skip }
+        Expr::Function { over: Some(_), .. } | Expr::Subquery(_) | Expr::Exists { .. }
+        | Expr::InSubquery { .. } | Expr::Wildcard | Expr::QualifiedWildcard(_) => Visit::Found(()),
+        _ => Visit::Descend,
+    }).is_none()
+}
+
+/// Replace unqualified column references naming one of `aliases` (when
+/// `use_alias` agrees) with the aliased expression. Arguments of aggregates
+/// are not searched, so an alias never ends up nested inside an aggregate.
+/// Returns whether anything was replaced.
+fn substitute_aliases(expr: &mut Expr, aliases: &[(&str, &Expr)], fold: Fold, use_alias: &dyn Fn(&ColumnRef) -> bool) -> bool {
     match expr {
-        Expr::Function { over: Some(_), .. } => true,
-        Expr::BinaryOp { left, right, .. } => expr_has_window(left) || expr_has_window(right),
-        Expr::UnaryOp { expr: inner, .. } => expr_has_window(inner),
+        Expr::Column(c) if c.table.is_none() => {
+            let found = aliases.iter().find(|(a, _)| fold.matches_ref(a, &c.name, c.quoted));
+            match found {
+                Some((_, aliased)) if use_alias(c) => {
+                    *expr = (*aliased).clone();
+                    true
+                }
+                _ => false,
+            }
+        }
+        Expr::Function { ref name, .. } if is_aggregate_call(expr) && !name.eq_ignore_ascii_case("GROUPING") => false,
+        _ => expr_children_mut(expr).into_iter()
+            .fold(false, |acc, e| substitute_aliases(e, aliases, fold, use_alias) | acc),
+    }
+}
+
+/// The first input column in `expr` that is neither inside an aggregate nor
+/// covered by a GROUP BY key. Names that match no input column (such as
+/// SELECT aliases in HAVING) are left for evaluation to resolve.
+fn ungrouped_column(expr: &Expr, group_by: &[Expr], cols: &[Col], fold: Fold) -> Option<String> {
+    visit_expr(expr, &mut |e| {
+        let display = expr_display_name(e);
+        if group_by.iter().any(|g| expr_display_name(g).eq_ignore_ascii_case(&display)) {
+            return Visit::Skip;
+        }
+        match e {
+            Expr::Column(c) => match find_col_idx(cols, c, fold) {
+                Some(idx) if !group_by.iter().any(|g| matches!(g, Expr::Column(gc) if find_col_idx(cols, gc, fold) == Some(idx))) => {
+                    Visit::Found(c.name.clone())
+                }
+                _ => Visit::Skip,
+            },
+            Expr::Function { over: Some(_), .. } => Visit::Skip,
+            _ if is_aggregate_call(e) => Visit::Skip,
+            _ => Visit::Descend,
+        }
+    })
+}
+
+/// Copy a stored table into a row set, tagging its columns with `alias`.
+fn store_rowset(store: &DataStore, alias: &str) -> Result<RowSet> {
+    let cols: Vec<Col> = store.schema().columns.iter().map(|c| Col {
+        table: Some(alias.to_string()),
+        name: c.name.clone(),
+        dtype: c.data_type.clone(),
+    }).collect();
+    let mut rs = RowSet::new(cols);
+    for row in 0..store.row_count() {
+        rs.rows.push(store.get_row(row)?);
+    }
+    Ok(rs)
+}
+
+/// Candidate right rows for each left row of an equi-join, from a hash
+/// table built over the smaller input. Keys with a NULL never match, as
+/// under `=`; hash-equal keys may still differ, so callers check each pair.
+enum HashProbe {
+    /// Keyed right rows, probed with each left row's key.
+    Right { table: HashMap<Vec<HashableScalar>, Vec<usize>>, left_cols: Vec<usize> },
+    /// The matches of every left row, in right-row order.
+    Left(Vec<Vec<usize>>),
+}
+
+impl HashProbe {
+    fn build(left: &[Vec<ScalarValue>], right: &[Vec<ScalarValue>], keys: &[(usize, usize)]) -> Self {
+        let left_cols: Vec<usize> = keys.iter().map(|&(l, _)| l).collect();
+        let right_cols: Vec<usize> = keys.iter().map(|&(_, r)| r).collect();
+        let index = |rows: &[Vec<ScalarValue>], cols: &[usize]| {
+            let mut table: HashMap<Vec<HashableScalar>, Vec<usize>> = HashMap::new();
+            for (i, row) in rows.iter().enumerate() {
+                if let Some(key) = join_key(row, cols) {
+                    table.entry(key).or_default().push(i);
+                }
+            }
+            table
+        };
+        if right.len() <= left.len() {
+            return HashProbe::Right { table: index(right, &right_cols), left_cols };
+        }
+        let table = index(left, &left_cols);
+        let mut matches = vec![Vec::new(); left.len()];
+        for (ri, row) in right.iter().enumerate() {
+            if let Some(lis) = join_key(row, &right_cols).and_then(|key| table.get(&key)) {
+                for &li in lis {
+                    matches[li].push(ri);
+                }
+            }
+        }
+        HashProbe::Left(matches)
+    }
+
+    fn candidates(&self, li: usize, row: &[ScalarValue]) -> &[usize] {
+        match self {
+            HashProbe::Right { table, left_cols } => join_key(row, left_cols)
+                .and_then(|key| table.get(&key))
+                .map_or(&[], |v| v.as_slice()),
+            HashProbe::Left(matches) => &matches[li],
+        }
+    }
+}
+
+/// Hash key of a row's join columns, None if any is NULL. Integers hash as
+/// the float they compare equal to, so `1 = 1.0` pairs.
+fn join_key(row: &[ScalarValue], cols: &[usize]) -> Option<Vec<HashableScalar>> {
+    cols.iter().map(|&i| match &row[i] {
+        ScalarValue::Null => None,
+        ScalarValue::Int64(v) => Some(HashableScalar(ScalarValue::Float64(*v as f64))),
+        v => Some(HashableScalar(v.clone())),
+    }).collect()
+}
+
+/// Collapse each NATURAL join key pair into its left column, taking the
+/// right value where the left side is NULL-extended.
+fn merge_natural_keys(rs: RowSet, left_len: usize, keys: &[(usize, usize)]) -> RowSet {
+    let dropped: HashSet<usize> = keys.iter().map(|&(_, ri)| left_len + ri).collect();
+    let keep = |i: &usize| !dropped.contains(i);
+    let cols = rs.cols.into_iter().enumerate().filter(|(i, _)| keep(i)).map(|(_, c)| c).collect();
+    let mut result = RowSet::new(cols);
+    result.rows = rs.rows.into_iter().map(|mut row| {
+        for &(li, ri) in keys {
+            if matches!(row[li], ScalarValue::Null) {
+                row[li] = std::mem::replace(&mut row[left_len + ri], ScalarValue::Null);
+            }
+        }
+        row.into_iter().enumerate().filter(|(i, _)| keep(i)).map(|(_, v)| v).collect()
+    }).collect();
+    result
+}
+
+/// Reject an INSERT that supplies a value for a GENERATED ALWAYS identity
+/// column; `targets` are the schema indices the INSERT writes.
+fn check_generated_always(schema: &Schema, targets: &[usize]) -> Result<()> {
+    match targets.iter().map(|&i| &schema.columns[i]).find(|c| c.generated_always) {
+        Some(col) => Err(PivotError::SqlError(format!(
+            "Cannot insert a value into column '{}', which is GENERATED ALWAYS AS IDENTITY", col.name
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Apply a CTE's column list to the rows of its query.
+fn name_cte_columns(cte: &Cte, mut rs: RowSet) -> Result<RowSet> {
+    if cte.columns.is_empty() {
+        return Ok(rs);
+    }
+    if cte.columns.len() != rs.cols.len() {
+        return Err(PivotError::SqlError(format!(
+            "CTE '{}' names {} columns but its query returns {}",
+            cte.name, cte.columns.len(), rs.cols.len()
+        )));
+    }
+    for (col, name) in rs.cols.iter_mut().zip(&cte.columns) {
+        col.name = name.clone();
+    }
+    Ok(rs)
+}
+
+/// Whether `stmt` reads `table` in a FROM or JOIN clause, directly or
+/// through a derived table, set operation or WITH.
+fn statement_reads_table(stmt: &Statement, table: &str) -> bool {
+    fn reads_ref(t: &TableRef, table: &str) -> bool {
+        match t {
+            TableRef::Table { name, .. } => name.name.eq_ignore_ascii_case(table),
+            TableRef::Subquery { query, .. } => statement_reads_table(query, table),
+            TableRef::Function { .. } => false,
+            TableRef::Pivot(p) => reads_ref(&p.source, table),
+            TableRef::Unpivot(u) => reads_ref(&u.source, table),
+        }
+    }
+    let reads = |t: &TableRef| reads_ref(t, table);
+    match stmt {
+        Statement::Select(s) => s.from.as_ref().is_some_and(reads) || s.joins.iter().any(|j| reads(&j.table)),
+        Statement::SetOp(s) => statement_reads_table(&s.left, table) || statement_reads_table(&s.right, table),
+        Statement::With(w) => w.ctes.iter().any(|c| statement_reads_table(&c.query, table))
+            || statement_reads_table(&w.body, table),
         _ => false,
     }
 }
 
+/// The rows window functions read: the projected columns, then the input
+/// columns of a row-for-row projection. `None` when there is no such input.
+fn window_input(rs: &RowSet, source: Option<&RowSet>) -> Option<RowSet> {
+    let src = source.filter(|src| src.rows.len() == rs.rows.len())?;
+    let mut cols = rs.cols.clone();
+    cols.extend(src.cols.iter().cloned());
+    let rows = rs.rows.iter().zip(&src.rows).map(|(row, extra)| {
+        let mut row = row.clone();
+        row.extend(extra.iter().cloned());
+        row
+    }).collect();
+    Some(RowSet { cols, rows })
+}
+
 fn tag_rowset(mut rs: RowSet, alias: &str) -> RowSet {
     for col in &mut rs.cols {
         col.table = Some(alias.to_string());
@@ -1816,18 +4426,10 @@ fn tag_rowset(mut rs: RowSet, alias: &str) -> RowSet {
     rs
 }
 
+/// Remove duplicate rows, keeping the first occurrence. NULLs are not
+/// distinct from each other here (see `HashableScalar`).
 fn dedup_rowset(mut rs: RowSet) -> RowSet {
-    let mut seen: Vec<Vec<ScalarValue>> = Vec::new();
-    rs.rows.retain(|row| {
-        let key: Vec<String> = row.iter().map(|v| format!("{:?}", v)).collect();
-        if seen.iter().any(|s| {
-            s.iter().zip(row.iter()).all(|(a, b)| format!("{:?}", a) == format!("{:?}", b))
-        }) {
-            false
-        } else {
-            seen.push(row.clone());
-            true
-        }
-    });
+    let mut seen: HashSet<Vec<HashableScalar>> = HashSet::new();
+    rs.rows.retain(|row| seen.insert(row_key(row)));
     rs
 }
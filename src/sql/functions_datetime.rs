@@ -1,24 +1,14 @@
 use crate::column::{
-    ScalarValue, epoch_days_to_ymd, ymd_to_epoch_days, epoch_days_to_date_string,
-    epoch_micros_to_ts_string, micros_to_time_string, date_string_to_epoch_days,
-    timestamp_string_to_epoch_micros,
+    IntervalValue, ScalarValue, add_interval_to_days, add_interval_to_micros, days_in_month, epoch_days_to_ymd,
+    ymd_to_epoch_days, date_string_to_epoch_days, timestamp_string_to_epoch_micros, utc_offset_micros,
 };
 
 /// Dispatch date/time functions.
 /// Returns None if the function is not recognized here.
 pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
     let result = match name {
-        "CURRENT_DATE" | "TODAY" | "GETDATE" => {
-            // Return a fixed date (1970-01-01 in tests; in real use would be system date)
-            Some(ScalarValue::Date(0))
-        }
-        "CURRENT_TIMESTAMP" | "NOW" | "CURRENT_TIMESTAMP()" => {
-            Some(ScalarValue::Timestamp(0))
-        }
-        "CURRENT_TIME" => Some(ScalarValue::Time(0)),
-
         "DATE" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Utf8(s)) => {
                     date_string_to_epoch_days(s)
                         .map(ScalarValue::Date)
@@ -31,7 +21,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "TIMESTAMP" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Utf8(s)) => {
                     timestamp_string_to_epoch_micros(s)
                         .map(ScalarValue::Timestamp)
@@ -43,8 +33,28 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
 
+        // TIMEZONE(zone, ts) backs `ts AT TIME ZONE zone`: shift a UTC
+        // timestamp to the wall-clock time at a fixed offset.
+        "TIMEZONE" => {
+            let ts = match args.get(1) {
+                Some(ScalarValue::Timestamp(t)) => Some(*t),
+                Some(ScalarValue::Date(d)) => Some(d * 86_400_000_000),
+                Some(ScalarValue::Utf8(s)) => timestamp_string_to_epoch_micros(s),
+                _ => None,
+            };
+            let offset = match args.first() {
+                Some(ScalarValue::Utf8(z)) => utc_offset_micros(z),
+                Some(ScalarValue::Interval(iv)) => Some(iv.days as i64 * 86_400_000_000 + iv.micros),
+                _ => None,
+            };
+            match (ts, offset) {
+                (Some(t), Some(o)) => Some(ScalarValue::Timestamp(t + o)),
+                _ => Some(ScalarValue::Null),
+            }
+        }
+
         "YEAR" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Date(d)) => {
                     let (y, _, _) = epoch_days_to_ymd(*d);
                     Some(ScalarValue::Int64(y as i64))
@@ -65,7 +75,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "MONTH" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Date(d)) => {
                     let (_, m, _) = epoch_days_to_ymd(*d);
                     Some(ScalarValue::Int64(m as i64))
@@ -86,7 +96,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "DAY" | "DAYOFMONTH" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Date(d)) => {
                     let (_, _, day) = epoch_days_to_ymd(*d);
                     Some(ScalarValue::Int64(day as i64))
@@ -101,7 +111,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "HOUR" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Time(t)) => {
                     Some(ScalarValue::Int64(t / 3_600_000_000))
                 }
@@ -115,7 +125,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "MINUTE" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Time(t)) => {
                     Some(ScalarValue::Int64((t / 60_000_000) % 60))
                 }
@@ -129,7 +139,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "SECOND" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Time(t)) => {
                     Some(ScalarValue::Int64((t / 1_000_000) % 60))
                 }
@@ -142,7 +152,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "DATE_TRUNC" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(unit)), Some(ScalarValue::Date(d))) => {
                     Some(date_trunc_date(unit, *d))
                 }
@@ -154,7 +164,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "DATE_PART" | "EXTRACT" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(field)), Some(val)) => {
                     Some(extract_field(field, val))
                 }
@@ -163,15 +173,23 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "DATEDIFF" | "DATE_DIFF" => {
-            match (args.get(0), args.get(1), args.get(2)) {
+            match (args.first(), args.get(1), args.get(2)) {
                 (Some(ScalarValue::Utf8(unit)), Some(a), Some(b)) => {
                     let da = coerce_to_days(a);
                     let db = coerce_to_days(b);
+                    // Months and years count calendar boundaries crossed,
+                    // so Jan 31 to Feb 1 is one month.
+                    let (ya, ma, _) = epoch_days_to_ymd(da);
+                    let (yb, mb, _) = epoch_days_to_ymd(db);
+                    let months = (yb as i64 * 12 + mb as i64) - (ya as i64 * 12 + ma as i64);
                     match unit.to_lowercase().as_str() {
                         "day" | "days" => Some(ScalarValue::Int64(db - da)),
                         "week" | "weeks" => Some(ScalarValue::Int64((db - da) / 7)),
-                        "month" | "months" => Some(ScalarValue::Int64((db - da) / 30)),
-                        "year" | "years" => Some(ScalarValue::Int64((db - da) / 365)),
+                        "month" | "months" => Some(ScalarValue::Int64(months)),
+                        "quarter" | "quarters" => {
+                            Some(ScalarValue::Int64((yb as i64 * 4 + (mb as i64 - 1) / 3) - (ya as i64 * 4 + (ma as i64 - 1) / 3)))
+                        }
+                        "year" | "years" => Some(ScalarValue::Int64(yb as i64 - ya as i64)),
                         _ => Some(ScalarValue::Int64(db - da)),
                     }
                 }
@@ -184,31 +202,101 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
 
-        "DATE_ADD" | "DATEADD" => {
-            match (args.get(0), args.get(1), args.get(2)) {
+        // DATE_ADD('month', n, value), DATE_ADD(value, interval) or
+        // DATE_ADD(value, days); DATE_SUB subtracts instead.
+        "DATE_ADD" | "DATEADD" | "DATE_SUB" => {
+            let sign = if name == "DATE_SUB" { -1 } else { 1 };
+            let (val, iv) = match (args.first(), args.get(1), args.get(2)) {
                 (Some(ScalarValue::Utf8(unit)), Some(ScalarValue::Int64(n)), Some(val)) => {
-                    let days = coerce_to_days(val);
-                    let result = match unit.to_lowercase().as_str() {
-                        "day" | "days" => days + n,
-                        "week" | "weeks" => days + n * 7,
-                        "month" | "months" => days + n * 30,
-                        "year" | "years" => days + n * 365,
-                        _ => days + n,
+                    // NULL when the count doesn't fit the interval field it lands in.
+                    let field = |scale: i64| n.checked_mul(scale).and_then(|v| i32::try_from(v).ok());
+                    let micros = |scale: i64| n.checked_mul(scale);
+                    let iv = match unit.to_lowercase().as_str() {
+                        "week" | "weeks" => field(7).map(|d| IntervalValue::new(0, 0, d, 0)),
+                        "month" | "months" => field(1).map(|m| IntervalValue::new(0, m, 0, 0)),
+                        "quarter" | "quarters" => field(3).map(|m| IntervalValue::new(0, m, 0, 0)),
+                        "year" | "years" => field(1).map(|y| IntervalValue::new(y, 0, 0, 0)),
+                        "hour" | "hours" => micros(3_600_000_000).map(|us| IntervalValue::new(0, 0, 0, us)),
+                        "minute" | "minutes" => micros(60_000_000).map(|us| IntervalValue::new(0, 0, 0, us)),
+                        "second" | "seconds" => micros(1_000_000).map(|us| IntervalValue::new(0, 0, 0, us)),
+                        _ => field(1).map(|d| IntervalValue::new(0, 0, d, 0)),
                     };
-                    Some(ScalarValue::Date(result))
+                    match iv {
+                        Some(iv) => (val, iv),
+                        None => return Some(ScalarValue::Null),
+                    }
+                }
+                (Some(val), Some(ScalarValue::Interval(iv)), None) => (val, iv.clone()),
+                (Some(val), Some(ScalarValue::Int64(n)), None) => match i32::try_from(*n) {
+                    Ok(days) => (val, IntervalValue::new(0, 0, days, 0)),
+                    Err(_) => return Some(ScalarValue::Null),
+                },
+                _ => return Some(ScalarValue::Null),
+            };
+            let Some(iv) = (if sign < 0 { IntervalValue::zero().checked_sub(&iv) } else { Some(iv) }) else {
+                return Some(ScalarValue::Null);
+            };
+            // NULL when the result is out of range.
+            let moved = match val {
+                ScalarValue::Null => None,
+                ScalarValue::Timestamp(t) => add_interval_to_micros(*t, &iv).map(ScalarValue::Timestamp),
+                _ if iv.micros != 0 => coerce_to_days(val).checked_mul(86_400_000_000)
+                    .and_then(|t| add_interval_to_micros(t, &iv))
+                    .map(ScalarValue::Timestamp),
+                _ => add_interval_to_days(coerce_to_days(val), &iv).map(ScalarValue::Date),
+            };
+            Some(moved.unwrap_or(ScalarValue::Null))
+        }
+
+        "TO_DATE" => {
+            match (args.first(), args.get(1)) {
+                (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(fmt))) => {
+                    Some(parse_with_format(s, fmt).map(|micros| ScalarValue::Date(micros.div_euclid(86_400_000_000)))
+                        .unwrap_or(ScalarValue::Null))
+                }
+                (Some(ScalarValue::Utf8(s)), None) => {
+                    Some(date_string_to_epoch_days(s).map(ScalarValue::Date).unwrap_or(ScalarValue::Null))
+                }
+                _ => Some(ScalarValue::Null),
+            }
+        }
+
+        "TO_TIMESTAMP" => {
+            match (args.first(), args.get(1)) {
+                (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(fmt))) => {
+                    Some(parse_with_format(s, fmt).map(ScalarValue::Timestamp).unwrap_or(ScalarValue::Null))
+                }
+                // Seconds since the epoch; NULL past the timestamp range
+                (Some(ScalarValue::Int64(secs)), None) => {
+                    Some(secs.checked_mul(1_000_000).map(ScalarValue::Timestamp).unwrap_or(ScalarValue::Null))
+                }
+                (Some(ScalarValue::Float64(secs)), None) => {
+                    let us = (secs * 1_000_000.0).round();
+                    Some(if us >= i64::MIN as f64 && us < i64::MAX as f64 {
+                        ScalarValue::Timestamp(us as i64)
+                    } else {
+                        ScalarValue::Null
+                    })
                 }
                 _ => Some(ScalarValue::Null),
             }
         }
 
-        "STRFTIME" | "FORMAT_DATE" | "TO_DATE" => {
-            match (args.get(0), args.get(1)) {
+        "STRFTIME" | "FORMAT_DATE" => {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(fmt)), Some(ScalarValue::Date(d))) => {
                     Some(ScalarValue::Utf8(format_date_str(*d, fmt)))
                 }
                 (Some(ScalarValue::Utf8(fmt)), Some(ScalarValue::Timestamp(t))) => {
                     Some(ScalarValue::Utf8(format_ts_str(*t, fmt)))
                 }
+                // STRFTIME(value, format) as in DuckDB
+                (Some(ScalarValue::Date(d)), Some(ScalarValue::Utf8(fmt))) => {
+                    Some(ScalarValue::Utf8(format_date_str(*d, fmt)))
+                }
+                (Some(ScalarValue::Timestamp(t)), Some(ScalarValue::Utf8(fmt))) => {
+                    Some(ScalarValue::Utf8(format_ts_str(*t, fmt)))
+                }
                 (Some(ScalarValue::Utf8(s)), None) => {
                     // Treat as date string parse
                     date_string_to_epoch_days(s)
@@ -220,7 +308,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "EPOCH" | "EPOCH_MS" => {
-            match args.get(0) {
+            match args.first() {
                 Some(ScalarValue::Timestamp(t)) => {
                     if name == "EPOCH" {
                         Some(ScalarValue::Int64(t / 1_000_000))
@@ -236,7 +324,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         "MAKE_DATE" => {
-            match (args.get(0), args.get(1), args.get(2)) {
+            match (args.first(), args.get(1), args.get(2)) {
                 (Some(ScalarValue::Int64(y)), Some(ScalarValue::Int64(m)), Some(ScalarValue::Int64(d))) => {
                     Some(ScalarValue::Date(ymd_to_epoch_days(*y as i32, *m as u32, *d as u32)))
                 }
@@ -264,7 +352,7 @@ fn coerce_to_days(v: &ScalarValue) -> i64 {
 }
 
 fn date_trunc_date(unit: &str, days: i64) -> ScalarValue {
-    let (y, m, d) = epoch_days_to_ymd(days);
+    let (y, m, _d) = epoch_days_to_ymd(days);
     let result_days = match unit.to_lowercase().as_str() {
         "year" | "years" => ymd_to_epoch_days(y, 1, 1),
         "month" | "months" => ymd_to_epoch_days(y, m, 1),
@@ -285,7 +373,7 @@ fn date_trunc_date(unit: &str, days: i64) -> ScalarValue {
 
 fn date_trunc_ts(unit: &str, micros: i64) -> ScalarValue {
     let days = micros / 86_400_000_000;
-    let (y, m, d) = epoch_days_to_ymd(days);
+    let (y, m, _d) = epoch_days_to_ymd(days);
     let secs_of_day = (micros / 1_000_000).rem_euclid(86400);
     let h = secs_of_day / 3600;
     let min = (secs_of_day % 3600) / 60;
@@ -333,10 +421,122 @@ fn extract_field(field: &str, val: &ScalarValue) -> ScalarValue {
     }
 }
 
-fn format_date_str(days: i64, _fmt: &str) -> String {
-    epoch_days_to_date_string(days)
+const DAY_NAMES: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+const MONTH_NAMES: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June",
+    "July", "August", "September", "October", "November", "December",
+];
+
+/// Parse `s` by a TO_DATE-style format into epoch microseconds. Fields are
+/// `YYYY MM DD HH24 HH MI SS`, `Mon` / `Month` for month names and `AM` / `PM`
+/// (all case-insensitive); any other format character must match itself.
+/// `None` if the text doesn't fit the format or names an invalid date.
+fn parse_with_format(s: &str, fmt: &str) -> Option<i64> {
+    let text = s.trim();
+    let upper_fmt = fmt.to_uppercase();
+    let (mut f, mut t) = (upper_fmt.as_str(), text);
+    let (mut year, mut month, mut day) = (1970i64, 1i64, 1i64);
+    let (mut hour, mut minute, mut second) = (0i64, 0i64, 0i64);
+    let mut pm: Option<bool> = None;
+    while !f.is_empty() {
+        if let Some(rest) = f.strip_prefix("YYYY") {
+            year = take_digits(&mut t, 4)?;
+            f = rest;
+        } else if let Some(rest) = f.strip_prefix("MONTH") {
+            let i = MONTH_NAMES.iter().position(|m| t.get(..m.len()).is_some_and(|p| p.eq_ignore_ascii_case(m)))?;
+            t = &t[MONTH_NAMES[i].len()..];
+            month = i as i64 + 1;
+            f = rest;
+        } else if let Some(rest) = f.strip_prefix("MON") {
+            let i = MONTH_NAMES.iter().position(|m| t.get(..3).is_some_and(|p| p.eq_ignore_ascii_case(&m[..3])))?;
+            t = &t[3..];
+            month = i as i64 + 1;
+            f = rest;
+        } else if let Some(rest) = f.strip_prefix("MM") {
+            month = take_digits(&mut t, 2)?;
+            f = rest;
+        } else if let Some(rest) = f.strip_prefix("DD") {
+            day = take_digits(&mut t, 2)?;
+            f = rest;
+        } else if let Some(rest) = f.strip_prefix("HH24").or_else(|| f.strip_prefix("HH")) {
+            hour = take_digits(&mut t, 2)?;
+            f = rest;
+        } else if let Some(rest) = f.strip_prefix("MI") {
+            minute = take_digits(&mut t, 2)?;
+            f = rest;
+        } else if let Some(rest) = f.strip_prefix("SS") {
+            second = take_digits(&mut t, 2)?;
+            f = rest;
+        } else if let Some(rest) = f.strip_prefix("AM").or_else(|| f.strip_prefix("PM")) {
+            let marker = t.get(..2)?.to_uppercase();
+            pm = Some(match marker.as_str() { "AM" => false, "PM" => true, _ => return None });
+            t = &t[2..];
+            f = rest;
+        } else {
+            let c = f.chars().next()?;
+            let tc = t.chars().next()?;
+            if !c.eq_ignore_ascii_case(&tc) { return None; }
+            f = &f[c.len_utf8()..];
+            t = &t[tc.len_utf8()..];
+        }
+    }
+    if !t.is_empty() { return None; }
+    if let Some(pm) = pm {
+        if !(1..=12).contains(&hour) { return None; }
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year as i32, month as u32) as i64
+        || hour > 23 || minute > 59 || second > 59
+    {
+        return None;
+    }
+    let days = ymd_to_epoch_days(year as i32, month as u32, day as u32);
+    Some(days * 86_400_000_000 + (hour * 3600 + minute * 60 + second) * 1_000_000)
+}
+
+/// Consume up to `max` leading digits of `t`.
+fn take_digits(t: &mut &str, max: usize) -> Option<i64> {
+    let n = t.chars().take(max).take_while(|c| c.is_ascii_digit()).count();
+    if n == 0 { return None; }
+    let v = t[..n].parse().ok()?;
+    *t = &t[n..];
+    Some(v)
+}
+
+fn format_date_str(days: i64, fmt: &str) -> String {
+    format_ts_str(days * 86_400_000_000, fmt)
 }
 
-fn format_ts_str(micros: i64, _fmt: &str) -> String {
-    epoch_micros_to_ts_string(micros)
+/// strftime-style formatting: `%Y %m %d %H %I %M %S %j %A %B %p` and `%%`.
+/// Any other `%x` is copied through as written.
+fn format_ts_str(micros: i64, fmt: &str) -> String {
+    let days = micros.div_euclid(86_400_000_000);
+    let secs_of_day = micros.rem_euclid(86_400_000_000) / 1_000_000;
+    let (y, m, d) = epoch_days_to_ymd(days);
+    let hour = secs_of_day / 3600;
+    let mut out = String::new();
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", y)),
+            Some('m') => out.push_str(&format!("{:02}", m)),
+            Some('d') => out.push_str(&format!("{:02}", d)),
+            Some('H') => out.push_str(&format!("{:02}", hour)),
+            Some('I') => out.push_str(&format!("{:02}", (hour + 11) % 12 + 1)),
+            Some('M') => out.push_str(&format!("{:02}", (secs_of_day % 3600) / 60)),
+            Some('S') => out.push_str(&format!("{:02}", secs_of_day % 60)),
+            Some('j') => out.push_str(&format!("{:03}", days - ymd_to_epoch_days(y, 1, 1) + 1)),
+            Some('A') => out.push_str(DAY_NAMES[(days + 4).rem_euclid(7) as usize]),
+            Some('B') => out.push_str(MONTH_NAMES[m as usize - 1]),
+            Some('p') => out.push_str(if hour < 12 { "AM" } else { "PM" }),
+            Some('%') => out.push('%'),
+            Some(other) => { out.push('%'); out.push(other); }
+            None => out.push('%'),
+        }
+    }
+    out
 }
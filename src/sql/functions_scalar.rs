@@ -1,48 +1,49 @@
 use crate::column::ScalarValue;
+use crate::sql::regex::Regex;
 
 /// Dispatch scalar (non-aggregate, non-datetime) functions.
 /// Returns None if the function name is not recognized here.
 pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
     let result = match name {
         // String functions
-        "UPPER" => args.get(0).map(|v| match v {
+        "UPPER" => args.first().map(|v| match v {
             ScalarValue::Utf8(s) => ScalarValue::Utf8(s.to_uppercase()),
             _ => ScalarValue::Null,
         }),
-        "LOWER" => args.get(0).map(|v| match v {
+        "LOWER" => args.first().map(|v| match v {
             ScalarValue::Utf8(s) => ScalarValue::Utf8(s.to_lowercase()),
             _ => ScalarValue::Null,
         }),
         "LENGTH" | "LEN" | "CHAR_LENGTH" | "CHARACTER_LENGTH" => {
-            args.get(0).map(|v| match v {
+            args.first().map(|v| match v {
                 ScalarValue::Utf8(s) => ScalarValue::Int64(s.chars().count() as i64),
                 _ => ScalarValue::Null,
             })
         }
         "OCTET_LENGTH" | "BYTE_LENGTH" => {
-            args.get(0).map(|v| match v {
+            args.first().map(|v| match v {
                 ScalarValue::Utf8(s) => ScalarValue::Int64(s.len() as i64),
                 _ => ScalarValue::Null,
             })
         }
-        "TRIM" => args.get(0).map(|v| match v {
+        "TRIM" => args.first().map(|v| match v {
             ScalarValue::Utf8(s) => ScalarValue::Utf8(s.trim().to_string()),
             _ => ScalarValue::Null,
         }),
-        "LTRIM" => args.get(0).map(|v| match v {
+        "LTRIM" => args.first().map(|v| match v {
             ScalarValue::Utf8(s) => ScalarValue::Utf8(s.trim_start().to_string()),
             _ => ScalarValue::Null,
         }),
-        "RTRIM" => args.get(0).map(|v| match v {
+        "RTRIM" => args.first().map(|v| match v {
             ScalarValue::Utf8(s) => ScalarValue::Utf8(s.trim_end().to_string()),
             _ => ScalarValue::Null,
         }),
-        "REVERSE" => args.get(0).map(|v| match v {
+        "REVERSE" => args.first().map(|v| match v {
             ScalarValue::Utf8(s) => ScalarValue::Utf8(s.chars().rev().collect()),
             _ => ScalarValue::Null,
         }),
         "SUBSTR" | "SUBSTRING" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Int64(start))) => {
                     let s_chars: Vec<char> = s.chars().collect();
                     let idx = (*start - 1).max(0) as usize;
@@ -59,7 +60,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "LEFT" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Int64(n))) => {
                     let chars: Vec<char> = s.chars().collect();
                     let n = (*n).max(0) as usize;
@@ -69,7 +70,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "RIGHT" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Int64(n))) => {
                     let chars: Vec<char> = s.chars().collect();
                     let n = (*n).max(0) as usize;
@@ -80,7 +81,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "REPEAT" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Int64(n))) => {
                     Some(ScalarValue::Utf8(s.repeat(*n as usize)))
                 }
@@ -88,13 +89,67 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "REPLACE" => {
-            match (args.get(0), args.get(1), args.get(2)) {
-                (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(from)), Some(ScalarValue::Utf8(to))) => {
+            // REPLACE(s, from, to [, occurrence]): occurrence n >= 1 replaces only
+            // the nth match; 0 or omitted replaces all of them.
+            let occurrence = match args.get(3) {
+                None => Some(0),
+                Some(ScalarValue::Int64(n)) if *n >= 0 => Some(*n as usize),
+                _ => None,
+            };
+            match (args.first(), args.get(1), args.get(2), occurrence) {
+                (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(from)), Some(ScalarValue::Utf8(to)), Some(0)) => {
                     Some(ScalarValue::Utf8(s.replace(from.as_str(), to.as_str())))
                 }
+                (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(from)), Some(ScalarValue::Utf8(to)), Some(n)) => {
+                    let replaced = match s.match_indices(from.as_str()).nth(n - 1) {
+                        Some((pos, _)) if !from.is_empty() => {
+                            format!("{}{}{}", &s[..pos], to, &s[pos + from.len()..])
+                        }
+                        _ => s.clone(),
+                    };
+                    Some(ScalarValue::Utf8(replaced))
+                }
+                _ => Some(ScalarValue::Null),
+            }
+        }
+        "REGEXP_REPLACE" => {
+            // REGEXP_REPLACE(s, pattern, replacement [, flags | occurrence]):
+            // replaces the first match by default, every match with flag 'g',
+            // or only the nth match when given an integer.
+            let occurrence = match args.get(3) {
+                None => Some(1),
+                Some(ScalarValue::Utf8(flags)) => Some(if flags.contains('g') { 0 } else { 1 }),
+                Some(ScalarValue::Int64(n)) if *n >= 0 => Some(*n as usize),
+                _ => None,
+            };
+            match (args.first(), args.get(1), args.get(2), occurrence) {
+                (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(pat)), Some(ScalarValue::Utf8(rep)), Some(n)) => {
+                    match Regex::cached(pat) {
+                        Ok(re) => Some(ScalarValue::Utf8(re.replace(s, rep, n))),
+                        Err(_) => Some(ScalarValue::Null),
+                    }
+                }
                 _ => Some(ScalarValue::Null),
             }
         }
+        // Invalid patterns give NULL rather than an error, as in REGEXP_REPLACE.
+        "REGEXP_LIKE" => match (args.first(), args.get(1)) {
+            (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(pat))) => Some(match Regex::cached(pat) {
+                Ok(re) => ScalarValue::Boolean(re.is_match(s)),
+                Err(_) => ScalarValue::Null,
+            }),
+            _ => Some(ScalarValue::Null),
+        },
+        "REGEXP_SUBSTR" => match (args.first(), args.get(1)) {
+            // The leftmost match, or NULL when there is none.
+            (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(pat))) => Some(
+                Regex::cached(pat).ok()
+                    .and_then(|re| re.captures(s))
+                    .and_then(|caps| caps[0])
+                    .map_or(ScalarValue::Null, |(b, e)| ScalarValue::Utf8(s[b..e].to_string())),
+            ),
+            _ => Some(ScalarValue::Null),
+        },
         "CONCAT" => {
             let mut result = String::new();
             for arg in args {
@@ -107,7 +162,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             Some(ScalarValue::Utf8(result))
         }
         "CONCAT_WS" => {
-            let sep = match args.get(0) {
+            let sep = match args.first() {
                 Some(ScalarValue::Utf8(s)) => s.clone(),
                 _ => ",".to_string(),
             };
@@ -119,7 +174,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             Some(ScalarValue::Utf8(parts.join(&sep)))
         }
         "SPLIT_PART" => {
-            match (args.get(0), args.get(1), args.get(2)) {
+            match (args.first(), args.get(1), args.get(2)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(delim)), Some(ScalarValue::Int64(n))) => {
                     let parts: Vec<&str> = s.split(delim.as_str()).collect();
                     let idx = (*n - 1).max(0) as usize;
@@ -129,7 +184,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "STARTS_WITH" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(prefix))) => {
                     Some(ScalarValue::Boolean(s.starts_with(prefix.as_str())))
                 }
@@ -137,7 +192,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "ENDS_WITH" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(suffix))) => {
                     Some(ScalarValue::Boolean(s.ends_with(suffix.as_str())))
                 }
@@ -145,7 +200,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "CONTAINS" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Utf8(needle))) => {
                     Some(ScalarValue::Boolean(s.contains(needle.as_str())))
                 }
@@ -154,7 +209,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
         "POSITION" => {
             // POSITION(needle IN haystack) - simplified as POSITION(needle, haystack)
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(needle)), Some(ScalarValue::Utf8(hay))) => {
                     let pos = hay.find(needle.as_str())
                         .map(|i| i as i64 + 1)
@@ -165,7 +220,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "LPAD" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Int64(n))) => {
                     let pad_char = match args.get(2) {
                         Some(ScalarValue::Utf8(p)) => p.chars().next().unwrap_or(' '),
@@ -176,7 +231,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
                     if len >= n {
                         Some(ScalarValue::Utf8(s.chars().take(n).collect()))
                     } else {
-                        let pad: String = std::iter::repeat(pad_char).take(n - len).collect();
+                        let pad: String = std::iter::repeat_n(pad_char, n - len).collect();
                         Some(ScalarValue::Utf8(pad + s))
                     }
                 }
@@ -184,7 +239,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "RPAD" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Utf8(s)), Some(ScalarValue::Int64(n))) => {
                     let pad_char = match args.get(2) {
                         Some(ScalarValue::Utf8(p)) => p.chars().next().unwrap_or(' '),
@@ -204,7 +259,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             }
         }
         "ASCII" => {
-            args.get(0).map(|v| match v {
+            args.first().map(|v| match v {
                 ScalarValue::Utf8(s) => {
                     ScalarValue::Int64(s.chars().next().map(|c| c as i64).unwrap_or(0))
                 }
@@ -212,7 +267,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             })
         }
         "CHR" | "CHAR" => {
-            args.get(0).map(|v| match v {
+            args.first().map(|v| match v {
                 ScalarValue::Int64(n) => {
                     char::from_u32(*n as u32)
                         .map(|c| ScalarValue::Utf8(c.to_string()))
@@ -223,23 +278,23 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
         }
 
         // Math functions
-        "ABS" => args.get(0).map(|v| match v {
+        "ABS" => args.first().map(|v| match v {
             ScalarValue::Int64(i) => ScalarValue::Int64(i.abs()),
             ScalarValue::Float64(f) => ScalarValue::Float64(f.abs()),
             _ => ScalarValue::Null,
         }),
-        "CEIL" | "CEILING" => args.get(0).map(|v| match v {
+        "CEIL" | "CEILING" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.ceil()),
             ScalarValue::Int64(i) => ScalarValue::Int64(*i),
             _ => ScalarValue::Null,
         }),
-        "FLOOR" => args.get(0).map(|v| match v {
+        "FLOOR" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.floor()),
             ScalarValue::Int64(i) => ScalarValue::Int64(*i),
             _ => ScalarValue::Null,
         }),
         "ROUND" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Float64(f)), Some(ScalarValue::Int64(n))) => {
                     let factor = 10f64.powi(*n as i32);
                     Some(ScalarValue::Float64((f * factor).round() / factor))
@@ -251,18 +306,18 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
                 _ => Some(ScalarValue::Null),
             }
         }
-        "TRUNC" | "TRUNCATE" => args.get(0).map(|v| match v {
+        "TRUNC" | "TRUNCATE" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.trunc()),
             ScalarValue::Int64(i) => ScalarValue::Int64(*i),
             _ => ScalarValue::Null,
         }),
-        "SQRT" => args.get(0).map(|v| match v {
+        "SQRT" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.sqrt()),
             ScalarValue::Int64(i) => ScalarValue::Float64((*i as f64).sqrt()),
             _ => ScalarValue::Null,
         }),
         "POWER" | "POW" => {
-            match (args.get(0), args.get(1)) {
+            match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Float64(a)), Some(ScalarValue::Float64(b))) => {
                     Some(ScalarValue::Float64(a.powf(*b)))
                 }
@@ -278,66 +333,105 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
                 _ => Some(ScalarValue::Null),
             }
         }
-        "LOG" | "LOG10" => args.get(0).map(|v| match v {
+        "LOG" | "LOG10" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.log10()),
             ScalarValue::Int64(i) => ScalarValue::Float64((*i as f64).log10()),
             _ => ScalarValue::Null,
         }),
-        "LOG2" => args.get(0).map(|v| match v {
+        "LOG2" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.log2()),
             ScalarValue::Int64(i) => ScalarValue::Float64((*i as f64).log2()),
             _ => ScalarValue::Null,
         }),
-        "LN" => args.get(0).map(|v| match v {
+        "LN" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.ln()),
             ScalarValue::Int64(i) => ScalarValue::Float64((*i as f64).ln()),
             _ => ScalarValue::Null,
         }),
-        "EXP" => args.get(0).map(|v| match v {
+        "EXP" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.exp()),
             ScalarValue::Int64(i) => ScalarValue::Float64((*i as f64).exp()),
             _ => ScalarValue::Null,
         }),
-        "MOD" => {
-            match (args.get(0), args.get(1)) {
+        "SIGN" => args.first().map(|v| match v {
+            ScalarValue::Int64(i) => ScalarValue::Int64(i.signum()),
+            ScalarValue::Float64(f) => ScalarValue::Float64(f.signum()),
+            _ => ScalarValue::Null,
+        }),
+        "GCD" | "LCM" | "BITAND" | "BITOR" | "BITXOR" | "SHIFTLEFT" | "SHIFTRIGHT" => {
+            Some(match (args.first(), args.get(1)) {
                 (Some(ScalarValue::Int64(a)), Some(ScalarValue::Int64(b))) => {
-                    Some(if *b == 0 { ScalarValue::Null } else { ScalarValue::Int64(a % b) })
-                }
-                (Some(ScalarValue::Float64(a)), Some(ScalarValue::Float64(b))) => {
-                    Some(ScalarValue::Float64(a % b))
+                    let (a, b) = (*a, *b);
+                    let v = match name {
+                        "GCD" => i64::try_from(gcd(a.unsigned_abs(), b.unsigned_abs())).ok(),
+                        "LCM" => match gcd(a.unsigned_abs(), b.unsigned_abs()) {
+                            0 => Some(0),
+                            g => (a.unsigned_abs() / g).checked_mul(b.unsigned_abs())
+                                .and_then(|l| i64::try_from(l).ok()),
+                        },
+                        "BITAND" => Some(a & b),
+                        "BITOR" => Some(a | b),
+                        "BITXOR" => Some(a ^ b),
+                        "SHIFTLEFT" => u32::try_from(b).ok().and_then(|n| a.checked_shl(n)),
+                        _ => u32::try_from(b).ok().and_then(|n| a.checked_shr(n)),
+                    };
+                    v.map(ScalarValue::Int64).unwrap_or(ScalarValue::Null)
                 }
-                _ => Some(ScalarValue::Null),
-            }
+                _ => ScalarValue::Null,
+            })
         }
-        "SIGN" => args.get(0).map(|v| match v {
-            ScalarValue::Int64(i) => ScalarValue::Int64(i.signum()),
-            ScalarValue::Float64(f) => ScalarValue::Float64(f.signum()),
+        "BITNOT" => args.first().map(|v| match v {
+            ScalarValue::Int64(i) => ScalarValue::Int64(!i),
+            _ => ScalarValue::Null,
+        }),
+        // NULL past 20!, the largest that fits in an INTEGER
+        "FACTORIAL" => args.first().map(|v| match v {
+            ScalarValue::Int64(n @ 0..=20) => ScalarValue::Int64((1..=*n).product()),
             _ => ScalarValue::Null,
         }),
         "PI" => Some(ScalarValue::Float64(std::f64::consts::PI)),
         "E" => Some(ScalarValue::Float64(std::f64::consts::E)),
-        "SIN" => args.get(0).map(|v| match v {
+        "SIN" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.sin()),
             ScalarValue::Int64(i) => ScalarValue::Float64((*i as f64).sin()),
             _ => ScalarValue::Null,
         }),
-        "COS" => args.get(0).map(|v| match v {
+        "COS" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.cos()),
             ScalarValue::Int64(i) => ScalarValue::Float64((*i as f64).cos()),
             _ => ScalarValue::Null,
         }),
-        "TAN" => args.get(0).map(|v| match v {
+        "TAN" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Float64(f.tan()),
             ScalarValue::Int64(i) => ScalarValue::Float64((*i as f64).tan()),
             _ => ScalarValue::Null,
         }),
+        "ASIN" => unary_float(args, f64::asin),
+        "ACOS" => unary_float(args, f64::acos),
+        "ATAN" => unary_float(args, f64::atan),
+        "ATAN2" => {
+            let as_f64 = |v: Option<&ScalarValue>| match v {
+                Some(ScalarValue::Float64(f)) => Some(*f),
+                Some(ScalarValue::Int64(i)) => Some(*i as f64),
+                _ => None,
+            };
+            Some(match (as_f64(args.first()), as_f64(args.get(1))) {
+                (Some(y), Some(x)) => ScalarValue::Float64(y.atan2(x)),
+                _ => ScalarValue::Null,
+            })
+        }
+        "SINH" => unary_float(args, f64::sinh),
+        "COSH" => unary_float(args, f64::cosh),
+        "TANH" => unary_float(args, f64::tanh),
+        "DEGREES" => unary_float(args, f64::to_degrees),
+        "RADIANS" => unary_float(args, f64::to_radians),
 
         // Type conversion
         "TO_VARCHAR" | "TO_STRING" => {
-            args.get(0).map(|v| ScalarValue::Utf8(format!("{}", v)))
+            args.first().map(|v| ScalarValue::Utf8(format!("{}", v)))
         }
         "TO_NUMBER" | "TO_NUMERIC" | "TO_DOUBLE" => {
-            args.get(0).map(|v| match v {
+            args.first().map(|v| match v {
                 ScalarValue::Int64(i) => ScalarValue::Float64(*i as f64),
                 ScalarValue::Float64(f) => ScalarValue::Float64(*f),
                 ScalarValue::Utf8(s) => s.parse::<f64>()
@@ -347,7 +441,7 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
             })
         }
         "TO_INTEGER" | "TO_INT" => {
-            args.get(0).map(|v| match v {
+            args.first().map(|v| match v {
                 ScalarValue::Int64(i) => ScalarValue::Int64(*i),
                 ScalarValue::Float64(f) => ScalarValue::Int64(*f as i64),
                 ScalarValue::Boolean(b) => ScalarValue::Int64(if *b { 1 } else { 0 }),
@@ -360,17 +454,27 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
 
         // NULL-related
         "ISNULL" | "IS_NULL" => {
-            args.get(0).map(|v| ScalarValue::Boolean(matches!(v, ScalarValue::Null)))
+            args.first().map(|v| ScalarValue::Boolean(matches!(v, ScalarValue::Null)))
         }
-        "ISNAN" => args.get(0).map(|v| match v {
+        "ISNAN" => args.first().map(|v| match v {
             ScalarValue::Float64(f) => ScalarValue::Boolean(f.is_nan()),
             _ => ScalarValue::Boolean(false),
         }),
 
-        // Array / list (simplified)
-        "ARRAY_LENGTH" | "ARRAY_SIZE" | "LEN" => {
-            args.get(0).map(|v| match v {
-                ScalarValue::Utf8(s) => ScalarValue::Int64(s.split(',').count() as i64),
+        // Array
+        "ARRAY" => Some(ScalarValue::Array(args.to_vec())),
+        "ARRAY_LENGTH" | "ARRAY_SIZE" => {
+            args.first().map(|v| match v {
+                ScalarValue::Array(items) => ScalarValue::Int64(items.len() as i64),
+                _ => ScalarValue::Null,
+            })
+        }
+        // 1-based, as `arr[i]`; out-of-range indices give NULL
+        "ARRAY_EXTRACT" => {
+            Some(match (args.first(), args.get(1)) {
+                (Some(ScalarValue::Array(items)), Some(ScalarValue::Int64(i))) if *i >= 1 => {
+                    items.get(*i as usize - 1).cloned().unwrap_or(ScalarValue::Null)
+                }
                 _ => ScalarValue::Null,
             })
         }
@@ -379,3 +483,19 @@ pub fn call(name: &str, args: &[ScalarValue]) -> Option<ScalarValue> {
     };
     Some(result.unwrap_or(ScalarValue::Null))
 }
+
+/// Apply `f` to a numeric first argument as a DOUBLE; NULL for anything else.
+fn unary_float(args: &[ScalarValue], f: fn(f64) -> f64) -> Option<ScalarValue> {
+    args.first().map(|v| match v {
+        ScalarValue::Float64(x) => ScalarValue::Float64(f(*x)),
+        ScalarValue::Int64(i) => ScalarValue::Float64(f(*i as f64)),
+        _ => ScalarValue::Null,
+    })
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
@@ -77,9 +77,13 @@ impl Lexer {
                 '=' => { self.advance(); Ok(Token::Eq) }
                 '!' => {
                     self.advance();
-                    if self.peek() == Some('=') { self.advance(); Ok(Token::NotEq) }
-                    else { Err(PivotError::SqlError("Unexpected '!'".to_string())) }
+                    match self.peek() {
+                        Some('=') => { self.advance(); Ok(Token::NotEq) }
+                        Some('~') => { self.advance(); Ok(Token::NotTilde) }
+                        _ => Err(PivotError::SqlError("Unexpected '!'".to_string())),
+                    }
                 }
+                '~' => { self.advance(); Ok(Token::Tilde) }
                 '<' => {
                     self.advance();
                     match self.peek() {
@@ -100,6 +104,8 @@ impl Lexer {
                 }
                 '(' => { self.advance(); Ok(Token::LParen) }
                 ')' => { self.advance(); Ok(Token::RParen) }
+                '[' => { self.advance(); Ok(Token::LBracket) }
+                ']' => { self.advance(); Ok(Token::RBracket) }
                 ',' => { self.advance(); Ok(Token::Comma) }
                 ';' => { self.advance(); Ok(Token::Semicolon) }
                 '.' => { self.advance(); Ok(Token::Dot) }
@@ -175,7 +181,7 @@ impl Lexer {
                 Some(c) => s.push(c),
             }
         }
-        Ok(Token::Ident(s))
+        Ok(Token::QuotedIdent(s))
     }
 
     fn read_backtick_ident(&mut self) -> Result<Token> {
@@ -188,7 +194,7 @@ impl Lexer {
                 Some(c) => s.push(c),
             }
         }
-        Ok(Token::Ident(s))
+        Ok(Token::QuotedIdent(s))
     }
 
     fn read_ident_or_keyword(&mut self) -> Result<Token> {
@@ -201,101 +207,117 @@ impl Lexer {
     }
 }
 
+/// Every word the lexer turns into a keyword token. Any of these must be
+/// quoted to be used as an identifier.
+pub const KEYWORDS: &[(&str, Token)] = &[
+    ("SELECT", Token::Select),
+    ("FROM", Token::From),
+    ("WHERE", Token::Where),
+    ("GROUP", Token::Group),
+    ("BY", Token::By),
+    ("ROLLUP", Token::Rollup),
+    ("CUBE", Token::Cube),
+    ("HAVING", Token::Having),
+    ("ORDER", Token::Order),
+    ("LIMIT", Token::Limit),
+    ("OFFSET", Token::Offset),
+    ("INSERT", Token::Insert),
+    ("INTO", Token::Into),
+    ("VALUES", Token::Values),
+    ("UPDATE", Token::Update),
+    ("SET", Token::Set),
+    ("DELETE", Token::Delete),
+    ("CREATE", Token::Create),
+    ("TABLE", Token::Table),
+    ("DROP", Token::Drop),
+    ("INDEX", Token::Index),
+    ("ON", Token::On),
+    ("AS", Token::As),
+    ("JOIN", Token::Join),
+    ("INNER", Token::Inner),
+    ("LEFT", Token::Left),
+    ("RIGHT", Token::Right),
+    ("FULL", Token::Full),
+    ("OUTER", Token::Outer),
+    ("CROSS", Token::Cross),
+    ("NATURAL", Token::Natural),
+    ("UNION", Token::Union),
+    ("INTERSECT", Token::Intersect),
+    ("EXCEPT", Token::Except),
+    ("ALL", Token::All),
+    ("DISTINCT", Token::Distinct),
+    ("AND", Token::And),
+    ("OR", Token::Or),
+    ("NOT", Token::Not),
+    ("IS", Token::Is),
+    ("IN", Token::In),
+    ("LIKE", Token::Like),
+    ("ILIKE", Token::ILike),
+    ("BETWEEN", Token::Between),
+    ("CASE", Token::Case),
+    ("WHEN", Token::When),
+    ("THEN", Token::Then),
+    ("ELSE", Token::Else),
+    ("END", Token::End),
+    ("CAST", Token::Cast),
+    ("WITH", Token::With),
+    ("RECURSIVE", Token::Recursive),
+    ("OVER", Token::Over),
+    ("PARTITION", Token::Partition),
+    ("ROWS", Token::Rows),
+    ("RANGE", Token::Range),
+    ("UNBOUNDED", Token::Unbounded),
+    ("PRECEDING", Token::Preceding),
+    ("FOLLOWING", Token::Following),
+    ("CURRENT", Token::Current),
+    ("ROW", Token::Row),
+    ("ASC", Token::Asc),
+    ("DESC", Token::Desc),
+    ("NULLS", Token::Nulls),
+    ("FIRST", Token::First),
+    ("LAST", Token::Last),
+    ("PRIMARY", Token::Primary),
+    ("KEY", Token::Key),
+    ("UNIQUE", Token::Unique),
+    ("DEFAULT", Token::Default),
+    ("CONSTRAINT", Token::Constraint),
+    ("FOREIGN", Token::Foreign),
+    ("REFERENCES", Token::References),
+    ("CHECK", Token::Check),
+    ("ALTER", Token::Alter),
+    ("ADD", Token::Add),
+    ("RENAME", Token::Rename),
+    ("TO", Token::To),
+    ("TRUNCATE", Token::Truncate),
+    ("VACUUM", Token::Vacuum),
+    ("ANALYZE", Token::Analyze),
+    ("BEGIN", Token::Begin),
+    ("COMMIT", Token::Commit),
+    ("ROLLBACK", Token::Rollback),
+    ("TRANSACTION", Token::Transaction),
+    ("EXPLAIN", Token::Explain),
+    ("IF", Token::If),
+    ("EXISTS", Token::Exists),
+    ("TEMPORARY", Token::Temporary),
+    ("TEMP", Token::Temp),
+    ("VIEW", Token::View),
+    ("TRUE", Token::True),
+    ("FALSE", Token::False),
+    ("NULL", Token::Null),
+    ("INTERVAL", Token::Interval),
+    ("FILTER", Token::Filter),
+    ("USING", Token::Using),
+];
+
 fn keyword_or_ident(s: &str) -> Token {
-    match s.to_uppercase().as_str() {
-        "SELECT" => Token::Select,
-        "FROM" => Token::From,
-        "WHERE" => Token::Where,
-        "GROUP" => Token::Group,
-        "BY" => Token::By,
-        "HAVING" => Token::Having,
-        "ORDER" => Token::Order,
-        "LIMIT" => Token::Limit,
-        "OFFSET" => Token::Offset,
-        "INSERT" => Token::Insert,
-        "INTO" => Token::Into,
-        "VALUES" => Token::Values,
-        "UPDATE" => Token::Update,
-        "SET" => Token::Set,
-        "DELETE" => Token::Delete,
-        "CREATE" => Token::Create,
-        "TABLE" => Token::Table,
-        "DROP" => Token::Drop,
-        "INDEX" => Token::Index,
-        "ON" => Token::On,
-        "AS" => Token::As,
-        "JOIN" => Token::Join,
-        "INNER" => Token::Inner,
-        "LEFT" => Token::Left,
-        "RIGHT" => Token::Right,
-        "FULL" => Token::Full,
-        "OUTER" => Token::Outer,
-        "CROSS" => Token::Cross,
-        "NATURAL" => Token::Natural,
-        "UNION" => Token::Union,
-        "INTERSECT" => Token::Intersect,
-        "EXCEPT" => Token::Except,
-        "ALL" => Token::All,
-        "DISTINCT" => Token::Distinct,
-        "AND" => Token::And,
-        "OR" => Token::Or,
-        "NOT" => Token::Not,
-        "IS" => Token::Is,
-        "IN" => Token::In,
-        "LIKE" => Token::Like,
-        "ILIKE" => Token::ILike,
-        "BETWEEN" => Token::Between,
-        "CASE" => Token::Case,
-        "WHEN" => Token::When,
-        "THEN" => Token::Then,
-        "ELSE" => Token::Else,
-        "END" => Token::End,
-        "CAST" => Token::Cast,
-        "WITH" => Token::With,
-        "RECURSIVE" => Token::Recursive,
-        "OVER" => Token::Over,
-        "PARTITION" => Token::Partition,
-        "ROWS" => Token::Rows,
-        "RANGE" => Token::Range,
-        "UNBOUNDED" => Token::Unbounded,
-        "PRECEDING" => Token::Preceding,
-        "FOLLOWING" => Token::Following,
-        "CURRENT" => Token::Current,
-        "ROW" => Token::Row,
-        "ASC" => Token::Asc,
-        "DESC" => Token::Desc,
-        "NULLS" => Token::Nulls,
-        "FIRST" => Token::First,
-        "LAST" => Token::Last,
-        "PRIMARY" => Token::Primary,
-        "KEY" => Token::Key,
-        "UNIQUE" => Token::Unique,
-        "DEFAULT" => Token::Default,
-        "CONSTRAINT" => Token::Constraint,
-        "FOREIGN" => Token::Foreign,
-        "REFERENCES" => Token::References,
-        "CHECK" => Token::Check,
-        "ALTER" => Token::Alter,
-        "ADD" => Token::Add,
-        "RENAME" => Token::Rename,
-        "TO" => Token::To,
-        "TRUNCATE" => Token::Truncate,
-        "BEGIN" => Token::Begin,
-        "COMMIT" => Token::Commit,
-        "ROLLBACK" => Token::Rollback,
-        "TRANSACTION" => Token::Transaction,
-        "EXPLAIN" => Token::Explain,
-        "IF" => Token::If,
-        "EXISTS" => Token::Exists,
-        "TEMPORARY" => Token::Temporary,
-        "TEMP" => Token::Temp,
-        "VIEW" => Token::View,
-        "TRUE" => Token::True,
-        "FALSE" => Token::False,
-        "NULL" => Token::Null,
-        "INTERVAL" => Token::Interval,
-        "FILTER" => Token::Filter,
-        "USING" => Token::Using,
-        _ => Token::Ident(s.to_string()),
-    }
+    let upper = s.to_uppercase();
+    KEYWORDS.iter()
+        .find(|(kw, _)| *kw == upper)
+        .map(|(_, tok)| tok.clone())
+        .unwrap_or_else(|| Token::Ident(s.to_string()))
+}
+
+/// The keyword a token was lexed from, if it is a keyword token.
+pub fn keyword_text(tok: &Token) -> Option<&'static str> {
+    KEYWORDS.iter().find(|(_, t)| t == tok).map(|(kw, _)| *kw)
 }
@@ -7,5 +7,7 @@ pub mod executor;
 pub mod cast;
 pub mod functions_scalar;
 pub mod functions_datetime;
+pub mod regex;
 
-pub use executor::{SqlEngine, QueryResult};
+pub use executor::{DuplicateColumns, ExecMetrics, ScalarFunction, SqlEngine, QueryResult};
+pub use catalog::Fold;
@@ -1,16 +1,48 @@
+use crate::column::interval_string_to_value;
 use crate::error::{PivotError, Result};
 use crate::schema::DataType;
 use crate::sql::ast::*;
+use crate::sql::lexer::keyword_text;
 use crate::sql::token::Token;
 
+/// Default limit on nested statements and expressions; see `with_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    depth: usize,
+    max_depth: usize,
+    /// Aggregate calls parsed so far in the current statement; lets
+    /// `parse_filter` spot aggregates inside its predicate.
+    aggregate_calls: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+        Self { tokens, pos: 0, depth: 0, max_depth: DEFAULT_MAX_DEPTH, aggregate_calls: 0 }
+    }
+
+
+    /// Reject input nesting statements, parenthesized expressions or NOTs
+    /// more than `max_depth` levels deep, instead of recursing until the
+    /// stack overflows.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Run `f` one nesting level deeper.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.max_depth {
+            return Err(PivotError::SqlError(format!(
+                "Query nesting exceeds the maximum depth of {}", self.max_depth
+            )));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
     }
 
     pub fn parse(&mut self) -> Result<Vec<Statement>> {
@@ -59,23 +91,59 @@ impl Parser {
 
     fn expect_ident(&mut self) -> Result<String> {
         match self.peek().clone() {
-            Token::Ident(s) => { self.advance(); Ok(s) }
+            Token::Ident(s) | Token::QuotedIdent(s) => { self.advance(); Ok(s) }
             Token::Table => { self.advance(); Ok("table".to_string()) }
             Token::Index => { self.advance(); Ok("index".to_string()) }
             Token::Filter => { self.advance(); Ok("filter".to_string()) }
             Token::Values => { self.advance(); Ok("value".to_string()) }
-            other => Err(PivotError::SqlError(format!("Expected identifier, got {:?}", other))),
+            other => Err(match keyword_text(&other) {
+                Some(kw) => reserved_keyword_error(kw),
+                None => PivotError::SqlError(format!("Expected identifier, got {:?}", other)),
+            }),
         }
     }
 
+    fn parse_table_name(&mut self) -> Result<TableName> {
+        let quoted = matches!(self.peek(), Token::QuotedIdent(_));
+        Ok(TableName { name: self.expect_ident()?, quoted })
+    }
+
+    fn peek_ident(&self, word: &str) -> bool {
+        matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case(word))
+    }
+
+    /// Skip a `WITH TIME ZONE` / `WITHOUT TIME ZONE` suffix after a type name.
+    /// Offsets are normalized to UTC on input, so both spellings map to the
+    /// same type.
+    fn skip_time_zone_suffix(&mut self) -> Result<()> {
+        let with = self.peek() == &Token::With;
+        if !(with || self.peek_ident("WITHOUT"))
+            || !matches!(self.peek2(), Token::Ident(s) if s.eq_ignore_ascii_case("TIME")) {
+            return Ok(());
+        }
+        self.advance();
+        self.advance();
+        if !self.peek_ident("ZONE") {
+            return Err(PivotError::SqlError(format!("Expected ZONE, got {:?}", self.peek())));
+        }
+        self.advance();
+        Ok(())
+    }
+
     fn try_consume(&mut self, tok: &Token) -> bool {
         if self.peek() == tok { self.advance(); true } else { false }
     }
 
     fn parse_statement(&mut self) -> Result<Statement> {
-        let left = self.parse_primary_stmt()?;
-        // Handle set operations (UNION, INTERSECT, EXCEPT)
-        self.parse_set_op(left)
+        // Aggregates in a subquery belong to it, not to an enclosing FILTER
+        let outer_aggregates = std::mem::take(&mut self.aggregate_calls);
+        let stmt = self.nested(|p| {
+            let left = p.parse_primary_stmt()?;
+            // Handle set operations (UNION, INTERSECT, EXCEPT)
+            p.parse_set_op(left)
+        });
+        self.aggregate_calls = outer_aggregates;
+        stmt
     }
 
     fn parse_primary_stmt(&mut self) -> Result<Statement> {
@@ -87,9 +155,30 @@ impl Parser {
             Token::Delete => self.parse_delete(),
             Token::Create => self.parse_create(),
             Token::Drop => self.parse_drop(),
+            Token::Alter => self.parse_alter(),
             Token::Begin => { self.advance(); self.try_consume(&Token::Transaction); Ok(Statement::Begin) }
             Token::Commit => { self.advance(); self.try_consume(&Token::Transaction); Ok(Statement::Commit) }
             Token::Rollback => { self.advance(); self.try_consume(&Token::Transaction); Ok(Statement::Rollback) }
+            Token::Values => {
+                self.advance();
+                Ok(Statement::Values(self.parse_values_rows()?))
+            }
+            Token::Vacuum => {
+                self.advance();
+                self.try_consume(&Token::Table);
+                let table = if let Token::Ident(_) | Token::QuotedIdent(_) = self.peek() {
+                    Some(self.parse_table_name()?)
+                } else { None };
+                Ok(Statement::Vacuum(table))
+            }
+            Token::Analyze => {
+                self.advance();
+                self.try_consume(&Token::Table);
+                let table = if let Token::Ident(_) | Token::QuotedIdent(_) = self.peek() {
+                    Some(self.parse_table_name()?)
+                } else { None };
+                Ok(Statement::Analyze(table))
+            }
             Token::Explain => {
                 self.advance();
                 let inner = self.parse_statement()?;
@@ -105,54 +194,55 @@ impl Parser {
         }
     }
 
-    fn parse_set_op(&mut self, left: Statement) -> Result<Statement> {
-        let (op, all) = match self.peek() {
-            Token::Union => {
-                self.advance();
-                let all = self.try_consume(&Token::All);
-                (SetOp::Union, all)
-            }
-            Token::Intersect => {
-                self.advance();
-                let all = self.try_consume(&Token::All);
-                (SetOp::Intersect, all)
-            }
-            Token::Except => {
-                self.advance();
-                let all = self.try_consume(&Token::All);
-                (SetOp::Except, all)
-            }
-            _ => return Ok(left),
-        };
-        let right = self.parse_primary_stmt()?;
-        let set_op = Statement::SetOp(SetOpStatement {
-            op, all, left: Box::new(left), right: Box::new(right),
-        });
-        // Handle chained set operations
-        self.parse_set_op(set_op)
+    /// Fold a chain of set operations left to right, so `a UNION b UNION c`
+    /// is `(a UNION b) UNION c`. Loops rather than recursing: a long chain is
+    /// not nesting.
+    fn parse_set_op(&mut self, mut left: Statement) -> Result<Statement> {
+        loop {
+            let op = match self.peek() {
+                Token::Union => SetOp::Union,
+                Token::Intersect => SetOp::Intersect,
+                Token::Except => SetOp::Except,
+                _ => return Ok(left),
+            };
+            self.advance();
+            let all = self.try_consume(&Token::All);
+            let right = self.parse_primary_stmt()?;
+            left = Statement::SetOp(SetOpStatement {
+                op, all, left: Box::new(left), right: Box::new(right),
+            });
+        }
     }
 
     fn parse_with(&mut self) -> Result<Statement> {
         self.expect(&Token::With)?;
-        self.try_consume(&Token::Recursive);
+        let recursive = self.try_consume(&Token::Recursive);
         let mut ctes = Vec::new();
         loop {
             let name = self.expect_ident()?;
+            let mut columns = Vec::new();
+            if self.try_consume(&Token::LParen) {
+                loop {
+                    columns.push(self.expect_ident()?);
+                    if !self.try_consume(&Token::Comma) { break; }
+                }
+                self.expect(&Token::RParen)?;
+            }
             self.expect(&Token::As)?;
             self.expect(&Token::LParen)?;
             let query = self.parse_statement()?;
             self.expect(&Token::RParen)?;
-            ctes.push(Cte { name, query: Box::new(query) });
+            ctes.push(Cte { name, columns, query: Box::new(query) });
             if !self.try_consume(&Token::Comma) { break; }
         }
         let body = self.parse_statement()?;
-        Ok(Statement::With(WithStatement { ctes, body: Box::new(body) }))
+        Ok(Statement::With(WithStatement { recursive, ctes, body: Box::new(body) }))
     }
 
     fn parse_select(&mut self) -> Result<SelectStatement> {
         self.expect(&Token::Select)?;
         let distinct = self.try_consume(&Token::Distinct);
-        if self.try_consume(&Token::All) {} // ALL is default
+        self.try_consume(&Token::All); // ALL is default
 
         // Parse SELECT items
         let columns = self.parse_select_items()?;
@@ -172,16 +262,29 @@ impl Parser {
         } else { None };
 
         // GROUP BY
-        let group_by = if self.peek() == &Token::Group && self.peek2() == &Token::By {
+        let (group_by, grouping_sets) = if self.peek() == &Token::Group && self.peek2() == &Token::By {
             self.advance(); self.advance();
-            self.parse_expr_list()?
-        } else { Vec::new() };
+            self.parse_group_by()?
+        } else { (Vec::new(), None) };
 
         // HAVING
         let having = if self.try_consume(&Token::Having) {
             Some(self.parse_expr()?)
         } else { None };
 
+        // WINDOW w AS (...), ...
+        let mut windows = Vec::new();
+        if self.peek_ident("WINDOW") {
+            self.advance();
+            loop {
+                let name = self.expect_ident()?;
+                self.expect(&Token::As)?;
+                self.expect(&Token::LParen)?;
+                windows.push((name, self.parse_window_spec_body()?));
+                if !self.try_consume(&Token::Comma) { break; }
+            }
+        }
+
         // ORDER BY
         let order_by = if self.peek() == &Token::Order && self.peek2() == &Token::By {
             self.advance(); self.advance();
@@ -200,12 +303,89 @@ impl Parser {
 
         Ok(SelectStatement {
             distinct, columns, from, joins, where_clause,
-            group_by, having, order_by, limit, offset,
+            group_by, grouping_sets, having, windows, order_by, limit, offset,
         })
     }
 
+    /// GROUP BY list. Each element contributes a choice of key sets (one for
+    /// a plain expression, `n + 1` for an `n`-key ROLLUP, `2^n` for CUBE) and
+    /// the query's grouping sets are their cross product, so
+    /// `GROUP BY a, ROLLUP(b, c)` yields `(a, b, c)`, `(a, b)` and `(a)`.
+    fn parse_group_by(&mut self) -> Result<(Vec<Expr>, Option<GroupingSets>)> {
+        let mut keys: Vec<Expr> = Vec::new();
+        let mut sets: Vec<Vec<usize>> = vec![Vec::new()];
+        let mut has_sets = false;
+        loop {
+            has_sets |= matches!(self.peek(), Token::Rollup | Token::Cube)
+                || (self.peek_ident("GROUPING") && matches!(self.peek2(), Token::Ident(s) if s.eq_ignore_ascii_case("SETS")));
+            let choices = self.parse_grouping_element()?;
+            let choices: Vec<Vec<usize>> = choices.into_iter()
+                .map(|exprs| exprs.into_iter().map(|e| intern_group_key(&mut keys, e)).collect())
+                .collect();
+            sets = sets.iter()
+                .flat_map(|set| choices.iter().map(move |choice| {
+                    let mut merged = set.clone();
+                    for &k in choice {
+                        if !merged.contains(&k) { merged.push(k); }
+                    }
+                    merged
+                }))
+                .collect();
+            if !self.try_consume(&Token::Comma) { break; }
+        }
+        Ok((keys, if has_sets { Some(sets) } else { None }))
+    }
+
+    /// One GROUP BY element as the key sets it can stand for, most detailed
+    /// first.
+    fn parse_grouping_element(&mut self) -> Result<Vec<Vec<Expr>>> {
+        if self.try_consume(&Token::Rollup) {
+            self.expect(&Token::LParen)?;
+            let exprs = self.parse_expr_list()?;
+            self.expect(&Token::RParen)?;
+            return Ok((0..=exprs.len()).rev().map(|n| exprs[..n].to_vec()).collect());
+        }
+        if self.try_consume(&Token::Cube) {
+            self.expect(&Token::LParen)?;
+            let exprs = self.parse_expr_list()?;
+            self.expect(&Token::RParen)?;
+            if exprs.len() > 12 {
+                return Err(PivotError::SqlError("CUBE supports at most 12 columns".to_string()));
+            }
+            // Bit i of the mask, counted from the top, selects the i-th key.
+            let n = exprs.len();
+            return Ok((0..1usize << n).rev()
+                .map(|mask| (0..n).filter(|i| mask & (1 << (n - 1 - i)) != 0).map(|i| exprs[i].clone()).collect())
+                .collect());
+        }
+        if self.peek_ident("GROUPING") && matches!(self.peek2(), Token::Ident(s) if s.eq_ignore_ascii_case("SETS")) {
+            self.advance(); self.advance();
+            self.expect(&Token::LParen)?;
+            let mut sets = Vec::new();
+            loop {
+                if self.try_consume(&Token::LParen) {
+                    // `(a, b)` or the empty set `()`
+                    if self.try_consume(&Token::RParen) {
+                        sets.push(Vec::new());
+                    } else {
+                        sets.push(self.parse_expr_list()?);
+                        self.expect(&Token::RParen)?;
+                    }
+                } else {
+                    sets.extend(self.parse_grouping_element()?);
+                }
+                if !self.try_consume(&Token::Comma) { break; }
+            }
+            self.expect(&Token::RParen)?;
+            return Ok(sets);
+        }
+        Ok(vec![vec![self.parse_expr()?]])
+    }
+
     fn parse_select_items(&mut self) -> Result<Vec<SelectItem>> {
         let mut items = Vec::new();
+        // `SELECT FROM t` projects zero columns, one empty row per input row.
+        if self.peek() == &Token::From { return Ok(items); }
         loop {
             let item = self.parse_select_item()?;
             items.push(item);
@@ -220,7 +400,7 @@ impl Parser {
             return Ok(SelectItem::Wildcard);
         }
         // Check for table.*
-        if let Token::Ident(_) = self.peek() {
+        if let Token::Ident(_) | Token::QuotedIdent(_) = self.peek() {
             if self.peek2() == &Token::Dot {
                 // Could be table.* or table.col
                 let save = self.pos;
@@ -235,57 +415,149 @@ impl Parser {
             }
         }
         let expr = self.parse_expr()?;
-        let alias = self.parse_alias();
+        let alias = self.parse_alias()?;
         Ok(SelectItem::Expr { expr, alias })
     }
 
-    fn parse_alias(&mut self) -> Option<String> {
+    fn parse_alias(&mut self) -> Result<Option<String>> {
         if self.try_consume(&Token::As) {
             // After AS, identifier is required
-            match self.peek().clone() {
-                Token::Ident(s) => { self.advance(); Some(s) }
-                Token::StringLiteral(s) => { self.advance(); Some(s) }
-                // Some keywords can be used as aliases
-                tok => {
-                    if let Some(s) = token_as_alias(&tok) {
-                        self.advance(); Some(s)
-                    } else {
-                        None
-                    }
-                }
+            let tok = self.peek().clone();
+            if let Some(alias) = token_as_alias(&tok) {
+                self.advance();
+                return Ok(Some(alias));
+            }
+            match keyword_text(&tok) {
+                Some(kw) => Err(reserved_keyword_error(kw)),
+                None => Err(PivotError::SqlError(format!("Expected alias after AS, got {:?}", tok))),
             }
         } else {
             // Optional alias without AS keyword
             match self.peek().clone() {
                 Token::Ident(s) if !is_reserved_keyword(&s) => {
                     // Check it's not a keyword that could follow a select item
-                    self.advance(); Some(s)
+                    self.advance();
+                    Ok(Some(s))
                 }
-                _ => None,
+                Token::QuotedIdent(s) => { self.advance(); Ok(Some(s)) }
+                _ => Ok(None),
             }
         }
     }
 
     fn parse_table_ref(&mut self) -> Result<TableRef> {
+        let mut table = self.parse_table_primary()?;
+        loop {
+            if self.peek_ident("PIVOT") && self.peek2() == &Token::LParen {
+                table = self.parse_pivot(table)?;
+            } else if self.peek_ident("UNPIVOT") {
+                table = self.parse_unpivot(table)?;
+            } else {
+                return Ok(table);
+            }
+        }
+    }
+
+    /// `UNPIVOT [INCLUDE NULLS | EXCLUDE NULLS] (value FOR name IN
+    /// (column [AS label], ...)) [alias]` after the source table.
+    fn parse_unpivot(&mut self, source: TableRef) -> Result<TableRef> {
+        self.advance();
+        let include_nulls = if self.peek_ident("INCLUDE") || self.peek_ident("EXCLUDE") {
+            let include = self.peek_ident("INCLUDE");
+            self.advance();
+            self.expect(&Token::Nulls)?;
+            include
+        } else {
+            false
+        };
+        self.expect(&Token::LParen)?;
+        let value = self.expect_ident()?;
+        if !self.peek_ident("FOR") {
+            return Err(PivotError::SqlError(format!("Expected FOR in UNPIVOT, got {:?}", self.peek())));
+        }
+        self.advance();
+        let name = self.expect_ident()?;
+        self.expect(&Token::In)?;
+        self.expect(&Token::LParen)?;
+        let mut columns = Vec::new();
+        loop {
+            let column = match self.parse_primary_expr()? {
+                Expr::Column(c) => c,
+                other => return Err(PivotError::SqlError(format!("UNPIVOT IN needs columns, got {:?}", other))),
+            };
+            let label = if self.peek() == &Token::As { self.parse_alias()? } else { None };
+            columns.push((column, label));
+            if !self.try_consume(&Token::Comma) { break; }
+        }
+        self.expect(&Token::RParen)?;
+        self.expect(&Token::RParen)?;
+        let alias = self.parse_alias()?;
+        Ok(TableRef::Unpivot(Box::new(UnpivotTable { source, value, name, columns, include_nulls, alias })))
+    }
+
+    /// `PIVOT (agg(...) FOR column IN (value [AS name], ...)) [alias]` after
+    /// the source table.
+    fn parse_pivot(&mut self, source: TableRef) -> Result<TableRef> {
+        self.advance();
+        self.expect(&Token::LParen)?;
+        let aggregate = self.parse_expr()?;
+        if !matches!(aggregate, Expr::Function { over: None, .. }) {
+            return Err(PivotError::SqlError(format!("PIVOT needs an aggregate call, got {:?}", aggregate)));
+        }
+        if !self.peek_ident("FOR") {
+            return Err(PivotError::SqlError(format!("Expected FOR in PIVOT, got {:?}", self.peek())));
+        }
+        self.advance();
+        let column = match self.parse_primary_expr()? {
+            Expr::Column(c) => c,
+            other => return Err(PivotError::SqlError(format!("PIVOT FOR needs a column, got {:?}", other))),
+        };
+        self.expect(&Token::In)?;
+        self.expect(&Token::LParen)?;
+        let mut values = Vec::new();
+        loop {
+            let value = self.parse_expr()?;
+            let name = if self.try_consume(&Token::As) { Some(self.expect_ident()?) } else { None };
+            values.push((value, name));
+            if !self.try_consume(&Token::Comma) { break; }
+        }
+        self.expect(&Token::RParen)?;
+        self.expect(&Token::RParen)?;
+        let alias = self.parse_alias()?;
+        Ok(TableRef::Pivot(Box::new(PivotTable { source, aggregate, column, values, alias })))
+    }
+
+    fn parse_table_primary(&mut self) -> Result<TableRef> {
         if self.peek() == &Token::LParen {
             self.advance();
             let query = self.parse_statement()?;
             self.expect(&Token::RParen)?;
             let alias = if self.try_consume(&Token::As) {
                 self.expect_ident()?
+            } else if matches!(self.peek(), Token::Ident(s) if is_reserved_keyword(s)) {
+                "subq".to_string()
             } else {
                 self.expect_ident().unwrap_or_else(|_| "subq".to_string())
             };
             return Ok(TableRef::Subquery { query: Box::new(query), alias });
         }
-        let name = self.expect_ident()?;
-        let alias = self.parse_alias();
+        if matches!(self.peek(), Token::Ident(_)) && self.peek2() == &Token::LParen {
+            let name = self.expect_ident()?.to_lowercase();
+            self.advance();
+            let args = if self.peek() == &Token::RParen { Vec::new() } else { self.parse_expr_list()? };
+            self.expect(&Token::RParen)?;
+            let alias = self.parse_alias()?;
+            return Ok(TableRef::Function { name, args, alias });
+        }
+        let name = self.parse_table_name()?;
+        let alias = self.parse_alias()?;
         Ok(TableRef::Table { name, alias })
     }
 
     fn parse_joins(&mut self) -> Result<Vec<Join>> {
         let mut joins = Vec::new();
         loop {
+            let natural = self.try_consume(&Token::Natural);
             let join_type = match self.peek() {
                 Token::Join | Token::Inner => {
                     if self.peek() == &Token::Inner { self.advance(); }
@@ -310,15 +582,20 @@ impl Parser {
                     self.expect(&Token::Join)?;
                     JoinType::Full
                 }
-                Token::Cross => {
+                Token::Cross if !natural => {
                     self.advance();
                     self.expect(&Token::Join)?;
                     JoinType::Cross
                 }
+                other if natural => {
+                    return Err(PivotError::SqlError(format!("Expected JOIN after NATURAL, got {:?}", other)));
+                }
                 _ => break,
             };
             let table = self.parse_table_ref()?;
-            let condition = if self.try_consume(&Token::On) {
+            let condition = if natural {
+                JoinCondition::Natural
+            } else if self.try_consume(&Token::On) {
                 JoinCondition::On(self.parse_expr()?)
             } else if self.try_consume(&Token::Using) {
                 self.expect(&Token::LParen)?;
@@ -363,7 +640,7 @@ impl Parser {
     fn parse_insert(&mut self) -> Result<Statement> {
         self.expect(&Token::Insert)?;
         self.expect(&Token::Into)?;
-        let table = self.expect_ident()?;
+        let table = self.parse_table_name()?;
         let columns = if self.peek() == &Token::LParen
             && !matches!(self.tokens.get(self.pos + 1), Some(Token::Select)) {
             self.advance();
@@ -377,27 +654,33 @@ impl Parser {
         } else { None };
 
         let values = if self.try_consume(&Token::Values) {
-            let mut all_rows = Vec::new();
-            loop {
-                self.expect(&Token::LParen)?;
-                let row = self.parse_expr_list()?;
-                self.expect(&Token::RParen)?;
-                all_rows.push(row);
-                if !self.try_consume(&Token::Comma) { break; }
-            }
-            InsertValues::Values(all_rows)
+            InsertValues::Values(self.parse_values_rows()?)
         } else {
             let stmt = self.parse_statement()?;
             InsertValues::Select(Box::new(stmt))
         };
 
-        Ok(Statement::Insert(InsertStatement { table, columns, values }))
+        let returning = self.parse_returning()?;
+        Ok(Statement::Insert(InsertStatement { table, columns, values, returning }))
+    }
+
+    /// The `(...), (...)` row list following VALUES.
+    fn parse_values_rows(&mut self) -> Result<Vec<Vec<Expr>>> {
+        let mut all_rows = Vec::new();
+        loop {
+            self.expect(&Token::LParen)?;
+            let row = self.parse_expr_list()?;
+            self.expect(&Token::RParen)?;
+            all_rows.push(row);
+            if !self.try_consume(&Token::Comma) { break; }
+        }
+        Ok(all_rows)
     }
 
     fn parse_update(&mut self) -> Result<Statement> {
         self.expect(&Token::Update)?;
-        let table = self.expect_ident()?;
-        let alias = self.parse_alias();
+        let table = self.parse_table_name()?;
+        let alias = self.parse_alias()?;
         self.expect(&Token::Set)?;
         let mut assignments = Vec::new();
         loop {
@@ -410,23 +693,95 @@ impl Parser {
         let where_clause = if self.try_consume(&Token::Where) {
             Some(self.parse_expr()?)
         } else { None };
-        Ok(Statement::Update(UpdateStatement { table, alias, assignments, where_clause }))
+        let returning = self.parse_returning()?;
+        Ok(Statement::Update(UpdateStatement { table, alias, assignments, where_clause, returning }))
     }
 
     fn parse_delete(&mut self) -> Result<Statement> {
         self.expect(&Token::Delete)?;
         self.expect(&Token::From)?;
-        let table = self.expect_ident()?;
+        let table = self.parse_table_name()?;
         let where_clause = if self.try_consume(&Token::Where) {
             Some(self.parse_expr()?)
         } else { None };
-        Ok(Statement::Delete(DeleteStatement { table, where_clause }))
+        let returning = self.parse_returning()?;
+        Ok(Statement::Delete(DeleteStatement { table, where_clause, returning }))
+    }
+
+    /// Optional `RETURNING item, ...` trailing a DML statement.
+    fn parse_returning(&mut self) -> Result<Option<Vec<SelectItem>>> {
+        if !self.peek_ident("RETURNING") { return Ok(None); }
+        self.advance();
+        Ok(Some(self.parse_select_items()?))
+    }
+
+    fn parse_alter(&mut self) -> Result<Statement> {
+        self.expect(&Token::Alter)?;
+        self.expect(&Token::Table)?;
+        let table = self.parse_table_name()?;
+        let operation = match self.peek().clone() {
+            Token::Alter => {
+                self.advance();
+                if self.peek_ident("COLUMN") { self.advance(); }
+                let column = self.expect_ident()?;
+                if self.try_consume(&Token::Set) {
+                    if !self.peek_ident("DATA") {
+                        return Err(PivotError::SqlError(format!("Expected DATA TYPE after SET, got {:?}", self.peek())));
+                    }
+                    self.advance();
+                }
+                if !self.peek_ident("TYPE") {
+                    return Err(PivotError::SqlError(format!("Expected TYPE after ALTER COLUMN {}, got {:?}", column, self.peek())));
+                }
+                self.advance();
+                let data_type = self.parse_data_type()?;
+                AlterTableOperation::AlterColumnType { column, data_type }
+            }
+            Token::Add => {
+                self.advance();
+                if self.peek_ident("COLUMN") { self.advance(); }
+                AlterTableOperation::AddColumn(self.parse_column_def()?)
+            }
+            Token::Drop => {
+                self.advance();
+                if self.peek_ident("COLUMN") { self.advance(); }
+                let if_exists = if self.peek() == &Token::If {
+                    self.advance();
+                    self.expect(&Token::Exists)?;
+                    true
+                } else { false };
+                AlterTableOperation::DropColumn { column: self.expect_ident()?, if_exists }
+            }
+            Token::Rename => {
+                self.advance();
+                if self.peek_ident("COLUMN") { self.advance(); }
+                let from = self.expect_ident()?;
+                self.expect(&Token::To)?;
+                AlterTableOperation::RenameColumn { from, to: self.expect_ident()? }
+            }
+            other => return Err(PivotError::SqlError(format!("Unsupported ALTER TABLE operation: {:?}", other))),
+        };
+        Ok(Statement::AlterTable(AlterTableStatement { table, operation }))
     }
 
     fn parse_create(&mut self) -> Result<Statement> {
         self.expect(&Token::Create)?;
+        let or_replace = if self.peek() == &Token::Or {
+            self.advance();
+            if !self.peek_ident("REPLACE") {
+                return Err(PivotError::SqlError(format!("Expected REPLACE after CREATE OR, got {:?}", self.peek())));
+            }
+            self.advance();
+            true
+        } else { false };
         self.try_consume(&Token::Temporary);
         self.try_consume(&Token::Temp);
+        if self.try_consume(&Token::View) {
+            return self.parse_create_view(or_replace);
+        }
+        if or_replace {
+            return Err(PivotError::SqlError("CREATE OR REPLACE is only supported for views".to_string()));
+        }
         self.expect(&Token::Table)?;
         let if_not_exists = if self.peek() == &Token::If {
             self.advance();
@@ -434,13 +789,26 @@ impl Parser {
             self.expect(&Token::Exists)?;
             true
         } else { false };
-        let name = self.expect_ident()?;
+        let name = self.parse_table_name()?;
         self.expect(&Token::LParen)?;
         let columns = self.parse_column_defs()?;
         self.expect(&Token::RParen)?;
         Ok(Statement::CreateTable(CreateTableStatement { name, if_not_exists, columns }))
     }
 
+    fn parse_create_view(&mut self, or_replace: bool) -> Result<Statement> {
+        let if_not_exists = if self.peek() == &Token::If {
+            self.advance();
+            self.expect(&Token::Not)?;
+            self.expect(&Token::Exists)?;
+            true
+        } else { false };
+        let name = self.parse_table_name()?;
+        self.expect(&Token::As)?;
+        let query = Box::new(self.parse_statement()?);
+        Ok(Statement::CreateView(CreateViewStatement { name, or_replace, if_not_exists, query }))
+    }
+
     fn parse_column_defs(&mut self) -> Result<Vec<ColumnDefAst>> {
         let mut cols = Vec::new();
         loop {
@@ -472,9 +840,18 @@ impl Parser {
 
     fn parse_column_def(&mut self) -> Result<ColumnDefAst> {
         let name = self.expect_ident()?;
-        let data_type = self.parse_data_type()?;
-        let mut nullable = true;
+        let serial = ["SERIAL", "BIGSERIAL", "SMALLSERIAL"].iter().any(|w| self.peek_ident(w));
+        let data_type = if serial {
+            self.advance();
+            DataType::Int64
+        } else {
+            self.parse_data_type()?
+        };
+        let mut identity = serial;
+        let mut generated_always = false;
+        let mut nullable = !serial;
         let mut primary_key = false;
+        let mut unique = false;
         let mut default = None;
         // Parse optional column constraints
         loop {
@@ -491,7 +868,31 @@ impl Parser {
                     primary_key = true;
                     nullable = false;
                 }
-                Token::Unique => { self.advance(); }
+                Token::Unique => { self.advance(); unique = true; }
+                Token::Ident(ref s) if s.eq_ignore_ascii_case("AUTOINCREMENT")
+                    || s.eq_ignore_ascii_case("AUTO_INCREMENT") => {
+                    self.advance();
+                    identity = true;
+                }
+                Token::Ident(ref s) if s.eq_ignore_ascii_case("GENERATED") => {
+                    // GENERATED { ALWAYS | BY DEFAULT } AS IDENTITY
+                    self.advance();
+                    if self.peek_ident("ALWAYS") {
+                        self.advance();
+                        generated_always = true;
+                    } else {
+                        self.expect(&Token::By)?;
+                        self.expect(&Token::Default)?;
+                    }
+                    self.expect(&Token::As)?;
+                    if !self.peek_ident("IDENTITY") {
+                        return Err(PivotError::SqlError(format!(
+                            "Expected IDENTITY after GENERATED ... AS, got {:?}", self.peek()
+                        )));
+                    }
+                    self.advance();
+                    identity = true;
+                }
                 Token::Default => {
                     self.advance();
                     default = Some(self.parse_primary_expr()?);
@@ -509,7 +910,7 @@ impl Parser {
                 _ => break,
             }
         }
-        Ok(ColumnDefAst { name, data_type, nullable, default, primary_key })
+        Ok(ColumnDefAst { name, data_type, nullable, default, primary_key, unique, identity, generated_always })
     }
 
     fn parse_data_type(&mut self) -> Result<DataType> {
@@ -559,9 +960,13 @@ impl Parser {
                     while self.peek() != &Token::RParen && !self.is_eof() { self.advance(); }
                     self.advance();
                 }
+                self.skip_time_zone_suffix()?;
                 Ok(DataType::Timestamp)
             }
-            "TIME" | "TIMETZ" => Ok(DataType::Time),
+            "TIME" | "TIMETZ" => {
+                self.skip_time_zone_suffix()?;
+                Ok(DataType::Time)
+            }
             "INTERVAL" => Ok(DataType::Interval),
             "DECIMAL" | "NUMERIC" => {
                 let (precision, scale) = if self.peek() == &Token::LParen {
@@ -587,20 +992,26 @@ impl Parser {
 
     fn parse_drop(&mut self) -> Result<Statement> {
         self.expect(&Token::Drop)?;
-        self.expect(&Token::Table)?;
+        let view = self.try_consume(&Token::View);
+        if !view {
+            self.expect(&Token::Table)?;
+        }
         let if_exists = if self.peek() == &Token::If {
             self.advance();
             self.expect(&Token::Exists)?;
             true
         } else { false };
-        let name = self.expect_ident()?;
+        let name = self.parse_table_name()?;
+        if view {
+            return Ok(Statement::DropView(DropViewStatement { name, if_exists }));
+        }
         Ok(Statement::DropTable(DropTableStatement { name, if_exists }))
     }
 
     // ─── Expression parsing ───────────────────────────────────────────────────
 
     pub fn parse_expr(&mut self) -> Result<Expr> {
-        self.parse_or()
+        self.nested(Self::parse_or)
     }
 
     fn parse_or(&mut self) -> Result<Expr> {
@@ -626,7 +1037,7 @@ impl Parser {
     fn parse_not(&mut self) -> Result<Expr> {
         if self.peek() == &Token::Not {
             self.advance();
-            let expr = self.parse_not()?;
+            let expr = self.nested(Self::parse_not)?;
             Ok(Expr::UnaryOp { op: UnaryOp::Not, expr: Box::new(expr) })
         } else {
             self.parse_comparison()
@@ -643,6 +1054,19 @@ impl Parser {
             Token::Gt => { self.advance(); let r = self.parse_addition()?; Ok(Expr::BinaryOp { left: Box::new(left), op: BinOp::Gt, right: Box::new(r) }) }
             Token::LtEq => { self.advance(); let r = self.parse_addition()?; Ok(Expr::BinaryOp { left: Box::new(left), op: BinOp::LtEq, right: Box::new(r) }) }
             Token::GtEq => { self.advance(); let r = self.parse_addition()?; Ok(Expr::BinaryOp { left: Box::new(left), op: BinOp::GtEq, right: Box::new(r) }) }
+            Token::Tilde | Token::NotTilde => {
+                let negated = self.advance() == &Token::NotTilde;
+                let r = self.parse_addition()?;
+                let matched = Expr::Function {
+                    name: "REGEXP_LIKE".to_string(),
+                    args: vec![left, r],
+                    distinct: false,
+                    within_group: Vec::new(),
+                    filter: None,
+                    over: None,
+                };
+                Ok(if negated { Expr::UnaryOp { op: UnaryOp::Not, expr: Box::new(matched) } } else { matched })
+            }
             Token::Is => {
                 self.advance();
                 let negated = self.try_consume(&Token::Not);
@@ -656,22 +1080,11 @@ impl Parser {
                         self.advance();
                         self.parse_in_expr(left, true)
                     }
-                    Token::Like => {
-                        self.advance();
-                        let pattern = self.parse_addition()?;
-                        Ok(Expr::Like { expr: Box::new(left), pattern: Box::new(pattern), negated: true, case_insensitive: false })
-                    }
-                    Token::ILike => {
-                        self.advance();
-                        let pattern = self.parse_addition()?;
-                        Ok(Expr::Like { expr: Box::new(left), pattern: Box::new(pattern), negated: true, case_insensitive: true })
-                    }
-                    Token::Between => {
-                        self.advance();
-                        let low = self.parse_addition()?;
-                        self.expect(&Token::And)?;
-                        let high = self.parse_addition()?;
-                        Ok(Expr::Between { expr: Box::new(left), low: Box::new(low), high: Box::new(high), negated: true })
+                    Token::Like => self.parse_like(left, true, false),
+                    Token::ILike => self.parse_like(left, true, true),
+                    Token::Between => self.parse_between(left, true),
+                    Token::Ident(ref s) if s.eq_ignore_ascii_case("SIMILAR") && self.peek2() == &Token::To => {
+                        self.parse_similar_to(left, true)
                     }
                     other => Err(PivotError::SqlError(format!("Unexpected token after NOT: {:?}", other)))
                 }
@@ -680,27 +1093,52 @@ impl Parser {
                 self.advance();
                 self.parse_in_expr(left, false)
             }
-            Token::Like => {
-                self.advance();
-                let pattern = self.parse_addition()?;
-                Ok(Expr::Like { expr: Box::new(left), pattern: Box::new(pattern), negated: false, case_insensitive: false })
-            }
-            Token::ILike => {
-                self.advance();
-                let pattern = self.parse_addition()?;
-                Ok(Expr::Like { expr: Box::new(left), pattern: Box::new(pattern), negated: false, case_insensitive: true })
-            }
-            Token::Between => {
-                self.advance();
-                let low = self.parse_addition()?;
-                self.expect(&Token::And)?;
-                let high = self.parse_addition()?;
-                Ok(Expr::Between { expr: Box::new(left), low: Box::new(low), high: Box::new(high), negated: false })
+            Token::Like => self.parse_like(left, false, false),
+            Token::ILike => self.parse_like(left, false, true),
+            Token::Between => self.parse_between(left, false),
+            Token::Ident(ref s) if s.eq_ignore_ascii_case("SIMILAR") && self.peek2() == &Token::To => {
+                self.parse_similar_to(left, false)
             }
             _ => Ok(left),
         }
     }
 
+    /// Parse `[NOT] LIKE|ILIKE pattern [ESCAPE char]` after `left`, with the
+    /// operator as the current token.
+    fn parse_like(&mut self, left: Expr, negated: bool, case_insensitive: bool) -> Result<Expr> {
+        self.advance();
+        let pattern = self.parse_addition()?;
+        let escape = if self.peek_ident("ESCAPE") {
+            self.advance();
+            Some(Box::new(self.parse_addition()?))
+        } else { None };
+        Ok(Expr::Like { expr: Box::new(left), pattern: Box::new(pattern), negated, case_insensitive, escape })
+    }
+
+    /// `BETWEEN [SYMMETRIC | ASYMMETRIC] low AND high`; the BETWEEN token is
+    /// still current.
+    fn parse_between(&mut self, left: Expr, negated: bool) -> Result<Expr> {
+        self.advance(); // BETWEEN
+        let symmetric = if self.peek_ident("SYMMETRIC") {
+            self.advance();
+            true
+        } else {
+            if self.peek_ident("ASYMMETRIC") { self.advance(); }
+            false
+        };
+        let low = self.parse_addition()?;
+        self.expect(&Token::And)?;
+        let high = self.parse_addition()?;
+        Ok(Expr::Between { expr: Box::new(left), low: Box::new(low), high: Box::new(high), negated, symmetric })
+    }
+
+    fn parse_similar_to(&mut self, left: Expr, negated: bool) -> Result<Expr> {
+        self.advance(); // SIMILAR
+        self.expect(&Token::To)?;
+        let pattern = self.parse_addition()?;
+        Ok(Expr::SimilarTo { expr: Box::new(left), pattern: Box::new(pattern), negated })
+    }
+
     fn parse_in_expr(&mut self, left: Expr, negated: bool) -> Result<Expr> {
         self.expect(&Token::LParen)?;
         // Check if it's a subquery
@@ -758,6 +1196,11 @@ impl Parser {
                     let right = self.parse_unary()?;
                     left = Expr::BinaryOp { left: Box::new(left), op: BinOp::Mod, right: Box::new(right) };
                 }
+                Token::Ident(s) if s.eq_ignore_ascii_case("DIV") => {
+                    self.advance();
+                    let right = self.parse_unary()?;
+                    left = Expr::BinaryOp { left: Box::new(left), op: BinOp::IntDiv, right: Box::new(right) };
+                }
                 _ => break,
             }
         }
@@ -783,6 +1226,46 @@ impl Parser {
                 self.advance();
                 let dt = self.parse_data_type()?;
                 expr = Expr::TypeCast { expr: Box::new(expr), data_type: dt };
+            } else if self.peek_ident("COLLATE") {
+                self.advance();
+                let collation = match self.advance().clone() {
+                    Token::Ident(s) | Token::QuotedIdent(s) | Token::StringLiteral(s) => s.to_uppercase(),
+                    other => return Err(PivotError::SqlError(format!("Expected collation name, got {:?}", other))),
+                };
+                if !matches!(collation.as_str(), "NOCASE" | "BINARY") {
+                    return Err(PivotError::SqlError(format!("Unknown collation '{}'", collation)));
+                }
+                expr = Expr::Collate { expr: Box::new(expr), collation };
+            } else if self.peek_ident("AT") && matches!(self.peek2(), Token::Ident(s) if s.eq_ignore_ascii_case("TIME")) {
+                // ts AT TIME ZONE zone
+                self.advance();
+                self.advance();
+                if !self.peek_ident("ZONE") {
+                    return Err(PivotError::SqlError(format!("Expected ZONE after AT TIME, got {:?}", self.peek())));
+                }
+                self.advance();
+                let zone = self.parse_primary_expr()?;
+                expr = Expr::Function {
+                    name: "TIMEZONE".to_string(),
+                    args: vec![zone, expr],
+                    distinct: false,
+                    within_group: Vec::new(),
+                    filter: None,
+                    over: None,
+                };
+            } else if self.peek() == &Token::LBracket {
+                // arr[i]
+                self.advance();
+                let index = self.parse_expr()?;
+                self.expect(&Token::RBracket)?;
+                expr = Expr::Function {
+                    name: "ARRAY_EXTRACT".to_string(),
+                    args: vec![expr, index],
+                    distinct: false,
+                    within_group: Vec::new(),
+                    filter: None,
+                    over: None,
+                };
             } else {
                 break;
             }
@@ -807,7 +1290,16 @@ impl Parser {
                     Token::Integer(n) => n.to_string(),
                     other => return Err(PivotError::SqlError(format!("Expected interval value, got {:?}", other))),
                 };
-                let unit = self.expect_ident()?;
+                let mut unit = self.parse_interval_unit().unwrap_or_default();
+                if !unit.is_empty() && self.peek() == &Token::To {
+                    self.advance();
+                    let to = self.parse_interval_unit().ok_or_else(|| PivotError::SqlError(
+                        format!("Expected interval unit after {} TO", unit)))?;
+                    unit = format!("{} TO {}", unit, to);
+                }
+                if interval_string_to_value(&val, &unit).is_none() {
+                    return Err(PivotError::SqlError(format!("Invalid interval: '{}' {}", val, unit)));
+                }
                 Ok(Expr::Literal(LiteralValue::Interval { value: val, unit }))
             }
 
@@ -871,11 +1363,55 @@ impl Parser {
                     return Ok(Expr::Subquery(Box::new(stmt)));
                 }
                 let expr = self.parse_expr()?;
+                if self.try_consume(&Token::Comma) {
+                    let mut items = vec![expr];
+                    loop {
+                        items.push(self.parse_expr()?);
+                        if !self.try_consume(&Token::Comma) { break; }
+                    }
+                    self.expect(&Token::RParen)?;
+                    return Ok(Expr::Row(items));
+                }
                 self.expect(&Token::RParen)?;
                 Ok(expr)
             }
 
-            Token::Ident(_) | Token::Row | Token::Current => {
+            Token::Row if self.peek2() == &Token::LParen => {
+                self.advance();
+                self.advance();
+                let mut items = Vec::new();
+                if !self.try_consume(&Token::RParen) {
+                    loop {
+                        items.push(self.parse_expr()?);
+                        if !self.try_consume(&Token::Comma) { break; }
+                    }
+                    self.expect(&Token::RParen)?;
+                }
+                Ok(Expr::Row(items))
+            }
+
+            Token::Ident(s) if s.eq_ignore_ascii_case("ARRAY") && self.peek2() == &Token::LBracket => {
+                self.advance();
+                self.advance();
+                let mut items = Vec::new();
+                if !self.try_consume(&Token::RBracket) {
+                    loop {
+                        items.push(self.parse_expr()?);
+                        if !self.try_consume(&Token::Comma) { break; }
+                    }
+                    self.expect(&Token::RBracket)?;
+                }
+                Ok(Expr::Function {
+                    name: "ARRAY".to_string(),
+                    args: items,
+                    distinct: false,
+                    within_group: Vec::new(),
+                    filter: None,
+                    over: None,
+                })
+            }
+
+            Token::Ident(_) | Token::QuotedIdent(_) | Token::Row | Token::Current => {
                 self.parse_ident_or_function()
             }
 
@@ -891,13 +1427,23 @@ impl Parser {
                     // Otherwise treat as function name
                     self.parse_function_call(type_name)
                 } else {
-                    Err(PivotError::SqlError(format!("Unexpected token in expression: {:?}", other)))
+                    Err(match keyword_text(&other) {
+                        Some(kw) => reserved_keyword_error(kw),
+                        None => PivotError::SqlError(format!("Unexpected token in expression: {:?}", other)),
+                    })
                 }
             }
         }
     }
 
     fn parse_ident_or_function(&mut self) -> Result<Expr> {
+        if let Token::QuotedIdent(name) = self.peek().clone() {
+            self.advance();
+            if self.peek() != &Token::Dot {
+                return Ok(Expr::Column(ColumnRef { table: None, name, quoted: true }));
+            }
+            return self.parse_qualified_column(name);
+        }
         let name = match self.peek().clone() {
             Token::Ident(s) => { self.advance(); s }
             Token::Row => { self.advance(); "row".to_string() }
@@ -905,30 +1451,26 @@ impl Parser {
             other => return Err(PivotError::SqlError(format!("Expected identifier: {:?}", other))),
         };
 
-        // table.column or schema.table.column
-        if self.peek() == &Token::Dot {
-            self.advance();
-            if self.peek() == &Token::Star {
-                self.advance();
-                return Ok(Expr::Wildcard); // actually table.*
-            }
-            let col = match self.peek().clone() {
-                Token::Ident(s) => { self.advance(); s }
-                other => {
-                    if let Some(s) = token_as_ident_name(&other) {
-                        self.advance(); s
-                    } else {
-                        return Err(PivotError::SqlError(format!("Expected column name after '.': {:?}", other)));
-                    }
-                }
+        // Typed literal: DATE '2020-01-01', TIMESTAMP '...', TIME '...'
+        if let Token::StringLiteral(s) = self.peek().clone() {
+            let data_type = match name.to_uppercase().as_str() {
+                "DATE" => Some(DataType::Date),
+                "TIMESTAMP" | "TIMESTAMPTZ" | "DATETIME" => Some(DataType::Timestamp),
+                "TIME" => Some(DataType::Time),
+                _ => None,
             };
-            // Check for another dot (schema.table.col)
-            if self.peek() == &Token::Dot {
+            if let Some(data_type) = data_type {
                 self.advance();
-                let col2 = self.expect_ident()?;
-                return Ok(Expr::Column(ColumnRef { table: Some(col), name: col2 }));
+                return Ok(Expr::Cast {
+                    expr: Box::new(Expr::Literal(LiteralValue::String(s))),
+                    data_type,
+                });
             }
-            return Ok(Expr::Column(ColumnRef { table: Some(name), name: col }));
+        }
+
+        // table.column or schema.table.column
+        if self.peek() == &Token::Dot {
+            return self.parse_qualified_column(name);
         }
 
         // Function call
@@ -936,7 +1478,62 @@ impl Parser {
             return self.parse_function_call(name);
         }
 
-        Ok(Expr::Column(ColumnRef { table: None, name }))
+        // SQL's niladic functions are written without parentheses
+        if matches!(name.to_uppercase().as_str(), "CURRENT_DATE" | "CURRENT_TIMESTAMP" | "CURRENT_TIME") {
+            return Ok(Expr::Function {
+                name: name.to_uppercase(),
+                args: Vec::new(),
+                distinct: false,
+                within_group: Vec::new(),
+                filter: None,
+                over: None,
+            });
+        }
+
+        Ok(Expr::Column(ColumnRef { table: None, name, quoted: false }))
+    }
+
+    /// Parse the `.column` (or `.table.column`) tail after a qualifier.
+    fn parse_qualified_column(&mut self, name: String) -> Result<Expr> {
+        self.expect(&Token::Dot)?;
+        if self.peek() == &Token::Star {
+            self.advance();
+            return Ok(Expr::QualifiedWildcard(name));
+        }
+        let (col, quoted) = match self.peek().clone() {
+            Token::Ident(s) => { self.advance(); (s, false) }
+            Token::QuotedIdent(s) => { self.advance(); (s, true) }
+            other => {
+                if let Some(s) = token_as_ident_name(&other) {
+                    self.advance(); (s, false)
+                } else {
+                    return Err(PivotError::SqlError(format!("Expected column name after '.': {:?}", other)));
+                }
+            }
+        };
+        // Check for another dot (schema.table.col)
+        if self.peek() == &Token::Dot {
+            self.advance();
+            let quoted = matches!(self.peek(), Token::QuotedIdent(_));
+            let col2 = self.expect_ident()?;
+            return Ok(Expr::Column(ColumnRef { table: Some(col), name: col2, quoted }));
+        }
+        Ok(Expr::Column(ColumnRef { table: Some(name), name: col, quoted }))
+    }
+
+    /// Consume an interval unit keyword (YEAR, MONTH, DAY, ...) if one follows.
+    fn parse_interval_unit(&mut self) -> Option<String> {
+        if let Token::Ident(s) = self.peek() {
+            let unit = s.to_uppercase();
+            if matches!(unit.trim_end_matches('S'),
+                "YEAR" | "MONTH" | "WEEK" | "DAY" | "HOUR" | "MINUTE" | "SECOND"
+                | "MILLISECOND" | "MICROSECOND")
+            {
+                self.advance();
+                return Some(unit);
+            }
+        }
+        None
     }
 
     fn parse_function_call(&mut self, name: String) -> Result<Expr> {
@@ -947,11 +1544,15 @@ impl Parser {
         if name.to_uppercase() == "COUNT" && self.peek() == &Token::Star {
             self.advance();
             self.expect(&Token::RParen)?;
+            let filter = self.parse_filter("COUNT")?;
             let over = self.parse_over()?;
+            self.aggregate_calls += usize::from(over.is_none());
             return Ok(Expr::Function {
                 name: "COUNT".to_string(),
                 args: vec![Expr::Wildcard],
                 distinct: false,
+                within_group: Vec::new(),
+                filter,
                 over,
             });
         }
@@ -961,20 +1562,61 @@ impl Parser {
         } else {
             self.parse_expr_list()?
         };
+        // STRING_AGG(x, ',' ORDER BY y): the order inputs are combined in
+        let order_by = if self.try_consume(&Token::Order) {
+            if !matches!(name.to_uppercase().as_str(), "STRING_AGG" | "GROUP_CONCAT" | "LISTAGG" | "ARRAY_AGG") {
+                return Err(PivotError::SqlError(format!(
+                    "ORDER BY inside the call is not supported for {}", name.to_uppercase())));
+            }
+            self.expect(&Token::By)?;
+            self.parse_order_by_items()?
+        } else { Vec::new() };
         self.expect(&Token::RParen)?;
 
-        // FILTER (WHERE ...) clause
-        if self.peek() == &Token::Filter {
-            self.advance();
+        // WITHIN GROUP (ORDER BY ...) for ordered-set functions
+        let within_group = if matches!(self.peek(), Token::Ident(s) if s.eq_ignore_ascii_case("WITHIN"))
+            && self.peek2() == &Token::Group
+        {
+            if !order_by.is_empty() {
+                return Err(PivotError::SqlError(format!(
+                    "{} cannot have both ORDER BY and WITHIN GROUP", name.to_uppercase())));
+            }
+            self.advance(); self.advance();
             self.expect(&Token::LParen)?;
-            self.expect(&Token::Where)?;
-            let _filter_expr = self.parse_expr()?;
+            self.expect(&Token::Order)?;
+            self.expect(&Token::By)?;
+            let items = self.parse_order_by_items()?;
             self.expect(&Token::RParen)?;
-        }
+            items
+        } else { order_by };
 
+        let name = name.to_uppercase();
+        let filter = self.parse_filter(&name)?;
         let over = self.parse_over()?;
+        if over.is_none() && is_aggregate_name(&name) {
+            self.aggregate_calls += 1;
+        }
+
+        Ok(Expr::Function { name, args, distinct, within_group, filter, over })
+    }
 
-        Ok(Expr::Function { name: name.to_uppercase(), args, distinct, over })
+    /// Optional `FILTER (WHERE ...)` after an aggregate call.
+    fn parse_filter(&mut self, name: &str) -> Result<Option<Box<Expr>>> {
+        if !self.try_consume(&Token::Filter) { return Ok(None); }
+        if !is_aggregate_name(name) {
+            return Err(PivotError::SqlError(format!(
+                "FILTER is only allowed on aggregate functions, not {}", name)));
+        }
+        self.expect(&Token::LParen)?;
+        self.expect(&Token::Where)?;
+        let aggregates_before = self.aggregate_calls;
+        let predicate = self.parse_expr()?;
+        if self.aggregate_calls != aggregates_before {
+            return Err(PivotError::SqlError(format!(
+                "FILTER predicate of {} cannot contain an aggregate function", name)));
+        }
+        self.expect(&Token::RParen)?;
+        Ok(Some(Box::new(predicate)))
     }
 
     fn parse_over(&mut self) -> Result<Option<WindowSpec>> {
@@ -982,7 +1624,7 @@ impl Parser {
         self.advance();
 
         if matches!(self.peek(), Token::Ident(_)) && !matches!(self.peek2(), Token::LParen) {
-            // Named window reference - parse as empty spec for now
+            // Named window reference, resolved against the WINDOW clause
             if let Token::Ident(name) = self.peek().clone() {
                 if !matches!(self.peek2(), Token::LParen) {
                     self.advance();
@@ -997,6 +1639,17 @@ impl Parser {
         }
 
         self.expect(&Token::LParen)?;
+        Ok(Some(self.parse_window_spec_body()?))
+    }
+
+    /// The inside of `( [base] [PARTITION BY ...] [ORDER BY ...] [frame] )`,
+    /// after the opening parenthesis.
+    fn parse_window_spec_body(&mut self) -> Result<WindowSpec> {
+        // Nothing else in a window spec starts with an identifier.
+        let name = match self.peek().clone() {
+            Token::Ident(name) => { self.advance(); Some(name) }
+            _ => None,
+        };
         let partition_by = if self.peek() == &Token::Partition && self.peek2() == &Token::By {
             self.advance(); self.advance();
             self.parse_expr_list()?
@@ -1010,24 +1663,26 @@ impl Parser {
         let frame = self.parse_window_frame()?;
 
         self.expect(&Token::RParen)?;
-        Ok(Some(WindowSpec { name: None, partition_by, order_by, frame }))
+        Ok(WindowSpec { name, partition_by, order_by, frame })
     }
 
     fn parse_window_frame(&mut self) -> Result<Option<WindowFrame>> {
         let kind = match self.peek() {
             Token::Rows => { self.advance(); WindowFrameKind::Rows }
             Token::Range => { self.advance(); WindowFrameKind::Range }
+            // GROUPS is not reserved, so `groups` still names tables and columns.
+            _ if self.peek_ident("GROUPS") => { self.advance(); WindowFrameKind::Groups }
             _ => return Ok(None),
         };
-        let start = self.parse_window_frame_bound()?;
-        let end = if self.peek() == &Token::Between {
-            // Actually BETWEEN ... AND ...
-            let low = start;
+        if self.try_consume(&Token::Between) {
+            let start = self.parse_window_frame_bound()?;
             self.expect(&Token::And)?;
-            let high = self.parse_window_frame_bound()?;
-            return Ok(Some(WindowFrame { kind, start: low, end: Some(high) }));
-        } else { None };
-        Ok(Some(WindowFrame { kind, start, end }))
+            let end = self.parse_window_frame_bound()?;
+            return Ok(Some(WindowFrame { kind, start, end: Some(end) }));
+        }
+        // A lone bound is the frame start; the end is the current row.
+        let start = self.parse_window_frame_bound()?;
+        Ok(Some(WindowFrame { kind, start, end: None }))
     }
 
     fn parse_window_frame_bound(&mut self) -> Result<WindowFrameBound> {
@@ -1068,21 +1723,42 @@ impl Parser {
 
 // ─── Helper functions ────────────────────────────────────────────────────────
 
+/// Index of `expr` among the GROUP BY keys, adding it if it's new.
+fn intern_group_key(keys: &mut Vec<Expr>, expr: Expr) -> usize {
+    let text = format!("{:?}", expr);
+    match keys.iter().position(|k| format!("{:?}", k) == text) {
+        Some(i) => i,
+        None => { keys.push(expr); keys.len() - 1 }
+    }
+}
+
+/// Words that stop an implicit (AS-less) alias. Lexer keywords never reach
+/// here as identifiers; these are the words the lexer leaves as identifiers
+/// but the parser gives meaning to when they follow an expression or table.
 fn is_reserved_keyword(s: &str) -> bool {
     matches!(s.to_uppercase().as_str(),
-        "SELECT" | "FROM" | "WHERE" | "GROUP" | "BY" | "HAVING" | "ORDER"
-        | "LIMIT" | "OFFSET" | "JOIN" | "INNER" | "LEFT" | "RIGHT" | "FULL"
-        | "OUTER" | "CROSS" | "ON" | "USING" | "UNION" | "INTERSECT" | "EXCEPT"
-        | "INSERT" | "INTO" | "VALUES" | "UPDATE" | "SET" | "DELETE"
-        | "CREATE" | "TABLE" | "DROP" | "WITH" | "AND" | "OR" | "NOT"
-        | "IS" | "IN" | "LIKE" | "BETWEEN" | "CASE" | "WHEN" | "THEN"
-        | "ELSE" | "END" | "DISTINCT" | "ALL"
+        "AT" | "COLLATE" | "SIMILAR" | "WITHIN" | "WINDOW" | "QUALIFY" | "RETURNING" | "PIVOT" | "UNPIVOT"
     )
 }
 
+fn reserved_keyword_error(keyword: &str) -> PivotError {
+    let word = keyword.to_lowercase();
+    PivotError::SqlError(format!(
+        "'{}' is a reserved keyword; quote it as \"{}\" to use as an identifier", word, word
+    ))
+}
+
+/// Keywords that only have meaning in specific clauses and so may still be
+/// used as an alias after `AS` (e.g. `AS first`, `AS key`).
 fn token_as_alias(tok: &Token) -> Option<String> {
     match tok {
-        Token::Ident(s) => Some(s.clone()),
+        Token::Ident(s) | Token::QuotedIdent(s) | Token::StringLiteral(s) => Some(s.clone()),
+        Token::First | Token::Last | Token::Key | Token::Temp | Token::Temporary | Token::View
+        | Token::Filter | Token::Rows | Token::Range | Token::Row | Token::Current | Token::Index
+        | Token::Nulls | Token::Transaction | Token::Preceding | Token::Following
+        | Token::Unbounded | Token::Partition | Token::Recursive => {
+            keyword_text(tok).map(|kw| kw.to_lowercase())
+        }
         _ => None,
     }
 }
@@ -1100,6 +1776,7 @@ fn token_as_ident_name(tok: &Token) -> Option<String> {
 fn token_to_type_name(tok: &Token) -> Option<String> {
     match tok {
         Token::Ident(s) => Some(s.to_uppercase()),
+        Token::Interval => Some("INTERVAL".to_string()),
         _ => None,
     }
 }
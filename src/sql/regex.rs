@@ -0,0 +1,542 @@
+use crate::error::{PivotError, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A small regular expression engine (Pike VM, linear in the input length).
+///
+/// Supported syntax: literals, `.`, `[...]`/`[^...]` classes with ranges,
+/// `\d \w \s` (and their negations), `^`, `$`, capturing `(...)` and
+/// non-capturing `(?:...)` groups, `|`, and the quantifiers `* + ? {m} {m,}
+/// {m,n}` with an optional trailing `?` for lazy matching. Bounds go up to
+/// 1000, and patterns that would compile to more than `MAX_PROG_LEN`
+/// instructions or nest deeper than `MAX_NESTING` are rejected.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    groups: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Bol,
+    Eol,
+    Match,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Bol,
+    Eol,
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32>, greedy: bool },
+}
+
+/// Byte offsets of group 0 (the whole match) followed by each capture group.
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let mut parser = RegexParser { chars: pattern.chars().collect(), pos: 0, groups: 0, depth: 0 };
+        let node = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return Err(PivotError::SqlError(format!("Unbalanced ')' in regex '{}'", pattern)));
+        }
+        if prog_len(&node) > MAX_PROG_LEN {
+            return Err(PivotError::SqlError(format!("Regex '{}' is too large", pattern)));
+        }
+        let mut prog = vec![Inst::Save(0)];
+        compile(&node, &mut prog);
+        prog.push(Inst::Save(1));
+        prog.push(Inst::Match);
+        Ok(Self { prog, groups: parser.groups })
+    }
+
+    /// `Regex::new` through a per-thread cache, so a pattern applied to every
+    /// row is compiled once. The cache is emptied when it reaches
+    /// `CACHE_CAPACITY` patterns; invalid patterns are not cached.
+    pub fn cached(pattern: &str) -> Result<Rc<Self>> {
+        thread_local! {
+            static CACHE: RefCell<HashMap<String, Rc<Regex>>> = RefCell::new(HashMap::new());
+        }
+        if let Some(re) = CACHE.with(|c| c.borrow().get(pattern).cloned()) {
+            return Ok(re);
+        }
+        let re = Rc::new(Self::new(pattern)?);
+        CACHE.with(|c| {
+            let mut cache = c.borrow_mut();
+            if cache.len() >= CACHE_CAPACITY { cache.clear(); }
+            cache.insert(pattern.to_string(), Rc::clone(&re));
+        });
+        Ok(re)
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.captures_from(text, 0).is_some()
+    }
+
+    /// Leftmost match in `text` with its capture groups.
+    pub fn captures(&self, text: &str) -> Option<Captures> {
+        self.captures_from(text, 0)
+    }
+
+    /// Leftmost match starting at or after byte offset `start`.
+    pub fn captures_from(&self, text: &str, start: usize) -> Option<Captures> {
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let first = chars.iter().position(|(b, _)| *b >= start).unwrap_or(chars.len());
+        let slots = self.run(&chars, text.len(), first)?;
+        Some((0..=self.groups).map(|g| match (slots[2 * g], slots[2 * g + 1]) {
+            (Some(s), Some(e)) => Some((s, e)),
+            _ => None,
+        }).collect())
+    }
+
+    /// Replace the `occurrence`-th match (1-based), or every match when
+    /// `occurrence` is 0. In `replacement`, `\N` inserts capture group N and
+    /// `\&` the whole match.
+    pub fn replace(&self, text: &str, replacement: &str, occurrence: usize) -> String {
+        let mut out = String::new();
+        let mut last = 0;
+        let mut start = 0;
+        let mut n = 0;
+        while start <= text.len() {
+            let Some(caps) = self.captures_from(text, start) else { break };
+            let (s, e) = caps[0].unwrap_or((start, start));
+            n += 1;
+            if occurrence == 0 || n == occurrence {
+                out.push_str(&text[last..s]);
+                expand_replacement(text, &caps, replacement, &mut out);
+                last = e;
+                if occurrence != 0 { break; }
+            }
+            // Step past empty matches so the scan always makes progress.
+            start = if e > s { e } else {
+                match text[e..].chars().next() {
+                    Some(c) => e + c.len_utf8(),
+                    None => break,
+                }
+            };
+        }
+        out.push_str(&text[last..]);
+        out
+    }
+
+    fn run(&self, chars: &[(usize, char)], text_len: usize, first: usize) -> Option<Vec<Option<usize>>> {
+        let n_slots = 2 * (self.groups + 1);
+        let byte_at = |i: usize| chars.get(i).map(|(b, _)| *b).unwrap_or(text_len);
+        let mut clist = Threads::new(self.prog.len());
+        let mut nlist = Threads::new(self.prog.len());
+        let mut matched: Option<Vec<Option<usize>>> = None;
+        for i in first..=chars.len() {
+            if matched.is_none() {
+                let slots = vec![None; n_slots];
+                self.add_thread(&mut clist, 0, i, chars.len(), byte_at(i), slots);
+            }
+            if clist.threads.is_empty() && matched.is_some() { break; }
+            let cur = chars.get(i).map(|(_, c)| *c);
+            for (pc, slots) in std::mem::take(&mut clist.threads) {
+                let advance = match (&self.prog[pc], cur) {
+                    (Inst::Char(c), Some(ch)) => *c == ch,
+                    (Inst::Any, Some(_)) => true,
+                    (Inst::Class(ranges, negated), Some(ch)) => {
+                        ranges.iter().any(|(lo, hi)| *lo <= ch && ch <= *hi) != *negated
+                    }
+                    (Inst::Match, _) => {
+                        matched = Some(slots);
+                        // Lower-priority threads can no longer win.
+                        break;
+                    }
+                    _ => false,
+                };
+                if advance {
+                    self.add_thread(&mut nlist, pc + 1, i + 1, chars.len(), byte_at(i + 1), slots);
+                }
+            }
+            clist.clear();
+            std::mem::swap(&mut clist, &mut nlist);
+        }
+        matched
+    }
+
+    fn add_thread(&self, list: &mut Threads, pc: usize, i: usize, len: usize, byte: usize, mut slots: Vec<Option<usize>>) {
+        if list.seen[pc] { return; }
+        list.seen[pc] = true;
+        match &self.prog[pc] {
+            Inst::Jmp(x) => self.add_thread(list, *x, i, len, byte, slots),
+            Inst::Split(x, y) => {
+                self.add_thread(list, *x, i, len, byte, slots.clone());
+                self.add_thread(list, *y, i, len, byte, slots);
+            }
+            Inst::Save(n) => {
+                slots[*n] = Some(byte);
+                self.add_thread(list, pc + 1, i, len, byte, slots);
+            }
+            Inst::Bol => if i == 0 { self.add_thread(list, pc + 1, i, len, byte, slots) },
+            Inst::Eol => if i == len { self.add_thread(list, pc + 1, i, len, byte, slots) },
+            _ => list.threads.push((pc, slots)),
+        }
+    }
+}
+
+fn expand_replacement(text: &str, caps: &Captures, replacement: &str, out: &mut String) {
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(d) if d.is_ascii_digit() => {
+                let g = d.to_digit(10).unwrap() as usize;
+                if let Some(Some((s, e))) = caps.get(g) {
+                    out.push_str(&text[*s..*e]);
+                }
+            }
+            Some('&') => {
+                if let Some((s, e)) = caps[0] { out.push_str(&text[s..e]); }
+            }
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+}
+
+struct Threads {
+    threads: Vec<(usize, Vec<Option<usize>>)>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(n: usize) -> Self {
+        Self { threads: Vec::new(), seen: vec![false; n] }
+    }
+
+    fn clear(&mut self) {
+        self.threads.clear();
+        self.seen.iter_mut().for_each(|s| *s = false);
+    }
+}
+
+/// Most instructions a compiled pattern may have. Nested counted repeats
+/// multiply, so `((a{1,100}){1,100}){1,100}` is refused before compiling.
+const MAX_PROG_LEN: usize = 100_000;
+
+/// Deepest nesting of groups and stacked quantifiers; parsing and compiling
+/// recurse once per level.
+const MAX_NESTING: usize = 200;
+
+/// Most compiled patterns `Regex::cached` keeps per thread.
+const CACHE_CAPACITY: usize = 256;
+
+/// Number of instructions `compile` emits for `node`, saturating.
+fn prog_len(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Char(_) | Node::Any | Node::Class(..) | Node::Bol | Node::Eol => 1,
+        Node::Group(inner, idx) => prog_len(inner).saturating_add(if idx.is_some() { 2 } else { 0 }),
+        Node::Concat(nodes) => nodes.iter().fold(0, |n, node| n.saturating_add(prog_len(node))),
+        Node::Alt(branches) => branches.iter()
+            .fold(2 * (branches.len() - 1), |n, branch| n.saturating_add(prog_len(branch))),
+        Node::Repeat { node, min, max, .. } => {
+            let len = prog_len(node);
+            let optional = match max {
+                None => len.saturating_add(2),
+                Some(max) => ((max - min) as usize).saturating_mul(len.saturating_add(1)),
+            };
+            (*min as usize).saturating_mul(len).saturating_add(optional)
+        }
+    }
+}
+
+fn compile(node: &Node, prog: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => prog.push(Inst::Char(*c)),
+        Node::Any => prog.push(Inst::Any),
+        Node::Class(ranges, negated) => prog.push(Inst::Class(ranges.clone(), *negated)),
+        Node::Bol => prog.push(Inst::Bol),
+        Node::Eol => prog.push(Inst::Eol),
+        Node::Group(inner, idx) => {
+            if let Some(g) = idx { prog.push(Inst::Save(2 * g)); }
+            compile(inner, prog);
+            if let Some(g) = idx { prog.push(Inst::Save(2 * g + 1)); }
+        }
+        Node::Concat(nodes) => nodes.iter().for_each(|n| compile(n, prog)),
+        Node::Alt(branches) => {
+            // Split to each branch in order; every branch jumps to the end.
+            let mut jumps = Vec::new();
+            for (i, branch) in branches.iter().enumerate() {
+                if i + 1 < branches.len() {
+                    let split = prog.len();
+                    prog.push(Inst::Split(split + 1, 0));
+                    compile(branch, prog);
+                    jumps.push(prog.len());
+                    prog.push(Inst::Jmp(0));
+                    let next = prog.len();
+                    prog[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(branch, prog);
+                }
+            }
+            let end = prog.len();
+            for j in jumps { prog[j] = Inst::Jmp(end); }
+        }
+        Node::Repeat { node, min, max, greedy } => {
+            for _ in 0..*min { compile(node, prog); }
+            let split = |a: usize, b: usize| if *greedy { Inst::Split(a, b) } else { Inst::Split(b, a) };
+            match max {
+                None => {
+                    let start = prog.len();
+                    prog.push(Inst::Jmp(0));
+                    compile(node, prog);
+                    prog.push(Inst::Jmp(start));
+                    let end = prog.len();
+                    prog[start] = split(start + 1, end);
+                }
+                Some(max) => {
+                    let mut holes = Vec::new();
+                    for _ in *min..*max {
+                        holes.push(prog.len());
+                        prog.push(Inst::Jmp(0));
+                        compile(node, prog);
+                    }
+                    let end = prog.len();
+                    for h in holes { prog[h] = split(h + 1, end); }
+                }
+            }
+        }
+    }
+}
+
+struct RegexParser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    /// Groups open around the current position.
+    depth: usize,
+}
+
+impl RegexParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn err(&self, msg: &str) -> PivotError {
+        PivotError::SqlError(format!("Invalid regex at position {}: {}", self.pos, msg))
+    }
+
+    fn parse_alt(&mut self) -> Result<Node> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { Node::Alt(branches) })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' { break; }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_quantifier(&mut self, mut atom: Node) -> Result<Node> {
+        let mut layers = self.depth;
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => { self.pos += 1; (0, None) }
+                Some('+') => { self.pos += 1; (1, None) }
+                Some('?') => { self.pos += 1; (0, Some(1)) }
+                Some('{') => match self.parse_braces()? {
+                    Some(bounds) => bounds,
+                    None => return Ok(atom),
+                },
+                _ => return Ok(atom),
+            };
+            if matches!(atom, Node::Bol | Node::Eol | Node::Empty) {
+                return Err(self.err("nothing to repeat"));
+            }
+            layers += 1;
+            if layers > MAX_NESTING {
+                return Err(self.err("pattern nests too deeply"));
+            }
+            let greedy = if self.peek() == Some('?') { self.pos += 1; false } else { true };
+            atom = Node::Repeat { node: Box::new(atom), min, max, greedy };
+        }
+    }
+
+    /// `{m}`, `{m,}` or `{m,n}`; a `{` not followed by a valid bound is a literal.
+    fn parse_braces(&mut self) -> Result<Option<(u32, Option<u32>)>> {
+        let close = match self.chars[self.pos..].iter().position(|&c| c == '}') {
+            Some(off) => self.pos + off,
+            None => return Ok(None),
+        };
+        let body: String = self.chars[self.pos + 1..close].iter().collect();
+        let num = |s: &str| s.trim().parse::<u32>().ok();
+        let bounds = match body.split_once(',') {
+            None => num(&body).map(|n| (n, Some(n))),
+            Some((lo, hi)) if hi.trim().is_empty() => num(lo).map(|n| (n, None)),
+            Some((lo, hi)) => match (num(lo), num(hi)) {
+                (Some(lo), Some(hi)) => Some((lo, Some(hi))),
+                _ => None,
+            },
+        };
+        let Some((min, max)) = bounds else { return Ok(None) };
+        if max.is_some_and(|m| m < min) || min > 1000 || max.is_some_and(|m| m > 1000) {
+            return Err(self.err("invalid repetition bounds"));
+        }
+        self.pos = close + 1;
+        Ok(Some((min, max)))
+    }
+
+    fn parse_atom(&mut self) -> Result<Node> {
+        let c = self.peek().ok_or_else(|| self.err("unexpected end of pattern"))?;
+        self.pos += 1;
+        match c {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Bol),
+            '$' => Ok(Node::Eol),
+            '(' => {
+                let idx = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                if self.depth == MAX_NESTING {
+                    return Err(self.err("pattern nests too deeply"));
+                }
+                self.depth += 1;
+                let inner = self.parse_alt()?;
+                self.depth -= 1;
+                if self.peek() != Some(')') {
+                    return Err(self.err("missing ')'"));
+                }
+                self.pos += 1;
+                Ok(Node::Group(Box::new(inner), idx))
+            }
+            '[' => self.parse_class(),
+            '\\' => self.parse_escape(),
+            '*' | '+' | '?' => Err(self.err("nothing to repeat")),
+            other => Ok(Node::Char(other)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node> {
+        let c = self.peek().ok_or_else(|| self.err("trailing backslash"))?;
+        self.pos += 1;
+        Ok(match escape_class(c) {
+            Some((ranges, negated)) => Node::Class(ranges, negated),
+            None => Node::Char(escape_char(c)),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Node> {
+        let negated = if self.peek() == Some('^') { self.pos += 1; true } else { false };
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or_else(|| self.err("missing ']'"))?;
+            self.pos += 1;
+            if c == ']' && !first { break; }
+            first = false;
+            let lo = if c == '\\' {
+                let e = self.peek().ok_or_else(|| self.err("trailing backslash"))?;
+                self.pos += 1;
+                if let Some((class, negated)) = escape_class(e) {
+                    ranges.extend(if negated { complement(&class) } else { class });
+                    continue;
+                }
+                escape_char(e)
+            } else {
+                c
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&n| n != ']') {
+                self.pos += 1;
+                let mut hi = self.chars[self.pos];
+                self.pos += 1;
+                if hi == '\\' {
+                    hi = escape_char(self.peek().ok_or_else(|| self.err("trailing backslash"))?);
+                    self.pos += 1;
+                }
+                if hi < lo {
+                    return Err(self.err("invalid character range"));
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        Ok(Node::Class(ranges, negated))
+    }
+}
+
+fn escape_class(c: char) -> Option<(Vec<(char, char)>, bool)> {
+    let digits = vec![('0', '9')];
+    let word = vec![('0', '9'), ('A', 'Z'), ('a', 'z'), ('_', '_')];
+    let space = vec![(' ', ' '), ('\t', '\r')];
+    match c {
+        'd' => Some((digits, false)),
+        'D' => Some((digits, true)),
+        'w' => Some((word, false)),
+        'W' => Some((word, true)),
+        's' => Some((space, false)),
+        'S' => Some((space, true)),
+        _ => None,
+    }
+}
+
+/// The characters outside `ranges`, which must be disjoint.
+fn complement(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    let mut sorted = ranges.to_vec();
+    sorted.sort();
+    let mut out = Vec::new();
+    let mut next = 0u32;
+    for (lo, hi) in sorted {
+        if let Some(before) = (lo as u32).checked_sub(1).filter(|&b| b >= next) {
+            out.extend(char_range(next, before));
+        }
+        next = hi as u32 + 1;
+    }
+    out.extend(char_range(next, char::MAX as u32));
+    out
+}
+
+/// `lo..=hi` as char ranges, split around the surrogate gap.
+fn char_range(lo: u32, hi: u32) -> Vec<(char, char)> {
+    [(lo, hi.min(0xD7FF)), (lo.max(0xE000), hi)].into_iter()
+        .filter(|(a, b)| a <= b)
+        .filter_map(|(a, b)| Some((char::from_u32(a)?, char::from_u32(b)?)))
+        .collect()
+}
+
+fn escape_char(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        other => other,
+    }
+}
@@ -10,6 +10,7 @@ pub enum Token {
 
     // Identifier
     Ident(String),
+    QuotedIdent(String),
 
     // Keywords
     Select,
@@ -17,6 +18,8 @@ pub enum Token {
     Where,
     Group,
     By,
+    Rollup,
+    Cube,
     Having,
     Order,
     Limit,
@@ -89,6 +92,8 @@ pub enum Token {
     Rename,
     To,
     Truncate,
+    Vacuum,
+    Analyze,
     Begin,
     Commit,
     Rollback,
@@ -116,10 +121,16 @@ pub enum Token {
     LtEq,
     GtEq,
     Concat,
+    /// `~`: regex match.
+    Tilde,
+    /// `!~`: regex non-match.
+    NotTilde,
 
     // Punctuation
     LParen,
     RParen,
+    LBracket,
+    RBracket,
     Comma,
     Semicolon,
     Dot,
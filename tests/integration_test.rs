@@ -43,6 +43,31 @@ fn test_group_by_sum() {
     assert_eq!(result.row_count(), 2);
 }
 
+#[test]
+fn test_group_by_api_unknown_column() {
+    use pivot_engine::csv::CsvReader;
+    let store = CsvReader::new().read_str("dept,name\neng,al\nops,bo\neng,cy").unwrap();
+    let groups = pivot_engine::grouping::group_by(&store, &["dept"]).unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].row_indices, vec![0, 2]);
+    assert!(pivot_engine::grouping::group_by(&store, &["missing"]).is_err());
+    assert!(pivot_engine::pivot::pivot_table(&store, &["missing"], "dept", "name").is_err());
+}
+
+#[test]
+fn test_count_distinct() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = make_engine_with_employees();
+    let result = engine.execute("SELECT COUNT(DISTINCT dept), COUNT(dept), SUM(DISTINCT salary) FROM employees").unwrap();
+    assert_eq!(result.rows[0][0], ScalarValue::Int64(2));
+    assert_eq!(result.rows[0][1], ScalarValue::Int64(5));
+    assert_eq!(result.rows[0][2], ScalarValue::Float64(410000.0));
+    engine.execute("INSERT INTO employees VALUES (6, 'Finn', 'Marketing', 70000.0)").unwrap();
+    let result = engine.execute("SELECT AVG(DISTINCT salary), AVG(salary) FROM employees WHERE dept = 'Marketing'").unwrap();
+    assert_eq!(result.rows[0][0], ScalarValue::Float64(72500.0));
+    assert_ne!(result.rows[0][1], ScalarValue::Float64(72500.0));
+}
+
 #[test]
 fn test_order_by() {
     let mut engine = make_engine_with_employees();
@@ -292,3 +317,553 @@ fn test_union() {
     let result = engine.execute("SELECT val FROM t1 UNION ALL SELECT val FROM t2 ORDER BY val").unwrap();
     assert_eq!(result.row_count(), 4);
 }
+
+#[test]
+fn test_query_result_iteration() {
+    use pivot_engine::column::ScalarValue;
+    use std::collections::HashMap;
+    let mut engine = make_engine_with_employees();
+    let result = engine.execute("SELECT id, name FROM employees WHERE dept = 'Marketing' ORDER BY id").unwrap();
+
+    let ids: Vec<&ScalarValue> = result.iter().map(|row| &row[0]).collect();
+    assert_eq!(ids, vec![&ScalarValue::Int64(3), &ScalarValue::Int64(4)]);
+    let mut n = 0;
+    for row in &result {
+        assert_eq!(row.len(), 2);
+        n += 1;
+    }
+    assert_eq!(n, 2);
+
+    let maps: Vec<HashMap<String, ScalarValue>> = result.rows_as_maps().collect();
+    assert_eq!(maps[0]["name"], ScalarValue::Utf8("Carol".to_string()));
+    assert_eq!(maps[1]["id"], ScalarValue::Int64(4));
+
+    let owned: Vec<Vec<ScalarValue>> = result.into_iter().collect();
+    assert_eq!(owned[1][1], ScalarValue::Utf8("Dave".to_string()));
+
+    // Maps are keyed by the output names; a repeated name keeps the later value.
+    let result = engine.execute("SELECT name AS who, id AS n, salary AS n FROM employees WHERE id = 1").unwrap();
+    let maps: Vec<HashMap<String, ScalarValue>> = result.rows_as_maps().collect();
+    assert_eq!(maps.len(), 1);
+    assert_eq!(maps[0].len(), 2);
+    assert_eq!(maps[0]["who"], ScalarValue::Utf8("Alice".to_string()));
+    assert_eq!(maps[0]["n"], ScalarValue::Float64(90000.0));
+
+    let empty = engine.execute("SELECT id FROM employees WHERE id > 99").unwrap();
+    assert_eq!(empty.iter().count(), 0);
+    assert_eq!(empty.rows_as_maps().count(), 0);
+    assert_eq!(empty.into_iter().count(), 0);
+}
+
+#[test]
+fn test_hashable_scalar_keys() {
+    use pivot_engine::column::{HashableScalar, ScalarValue};
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+    fn hash_of(v: ScalarValue) -> u64 {
+        let mut h = DefaultHasher::new();
+        HashableScalar(v).hash(&mut h);
+        h.finish()
+    }
+    let key = HashableScalar;
+
+    assert_eq!(key(ScalarValue::Int64(1)), key(ScalarValue::Float64(1.0)));
+    assert_eq!(hash_of(ScalarValue::Int64(1)), hash_of(ScalarValue::Float64(1.0)));
+    assert_eq!(key(ScalarValue::Float64(-0.0)), key(ScalarValue::Float64(0.0)));
+    assert_eq!(hash_of(ScalarValue::Float64(-0.0)), hash_of(ScalarValue::Float64(0.0)));
+    assert_eq!(key(ScalarValue::Float64(f64::NAN)), key(ScalarValue::Float64(-f64::NAN)));
+    assert_eq!(hash_of(ScalarValue::Float64(f64::NAN)), hash_of(ScalarValue::Float64(-f64::NAN)));
+    assert_eq!(key(ScalarValue::Null), key(ScalarValue::Null));
+    assert_ne!(key(ScalarValue::Utf8("1".to_string())), key(ScalarValue::Int64(1)));
+    assert_ne!(key(ScalarValue::Float64(1.5)), key(ScalarValue::Int64(1)));
+
+    let set: HashSet<HashableScalar> = [
+        ScalarValue::Int64(2), ScalarValue::Float64(2.0), ScalarValue::Float64(f64::NAN),
+        ScalarValue::Float64(f64::NAN), ScalarValue::Null, ScalarValue::Null,
+    ].into_iter().map(HashableScalar).collect();
+    assert_eq!(set.len(), 3);
+
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t1 (v INTEGER)").unwrap();
+    engine.execute("INSERT INTO t1 VALUES (1), (2), (NULL)").unwrap();
+    engine.execute("CREATE TABLE t2 (v DOUBLE)").unwrap();
+    engine.execute("INSERT INTO t2 VALUES (1.0), (NULL)").unwrap();
+    let result = engine.execute("SELECT v FROM t1 INTERSECT SELECT v FROM t2").unwrap();
+    assert_eq!(result.row_count(), 2);
+    let result = engine.execute("SELECT v FROM t1 EXCEPT SELECT v FROM t2").unwrap();
+    assert_eq!(result.row_count(), 1);
+}
+
+#[test]
+fn test_schema_diff_and_insert_compatibility() {
+    use pivot_engine::schema::{ColumnDef, DataType, Schema, SchemaChange};
+    let old = Schema::new(vec![
+        ColumnDef::new("id", DataType::Int64, false),
+        ColumnDef::new("name", DataType::Utf8, true),
+        ColumnDef::new("legacy", DataType::Boolean, true),
+    ]);
+    let new = Schema::new(vec![
+        ColumnDef::new("ID", DataType::Int64, true),
+        ColumnDef::new("name", DataType::Float64, true),
+        ColumnDef::new("created", DataType::Date, false),
+    ]);
+    assert_eq!(old.diff(&new), vec![
+        SchemaChange::ColumnRemoved { name: "legacy".to_string() },
+        SchemaChange::NullabilityChanged { name: "ID".to_string(), nullable: true },
+        SchemaChange::TypeChanged { name: "name".to_string(), from: DataType::Utf8, to: DataType::Float64 },
+        SchemaChange::ColumnAdded { name: "created".to_string(), data_type: DataType::Date, nullable: false },
+    ]);
+    assert!(old.diff(&old).is_empty());
+
+    let source = Schema::new(vec![
+        ColumnDef::new("a", DataType::Float64, true),
+        ColumnDef::new("b", DataType::Utf8, true),
+    ]);
+    assert!(new.is_compatible_for_insert(&source).is_ok());
+    assert!(old.is_compatible_for_insert(&source).is_ok());
+    let bad = Schema::new(vec![ColumnDef::new("flag", DataType::Boolean, true)]);
+    assert!(new.is_compatible_for_insert(&bad).is_err());
+    let wide = Schema::new(vec![ColumnDef::new("x", DataType::Utf8, true); 4]);
+    assert!(new.is_compatible_for_insert(&wide).is_err());
+
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE src (id INTEGER, ok BOOLEAN)").unwrap();
+    engine.execute("CREATE TABLE dst (id INTEGER, label VARCHAR)").unwrap();
+    engine.execute("INSERT INTO src VALUES (1, true)").unwrap();
+    assert!(engine.execute("INSERT INTO dst SELECT id, ok FROM src").is_err());
+    assert!(engine.execute("INSERT INTO dst (id) SELECT id, ok FROM src").is_err());
+    engine.execute("INSERT INTO dst SELECT id, 'x' FROM src").unwrap();
+    // Computed values and VALUES rows follow the same conversions as typed
+    // columns: an integer is never stored in a VARCHAR column.
+    assert!(engine.execute("INSERT INTO dst (label) SELECT id FROM src").is_err());
+    assert!(engine.execute("INSERT INTO dst (label) SELECT id + 1 FROM src").is_err());
+    assert!(engine.execute("INSERT INTO dst (label) VALUES (5)").is_err());
+    assert!(engine.execute("INSERT INTO dst (id) VALUES (true)").is_err());
+    engine.execute("INSERT INTO dst VALUES ('7', 'y'), (2.0, NULL)").unwrap();
+    let r = engine.execute("SELECT id FROM dst ORDER BY id").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![pivot_engine::column::ScalarValue::Int64(1)],
+        vec![pivot_engine::column::ScalarValue::Int64(2)],
+        vec![pivot_engine::column::ScalarValue::Int64(7)],
+    ]);
+}
+
+#[test]
+fn test_csv_empty_as_null() {
+    use pivot_engine::column::ScalarValue;
+    use pivot_engine::csv::CsvReader;
+    let data = "name,note\nalice,\nbob,\"\"\ncarol,hi";
+
+    // Default: empty fields (quoted or not) are NULL.
+    let store = CsvReader::new().read_str(data).unwrap();
+    assert_eq!(store.get_value(0, "note").unwrap(), ScalarValue::Null);
+    assert_eq!(store.get_value(1, "note").unwrap(), ScalarValue::Null);
+    assert_eq!(store.get_value(2, "note").unwrap(), ScalarValue::Utf8("hi".into()));
+
+    let store = CsvReader::new().with_empty_as_null(false).read_str(data).unwrap();
+    assert_eq!(store.get_value(0, "note").unwrap(), ScalarValue::Utf8(String::new()));
+    assert_eq!(store.get_value(1, "note").unwrap(), ScalarValue::Utf8(String::new()));
+    assert_eq!(store.get_value(2, "note").unwrap(), ScalarValue::Utf8("hi".into()));
+    // Short rows are still padded with NULL.
+    let store = CsvReader::new().with_empty_as_null(false).read_str("a,b\n1").unwrap();
+    assert_eq!(store.get_value(0, "b").unwrap(), ScalarValue::Null);
+}
+
+#[test]
+fn test_csv_type_inference() {
+    use pivot_engine::aggregation;
+    use pivot_engine::column::{date_string_to_epoch_days, ScalarValue};
+    use pivot_engine::csv::CsvReader;
+    use pivot_engine::schema::DataType;
+    let data = "id,hired,score,code\n1,2024-01-15,1.5,7\n2,,2,x7\n3,2023-12-31,,8";
+
+    // Without inference every column is text.
+    let store = CsvReader::new().read_str(data).unwrap();
+    assert!(store.schema().columns.iter().all(|c| c.data_type == DataType::Utf8));
+    assert_eq!(aggregation::sum(&store, "id").unwrap(), ScalarValue::Null);
+
+    let store = CsvReader::new().with_type_inference(true).read_str(data).unwrap();
+    let types: Vec<DataType> = store.schema().columns.iter().map(|c| c.data_type.clone()).collect();
+    assert_eq!(types, vec![DataType::Int64, DataType::Date, DataType::Float64, DataType::Utf8]);
+    assert_eq!(aggregation::sum(&store, "id").unwrap(), ScalarValue::Int64(6));
+    assert_eq!(store.get_value(0, "hired").unwrap(), ScalarValue::Date(date_string_to_epoch_days("2024-01-15").unwrap()));
+    // Empty fields stay NULL; mixed content falls back to text.
+    assert_eq!(store.get_value(1, "hired").unwrap(), ScalarValue::Null);
+    assert_eq!(store.get_value(1, "code").unwrap(), ScalarValue::Utf8("x7".into()));
+}
+
+#[test]
+fn test_register_table_and_load_csv() {
+    use pivot_engine::column::ScalarValue;
+    use pivot_engine::csv::CsvReader;
+    let mut engine = SqlEngine::new();
+    let reader = CsvReader::new().with_type_inference(true);
+    engine.load_csv("sales", "region,amount\neast,10\nwest,25\neast,5", &reader, false).unwrap();
+
+    let r = engine.execute("SELECT COUNT(*), SUM(amount) FROM sales").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(3), ScalarValue::Int64(40)]);
+    let r = engine.execute("SELECT region, SUM(amount) FROM Sales GROUP BY region ORDER BY region").unwrap();
+    assert_eq!(r.rows[1], vec![ScalarValue::Utf8("west".into()), ScalarValue::Int64(25)]);
+
+    // Re-registering a name needs `replace`; the new data then wins.
+    assert!(engine.load_csv("sales", "region,amount\nnorth,1", &reader, false).is_err());
+    assert_eq!(engine.execute("SELECT COUNT(*) FROM sales").unwrap().rows[0][0], ScalarValue::Int64(3));
+    let store = reader.read_str("region,amount\nnorth,1").unwrap();
+    engine.register_table("sales", store, true).unwrap();
+    assert_eq!(engine.execute("SELECT COUNT(*) FROM sales").unwrap().rows[0][0], ScalarValue::Int64(1));
+
+    // A registered table takes SQL writes like any other.
+    engine.execute("INSERT INTO sales VALUES ('south', 4)").unwrap();
+    assert_eq!(engine.execute("SELECT SUM(amount) FROM sales").unwrap().rows[0][0], ScalarValue::Int64(5));
+    engine.execute("CREATE VIEW v AS SELECT 1").unwrap();
+    assert!(engine.load_csv("v", "a\n1", &reader, true).is_err());
+}
+
+#[test]
+fn test_csv_write_query_result() {
+    use pivot_engine::csv::{CsvReader, CsvWriter};
+    use pivot_engine::column::ScalarValue;
+    let mut engine = make_engine_with_employees();
+    engine.execute("INSERT INTO employees VALUES (6, 'Doe, \"JD\"', NULL, 50000.5)").unwrap();
+    let result = engine.execute("SELECT name, salary AS pay, dept FROM employees ORDER BY id").unwrap();
+
+    let csv = CsvWriter::new().write_query_result(&result).unwrap();
+    assert!(csv.starts_with("name,pay,dept\n"));
+    assert!(csv.ends_with("\n\"Doe, \"\"JD\"\"\",50000.5,\n"), "{}", csv);
+
+    let back = CsvReader::new().with_type_inference(true).read_str(&csv).unwrap();
+    assert_eq!(back.row_count(), result.row_count());
+    assert_eq!(back.schema().column_names(), result.columns);
+    for (row, expected) in result.rows.iter().enumerate() {
+        assert_eq!(&back.get_row(row).unwrap(), expected);
+    }
+
+    let headerless = CsvWriter { delimiter: ';', write_header: false }.write_query_result(&result).unwrap();
+    assert_eq!(headerless.lines().count(), result.row_count());
+    assert_eq!(CsvWriter::new().write_query_result(&engine.execute("SELECT '' AS e, NULL AS n").unwrap()).unwrap(), "e,n\n\"\",\n");
+
+    // Quoted fields may span lines.
+    engine.execute("INSERT INTO employees VALUES (7, 'line one\nline two\r\nthree', 'Ops', 1.0)").unwrap();
+    let result = engine.execute("SELECT name, dept FROM employees WHERE id = 7").unwrap();
+    let csv = CsvWriter::new().write_query_result(&result).unwrap();
+    let back = CsvReader::new().read_str(&csv).unwrap();
+    assert_eq!(back.row_count(), 1);
+    assert_eq!(back.get_row(0).unwrap(), result.rows[0]);
+
+    // A quote inside an unquoted field is literal; one left open is an error.
+    let store = CsvReader::new().read_str("item,size\npipe,5\" wide\nbolt,3\nnut,4").unwrap();
+    assert_eq!(store.row_count(), 3);
+    assert_eq!(store.get_row(0).unwrap()[1], ScalarValue::Utf8("5\" wide".to_string()));
+    assert!(CsvReader::new().read_str("item,size\npipe,\"5 wide\nbolt,3").is_err());
+}
+
+#[test]
+fn test_csv_null_tokens() {
+    use pivot_engine::column::ScalarValue;
+    use pivot_engine::csv::CsvReader;
+    let data = "id,city\n1,\\N\n2,NULL\n3,null\n4,Oslo";
+
+    let store = CsvReader::new().with_null_tokens(&["\\N"]).read_str(data).unwrap();
+    assert_eq!(store.get_value(0, "city").unwrap(), ScalarValue::Null);
+    // "NULL" is ordinary text unless configured as a token.
+    assert_eq!(store.get_value(1, "city").unwrap(), ScalarValue::Utf8("NULL".into()));
+    assert_eq!(store.get_value(3, "city").unwrap(), ScalarValue::Utf8("Oslo".into()));
+
+    let store = CsvReader::new().with_null_tokens(&["\\N", "NULL"]).read_str(data).unwrap();
+    assert_eq!(store.get_value(1, "city").unwrap(), ScalarValue::Null);
+    assert_eq!(store.get_value(2, "city").unwrap(), ScalarValue::Utf8("null".into()));
+
+    let store = CsvReader::new()
+        .with_null_tokens(&["NULL"])
+        .with_null_tokens_case_sensitive(false)
+        .read_str(data)
+        .unwrap();
+    assert_eq!(store.get_value(1, "city").unwrap(), ScalarValue::Null);
+    assert_eq!(store.get_value(2, "city").unwrap(), ScalarValue::Null);
+    assert_eq!(store.get_value(0, "city").unwrap(), ScalarValue::Utf8("\\N".into()));
+}
+
+#[test]
+fn test_get_row_by_primary_key() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE users (id INTEGER PRIMARY KEY, name VARCHAR)").unwrap();
+    engine.execute("INSERT INTO users VALUES (10, 'ann'), (20, 'bo'), (30, 'cy'), (40, 'di')").unwrap();
+    engine.execute("DELETE FROM users WHERE id = 20").unwrap();
+    engine.execute("UPDATE users SET id = 45 WHERE id = 40").unwrap();
+
+    let users = engine.catalog.get_table("users").unwrap();
+    assert_eq!(users.get_row_by_key(&ScalarValue::Int64(30)),
+        Some(vec![ScalarValue::Int64(30), ScalarValue::Utf8("cy".into())]));
+    assert_eq!(users.get_row_by_key(&ScalarValue::Int64(45)),
+        Some(vec![ScalarValue::Int64(45), ScalarValue::Utf8("di".into())]));
+    assert_eq!(users.get_row_by_key(&ScalarValue::Int64(10)).unwrap()[1], ScalarValue::Utf8("ann".into()));
+    assert_eq!(users.get_row_by_key(&ScalarValue::Int64(20)), None);
+    assert_eq!(users.get_row_by_key(&ScalarValue::Int64(40)), None);
+
+    // Keys stay unique.
+    assert!(engine.execute("INSERT INTO users VALUES (30, 'dup')").is_err());
+    assert!(engine.execute("UPDATE users SET id = 10 WHERE id = 30").is_err());
+    assert_eq!(engine.execute("SELECT * FROM users").unwrap().row_count(), 3);
+
+    // Tables without a primary key have nothing to look up.
+    engine.execute("CREATE TABLE logs (id INTEGER)").unwrap();
+    engine.execute("INSERT INTO logs VALUES (1)").unwrap();
+    assert_eq!(engine.catalog.get_table("logs").unwrap().get_row_by_key(&ScalarValue::Int64(1)), None);
+}
+
+#[test]
+fn test_interval_display_round_trip() {
+    use pivot_engine::column::{IntervalValue, ScalarValue, parse_interval};
+    use pivot_engine::csv::{CsvReader, CsvWriter};
+    use pivot_engine::datastore::DataStore;
+    use pivot_engine::schema::{ColumnDef, DataType, Schema};
+
+    let iv = IntervalValue::new(1, -2, 3, 4_000_000_000_123);
+    let text = ScalarValue::Interval(iv.clone()).to_string();
+    assert_eq!(text, "1 years -2 months 3 days 4000000000123 micros");
+    assert_eq!(parse_interval(&text), Some(iv.clone()));
+    assert_eq!(parse_interval("1 years 2 months"), None);
+    assert_eq!(parse_interval("1.5 years 0 months 0 days 0 micros"), None);
+
+    // Through a DataStore, out to CSV and back into an INTERVAL column.
+    let schema = Schema::new(vec![
+        ColumnDef::new("id", DataType::Int64, false),
+        ColumnDef::new("gap", DataType::Interval, true),
+    ]);
+    let mut store = DataStore::new(schema.clone());
+    store.append_row(vec![ScalarValue::Int64(1), ScalarValue::Interval(iv.clone())]).unwrap();
+    store.append_row(vec![ScalarValue::Int64(2), ScalarValue::Null]).unwrap();
+    assert_eq!(store.get_value(0, "gap").unwrap(), ScalarValue::Interval(iv.clone()));
+
+    let csv = CsvWriter::new().write_str(&store).unwrap();
+    let read = CsvReader::new().read_str(&csv).unwrap();
+    let mut back = DataStore::new(schema);
+    for row in 0..read.row_count() {
+        back.append_row(vec![ScalarValue::Int64(row as i64 + 1), read.get_value(row, "gap").unwrap()]).unwrap();
+    }
+    assert_eq!(back.get_value(0, "gap").unwrap(), ScalarValue::Interval(iv));
+    assert_eq!(back.get_value(1, "gap").unwrap(), ScalarValue::Null);
+
+    // CAST accepts the canonical text as well as literal forms.
+    let mut engine = SqlEngine::new();
+    let r = engine.execute("SELECT CAST('0 years 1 months 2 days 0 micros' AS INTERVAL) = INTERVAL '1 month 2 days'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Boolean(true));
+}
+
+#[test]
+fn test_analyze_collects_table_stats() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = make_engine_with_employees();
+    engine.execute("INSERT INTO employees VALUES (6, NULL, 'Marketing', 60000.0)").unwrap();
+    engine.execute("CREATE TABLE empty (x INTEGER)").unwrap();
+    assert!(engine.catalog.table_stats("employees").is_none());
+
+    engine.execute("ANALYZE TABLE employees").unwrap();
+    assert!(engine.catalog.table_stats("empty").is_none());
+    let stats = engine.catalog.table_stats("employees").unwrap();
+    assert_eq!(stats.row_count, 6);
+    let salary = stats.column("salary").unwrap();
+    assert_eq!(salary.min, ScalarValue::Float64(60000.0));
+    assert_eq!(salary.max, ScalarValue::Float64(95000.0));
+    assert_eq!(salary.distinct_count, 6);
+    let dept = stats.column("dept").unwrap();
+    assert_eq!(dept.distinct_count, 2);
+    assert_eq!(dept.min, ScalarValue::Utf8("Engineering".into()));
+    let name = stats.column("name").unwrap();
+    assert_eq!((name.null_count, name.distinct_count), (1, 5));
+
+    // Stats are a snapshot; a bare ANALYZE refreshes every table.
+    engine.execute("DELETE FROM employees WHERE salary < 80000").unwrap();
+    assert_eq!(engine.catalog.table_stats("employees").unwrap().row_count, 6);
+    engine.execute("ANALYZE").unwrap();
+    assert_eq!(engine.catalog.table_stats("employees").unwrap().row_count, 3);
+    let x = engine.catalog.table_stats("empty").unwrap().column("x").unwrap().clone();
+    assert_eq!((x.min, x.distinct_count), (ScalarValue::Null, 0));
+
+    assert!(engine.execute("ANALYZE missing").is_err());
+    engine.execute("DROP TABLE employees").unwrap();
+    assert!(engine.catalog.table_stats("employees").is_none());
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn test_exec_metrics_counters() {
+    let mut engine = make_engine_with_employees();
+    engine.execute("CREATE TABLE depts (name VARCHAR, floor INTEGER)").unwrap();
+    engine.execute("INSERT INTO depts VALUES ('Engineering', 3), ('Marketing', 1)").unwrap();
+    assert_eq!(engine.last_metrics().rows_scanned, 0);
+
+    engine.execute("SELECT e.name, d.floor FROM employees e JOIN depts d ON e.dept = d.name \
+                    WHERE e.salary > 75000 ORDER BY e.salary").unwrap();
+    let m = engine.last_metrics();
+    assert_eq!(m.rows_scanned, 5 + 2);
+    // The equi-join only tries pairs whose keys hash alike: one per employee.
+    assert_eq!(m.join_comparisons, 5);
+    // Every candidate pair is materialized, then the 3 rows passing WHERE.
+    assert_eq!(m.rows_cloned, 5 + 3);
+    assert!(m.sort_comparisons >= 2);
+
+    // Any other condition tries every pair.
+    engine.execute("SELECT e.name FROM employees e JOIN depts d ON e.id > d.floor").unwrap();
+    assert_eq!(engine.last_metrics().join_comparisons, 5 * 2);
+
+    // Counters cover only the last statement.
+    engine.execute("SELECT * FROM depts").unwrap();
+    let m = engine.last_metrics();
+    assert_eq!((m.rows_scanned, m.join_comparisons, m.sort_comparisons), (2, 0, 0));
+}
+
+#[test]
+fn test_query_result_typed_accessors() {
+    let mut engine = make_engine_with_employees();
+    let result = engine.execute(
+        "SELECT id, name, salary, salary > 80000 AS well_paid, NULL AS nothing FROM employees ORDER BY id",
+    ).unwrap();
+
+    // Lookups by name ignore case.
+    let id = result.column_index("ID").unwrap();
+    let name = result.column_index("Name").unwrap();
+    let salary = result.column_index("salary").unwrap();
+    let well_paid = result.column_index("WELL_PAID").unwrap();
+    let nothing = result.column_index("nothing").unwrap();
+    assert_eq!(result.column_index("missing"), None);
+
+    assert_eq!(result.get_i64(0, id), Some(1));
+    assert_eq!(result.get_str(0, name), Some("Alice"));
+    assert_eq!(result.get_f64(0, salary), Some(90000.0));
+    assert_eq!(result.get_bool(0, well_paid), Some(true));
+
+    // INTEGERs widen for get_f64; other mismatches and NULLs are None.
+    assert_eq!(result.get_f64(1, id), Some(2.0));
+    assert_eq!(result.get_i64(0, salary), None);
+    assert_eq!(result.get_str(0, id), None);
+    assert_eq!(result.get_bool(0, name), None);
+    assert_eq!(result.get_i64(0, nothing), None);
+    assert_eq!(result.get_str(0, nothing), None);
+
+    // Out of range is None too.
+    assert_eq!(result.get_i64(result.row_count(), id), None);
+    assert_eq!(result.get(0, result.column_count()), None);
+}
+
+#[test]
+fn test_register_scalar_function() {
+    use pivot_engine::column::ScalarValue;
+    use pivot_engine::error::PivotError;
+    let mut engine = make_engine_with_employees();
+    engine.register_function("double_it", Box::new(|args: &[ScalarValue]| {
+        Ok(match args.first() {
+            Some(ScalarValue::Int64(i)) => ScalarValue::Int64(i * 2),
+            Some(ScalarValue::Float64(f)) => ScalarValue::Float64(f * 2.0),
+            _ => ScalarValue::Null,
+        })
+    }));
+
+    // Names match in any case, and the function composes with the rest of SQL.
+    let result = engine.execute(
+        "SELECT DOUBLE_IT(id), Double_It(salary) FROM employees WHERE double_it(id) > 6 ORDER BY id",
+    ).unwrap();
+    assert_eq!(result.rows, vec![
+        vec![ScalarValue::Int64(8), ScalarValue::Float64(150000.0)],
+        vec![ScalarValue::Int64(10), ScalarValue::Float64(190000.0)],
+    ]);
+    let result = engine.execute("SELECT DOUBLE_IT(SUM(id)) FROM employees").unwrap();
+    assert_eq!(result.rows[0][0], ScalarValue::Int64(30));
+
+    // A registered function shadows a built-in, and its errors surface as-is.
+    engine.register_function("UPPER", Box::new(|_: &[ScalarValue]| {
+        Err(PivotError::SqlError("UPPER is disabled".to_string()))
+    }));
+    let err = engine.execute("SELECT UPPER(name) FROM employees").unwrap_err();
+    assert!(err.to_string().contains("UPPER is disabled"), "{}", err);
+}
+
+#[test]
+fn test_order_by_evaluates_each_key_once_per_row() {
+    use pivot_engine::column::ScalarValue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE nums (id INTEGER)").unwrap();
+    let values = (1..=200).map(|i| format!("({})", i)).collect::<Vec<_>>().join(", ");
+    engine.execute(&format!("INSERT INTO nums VALUES {}", values)).unwrap();
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&calls);
+    engine.register_function("scramble", Box::new(move |args: &[ScalarValue]| {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Ok(match args.first() {
+            Some(ScalarValue::Int64(i)) if i % 10 == 0 => ScalarValue::Null,
+            Some(ScalarValue::Int64(i)) => ScalarValue::Int64(i * 37 % 101),
+            _ => ScalarValue::Null,
+        })
+    }));
+
+    let key = |i: i64| if i % 10 == 0 { None } else { Some(i * 37 % 101) };
+    let mut expected: Vec<i64> = (1..=200).collect();
+    expected.sort_by(|a, b| match (key(*a), key(*b)) {
+        (None, None) => a.cmp(b),
+        (None, _) => std::cmp::Ordering::Less,
+        (_, None) => std::cmp::Ordering::Greater,
+        (Some(x), Some(y)) => y.cmp(&x).then(a.cmp(b)),
+    });
+
+    let result = engine.execute("SELECT id FROM nums ORDER BY scramble(id) DESC NULLS FIRST, id").unwrap();
+    let ids: Vec<i64> = result.rows.iter().map(|row| match row[0] {
+        ScalarValue::Int64(i) => i,
+        ref other => panic!("unexpected {:?}", other),
+    }).collect();
+    assert_eq!(ids, expected);
+    // Keys are computed up front, not per comparison, and once per row.
+    assert_eq!(calls.load(Ordering::Relaxed), 200);
+    // Also when the key reads a column only the input rows have.
+    calls.store(0, Ordering::Relaxed);
+    let result = engine.execute("SELECT id * 2 AS twice FROM nums ORDER BY scramble(id) DESC NULLS FIRST, id").unwrap();
+    assert_eq!(result.rows[0][0], ScalarValue::Int64(expected[0] * 2));
+    assert_eq!(calls.load(Ordering::Relaxed), 200);
+
+    // Window ordering places NULLs the same way.
+    let result = engine.execute(
+        "SELECT id, ROW_NUMBER() OVER (ORDER BY scramble(id) DESC NULLS FIRST, id) AS rn FROM nums ORDER BY rn LIMIT 3",
+    ).unwrap();
+    assert_eq!(result.rows.iter().map(|row| row[0].clone()).collect::<Vec<_>>(),
+        expected[..3].iter().map(|i| ScalarValue::Int64(*i)).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_query_result_to_table_string() {
+    let mut engine = make_engine_with_employees();
+    let result = engine.execute(
+        "SELECT id, name, CASE WHEN id = 3 THEN NULL ELSE salary END AS pay FROM employees WHERE id <= 3 ORDER BY id",
+    ).unwrap();
+    assert_eq!(result.to_table_string(), [
+        "+----+-------+---------+",
+        "| id | name  | pay     |",
+        "+----+-------+---------+",
+        "|  1 | Alice | 90000.0 |",
+        "|  2 | Bob   | 80000.0 |",
+        "|  3 | Carol | NULL    |",
+        "+----+-------+---------+",
+    ].join("\n"));
+
+    // Widths follow the widest shown cell, and the rest are counted.
+    let result = engine.execute("SELECT name FROM employees ORDER BY id").unwrap();
+    let table = result.to_table_string_truncated(2);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines[1], "| name  |");
+    assert_eq!(lines[0], lines[2]);
+    assert_eq!(lines[6], "... 3 more rows");
+    assert_eq!(lines.len(), 7);
+
+    let done = engine.execute("DELETE FROM employees WHERE id = 5").unwrap();
+    assert_eq!(done.to_table_string(), "1 row affected");
+}
+
+#[test]
+fn test_engine_is_send() {
+    fn is_send<T: Send>() {}
+    is_send::<SqlEngine>();
+    is_send::<pivot_engine::sql::QueryResult>();
+}
@@ -105,8 +105,7 @@ fn test_sql_subquery() {
         "SELECT name FROM employees WHERE salary > (SELECT AVG(salary) FROM employees)"
     ).unwrap();
     // AVG = 88333.3; Alice (90000) and Eve (95000) are above avg
-    // Note: subquery returns NULL in simplified executor, so this tests NULL handling
-    assert!(r.row_count() >= 0); // just check it doesn't error
+    assert_eq!(r.row_count(), 2);
 }
 
 #[test]
@@ -140,11 +139,23 @@ fn test_transaction_rollback() {
     engine.execute("INSERT INTO t VALUES (1)").unwrap();
     engine.execute("BEGIN").unwrap();
     engine.execute("INSERT INTO t VALUES (2)").unwrap();
+    engine.execute("UPDATE t SET n = 10 WHERE n = 1").unwrap();
+    engine.execute("CREATE TABLE scratch (x INTEGER)").unwrap();
+    assert!(engine.in_transaction());
+    assert!(engine.execute("BEGIN").is_err());
+    engine.execute("ROLLBACK").unwrap();
+    assert!(!engine.in_transaction());
+    let r = engine.execute("SELECT n FROM t").unwrap();
+    assert_eq!(r.rows, vec![vec![pivot_engine::column::ScalarValue::Int64(1)]]);
+    assert!(engine.execute("SELECT * FROM scratch").is_err());
+
+    // Committed work stays, and a later ROLLBACK can't undo it.
+    engine.execute("BEGIN").unwrap();
+    engine.execute("DELETE FROM t").unwrap();
+    engine.execute("COMMIT").unwrap();
     engine.execute("ROLLBACK").unwrap();
-    // In our simple implementation, rollback doesn't actually undo,
-    // but the command should succeed
     let r = engine.execute("SELECT COUNT(*) FROM t").unwrap();
-    assert!(r.row_count() >= 1);
+    assert_eq!(r.rows[0][0], pivot_engine::column::ScalarValue::Int64(0));
 }
 
 #[test]
@@ -181,3 +192,2552 @@ fn test_math_functions() {
     let r = engine.execute("SELECT SQRT(16.0)").unwrap();
     assert_eq!(r.rows[0][0], pivot_engine::column::ScalarValue::Float64(4.0));
 }
+
+#[test]
+fn test_vacuum_reclaims_capacity() {
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (id INTEGER, name VARCHAR)").unwrap();
+    for i in 0..1000 {
+        engine.execute(&format!("INSERT INTO t VALUES ({}, 'row{}')", i, i)).unwrap();
+    }
+    engine.execute("DELETE FROM t WHERE id >= 10").unwrap();
+
+    let before = engine.catalog.get_table("t").unwrap().capacity();
+    engine.execute("VACUUM TABLE t").unwrap();
+    let after = engine.catalog.get_table("t").unwrap().capacity();
+    assert!(after < before);
+    assert_eq!(after, 10);
+
+    let r = engine.execute("SELECT id, name FROM t ORDER BY id").unwrap();
+    assert_eq!(r.row_count(), 10);
+    assert_eq!(r.rows[9][0], pivot_engine::column::ScalarValue::Int64(9));
+    assert_eq!(r.rows[9][1], pivot_engine::column::ScalarValue::Utf8("row9".to_string()));
+
+    // Bare VACUUM compacts every table
+    engine.execute("VACUUM").unwrap();
+}
+
+#[test]
+fn test_percentile_window_median_per_dept() {
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (name VARCHAR, dept VARCHAR, salary DOUBLE)").unwrap();
+    engine.execute("INSERT INTO emp VALUES ('A', 'Eng', 100.0), ('B', 'Eng', 200.0), ('C', 'Eng', 400.0)").unwrap();
+    engine.execute("INSERT INTO emp VALUES ('D', 'Ops', 10.0), ('E', 'Ops', 30.0)").unwrap();
+
+    let r = engine.execute(
+        "SELECT name, dept, salary, \
+         PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY salary) OVER (PARTITION BY dept) AS med, \
+         PERCENTILE_DISC(0.5) WITHIN GROUP (ORDER BY salary) OVER (PARTITION BY dept) AS med_disc \
+         FROM emp ORDER BY name"
+    ).unwrap();
+    assert_eq!(r.row_count(), 5);
+    for row in &r.rows[0..3] {
+        assert_eq!(row[3], pivot_engine::column::ScalarValue::Float64(200.0));
+        assert_eq!(row[4], pivot_engine::column::ScalarValue::Float64(200.0));
+    }
+    for row in &r.rows[3..5] {
+        assert_eq!(row[3], pivot_engine::column::ScalarValue::Float64(20.0));
+        assert_eq!(row[4], pivot_engine::column::ScalarValue::Float64(10.0));
+    }
+}
+
+#[test]
+fn test_window_row_number_stable_ties() {
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (id INTEGER, grp VARCHAR, score INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1, 'a', 10), (2, 'a', 5), (3, 'a', 10), (4, 'a', 10), (5, 'a', 5)").unwrap();
+
+    let r = engine.execute(
+        "SELECT id, grp, score, ROW_NUMBER() OVER (PARTITION BY grp ORDER BY score DESC) AS rn \
+         FROM t ORDER BY id"
+    ).unwrap();
+    let rn: Vec<pivot_engine::column::ScalarValue> = r.rows.iter().map(|row| row[3].clone()).collect();
+    assert_eq!(rn, vec![
+        pivot_engine::column::ScalarValue::Int64(1),
+        pivot_engine::column::ScalarValue::Int64(4),
+        pivot_engine::column::ScalarValue::Int64(2),
+        pivot_engine::column::ScalarValue::Int64(3),
+        pivot_engine::column::ScalarValue::Int64(5),
+    ]);
+}
+
+#[test]
+fn test_window_sum_order_by_running_total() {
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (id INTEGER, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES (3, 30), (1, 10), (2, 20), (4, 40)").unwrap();
+
+    let r = engine.execute(
+        "SELECT id, salary, SUM(salary) OVER (ORDER BY id) AS running, \
+         SUM(salary) OVER () AS total FROM emp ORDER BY id"
+    ).unwrap();
+    let running: Vec<pivot_engine::column::ScalarValue> = r.rows.iter().map(|row| row[2].clone()).collect();
+    assert_eq!(running, vec![
+        pivot_engine::column::ScalarValue::Int64(10),
+        pivot_engine::column::ScalarValue::Int64(30),
+        pivot_engine::column::ScalarValue::Int64(60),
+        pivot_engine::column::ScalarValue::Int64(100),
+    ]);
+    for row in &r.rows {
+        assert_eq!(row[3], pivot_engine::column::ScalarValue::Int64(100));
+    }
+}
+
+#[test]
+fn test_cast_decimal_rounds_to_scale() {
+    let mut engine = SqlEngine::new();
+    let r = engine.execute("SELECT CAST(1.237 AS DECIMAL(10,2)), CAST(-2.5 AS DECIMAL(5,0)), 1.23456::DECIMAL(4,3)").unwrap();
+    assert_eq!(r.rows[0][0], pivot_engine::column::ScalarValue::Float64(1.24));
+    assert_eq!(r.rows[0][1], pivot_engine::column::ScalarValue::Float64(-3.0));
+    assert_eq!(r.rows[0][2], pivot_engine::column::ScalarValue::Float64(1.235));
+
+    // Lenient mode: overflow yields NULL
+    let r = engine.execute("SELECT CAST(12345.6 AS DECIMAL(5,2))").unwrap();
+    assert_eq!(r.rows[0][0], pivot_engine::column::ScalarValue::Null);
+}
+
+#[test]
+fn test_cast_decimal_precision_overflow_strict() {
+    let mut engine = SqlEngine::new();
+    engine.set_strict(true);
+    assert!(engine.execute("SELECT CAST(12345.6 AS DECIMAL(5,2))").is_err());
+    let r = engine.execute("SELECT CAST(999.994 AS DECIMAL(5,2))").unwrap();
+    assert_eq!(r.rows[0][0], pivot_engine::column::ScalarValue::Float64(999.99));
+}
+
+#[test]
+fn test_interval_compound_and_iso() {
+    use pivot_engine::column::{IntervalValue, ScalarValue};
+    let mut engine = SqlEngine::new();
+
+    let r = engine.execute("SELECT INTERVAL '1 year 2 months 3 days 04:05:06'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Interval(IntervalValue::new(1, 2, 3, 14_706_000_000)));
+
+    let r = engine.execute("SELECT INTERVAL '1-6' YEAR TO MONTH, INTERVAL '2 12:30' DAY TO MINUTE").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Interval(IntervalValue::new(1, 6, 0, 0)));
+    assert_eq!(r.rows[0][1], ScalarValue::Interval(IntervalValue::new(0, 0, 2, 45_000_000_000)));
+
+    let r = engine.execute("SELECT INTERVAL 'P1Y2M10DT2H30M1.5S', INTERVAL '-P3W'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Interval(IntervalValue::new(1, 2, 10, 9_001_500_000)));
+    assert_eq!(r.rows[0][1], ScalarValue::Interval(IntervalValue::new(0, 0, -21, 0)));
+
+    let r = engine.execute("SELECT INTERVAL '90' MINUTE").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Interval(IntervalValue::new(0, 0, 0, 5_400_000_000)));
+
+    assert!(engine.execute("SELECT INTERVAL 'P1X'").is_err());
+}
+
+#[test]
+fn test_date_and_timestamp_subtraction() {
+    use pivot_engine::column::{IntervalValue, ScalarValue};
+    let mut engine = SqlEngine::new();
+    let r = engine.execute("SELECT DATE '2020-01-10' - DATE '2020-01-01'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(9));
+
+    let r = engine.execute(
+        "SELECT TIMESTAMP '2020-01-02 00:00:30' - TIMESTAMP '2020-01-01 00:00:00', \
+         TIME '10:30:00' - TIME '10:00:00'"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Interval(IntervalValue::new(0, 0, 0, 86_430_000_000)));
+    assert_eq!(r.rows[0][1], ScalarValue::Interval(IntervalValue::new(0, 0, 0, 1_800_000_000)));
+
+    engine.execute("CREATE TABLE d (a DATE, b DATE)").unwrap();
+    engine.execute("INSERT INTO d VALUES ('2024-03-01', '2024-02-01')").unwrap();
+    let r = engine.execute("SELECT a - b FROM d").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(29));
+}
+
+#[test]
+fn test_distinct_aggregates_ignore_nulls() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (g VARCHAR, x INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES ('a', 1), ('a', 1), ('a', 2), ('a', NULL), ('b', NULL), ('b', NULL)").unwrap();
+
+    let r = engine.execute(
+        "SELECT COUNT(DISTINCT x), SUM(DISTINCT x), AVG(DISTINCT x), COUNT(x), COUNT(*) FROM t"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(2));
+    assert_eq!(r.rows[0][1], ScalarValue::Int64(3));
+    assert_eq!(r.rows[0][2], ScalarValue::Float64(1.5));
+    assert_eq!(r.rows[0][3], ScalarValue::Int64(3));
+    assert_eq!(r.rows[0][4], ScalarValue::Int64(6));
+
+    let r = engine.execute("SELECT g, COUNT(DISTINCT x), SUM(DISTINCT x) FROM t GROUP BY g ORDER BY g").unwrap();
+    assert_eq!(r.rows[1][1], ScalarValue::Int64(0));
+    assert_eq!(r.rows[1][2], ScalarValue::Null);
+}
+
+#[test]
+fn test_identifier_case_policies() {
+    use pivot_engine::sql::Fold;
+
+    // Default: names are stored as written and unquoted references match
+    // them under upper-case folding; quoted references must spell them
+    // exactly, as written or upper-cased.
+    let mut engine = SqlEngine::new();
+    let r = engine.execute("CREATE TABLE Emp (Name VARCHAR)").unwrap();
+    assert_eq!(r.message.as_deref(), Some("Table 'Emp' created"));
+    engine.execute("INSERT INTO emp VALUES ('a')").unwrap();
+    assert_eq!(engine.catalog.table_names(), vec!["Emp".to_string()]);
+    assert!(engine.execute("SELECT name, NAME FROM EMP").is_ok());
+    assert!(engine.execute("SELECT * FROM \"EMP\"").is_ok());
+    assert!(engine.execute("SELECT * FROM \"Emp\"").is_ok());
+    assert!(engine.execute("SELECT * FROM \"emp\"").is_err());
+    assert!(engine.execute("SELECT \"NAME\" FROM emp").is_ok());
+    assert!(engine.execute("SELECT \"Name\" FROM emp").is_ok());
+    assert!(engine.execute("SELECT \"name\" FROM emp").is_err());
+    assert!(engine.catalog.get_table("emp").is_some());
+    assert_eq!(engine.execute("SELECT * FROM emp").unwrap().columns, vec!["Name"]);
+    assert!(engine.execute("CREATE TABLE EMP (x INTEGER)").is_err());
+    let err = engine.execute("SELECT missing FROM emp").unwrap_err();
+    assert!(err.to_string().contains("missing"), "{}", err);
+
+    let mut engine = SqlEngine::new();
+    engine.set_identifier_case(Fold::Lower);
+    engine.execute("CREATE TABLE Emp (Name VARCHAR)").unwrap();
+    engine.execute("INSERT INTO EMP VALUES ('a')").unwrap();
+    assert_eq!(engine.catalog.table_names(), vec!["Emp".to_string()]);
+    assert!(engine.execute("SELECT NAME FROM EMP").is_ok());
+    assert!(engine.execute("SELECT * FROM \"emp\"").is_ok());
+    assert!(engine.execute("SELECT * FROM \"Emp\"").is_ok());
+    assert!(engine.execute("SELECT * FROM \"EMP\"").is_err());
+
+    let mut engine = SqlEngine::new();
+    engine.set_identifier_case(Fold::Preserve);
+    engine.execute("CREATE TABLE Emp (Name VARCHAR)").unwrap();
+    engine.execute("INSERT INTO Emp VALUES ('a')").unwrap();
+    assert_eq!(engine.catalog.table_names(), vec!["Emp".to_string()]);
+    assert!(engine.execute("SELECT Name FROM Emp").is_ok());
+    assert!(engine.execute("SELECT Name FROM emp").is_err());
+    assert!(engine.execute("SELECT name FROM Emp").is_err());
+}
+
+#[test]
+fn test_timestamp_offsets_and_at_time_zone() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    // 12:00 at +02:00 and 10:00Z are the same instant.
+    let r = engine.execute(
+        "SELECT TIMESTAMP '2024-01-01 12:00:00+02:00' = TIMESTAMP '2024-01-01T10:00:00Z', \
+         CAST('2024-01-01 05:30:00-0430' AS TIMESTAMP WITH TIME ZONE)"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Boolean(true));
+    assert_eq!(r.rows[0][1].to_string(), "2024-01-01 10:00:00");
+
+    engine.execute("CREATE TABLE ev (ts TIMESTAMPTZ)").unwrap();
+    engine.execute("INSERT INTO ev VALUES ('2024-01-01 12:00:00+02:00')").unwrap();
+    let r = engine.execute(
+        "SELECT ts AT TIME ZONE 'UTC', ts AT TIME ZONE '-05:00', ts AT TIME ZONE 'UTC+05:30' FROM ev"
+    ).unwrap();
+    assert_eq!(r.rows[0][0].to_string(), "2024-01-01 10:00:00");
+    assert_eq!(r.rows[0][1].to_string(), "2024-01-01 05:00:00");
+    assert_eq!(r.rows[0][2].to_string(), "2024-01-01 15:30:00");
+
+    let r = engine.execute("SELECT TIMESTAMP '2024-01-01 00:00:00+25:00'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Null);
+}
+
+#[test]
+fn test_select_distinct_collapses_nulls() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (val VARCHAR, n INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (NULL, 1), ('a', 1), (NULL, 1), ('1', 1), (NULL, NULL)").unwrap();
+
+    let r = engine.execute("SELECT DISTINCT val FROM t").unwrap();
+    assert_eq!(r.row_count(), 3);
+    assert_eq!(r.rows.iter().filter(|row| row[0] == ScalarValue::Null).count(), 1);
+
+    let r = engine.execute("SELECT DISTINCT val, n FROM t").unwrap();
+    assert_eq!(r.row_count(), 4);
+
+    // A string and a number with the same text are still distinct values.
+    let r = engine.execute("SELECT DISTINCT x FROM (SELECT '1' AS x UNION ALL SELECT 1 AS x) s").unwrap();
+    assert_eq!(r.row_count(), 2);
+}
+
+#[test]
+fn test_select_distinct_large_keeps_first_seen_order() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (k INTEGER, tag VARCHAR)").unwrap();
+    // 20k rows cycling through 5k distinct (k, tag) pairs in descending k order.
+    let values: Vec<String> = (0..20_000)
+        .map(|i| {
+            let k = 4_999 - i % 5_000;
+            if k % 100 == 0 { format!("({}, NULL)", k) } else { format!("({}, 't{}')", k, k % 3) }
+        })
+        .collect();
+    engine.execute(&format!("INSERT INTO t VALUES {}", values.join(", "))).unwrap();
+
+    let r = engine.execute("SELECT DISTINCT k, tag FROM t").unwrap();
+    assert_eq!(r.row_count(), 5_000);
+    let ks: Vec<ScalarValue> = r.rows.iter().map(|row| row[0].clone()).collect();
+    assert_eq!(ks, (0..5_000).rev().map(ScalarValue::Int64).collect::<Vec<_>>());
+    assert_eq!(r.rows[4_999], vec![ScalarValue::Int64(0), ScalarValue::Null]);
+}
+
+#[test]
+fn test_similar_to() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE f (name VARCHAR)").unwrap();
+    engine.execute("INSERT INTO f VALUES ('report.csv'), ('report.tsv'), ('data.json'), ('notes_v2.txt'), ('reportxcsv')").unwrap();
+
+    let r = engine.execute("SELECT name FROM f WHERE name SIMILAR TO '%.(csv|tsv)' ORDER BY name").unwrap();
+    assert_eq!(r.row_count(), 2);
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("report.csv".into()));
+    assert_eq!(r.rows[1][0], ScalarValue::Utf8("report.tsv".into()));
+
+    let r = engine.execute("SELECT COUNT(*) FROM f WHERE name NOT SIMILAR TO '%.(csv|tsv)'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(3));
+
+    let r = engine.execute(
+        "SELECT 'notes_v2.txt' SIMILAR TO 'notes\\_v_.txt', 'abc' SIMILAR TO 'a_', \
+         'aaab' SIMILAR TO 'a+b', 'abc' SIMILAR TO '[a-c]{3}', NULL SIMILAR TO 'a'"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Boolean(true));
+    assert_eq!(r.rows[0][1], ScalarValue::Boolean(false));
+    assert_eq!(r.rows[0][2], ScalarValue::Boolean(true));
+    assert_eq!(r.rows[0][3], ScalarValue::Boolean(true));
+    assert_eq!(r.rows[0][4], ScalarValue::Null);
+
+    // Nested counted repeats would compile to billions of instructions.
+    let err = engine.execute("SELECT 'a' SIMILAR TO '(((a{1,100}){1,100}){1,100}){1,100}'").unwrap_err();
+    assert!(err.to_string().contains("too large"), "{}", err);
+    assert!(engine.execute("SELECT 'a' SIMILAR TO 'a{1001}'").is_err());
+    let r = engine.execute("SELECT 'aa' SIMILAR TO '(a{1,100}){1,100}'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Boolean(true));
+}
+
+#[test]
+fn test_replace_nth_occurrence() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let r = engine.execute(
+        "SELECT REPLACE('a-b-c-d', '-', '+'), REPLACE('a-b-c-d', '-', '+', 2), REPLACE('a-b', '-', '+', 5)"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("a+b+c+d".into()));
+    assert_eq!(r.rows[0][1], ScalarValue::Utf8("a-b+c-d".into()));
+    assert_eq!(r.rows[0][2], ScalarValue::Utf8("a-b".into()));
+
+    let r = engine.execute(
+        "SELECT REGEXP_REPLACE('x1 y22 z333', '[0-9]+', '#'), \
+                REGEXP_REPLACE('x1 y22 z333', '[0-9]+', '#', 'g'), \
+                REGEXP_REPLACE('x1 y22 z333', '[0-9]+', '#', 2), \
+                REGEXP_REPLACE('2024-03-15', '(\\d+)-(\\d+)-(\\d+)', '\\3/\\2/\\1'), \
+                REGEXP_REPLACE('abc', '(', 'x')"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("x# y22 z333".into()));
+    assert_eq!(r.rows[0][1], ScalarValue::Utf8("x# y# z#".into()));
+    assert_eq!(r.rows[0][2], ScalarValue::Utf8("x1 y# z333".into()));
+    assert_eq!(r.rows[0][3], ScalarValue::Utf8("15/03/2024".into()));
+    assert_eq!(r.rows[0][4], ScalarValue::Null);
+}
+
+#[test]
+fn test_collate_nocase_group_by_and_min_max() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE fruit (name VARCHAR, qty INTEGER)").unwrap();
+    engine.execute("INSERT INTO fruit VALUES ('Apple', 1), ('apple', 2), ('APPLE', 3), ('banana', 4), ('Banana', 5)").unwrap();
+
+    let r = engine.execute("SELECT COUNT(*) FROM fruit GROUP BY name").unwrap();
+    assert_eq!(r.row_count(), 5);
+
+    let r = engine.execute(
+        "SELECT name COLLATE NOCASE, SUM(qty) FROM fruit GROUP BY name COLLATE NOCASE ORDER BY name COLLATE NOCASE"
+    ).unwrap();
+    assert_eq!(r.row_count(), 2);
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("Apple".into()));
+    assert_eq!(r.rows[0][1], ScalarValue::Int64(6));
+    assert_eq!(r.rows[1][1], ScalarValue::Int64(9));
+
+    // Binary order puts upper case first; NOCASE compares 'apple' and 'banana'.
+    let r = engine.execute("SELECT MIN(name), MAX(name), MAX(name COLLATE NOCASE) FROM fruit").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("APPLE".into()));
+    assert_eq!(r.rows[0][1], ScalarValue::Utf8("banana".into()));
+    assert_eq!(r.rows[0][2], ScalarValue::Utf8("banana".into()));
+    let r = engine.execute("SELECT MIN(name COLLATE NOCASE), COUNT(DISTINCT name COLLATE NOCASE) FROM fruit").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("Apple".into()));
+    assert_eq!(r.rows[0][1], ScalarValue::Int64(2));
+
+    assert!(engine.execute("SELECT name COLLATE klingon FROM fruit").is_err());
+}
+
+#[test]
+fn test_reserved_keywords_as_identifiers() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let err = engine.execute("CREATE TABLE t (order INTEGER)").unwrap_err();
+    assert!(err.to_string().contains("'order' is a reserved keyword; quote it as \"order\""), "{}", err);
+
+    engine.execute("CREATE TABLE t (\"order\" INTEGER, \"select\" VARCHAR)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1, 'a')").unwrap();
+    let r = engine.execute("SELECT \"order\", \"select\" AS \"from\" FROM t").unwrap();
+    assert_eq!(r.columns, vec!["order".to_string(), "from".to_string()]);
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(1));
+
+    let err = engine.execute("SELECT order FROM t").unwrap_err();
+    assert!(err.to_string().contains("'order' is a reserved keyword"), "{}", err);
+    let err = engine.execute("SELECT \"select\" AS from FROM t").unwrap_err();
+    assert!(err.to_string().contains("'from' is a reserved keyword"), "{}", err);
+
+    // Clause-specific keywords still work as aliases after AS.
+    let r = engine.execute("SELECT \"order\" AS first, \"select\" AS key FROM t").unwrap();
+    assert_eq!(r.columns, vec!["first".to_string(), "key".to_string()]);
+}
+
+#[test]
+fn test_standalone_values() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let r = engine.execute("VALUES (1, 'a'), (2, 'b')").unwrap();
+    assert_eq!(r.columns, vec!["column1".to_string(), "column2".to_string()]);
+    assert_eq!(r.row_count(), 2);
+    assert_eq!(r.rows[1][1], ScalarValue::Utf8("b".into()));
+
+    let r = engine.execute("SELECT 0, 'z' UNION ALL VALUES (1, 'a'), (2, 'b')").unwrap();
+    assert_eq!(r.row_count(), 3);
+    assert_eq!(r.rows[2][0], ScalarValue::Int64(2));
+
+    let r = engine.execute(
+        "WITH v AS (VALUES (1, 10), (2, 20)) SELECT SUM(column2) FROM v WHERE column1 > 1"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(20));
+    let r = engine.execute("SELECT COUNT(*) FROM (VALUES (1), (2), (3)) AS t").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(3));
+
+    assert!(engine.execute("VALUES (1, 2), (3)").is_err());
+}
+
+#[test]
+fn test_between_symmetric() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (x INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (0), (1), (5), (10), (11)").unwrap();
+
+    let r = engine.execute("SELECT x FROM t WHERE x BETWEEN SYMMETRIC 10 AND 1 ORDER BY x").unwrap();
+    let xs: Vec<ScalarValue> = r.rows.iter().map(|row| row[0].clone()).collect();
+    assert_eq!(xs, vec![ScalarValue::Int64(1), ScalarValue::Int64(5), ScalarValue::Int64(10)]);
+
+    let r = engine.execute("SELECT COUNT(*) FROM t WHERE x BETWEEN 10 AND 1").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(0));
+    let r = engine.execute("SELECT COUNT(*) FROM t WHERE x NOT BETWEEN SYMMETRIC 10 AND 1").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(2));
+    let r = engine.execute("SELECT COUNT(*) FROM t WHERE x BETWEEN ASYMMETRIC 1 AND 10").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(3));
+}
+
+#[test]
+fn test_update_delete_returning() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE stock (id INTEGER, item VARCHAR, qty INTEGER)").unwrap();
+    engine.execute("INSERT INTO stock VALUES (1, 'bolt', 10), (2, 'nut', 0), (3, 'gear', 4)").unwrap();
+
+    let r = engine.execute("UPDATE stock SET qty = qty - 1 WHERE qty > 0 RETURNING id, qty AS remaining").unwrap();
+    assert_eq!(r.columns, vec!["id".to_string(), "remaining".to_string()]);
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Int64(1), ScalarValue::Int64(9)],
+        vec![ScalarValue::Int64(3), ScalarValue::Int64(3)],
+    ]);
+
+    let r = engine.execute("DELETE FROM stock WHERE qty < 5 RETURNING *").unwrap();
+    assert_eq!(r.row_count(), 2);
+    assert_eq!(r.rows[0][1], ScalarValue::Utf8("nut".into()));
+    assert_eq!(r.rows[1], vec![ScalarValue::Int64(3), ScalarValue::Utf8("gear".into()), ScalarValue::Int64(3)]);
+
+    let r = engine.execute("SELECT COUNT(*) FROM stock").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(1));
+    let r = engine.execute("DELETE FROM stock WHERE id = 99 RETURNING id").unwrap();
+    assert_eq!(r.row_count(), 0);
+}
+
+#[test]
+fn test_insert_returning_with_defaults() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute(
+        "CREATE TABLE orders (id INTEGER, status VARCHAR NOT NULL DEFAULT 'new', qty INTEGER DEFAULT 1)"
+    ).unwrap();
+
+    let r = engine.execute("INSERT INTO orders (id) VALUES (1), (2) RETURNING id, status, qty * 10 AS units").unwrap();
+    assert_eq!(r.columns, vec!["id".to_string(), "status".to_string(), "units".to_string()]);
+    assert_eq!(r.affected_rows, 2);
+    assert_eq!(r.rows[1], vec![ScalarValue::Int64(2), ScalarValue::Utf8("new".into()), ScalarValue::Int64(10)]);
+
+    let r = engine.execute("INSERT INTO orders (id, qty) SELECT 3, 5 RETURNING *").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(3), ScalarValue::Utf8("new".into()), ScalarValue::Int64(5)]]);
+
+    let r = engine.execute("INSERT INTO orders VALUES (4, 'paid', 2)").unwrap();
+    assert_eq!(r.affected_rows, 1);
+    assert!(r.rows.is_empty());
+}
+
+#[test]
+fn test_identity_columns() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE users (id SERIAL PRIMARY KEY, name VARCHAR)").unwrap();
+    let r = engine.execute("INSERT INTO users (name) VALUES ('ann'), ('bo') RETURNING id").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(1)], vec![ScalarValue::Int64(2)]]);
+
+    // An explicit id moves the sequence past it.
+    engine.execute("INSERT INTO users VALUES (10, 'cy')").unwrap();
+    let r = engine.execute("INSERT INTO users (name) VALUES ('di') RETURNING id, name").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(11), ScalarValue::Utf8("di".into())]);
+
+    engine.execute("CREATE TABLE events (seq INTEGER GENERATED ALWAYS AS IDENTITY, kind VARCHAR)").unwrap();
+    engine.execute("CREATE TABLE logs (n INTEGER AUTOINCREMENT, msg VARCHAR)").unwrap();
+    engine.execute("INSERT INTO events (kind) SELECT name FROM users").unwrap();
+    engine.execute("INSERT INTO logs (msg) VALUES ('a')").unwrap();
+    let r = engine.execute("SELECT MAX(seq), COUNT(*) FROM events").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(4), ScalarValue::Int64(4)]);
+    let r = engine.execute("SELECT n FROM logs").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(1));
+
+    // GENERATED ALWAYS columns take no explicit values, not even NULL.
+    assert!(engine.execute("INSERT INTO events VALUES (100, 'x')").is_err());
+    assert!(engine.execute("INSERT INTO events (seq, kind) VALUES (NULL, 'x')").is_err());
+    assert!(engine.execute("INSERT INTO events (seq, kind) SELECT id, name FROM users").is_err());
+    let r = engine.execute("SELECT COUNT(*) FROM events").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(4));
+    // Identity columns must be integers.
+    assert!(engine.execute("CREATE TABLE bad (id VARCHAR GENERATED ALWAYS AS IDENTITY)").is_err());
+    assert!(engine.execute("CREATE TABLE bad (id DOUBLE AUTOINCREMENT)").is_err());
+    assert!(engine.execute("SELECT * FROM bad").is_err());
+
+    // Dropping the table resets its sequence.
+    engine.execute("DROP TABLE logs").unwrap();
+    engine.execute("CREATE TABLE logs (n SERIAL, msg VARCHAR)").unwrap();
+    let r = engine.execute("INSERT INTO logs (msg) VALUES ('b') RETURNING n").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(1));
+}
+
+#[test]
+fn test_empty_aggregate_as_zero() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE sales (region VARCHAR, amount INTEGER, rep VARCHAR)").unwrap();
+    engine.execute("INSERT INTO sales VALUES ('east', 10, 'al'), ('west', NULL, NULL)").unwrap();
+    let empty = "SELECT SUM(amount), AVG(amount), STRING_AGG(rep, ','), COUNT(*) FROM sales WHERE region = 'north'";
+    let grouped = "SELECT region, SUM(amount), STRING_AGG(rep, ',') FROM sales GROUP BY region ORDER BY region";
+
+    let r = engine.execute(empty).unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Null, ScalarValue::Null, ScalarValue::Null, ScalarValue::Int64(0)]);
+    let r = engine.execute(grouped).unwrap();
+    assert_eq!(r.rows[1][1], ScalarValue::Null);
+    assert_eq!(r.rows[1][2], ScalarValue::Null);
+
+    engine.set_empty_aggregate_as_zero(true);
+    let r = engine.execute(empty).unwrap();
+    assert_eq!(r.rows[0], vec![
+        ScalarValue::Int64(0), ScalarValue::Float64(0.0), ScalarValue::Utf8(String::new()), ScalarValue::Int64(0),
+    ]);
+    let r = engine.execute(grouped).unwrap();
+    assert_eq!(r.rows[0][1], ScalarValue::Int64(10));
+    assert_eq!(r.rows[1][1], ScalarValue::Int64(0));
+    assert_eq!(r.rows[1][2], ScalarValue::Utf8(String::new()));
+}
+
+#[test]
+fn test_order_by_key_errors_surface() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (id INTEGER, name VARCHAR, score INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1, 'a', 30), (2, 'b', NULL), (3, 'c', 10)").unwrap();
+
+    assert!(engine.execute("SELECT name FROM t ORDER BY missing").is_err());
+    assert!(engine.execute("SELECT name FROM t ORDER BY id / (id - 2)").is_err());
+    assert!(engine.execute("SELECT id, name FROM t ORDER BY id / (id - 2)").is_err());
+
+    // Columns left out of the SELECT list still sort, and NULL keys follow the nulls policy.
+    let r = engine.execute("SELECT name FROM t ORDER BY score NULLS FIRST").unwrap();
+    let names: Vec<ScalarValue> = r.rows.iter().map(|row| row[0].clone()).collect();
+    assert_eq!(names, vec![
+        ScalarValue::Utf8("b".into()), ScalarValue::Utf8("c".into()), ScalarValue::Utf8("a".into()),
+    ]);
+    let r = engine.execute("SELECT name AS n FROM t ORDER BY n DESC").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("c".into()));
+}
+
+#[test]
+fn test_window_functions_rejected_outside_select() {
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (g VARCHAR, v INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES ('a', 1), ('a', 2), ('b', 3)").unwrap();
+
+    for sql in [
+        "SELECT v FROM t WHERE ROW_NUMBER() OVER (ORDER BY v) = 1",
+        "SELECT COUNT(*) FROM t GROUP BY RANK() OVER (ORDER BY v)",
+        "SELECT g FROM t GROUP BY g HAVING SUM(v) > MAX(v) OVER ()",
+        "SELECT v FROM t WHERE v IN (1, CAST(LAG(v) OVER (ORDER BY v) AS INTEGER))",
+    ] {
+        let err = engine.execute(sql).unwrap_err().to_string();
+        assert!(err.contains("not allowed in"), "{}: {}", sql, err);
+    }
+
+    // Window functions in the SELECT list, and subqueries in WHERE, are fine.
+    assert!(engine.execute("SELECT v, ROW_NUMBER() OVER (ORDER BY v) FROM t").is_ok());
+    assert!(engine.execute(
+        "SELECT g FROM (SELECT g, ROW_NUMBER() OVER (PARTITION BY g ORDER BY v) AS rn FROM t) s WHERE rn = 1"
+    ).is_ok());
+}
+
+#[test]
+fn test_count_qualified_wildcard() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (id INTEGER, dept_id INTEGER)").unwrap();
+    engine.execute("CREATE TABLE dept (id INTEGER, name VARCHAR)").unwrap();
+    engine.execute("INSERT INTO emp VALUES (1, 10), (2, 10), (3, 20)").unwrap();
+    engine.execute("INSERT INTO dept VALUES (10, 'eng'), (20, 'ops')").unwrap();
+
+    let r = engine.execute("SELECT COUNT(e.*) FROM emp e JOIN dept d ON e.dept_id = d.id").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(3));
+    let r = engine.execute(
+        "SELECT d.name, COUNT(e.*) AS n FROM emp e JOIN dept d ON e.dept_id = d.id GROUP BY d.name ORDER BY d.name"
+    ).unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Utf8("eng".into()), ScalarValue::Int64(2)]);
+
+    let r = engine.execute("SELECT d.* FROM emp e JOIN dept d ON e.dept_id = d.id WHERE e.id = 3").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(20), ScalarValue::Utf8("ops".into())]]);
+
+    assert!(engine.execute("SELECT COUNT(x.*) FROM emp e").is_err());
+    assert!(engine.execute("SELECT x.* FROM emp e").is_err());
+}
+
+#[test]
+fn test_alter_column_type() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (id INTEGER, qty VARCHAR)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1, '10'), (2, ' 25 '), (3, NULL)").unwrap();
+
+    engine.execute("ALTER TABLE t ALTER COLUMN qty TYPE INTEGER").unwrap();
+    let r = engine.execute("SELECT qty FROM t ORDER BY id").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(10)], vec![ScalarValue::Int64(25)], vec![ScalarValue::Null]]);
+    let r = engine.execute("SELECT SUM(qty) FROM t").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(35));
+    engine.execute("ALTER TABLE t ALTER qty SET DATA TYPE DOUBLE").unwrap();
+    let r = engine.execute("SELECT qty FROM t WHERE id = 1").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Float64(10.0));
+
+    // Unconvertible values become NULL by default and fail the ALTER in strict mode.
+    engine.execute("CREATE TABLE u (code VARCHAR)").unwrap();
+    engine.execute("INSERT INTO u VALUES ('7'), ('n/a')").unwrap();
+    engine.set_strict(true);
+    assert!(engine.execute("ALTER TABLE u ALTER COLUMN code TYPE INTEGER").is_err());
+    let r = engine.execute("SELECT code FROM u").unwrap();
+    assert_eq!(r.rows[1][0], ScalarValue::Utf8("n/a".into()));
+    engine.set_strict(false);
+    engine.execute("ALTER TABLE u ALTER COLUMN code TYPE INTEGER").unwrap();
+    let r = engine.execute("SELECT code FROM u").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(7)], vec![ScalarValue::Null]]);
+
+    assert!(engine.execute("ALTER TABLE u ALTER COLUMN missing TYPE INTEGER").is_err());
+}
+
+#[test]
+fn test_alter_table_add_drop_rename_column() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name VARCHAR)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1, 'a'), (2, 'b')").unwrap();
+
+    // Existing rows get the default, or NULL without one.
+    engine.execute("ALTER TABLE t ADD COLUMN qty INTEGER DEFAULT 5").unwrap();
+    engine.execute("ALTER TABLE t ADD note VARCHAR").unwrap();
+    engine.execute("INSERT INTO t (id, name) VALUES (3, 'c')").unwrap();
+    let r = engine.execute("SELECT id, qty, note FROM t ORDER BY id").unwrap();
+    assert_eq!(r.columns, vec!["id", "qty", "note"]);
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Int64(1), ScalarValue::Int64(5), ScalarValue::Null],
+        vec![ScalarValue::Int64(2), ScalarValue::Int64(5), ScalarValue::Null],
+        vec![ScalarValue::Int64(3), ScalarValue::Int64(5), ScalarValue::Null],
+    ]);
+    assert!(engine.execute("ALTER TABLE t ADD COLUMN qty INTEGER").is_err());
+    assert!(engine.execute("ALTER TABLE t ADD COLUMN req INTEGER NOT NULL").is_err());
+    assert!(engine.execute("ALTER TABLE t ADD COLUMN code INTEGER UNIQUE DEFAULT 1").is_err());
+    assert_eq!(engine.execute("SELECT * FROM t").unwrap().columns.len(), 4);
+
+    engine.execute("ALTER TABLE t RENAME COLUMN name TO label").unwrap();
+    let r = engine.execute("SELECT label FROM t WHERE id = 2").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("b".into()));
+    assert!(engine.execute("SELECT name FROM t").is_err());
+    assert!(engine.execute("ALTER TABLE t RENAME COLUMN label TO qty").is_err());
+
+    engine.execute("ALTER TABLE t DROP COLUMN note").unwrap();
+    engine.execute("ALTER TABLE t DROP COLUMN IF EXISTS note").unwrap();
+    assert!(engine.execute("ALTER TABLE t DROP COLUMN note").is_err());
+    let r = engine.execute("SELECT * FROM t WHERE id = 1").unwrap();
+    assert_eq!(r.columns, vec!["id", "label", "qty"]);
+
+    engine.execute("CREATE TABLE one (x INTEGER)").unwrap();
+    assert!(engine.execute("ALTER TABLE one DROP COLUMN x").is_err());
+}
+
+#[test]
+fn test_read_csv_and_read_json_table_functions() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+
+    // Inline CSV: column types are inferred from the values.
+    let r = engine.execute("SELECT name, qty * 2 FROM read_csv('name,qty,price
+apple,3,1.5
+pear,,2') WHERE price > 1 ORDER BY name").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Utf8("apple".into()), ScalarValue::Int64(6)],
+        vec![ScalarValue::Utf8("pear".into()), ScalarValue::Null],
+    ]);
+
+    engine.register_source("sales", "region;amount\neast;10\nwest;5\neast;7\n");
+    let r = engine.execute(
+        "SELECT s.region, SUM(s.amount) FROM read_csv('sales', ';') AS s GROUP BY s.region ORDER BY s.region",
+    ).unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Utf8("east".into()), ScalarValue::Int64(17)],
+        vec![ScalarValue::Utf8("west".into()), ScalarValue::Int64(5)],
+    ]);
+    let r = engine.execute("SELECT col1 FROM read_csv('a,1', ',', false)").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(1)]]);
+
+    // Array or newline-delimited JSON; missing keys are NULL, nested values stay as text.
+    let r = engine.execute(r#"SELECT id, score, tags FROM read_json('[{"id": 1, "score": 2.5, "tags": ["a"]}, {"id": 2, "score": 3}]') ORDER BY id"#).unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Int64(1), ScalarValue::Float64(2.5), ScalarValue::Utf8("[\"a\"]".into())],
+        vec![ScalarValue::Int64(2), ScalarValue::Float64(3.0), ScalarValue::Null],
+    ]);
+    engine.register_source("events", "{\"kind\": \"click\", \"ok\": true}\n{\"kind\": \"view\", \"ok\": false}\n");
+    let r = engine.execute("SELECT e.kind FROM read_json('events') e JOIN read_json('events') f ON e.kind = f.kind WHERE e.ok").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Utf8("click".into())]]);
+
+    assert!(engine.execute("SELECT * FROM read_json('{\"a\": }')").is_err());
+    assert!(engine.execute("SELECT * FROM read_parquet('x')").is_err());
+    assert!(engine.execute("SELECT * FROM read_csv('a,b', 'ab')").is_err());
+}
+
+#[test]
+fn test_limit_short_circuits_join() {
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE a (id INTEGER)").unwrap();
+    engine.execute("CREATE TABLE b (id INTEGER)").unwrap();
+    for i in 0..300 {
+        engine.execute(&format!("INSERT INTO a VALUES ({})", i)).unwrap();
+        engine.execute(&format!("INSERT INTO b VALUES ({})", i % 5)).unwrap();
+    }
+
+    let r = engine.execute("SELECT * FROM a CROSS JOIN b LIMIT 10").unwrap();
+    assert_eq!(r.row_count(), 10);
+    let r = engine.execute("SELECT * FROM a JOIN b ON a.id % 5 = b.id LIMIT 7 OFFSET 3").unwrap();
+    assert_eq!(r.row_count(), 7);
+    let r = engine.execute("SELECT * FROM a LEFT JOIN b ON a.id = b.id + 1000 LIMIT 4").unwrap();
+    assert_eq!(r.row_count(), 4);
+
+    // The ON condition divides by zero for a.id = 250, which a full join
+    // would evaluate; the limited join stops long before it.
+    let sql = "SELECT a.id FROM a JOIN b ON a.id % 5 = b.id AND 1 / (a.id - 250) IS NOT NULL";
+    assert!(engine.execute(sql).is_err());
+    let r = engine.execute(&format!("{} LIMIT 5", sql)).unwrap();
+    assert_eq!(r.row_count(), 5);
+    // Clauses that need every joined row disable the shortcut.
+    assert!(engine.execute(&format!("{} ORDER BY a.id LIMIT 5", sql)).is_err());
+    assert!(engine.execute(&format!("{} WHERE a.id > 0 LIMIT 5", sql)).is_err());
+    // RIGHT joins still return their unmatched right rows.
+    let r = engine.execute("SELECT b.id FROM a RIGHT JOIN b ON a.id = b.id + 1000 LIMIT 3").unwrap();
+    assert_eq!(r.row_count(), 3);
+}
+
+#[test]
+fn test_like_linear_matcher_and_escape() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let b = |r: pivot_engine::sql::QueryResult| r.rows[0][0].clone();
+    let t = ScalarValue::Boolean(true);
+    let f = ScalarValue::Boolean(false);
+
+    // Pathological pattern against a long non-matching string finishes quickly.
+    let text = "a".repeat(5000);
+    let pattern = format!("{}b", "%a".repeat(30));
+    let start = std::time::Instant::now();
+    let r = engine.execute(&format!("SELECT '{}' LIKE '{}'", text, pattern)).unwrap();
+    assert_eq!(b(r), f);
+    assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    let r = engine.execute(&format!("SELECT '{}' LIKE '{}'", text, "%a".repeat(30))).unwrap();
+    assert_eq!(b(r), t);
+
+    // Matches are anchored at both ends; % and _ behave as before.
+    for (sql, expected) in [
+        ("SELECT 'abc' LIKE 'b%'", &f),
+        ("SELECT 'abc' LIKE '%b'", &f),
+        ("SELECT 'abc' LIKE '%b%'", &t),
+        ("SELECT 'abc' LIKE 'a_c'", &t),
+        ("SELECT 'abc' LIKE '___'", &t),
+        ("SELECT 'abc' LIKE '____'", &f),
+        ("SELECT '' LIKE '%%'", &t),
+        ("SELECT 'ABC' ILIKE 'a%C'", &t),
+        ("SELECT 'ABC' NOT ILIKE 'a%C'", &f),
+        ("SELECT 'mississippi' LIKE '%iss%ppi'", &t),
+        // ESCAPE makes the next character literal.
+        ("SELECT '50%' LIKE '50!%' ESCAPE '!'", &t),
+        ("SELECT '500' LIKE '50!%' ESCAPE '!'", &f),
+        ("SELECT 'a_b' LIKE 'a\\_b' ESCAPE '\\'", &t),
+        ("SELECT 'axb' LIKE 'a\\_b' ESCAPE '\\'", &f),
+        ("SELECT 'A!%' ILIKE 'a!!!%' ESCAPE '!'", &t),
+        ("SELECT 'a%' NOT LIKE 'a#%' ESCAPE '#'", &f),
+    ] {
+        assert_eq!(&b(engine.execute(sql).unwrap()), expected, "{}", sql);
+    }
+    assert!(engine.execute("SELECT 'a' LIKE 'a!' ESCAPE '!'").is_err());
+    assert!(engine.execute("SELECT 'a' LIKE 'a' ESCAPE 'ab'").is_err());
+}
+
+#[test]
+fn test_duplicate_output_columns() {
+    use pivot_engine::sql::DuplicateColumns;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE a (id INTEGER, name VARCHAR)").unwrap();
+    engine.execute("CREATE TABLE b (id INTEGER, score INTEGER)").unwrap();
+    engine.execute("INSERT INTO a VALUES (1, 'x')").unwrap();
+    engine.execute("INSERT INTO b VALUES (1, 5)").unwrap();
+
+    // Default: duplicates are kept and column_index finds the first.
+    let r = engine.execute("SELECT id, name, id FROM a").unwrap();
+    assert_eq!(r.columns, vec!["id", "name", "id"]);
+    assert_eq!(r.column_index("id"), Some(0));
+    assert_eq!(r.column_index("missing"), None);
+
+    engine.set_duplicate_columns(DuplicateColumns::Suffix);
+    let r = engine.execute("SELECT id, name, id, id FROM a").unwrap();
+    assert_eq!(r.columns, vec!["id", "name", "id:1", "id:2"]);
+    assert_eq!(r.column_index("id:2"), Some(3));
+    let r = engine.execute("SELECT * FROM a JOIN b ON a.id = b.id").unwrap();
+    assert_eq!(r.columns, vec!["id", "name", "id:1", "score"]);
+    // Distinct names are left alone.
+    let r = engine.execute("SELECT a.id AS left_id, b.id AS right_id FROM a JOIN b ON a.id = b.id").unwrap();
+    assert_eq!(r.columns, vec!["left_id", "right_id"]);
+
+    engine.set_duplicate_columns(DuplicateColumns::Error);
+    assert!(engine.execute("SELECT id, id FROM a").is_err());
+    assert!(engine.execute("SELECT * FROM a JOIN b ON a.id = b.id").is_err());
+    assert!(engine.execute("SELECT id, name FROM a").is_ok());
+}
+
+#[test]
+fn test_in_list_with_scalar_subqueries() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (y INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (2), (5), (7), (9)").unwrap();
+
+    let r = engine.execute("SELECT y FROM t WHERE y IN (1, 2, (SELECT MAX(y) FROM t)) ORDER BY y").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(2)], vec![ScalarValue::Int64(9)]]);
+    let r = engine.execute(
+        "SELECT y FROM t WHERE y NOT IN ((SELECT MIN(y) FROM t), (SELECT MAX(y) FROM t) - 2) ORDER BY y",
+    ).unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(5)], vec![ScalarValue::Int64(9)]]);
+    // An empty scalar subquery is NULL and matches nothing.
+    let r = engine.execute("SELECT y FROM t WHERE y IN (5, (SELECT y FROM t WHERE y > 100))").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(5)]]);
+
+    assert!(engine.execute("SELECT y FROM t WHERE y IN (1, (SELECT y FROM t))").is_err());
+    assert!(engine.execute("SELECT y FROM t WHERE y IN (1, (SELECT y, y FROM t WHERE y = 2))").is_err());
+}
+
+#[test]
+fn test_in_list_null_semantics() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (x INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1), (2), (NULL)").unwrap();
+
+    // x = 2 neither matches nor is known not to match NULL.
+    let r = engine.execute("SELECT x FROM t WHERE x NOT IN (1, NULL)").unwrap();
+    assert_eq!(r.row_count(), 0);
+    let r = engine.execute("SELECT x FROM t WHERE x IN (1, NULL)").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(1)]]);
+    let r = engine.execute("SELECT x FROM t WHERE x NOT IN (1, 3) ORDER BY x").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(2)]]);
+
+    let v = |sql: &str| SqlEngine::new().execute(sql).unwrap().rows[0][0].clone();
+    assert_eq!(v("SELECT 2 NOT IN (1, NULL)"), ScalarValue::Null);
+    assert_eq!(v("SELECT 2 IN (1, NULL)"), ScalarValue::Null);
+    assert_eq!(v("SELECT 1 IN (1, NULL)"), ScalarValue::Boolean(true));
+    assert_eq!(v("SELECT 1 NOT IN (1, NULL)"), ScalarValue::Boolean(false));
+    assert_eq!(v("SELECT NULL IN (1, 2)"), ScalarValue::Null);
+    assert_eq!(v("SELECT 3 NOT IN (1, 2)"), ScalarValue::Boolean(true));
+}
+
+#[test]
+fn test_max_nesting_depth() {
+    // Run on a roomy stack so the default limit, not the stack, is what stops it.
+    std::thread::Builder::new().stack_size(64 << 20).spawn(|| {
+        let mut engine = SqlEngine::new();
+        let sql = format!("SELECT {}1{}", "(SELECT ".repeat(500), ")".repeat(500));
+        let err = engine.execute(&sql).unwrap_err();
+        assert!(err.to_string().contains("maximum depth"), "{}", err);
+        let sql = format!("SELECT {}1{}", "(".repeat(5000), ")".repeat(5000));
+        assert!(engine.execute(&sql).is_err());
+        let sql = format!("SELECT {}TRUE", "NOT ".repeat(5000));
+        assert!(engine.execute(&sql).is_err());
+
+        // Modest nesting is fine.
+        let sql = format!("SELECT {}1{}", "(SELECT ".repeat(8), ")".repeat(8));
+        assert_eq!(engine.execute(&sql).unwrap().rows[0][0], pivot_engine::column::ScalarValue::Int64(1));
+    }).unwrap().join().unwrap();
+
+    let mut engine = SqlEngine::new();
+    engine.set_max_depth(6);
+    assert!(engine.execute("SELECT ((((((((1))))))))").is_err());
+    assert!(engine.execute("SELECT ((1))").is_ok());
+
+    // A long chain of set operations is not nesting, inside a subquery or not.
+    let branches = (0..2000).map(|i| format!("SELECT {}", i % 7)).collect::<Vec<_>>();
+    let r = engine.execute(&branches.join(" UNION ALL ")).unwrap();
+    assert_eq!(r.rows.len(), 2000);
+    let r = engine.execute(&format!("SELECT COUNT(*) FROM ({}) t", branches.join(" UNION "))).unwrap();
+    assert_eq!(r.rows[0][0], pivot_engine::column::ScalarValue::Int64(7));
+}
+
+#[test]
+fn test_insert_select_column_mapping() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE s (x INTEGER, y VARCHAR)").unwrap();
+    engine.execute("INSERT INTO s VALUES (1, 'one'), (2, 'two')").unwrap();
+    engine.execute("CREATE TABLE t (a VARCHAR, b INTEGER, c INTEGER DEFAULT 0)").unwrap();
+
+    // Positional against the listed targets: x -> b, y -> a.
+    engine.execute("INSERT INTO t (b, a) SELECT x, y FROM s").unwrap();
+    let r = engine.execute("SELECT a, b, c FROM t ORDER BY b").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Utf8("one".into()), ScalarValue::Int64(1), ScalarValue::Int64(0)],
+        vec![ScalarValue::Utf8("two".into()), ScalarValue::Int64(2), ScalarValue::Int64(0)],
+    ]);
+    // Source names don't matter, only order.
+    engine.execute("INSERT INTO t (b, a) SELECT x AS a, y AS b FROM s WHERE x = 1").unwrap();
+    assert_eq!(engine.execute("SELECT COUNT(*) FROM t WHERE a = 'one' AND b = 1").unwrap().rows[0][0], ScalarValue::Int64(2));
+
+    // Arity must match an explicit list.
+    assert!(engine.execute("INSERT INTO t (b, a) SELECT x FROM s").is_err());
+    assert!(engine.execute("INSERT INTO t (b) SELECT x, y FROM s").is_err());
+    assert!(engine.execute("INSERT INTO t (b, b) SELECT x, x FROM s").is_err());
+    // Types must be insertable: a BOOLEAN can't go into an INTEGER column.
+    assert!(engine.execute("INSERT INTO t (a, b) SELECT y, CAST(x > 1 AS BOOLEAN) FROM s").is_err());
+    assert_eq!(engine.execute("SELECT COUNT(*) FROM t").unwrap().rows[0][0], ScalarValue::Int64(3));
+}
+
+#[test]
+fn test_any_value_and_strict_grouping() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (dept TEXT, name TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES ('eng', NULL, 100), ('eng', 'ann', 120), ('ops', 'bob', 90)").unwrap();
+
+    // ANY_VALUE skips NULLs and takes the first remaining value of the group.
+    let sql = "SELECT dept, ANY_VALUE(name) AS who, MAX(salary) FROM emp GROUP BY dept ORDER BY dept";
+    let r = engine.execute(sql).unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Utf8("eng".into()), ScalarValue::Utf8("ann".into()), ScalarValue::Int64(120)],
+        vec![ScalarValue::Utf8("ops".into()), ScalarValue::Utf8("bob".into()), ScalarValue::Int64(90)],
+    ]);
+
+    // Bare ungrouped columns are tolerated by default...
+    let r = engine.execute("SELECT dept, name FROM emp GROUP BY dept").unwrap();
+    assert_eq!(r.row_count(), 2);
+
+    // ...but rejected in strict mode, pointing at ANY_VALUE.
+    engine.set_strict(true);
+    let err = engine.execute("SELECT dept, name FROM emp GROUP BY dept").unwrap_err();
+    assert!(err.to_string().contains("ANY_VALUE(name)"), "{}", err);
+    let err = engine.execute("SELECT name, COUNT(*) FROM emp").unwrap_err();
+    assert!(err.to_string().contains("'name'"), "{}", err);
+    assert!(engine.execute(sql).is_ok());
+    assert!(engine.execute("SELECT UPPER(dept) AS d, COUNT(*) FROM emp GROUP BY UPPER(dept)").is_ok());
+    assert!(engine.execute("SELECT dept, COUNT(*) FROM emp GROUP BY dept HAVING COUNT(*) > 1").is_ok());
+    // Every kind of expression is searched, but not subqueries.
+    for sql in ["SELECT dept, (name, 1) FROM emp GROUP BY dept", "SELECT dept, name IS NULL FROM emp GROUP BY dept",
+                "SELECT dept, ROUND(SUM(salary), salary) FROM emp GROUP BY dept"] {
+        assert!(engine.execute(sql).is_err(), "{}", sql);
+    }
+    assert!(engine.execute("SELECT dept, EXISTS (SELECT 1) FROM emp GROUP BY dept").is_ok());
+}
+
+#[test]
+fn test_aggregates_and_window_calls_inside_expressions() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (a INTEGER, b VARCHAR)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1, 'x'), (2, 'y'), (3, 'x')").unwrap();
+
+    let r = engine.execute("SELECT MAX(a) IS NULL, COUNT(*) BETWEEN 1 AND 3 FROM t").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Boolean(false), ScalarValue::Boolean(true)]]);
+    let r = engine.execute("SELECT b, CASE WHEN SUM(a) > 2 THEN 'big' END FROM t GROUP BY b ORDER BY b").unwrap();
+    assert_eq!(r.rows[0][1], ScalarValue::Utf8("big".into()));
+    assert_eq!(r.rows[1][1], ScalarValue::Null);
+
+    let r = engine.execute("SELECT a, CAST(ROW_NUMBER() OVER (ORDER BY a DESC) AS VARCHAR), -RANK() OVER (ORDER BY a) FROM t ORDER BY a").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(1), ScalarValue::Utf8("3".into()), ScalarValue::Int64(-1)]);
+    let r = engine.execute("SELECT a FROM t ORDER BY -ROW_NUMBER() OVER (ORDER BY a)").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(3)], vec![ScalarValue::Int64(2)], vec![ScalarValue::Int64(1)]]);
+    assert!(engine.execute("SELECT a FROM t WHERE (a, ROW_NUMBER() OVER ()) = (1, 1)").is_err());
+}
+
+#[test]
+fn test_order_by_aggregate_in_grouped_query() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE sales (region TEXT, amount INTEGER)").unwrap();
+    engine.execute("INSERT INTO sales VALUES ('east', 5), ('west', 1), ('west', 2), ('north', 9), ('west', 4), ('east', 1)").unwrap();
+    let regions = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|r| r[0].clone()).collect()
+    };
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+
+    // The ordering aggregate is not in the SELECT list.
+    assert_eq!(regions("SELECT region FROM sales GROUP BY region ORDER BY COUNT(*) DESC, region", &mut engine),
+        vec![s("west"), s("east"), s("north")]);
+    assert_eq!(regions("SELECT region FROM sales GROUP BY region ORDER BY SUM(amount)", &mut engine),
+        vec![s("east"), s("west"), s("north")]);
+    // Aggregates that are also selected, and expressions over them.
+    let r = engine.execute("SELECT region, COUNT(*) FROM sales GROUP BY region ORDER BY COUNT(*) DESC, region").unwrap();
+    assert_eq!(r.rows[0], vec![s("west"), ScalarValue::Int64(3)]);
+    assert_eq!(regions("SELECT region FROM sales GROUP BY region ORDER BY MAX(amount) - MIN(amount) DESC", &mut engine),
+        vec![s("east"), s("west"), s("north")]);
+    // Grouped columns can be ordered on even when the SELECT list aliases them.
+    assert_eq!(regions("SELECT region AS r FROM sales GROUP BY region ORDER BY region DESC", &mut engine),
+        vec![s("west"), s("north"), s("east")]);
+}
+
+#[test]
+fn test_order_by_window_function() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (name TEXT, dept TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES ('ann', 'eng', 100), ('bob', 'ops', 70), ('cy', 'eng', 120), ('di', 'ops', 90)").unwrap();
+    let names = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|r| r[0].clone()).collect()
+    };
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+
+    // The rank is not selected.
+    assert_eq!(names("SELECT name, dept, salary FROM emp ORDER BY RANK() OVER (PARTITION BY dept ORDER BY salary DESC), name", &mut engine),
+        vec![s("cy"), s("di"), s("ann"), s("bob")]);
+    // A selected window expression orders by its computed column.
+    let r = engine.execute("SELECT name, salary, ROW_NUMBER() OVER (ORDER BY salary) FROM emp ORDER BY ROW_NUMBER() OVER (ORDER BY salary) DESC").unwrap();
+    assert_eq!(r.rows[0], vec![s("cy"), ScalarValue::Int64(120), ScalarValue::Int64(4)]);
+    assert_eq!(r.rows[3][2], ScalarValue::Int64(1));
+    // Expressions over a window function work as well.
+    assert_eq!(names("SELECT name, salary FROM emp ORDER BY 0 - ROW_NUMBER() OVER (ORDER BY salary)", &mut engine),
+        vec![s("cy"), s("ann"), s("di"), s("bob")]);
+    // A selected window with a different OVER is not reused for the key.
+    assert_eq!(names("SELECT name, ROW_NUMBER() OVER (ORDER BY name) FROM emp ORDER BY ROW_NUMBER() OVER (ORDER BY salary)", &mut engine),
+        vec![s("bob"), s("di"), s("ann"), s("cy")]);
+    assert_eq!(names("SELECT name FROM emp ORDER BY ROW_NUMBER() OVER (PARTITION BY dept ORDER BY salary DESC), salary", &mut engine),
+        vec![s("di"), s("cy"), s("bob"), s("ann")]);
+}
+
+#[test]
+fn test_join_order_by_input_size() {
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE orders (id INTEGER, customer_id INTEGER, amount INTEGER)").unwrap();
+    engine.execute("CREATE TABLE customers (id INTEGER, region_id INTEGER, name TEXT)").unwrap();
+    engine.execute("CREATE TABLE regions (id INTEGER, name TEXT)").unwrap();
+    let orders: Vec<String> = (0..300).map(|i| format!("({}, {}, {})", i, i % 20, i * 10)).collect();
+    engine.execute(&format!("INSERT INTO orders VALUES {}", orders.join(", "))).unwrap();
+    let customers: Vec<String> = (0..20).map(|i| format!("({}, {}, 'c{}')", i, i % 5, i)).collect();
+    engine.execute(&format!("INSERT INTO customers VALUES {}", customers.join(", "))).unwrap();
+    engine.execute("INSERT INTO regions VALUES (0, 'north'), (9, 'nowhere')").unwrap();
+
+    let sql = "SELECT * FROM orders o JOIN customers c ON o.customer_id = c.id \
+               JOIN regions r ON c.region_id = r.id AND r.name <> 'south'";
+    let reordered = engine.execute(sql).unwrap();
+    #[cfg(feature = "metrics")]
+    let reordered_rows = engine.last_metrics().join_rows;
+    engine.set_join_reordering(false);
+    let written = engine.execute(sql).unwrap();
+    #[cfg(feature = "metrics")]
+    let written_rows = engine.last_metrics().join_rows;
+
+    // Same rows, columns and row order either way...
+    assert_eq!(reordered.columns, written.columns);
+    assert_eq!(reordered.rows, written.rows);
+    assert_eq!(written.row_count(), 60);
+    // ...but starting from regions keeps the intermediate small:
+    // regions x customers gives 4 rows instead of orders x customers' 300.
+    #[cfg(feature = "metrics")]
+    {
+        assert_eq!(written_rows, 300 + 60);
+        assert_eq!(reordered_rows, 4 + 60);
+    }
+
+    // Outer joins keep their written order.
+    engine.set_join_reordering(true);
+    let sql = "SELECT COUNT(*) FROM orders o LEFT JOIN customers c ON o.customer_id = c.id \
+               JOIN regions r ON c.region_id = r.id";
+    assert_eq!(engine.execute(sql).unwrap().rows[0][0], pivot_engine::column::ScalarValue::Int64(60));
+    #[cfg(feature = "metrics")]
+    assert_eq!(engine.last_metrics().join_rows, 300 + 60);
+}
+
+#[test]
+fn test_row_constructors() {
+    use pivot_engine::column::ScalarValue;
+    let v = |sql: &str| SqlEngine::new().execute(sql).unwrap().rows[0][0].clone();
+    let b = ScalarValue::Boolean;
+
+    assert_eq!(v("SELECT ROW(1, 'a')"), ScalarValue::Row(vec![ScalarValue::Int64(1), ScalarValue::Utf8("a".into())]));
+    assert_eq!(v("SELECT ROW(1, 2) = (1, 2)"), b(true));
+    assert_eq!(v("SELECT (1, 2) <> (1, 3)"), b(true));
+    assert_eq!(v("SELECT (1, 2) < (1, 3)"), b(true));
+    assert_eq!(v("SELECT (2, 0) > (1, 9)"), b(true));
+    assert_eq!(v("SELECT (1, 2) <= (1, 2)"), b(true));
+    assert_eq!(v("SELECT (1, 2) >= (1, 3)"), b(false));
+    // NULLs matter only when they decide the outcome.
+    assert_eq!(v("SELECT (1, NULL) = (1, 2)"), ScalarValue::Null);
+    assert_eq!(v("SELECT (1, NULL) = (2, 2)"), b(false));
+    assert_eq!(v("SELECT (1, NULL) < (2, 0)"), b(true));
+    assert_eq!(v("SELECT (NULL, 1) < (2, 0)"), ScalarValue::Null);
+    assert!(SqlEngine::new().execute("SELECT (1, 2) = (1, 2, 3)").is_err());
+
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE pts (x INTEGER, y INTEGER)").unwrap();
+    engine.execute("INSERT INTO pts VALUES (1, 1), (1, 2), (2, 1), (0, 5)").unwrap();
+    let r = engine.execute("SELECT x, y FROM pts WHERE (x, y) IN ((1, 2), (2, 1)) ORDER BY x").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Int64(1), ScalarValue::Int64(2)],
+        vec![ScalarValue::Int64(2), ScalarValue::Int64(1)],
+    ]);
+    let r = engine.execute("SELECT x, y FROM pts WHERE (x, y) > (1, 1) ORDER BY (x, y)").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Int64(1), ScalarValue::Int64(2)],
+        vec![ScalarValue::Int64(2), ScalarValue::Int64(1)],
+    ]);
+    let r = engine.execute("SELECT ROW(MIN(x), MAX(y)) FROM pts").unwrap();
+    assert_eq!(r.rows[0][0].to_string(), "(0, 5)");
+}
+
+#[test]
+fn test_correlated_subqueries() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE employees (name TEXT, dept TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO employees VALUES ('ann', 'eng', 100), ('bob', 'ops', 70), ('cy', 'eng', 120), ('di', 'ops', 90)").unwrap();
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+
+    // Earners above their own department's average.
+    let r = engine.execute("SELECT name FROM employees e WHERE salary > (SELECT AVG(salary) FROM employees WHERE dept = e.dept) ORDER BY name").unwrap();
+    assert_eq!(r.rows, vec![vec![s("cy")], vec![s("di")]]);
+
+    // An unqualified name resolves to the subquery's own columns first.
+    let r = engine.execute("SELECT name FROM employees e WHERE (SELECT COUNT(*) FROM employees WHERE salary > 95) = 2").unwrap();
+    assert_eq!(r.rows.len(), 4);
+
+    // Correlated scalar subquery in the select list.
+    let r = engine.execute("SELECT name, (SELECT COUNT(*) FROM employees i WHERE i.dept = o.dept AND i.salary > o.salary) FROM employees o ORDER BY name").unwrap();
+    let counts: Vec<ScalarValue> = r.rows.iter().map(|row| row[1].clone()).collect();
+    assert_eq!(counts, vec![ScalarValue::Int64(1), ScalarValue::Int64(1), ScalarValue::Int64(0), ScalarValue::Int64(0)]);
+
+    // Unknown columns still fail.
+    assert!(engine.execute("SELECT name FROM employees e WHERE salary > (SELECT AVG(salary) FROM employees WHERE dept = e.nope)").is_err());
+}
+
+#[test]
+fn test_uncorrelated_subqueries_run_once() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE big (id INTEGER)").unwrap();
+    engine.execute("CREATE TABLE small (id INTEGER)").unwrap();
+    let ids: Vec<String> = (0..1000).map(|i| format!("({})", i)).collect();
+    engine.execute(&format!("INSERT INTO big VALUES {}", ids.join(", "))).unwrap();
+    engine.execute("INSERT INTO small VALUES (3), (7), (900)").unwrap();
+    let i = ScalarValue::Int64;
+
+    let r = engine.execute("SELECT id FROM big WHERE id IN (SELECT id FROM small) AND id < (SELECT MAX(id) FROM small) ORDER BY id").unwrap();
+    assert_eq!(r.rows, vec![vec![i(3)], vec![i(7)]]);
+    // Each subquery reads `small` once, not once per row of `big`.
+    #[cfg(feature = "metrics")]
+    assert_eq!(engine.last_metrics().rows_scanned, 1000 + 3 + 3);
+
+    // A correlated subquery still runs per row, and an uncorrelated one
+    // inside it is reused only while that run lasts.
+    let r = engine.execute("SELECT id, (SELECT COUNT(*) FROM small s WHERE s.id <= b.id AND s.id IN (SELECT id FROM small WHERE id > 5)) FROM big b WHERE id IN (5, 7, 950) ORDER BY id").unwrap();
+    assert_eq!(r.rows, vec![vec![i(5), i(0)], vec![i(7), i(1)], vec![i(950), i(2)]]);
+    let r = engine.execute("SELECT COUNT(*) FROM big b WHERE EXISTS (SELECT 1 FROM small WHERE id = b.id)").unwrap();
+    assert_eq!(r.rows, vec![vec![i(3)]]);
+}
+
+#[test]
+fn test_exists_semi_and_anti_join() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE customers (id INTEGER, name TEXT)").unwrap();
+    engine.execute("CREATE TABLE orders (id INTEGER, customer_id INTEGER, note TEXT)").unwrap();
+    engine.execute("INSERT INTO customers VALUES (1, 'ann'), (2, 'bob'), (3, 'cy')").unwrap();
+    engine.execute("INSERT INTO orders VALUES (10, 1, NULL), (11, 1, 'x'), (12, 3, NULL)").unwrap();
+    let names = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|r| r[0].clone()).collect()
+    };
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+
+    // Semi-join: customers with at least one order, each listed once.
+    assert_eq!(names("SELECT name FROM customers c WHERE EXISTS (SELECT 1 FROM orders o WHERE o.customer_id = c.id) ORDER BY name", &mut engine),
+        vec![s("ann"), s("cy")]);
+    // Anti-join.
+    assert_eq!(names("SELECT name FROM customers c WHERE NOT EXISTS (SELECT 1 FROM orders o WHERE o.customer_id = c.id)", &mut engine),
+        vec![s("bob")]);
+    // NULLs in the inner projection don't matter, nor do zero-column rows.
+    assert_eq!(names("SELECT name FROM customers c WHERE EXISTS (SELECT note FROM orders WHERE customer_id = c.id AND note IS NULL) ORDER BY name", &mut engine),
+        vec![s("ann"), s("cy")]);
+    let r = engine.execute("SELECT EXISTS (SELECT FROM orders), EXISTS (SELECT FROM orders WHERE id > 99)").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Boolean(true), ScalarValue::Boolean(false)]]);
+}
+
+#[test]
+fn test_in_subquery() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE departments (id INTEGER, name TEXT)").unwrap();
+    engine.execute("CREATE TABLE staff (name TEXT, dept_id INTEGER)").unwrap();
+    engine.execute("INSERT INTO departments VALUES (1, 'eng'), (2, 'ops')").unwrap();
+    engine.execute("INSERT INTO staff VALUES ('ann', 1), ('bob', 9), ('cy', 2), ('di', NULL)").unwrap();
+    let names = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|r| r[0].clone()).collect()
+    };
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+
+    assert_eq!(names("SELECT name FROM staff WHERE dept_id IN (SELECT id FROM departments) ORDER BY name", &mut engine),
+        vec![s("ann"), s("cy")]);
+    assert_eq!(names("SELECT name FROM staff WHERE dept_id NOT IN (SELECT id FROM departments)", &mut engine),
+        vec![s("bob")]);
+    // A NULL in the subquery result makes NOT IN unknown for non-matches.
+    engine.execute("INSERT INTO departments VALUES (NULL, 'tbd')").unwrap();
+    assert!(names("SELECT name FROM staff WHERE dept_id NOT IN (SELECT id FROM departments)", &mut engine).is_empty());
+    let r = engine.execute("SELECT 9 NOT IN (SELECT id FROM departments), 1 IN (SELECT id FROM departments)").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Null, ScalarValue::Boolean(true)]]);
+    // Correlated, and row-valued.
+    assert_eq!(names("SELECT name FROM staff s WHERE 'ops' IN (SELECT d.name FROM departments d WHERE d.id = s.dept_id)", &mut engine),
+        vec![s("cy")]);
+    assert_eq!(names("SELECT name FROM staff WHERE (dept_id, 'eng') IN (SELECT id, name FROM departments)", &mut engine),
+        vec![s("ann")]);
+    assert!(engine.execute("SELECT name FROM staff WHERE dept_id IN (SELECT id, name FROM departments)").is_err());
+}
+
+#[test]
+fn test_subqueries_see_enclosing_ctes() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (id INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1), (5), (7)").unwrap();
+    let i = ScalarValue::Int64;
+
+    let r = engine.execute("WITH c AS (SELECT 5 AS x) SELECT (SELECT x FROM c) FROM t").unwrap();
+    assert_eq!(r.rows, vec![vec![i(5)], vec![i(5)], vec![i(5)]]);
+    let r = engine.execute("WITH c AS (SELECT 5 AS x UNION ALL SELECT 7) SELECT id FROM t WHERE id IN (SELECT x FROM c) ORDER BY id").unwrap();
+    assert_eq!(r.rows, vec![vec![i(5)], vec![i(7)]]);
+    let r = engine.execute("WITH c AS (SELECT 5 AS x) SELECT id FROM t WHERE NOT EXISTS (SELECT 1 FROM c WHERE x = t.id) ORDER BY id").unwrap();
+    assert_eq!(r.rows, vec![vec![i(1)], vec![i(7)]]);
+    // A subquery's own WITH sees the outer CTEs too.
+    let r = engine.execute("WITH c AS (SELECT 5 AS x) SELECT (WITH d AS (SELECT x + 1 AS y FROM c) SELECT y FROM d) FROM t WHERE id = 1").unwrap();
+    assert_eq!(r.rows, vec![vec![i(6)]]);
+}
+
+#[test]
+fn test_group_by_rollup() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE sales (dept TEXT, region TEXT, amt INTEGER)").unwrap();
+    engine.execute("INSERT INTO sales VALUES ('eng', 'eu', 10), ('eng', 'us', 20), ('ops', 'eu', 5), ('eng', 'eu', 1)").unwrap();
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+    let i = ScalarValue::Int64;
+
+    // Three detail rows, a subtotal per dept, one grand total.
+    let r = engine.execute("SELECT dept, region, SUM(amt), COUNT(*) FROM sales GROUP BY ROLLUP(dept, region)").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![s("eng"), s("eu"), i(11), i(2)],
+        vec![s("eng"), s("us"), i(20), i(1)],
+        vec![s("ops"), s("eu"), i(5), i(1)],
+        vec![s("eng"), ScalarValue::Null, i(31), i(3)],
+        vec![s("ops"), ScalarValue::Null, i(5), i(1)],
+        vec![ScalarValue::Null, ScalarValue::Null, i(36), i(4)],
+    ]);
+
+    // Rolled-up keys are NULL inside expressions too; HAVING applies per row.
+    let r = engine.execute("SELECT dept, region || '!', SUM(amt) FROM sales GROUP BY ROLLUP(dept, region) HAVING SUM(amt) > 10").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![s("eng"), s("eu!"), i(11)],
+        vec![s("eng"), s("us!"), i(20)],
+        vec![s("eng"), ScalarValue::Null, i(31)],
+        vec![ScalarValue::Null, ScalarValue::Null, i(36)],
+    ]);
+
+    // A plain key before the ROLLUP is never rolled up.
+    let r = engine.execute("SELECT dept, region, SUM(amt) FROM sales GROUP BY dept, ROLLUP(region)").unwrap();
+    assert_eq!(r.row_count(), 5);
+
+    // The grand total survives an empty input.
+    let r = engine.execute("SELECT COUNT(*) FROM sales WHERE amt > 100 GROUP BY ROLLUP(dept)").unwrap();
+    assert_eq!(r.rows, vec![vec![i(0)]]);
+}
+
+#[test]
+fn test_grouping_sets_and_cube() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE sales (dept TEXT, region TEXT, amt INTEGER)").unwrap();
+    engine.execute("INSERT INTO sales VALUES ('eng', 'eu', 10), ('eng', 'us', 20), ('ops', 'eu', 5), ('eng', 'eu', 1)").unwrap();
+    let i = ScalarValue::Int64;
+
+    // 3 (dept, region) + 2 (dept) + 2 (region) + 1 grand total
+    let r = engine.execute("SELECT dept, region, SUM(amt), GROUPING(dept), GROUPING(dept, region) FROM sales GROUP BY CUBE(dept, region)").unwrap();
+    assert_eq!(r.row_count(), 8);
+    let totals: Vec<Vec<ScalarValue>> = r.rows.iter().map(|row| row[2..].to_vec()).collect();
+    assert_eq!(totals[3], vec![i(31), i(0), i(1)]);
+    assert_eq!(totals[5], vec![i(16), i(1), i(2)]);
+    assert_eq!(totals[7], vec![i(36), i(1), i(3)]);
+
+    let r = engine.execute("SELECT dept, region, SUM(amt) FROM sales GROUP BY GROUPING SETS ((dept, region), (dept), ())").unwrap();
+    assert_eq!(r.row_count(), 6);
+    let r = engine.execute("SELECT region, SUM(amt) FROM sales GROUP BY GROUPING SETS (region, ())").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Utf8("eu".into()), i(16)],
+        vec![ScalarValue::Utf8("us".into()), i(20)],
+        vec![ScalarValue::Null, i(36)],
+    ]);
+
+    // GROUPING separates a NULL key from a rolled-up one.
+    engine.execute("INSERT INTO sales VALUES (NULL, 'eu', 2)").unwrap();
+    let r = engine.execute("SELECT dept, GROUPING(dept), SUM(amt) FROM sales GROUP BY ROLLUP(dept) HAVING dept IS NULL").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Null, i(0), i(2)], vec![ScalarValue::Null, i(1), i(38)]]);
+}
+
+#[test]
+fn test_window_frame_bounds() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (id INTEGER, dept TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES (1, 'eng', 10), (2, 'eng', 20), (3, 'eng', 30), (4, 'eng', 40), (5, 'ops', 5), (6, 'ops', 7)").unwrap();
+    let col = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|r| r.last().unwrap().clone()).collect()
+    };
+    let ints = |v: &[i64]| v.iter().map(|&n| ScalarValue::Int64(n)).collect::<Vec<_>>();
+
+    // Running sum per dept.
+    let sums = col("SELECT id, dept, salary, SUM(salary) OVER (PARTITION BY dept ORDER BY salary) FROM emp ORDER BY id", &mut engine);
+    assert_eq!(sums, ints(&[10, 30, 60, 100, 5, 12]));
+
+    assert_eq!(col("SELECT id, salary, SUM(salary) OVER (ORDER BY id ROWS BETWEEN 2 PRECEDING AND CURRENT ROW) FROM emp ORDER BY id", &mut engine),
+        ints(&[10, 30, 60, 90, 75, 52]));
+    assert_eq!(col("SELECT id, salary, MAX(salary) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING) FROM emp ORDER BY id", &mut engine),
+        ints(&[20, 30, 40, 40, 40, 7]));
+    assert_eq!(col("SELECT id, COUNT(*) OVER (ORDER BY id ROWS 1 PRECEDING) FROM emp ORDER BY id", &mut engine),
+        ints(&[1, 2, 2, 2, 2, 2]));
+    // A frame past the partition end is empty.
+    assert_eq!(col("SELECT id, dept, salary, SUM(salary) OVER (PARTITION BY dept ORDER BY id ROWS BETWEEN 2 FOLLOWING AND 3 FOLLOWING) FROM emp ORDER BY id", &mut engine),
+        vec![ScalarValue::Int64(70), ScalarValue::Int64(40), ScalarValue::Null, ScalarValue::Null, ScalarValue::Null, ScalarValue::Null]);
+    // RANGE offsets are distances between ORDER BY values.
+    assert_eq!(col("SELECT id, salary, SUM(salary) OVER (ORDER BY salary RANGE BETWEEN 10 PRECEDING AND CURRENT ROW) FROM emp ORDER BY id", &mut engine),
+        ints(&[22, 30, 50, 70, 5, 12]));
+
+    assert!(engine.execute("SELECT id, salary, SUM(salary) OVER (ORDER BY id ROWS BETWEEN -1 PRECEDING AND CURRENT ROW) FROM emp").is_err());
+    assert!(engine.execute("SELECT id, salary, SUM(salary) OVER (ORDER BY id, salary RANGE BETWEEN 1 PRECEDING AND CURRENT ROW) FROM emp").is_err());
+}
+
+#[test]
+fn test_first_last_value_frames() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (id INTEGER, dept TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES (1, 'eng', 10), (2, 'eng', 20), (3, 'eng', 30), (4, 'ops', 5), (5, 'ops', 7)").unwrap();
+    let r = engine.execute("SELECT id, dept, salary, \
+        FIRST_VALUE(salary) OVER (PARTITION BY dept ORDER BY salary), \
+        LAST_VALUE(salary) OVER (PARTITION BY dept ORDER BY salary) FROM emp ORDER BY id").unwrap();
+    let pairs: Vec<(ScalarValue, ScalarValue)> = r.rows.iter().map(|row| (row[3].clone(), row[4].clone())).collect();
+    let i = ScalarValue::Int64;
+    // Default frame: FIRST_VALUE is the partition's first row, LAST_VALUE the current row.
+    assert_eq!(pairs, vec![(i(10), i(10)), (i(10), i(20)), (i(10), i(30)), (i(5), i(5)), (i(5), i(7))]);
+
+    let r = engine.execute("SELECT id, salary, \
+        FIRST_VALUE(salary) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW), \
+        LAST_VALUE(salary) OVER (ORDER BY id ROWS BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING) FROM emp ORDER BY id").unwrap();
+    assert_eq!(r.rows.iter().map(|row| row[2].clone()).collect::<Vec<_>>(), vec![i(10), i(10), i(20), i(30), i(5)]);
+    assert!(r.rows.iter().all(|row| row[3] == i(7)));
+}
+
+#[test]
+fn test_named_windows() {
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (id INTEGER, dept TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES (1, 'eng', 10), (2, 'eng', 30), (3, 'eng', 20), (4, 'ops', 5), (5, 'ops', 7)").unwrap();
+
+    // Two functions sharing one named window match the inline spec.
+    let named = engine.execute("SELECT id, salary, RANK() OVER w, SUM(salary) OVER w FROM emp \
+        WINDOW w AS (PARTITION BY dept ORDER BY salary DESC) ORDER BY id").unwrap();
+    let inline = engine.execute("SELECT id, salary, RANK() OVER (PARTITION BY dept ORDER BY salary DESC), \
+        SUM(salary) OVER (PARTITION BY dept ORDER BY salary DESC) FROM emp ORDER BY id").unwrap();
+    assert_eq!(named.rows, inline.rows);
+
+    // `OVER (w ORDER BY ...)` adds ordering to a base window, and windows
+    // may build on earlier ones.
+    let extended = engine.execute("SELECT id, salary, ROW_NUMBER() OVER (p ORDER BY salary DESC), SUM(salary) OVER ranked FROM emp \
+        WINDOW p AS (PARTITION BY dept), ranked AS (p ORDER BY salary DESC) ORDER BY id").unwrap();
+    assert_eq!(extended.rows.iter().map(|r| r[3].clone()).collect::<Vec<_>>(),
+        inline.rows.iter().map(|r| r[3].clone()).collect::<Vec<_>>());
+    assert_eq!(extended.rows.iter().map(|r| r[2].clone()).collect::<Vec<_>>(),
+        inline.rows.iter().map(|r| r[2].clone()).collect::<Vec<_>>());
+
+    assert!(engine.execute("SELECT id, salary, RANK() OVER nope FROM emp").is_err());
+    assert!(engine.execute("SELECT id, salary, RANK() OVER (w ORDER BY id) FROM emp WINDOW w AS (ORDER BY salary)").is_err());
+    assert!(engine.execute("SELECT id, salary, RANK() OVER (w PARTITION BY id) FROM emp WINDOW w AS (ORDER BY salary)").is_err());
+}
+
+#[test]
+fn test_update_enforces_constraints() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE acct (id INTEGER PRIMARY KEY, email TEXT UNIQUE, name TEXT NOT NULL)").unwrap();
+    engine.execute("INSERT INTO acct VALUES (1, 'a@x', 'ann'), (2, 'b@x', 'bob'), (3, NULL, 'cy')").unwrap();
+
+    assert!(matches!(engine.execute("UPDATE acct SET name = NULL WHERE id = 1"), Err(pivot_engine::error::PivotError::NullError(_))));
+    assert!(engine.execute("UPDATE acct SET id = 2 WHERE id = 1").is_err());
+    assert!(engine.execute("UPDATE acct SET email = 'b@x' WHERE id = 1").is_err());
+    assert!(engine.execute("INSERT INTO acct VALUES (4, 'a@x', 'di')").is_err());
+    // NULLs don't collide in a UNIQUE column.
+    engine.execute("INSERT INTO acct VALUES (4, NULL, 'di')").unwrap();
+
+    // A failed UPDATE writes nothing, even to rows checked before the bad one.
+    assert!(engine.execute("UPDATE acct SET email = 'same'").is_err());
+    let r = engine.execute("SELECT email FROM acct WHERE id = 1").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Utf8("a@x".into()));
+
+    // Keys may move through each other within one statement.
+    engine.execute("UPDATE acct SET id = id + 1").unwrap();
+    let r = engine.execute("SELECT id FROM acct ORDER BY id").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(2)], vec![ScalarValue::Int64(3)], vec![ScalarValue::Int64(4)], vec![ScalarValue::Int64(5)]]);
+
+    // Rows of one INSERT are checked against each other too.
+    assert!(engine.execute("INSERT INTO acct VALUES (10, 'e@x', 'ed'), (11, 'e@x', 'flo')").is_err());
+    assert!(engine.execute("INSERT INTO acct (id, email, name) SELECT id + 20, 'f@x', name FROM acct WHERE id < 4").is_err());
+}
+
+#[test]
+fn test_create_view() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE employees (name TEXT, dept TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO employees VALUES ('ann', 'eng', 100), ('bob', 'ops', 70), ('cy', 'eng', 120), ('di', 'ops', 90)").unwrap();
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+
+    engine.execute("CREATE VIEW high_earners AS SELECT name, dept, salary FROM employees WHERE salary >= 90").unwrap();
+    let r = engine.execute("SELECT h.dept, COUNT(*), SUM(h.salary) FROM high_earners h GROUP BY h.dept ORDER BY h.dept").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![s("eng"), ScalarValue::Int64(2), ScalarValue::Int64(220)],
+        vec![s("ops"), ScalarValue::Int64(1), ScalarValue::Int64(90)],
+    ]);
+
+    // Views are re-run on every read and may build on other views.
+    engine.execute("CREATE VIEW eng_high AS SELECT name FROM high_earners WHERE dept = 'eng'").unwrap();
+    engine.execute("INSERT INTO employees VALUES ('ed', 'eng', 95)").unwrap();
+    let r = engine.execute("SELECT name FROM eng_high ORDER BY name").unwrap();
+    assert_eq!(r.rows, vec![vec![s("ann")], vec![s("cy")], vec![s("ed")]]);
+
+    assert!(engine.execute("CREATE VIEW high_earners AS SELECT 1").is_err());
+    engine.execute("CREATE VIEW IF NOT EXISTS high_earners AS SELECT 1").unwrap();
+    engine.execute("CREATE OR REPLACE VIEW high_earners AS SELECT name, dept, salary FROM employees WHERE salary < 90").unwrap();
+    let r = engine.execute("SELECT name FROM high_earners").unwrap();
+    assert_eq!(r.rows, vec![vec![s("bob")]]);
+    assert!(engine.execute("CREATE VIEW employees AS SELECT 1").is_err());
+    assert!(engine.execute("CREATE VIEW broken AS SELECT nope FROM employees").is_err());
+
+    engine.execute("DROP VIEW eng_high").unwrap();
+    assert!(engine.execute("SELECT * FROM eng_high").is_err());
+    assert!(engine.execute("DROP VIEW eng_high").is_err());
+    engine.execute("DROP VIEW IF EXISTS eng_high").unwrap();
+}
+
+#[test]
+fn test_natural_join() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (id INTEGER, name TEXT)").unwrap();
+    engine.execute("CREATE TABLE pay (id INTEGER, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES (1, 'ann'), (2, 'bob'), (NULL, 'cy')").unwrap();
+    engine.execute("INSERT INTO pay VALUES (1, 100), (3, 70), (NULL, 50)").unwrap();
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+    let i = ScalarValue::Int64;
+
+    // The shared id column appears once; NULL ids never match.
+    let r = engine.execute("SELECT * FROM emp NATURAL JOIN pay").unwrap();
+    assert_eq!(r.columns, vec!["id", "name", "salary"]);
+    assert_eq!(r.rows, vec![vec![i(1), s("ann"), i(100)]]);
+
+    let r = engine.execute("SELECT id, name, salary FROM emp NATURAL LEFT JOIN pay ORDER BY name").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![i(1), s("ann"), i(100)],
+        vec![i(2), s("bob"), ScalarValue::Null],
+        vec![ScalarValue::Null, s("cy"), ScalarValue::Null],
+    ]);
+
+    // Rows only on the right keep their own id.
+    let r = engine.execute("SELECT id, name, salary FROM emp NATURAL RIGHT OUTER JOIN pay ORDER BY salary").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Null, ScalarValue::Null, i(50)],
+        vec![i(3), ScalarValue::Null, i(70)],
+        vec![i(1), s("ann"), i(100)],
+    ]);
+
+    // With no shared columns every pair of rows is kept.
+    engine.execute("CREATE TABLE tag (label TEXT)").unwrap();
+    engine.execute("INSERT INTO tag VALUES ('x'), ('y')").unwrap();
+    let r = engine.execute("SELECT COUNT(*) FROM emp NATURAL JOIN tag").unwrap();
+    assert_eq!(r.rows[0][0], i(6));
+    assert!(engine.execute("SELECT * FROM emp NATURAL CROSS JOIN pay").is_err());
+}
+
+#[test]
+fn test_hash_join_matches_nested_loop() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE a (k INTEGER, tag TEXT)").unwrap();
+    engine.execute("CREATE TABLE b (k DOUBLE, n INTEGER)").unwrap();
+    engine.execute("INSERT INTO a VALUES (1, 'x'), (2, 'y'), (NULL, 'z'), (2, 'w'), (4, 'v')").unwrap();
+    engine.execute("INSERT INTO b VALUES (2.0, 10), (1.0, 20), (NULL, 30), (2.0, 40), (3.5, 50), (1.0, 60), (9.0, 70)").unwrap();
+
+    // `a.k <= b.k AND a.k >= b.k` is not an equi-join, so it runs as a
+    // nested loop; both forms must give the same rows in the same order.
+    for join in ["JOIN", "LEFT JOIN", "RIGHT JOIN", "FULL JOIN"] {
+        for (l, r) in [("a", "b"), ("b", "a")] {
+            let hashed = engine.execute(&format!(
+                "SELECT * FROM {l} {join} {r} ON {l}.k = {r}.k"
+            )).unwrap();
+            let looped = engine.execute(&format!(
+                "SELECT * FROM {l} {join} {r} ON {l}.k <= {r}.k AND {l}.k >= {r}.k"
+            )).unwrap();
+            assert_eq!(hashed.rows, looped.rows, "{l} {join} {r}");
+        }
+    }
+
+    // Other conjuncts still filter the hashed pairs.
+    let r = engine.execute("SELECT a.tag, b.n FROM a JOIN b ON b.k = a.k AND b.n > 20 ORDER BY b.n").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Utf8("y".into()), ScalarValue::Int64(40)],
+        vec![ScalarValue::Utf8("w".into()), ScalarValue::Int64(40)],
+        vec![ScalarValue::Utf8("x".into()), ScalarValue::Int64(60)],
+    ]);
+
+    // 10k x 10k rows: a nested loop would compare 100M pairs.
+    engine.execute("CREATE TABLE big_l (id INTEGER, v INTEGER)").unwrap();
+    engine.execute("CREATE TABLE big_r (id INTEGER, w INTEGER)").unwrap();
+    for table in ["big_l", "big_r"] {
+        let values: Vec<String> = (0..10_000).map(|i| format!("({}, {})", i, i % 7)).collect();
+        engine.execute(&format!("INSERT INTO {} VALUES {}", table, values.join(", "))).unwrap();
+    }
+    let r = engine.execute("SELECT COUNT(*), SUM(l.v + r.w) FROM big_l l JOIN big_r r ON l.id = r.id").unwrap();
+    let sum: i64 = (0..10_000).map(|i| 2 * (i % 7)).sum();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(10_000), ScalarValue::Int64(sum)]]);
+    let r = engine.execute("SELECT COUNT(r.id) FROM big_l l LEFT JOIN (SELECT id FROM big_r WHERE id >= 5000) r ON l.id = r.id").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(5_000));
+}
+
+#[test]
+fn test_reordered_join_chain_hashes_equi_keys() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    for (table, n) in [("big1", 10_000), ("big2", 10_000), ("small", 2_000)] {
+        engine.execute(&format!("CREATE TABLE {} (id INTEGER, v INTEGER)", table)).unwrap();
+        let values: Vec<String> = (0..n).map(|i| format!("({}, {})", i, i % 7)).collect();
+        engine.execute(&format!("INSERT INTO {} VALUES {}", table, values.join(", "))).unwrap();
+    }
+
+    // Reordering starts from `small`; every later step must still hash its
+    // equi-keys, or this is 2000 x 10000 x 10000 pair checks.
+    let sql = "SELECT big1.id, big2.v, small.v FROM big1 JOIN big2 ON big1.id = big2.id \
+               JOIN small ON small.id = big2.id AND small.v < 3";
+    let reordered = engine.execute(sql).unwrap();
+    #[cfg(feature = "metrics")]
+    assert!(engine.last_metrics().join_comparisons <= 2 * 2_000, "{:?}", engine.last_metrics());
+    engine.set_join_reordering(false);
+    let written = engine.execute(sql).unwrap();
+    assert_eq!(reordered.rows, written.rows);
+    let expected = (0..2_000).filter(|i| i % 7 < 3).count();
+    assert_eq!(reordered.row_count(), expected);
+    assert_eq!(reordered.rows[1], vec![ScalarValue::Int64(1), ScalarValue::Int64(1), ScalarValue::Int64(1)]);
+}
+
+#[test]
+fn test_intersect_and_except_all() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE l (v INTEGER)").unwrap();
+    engine.execute("CREATE TABLE r (v INTEGER)").unwrap();
+    engine.execute("INSERT INTO l VALUES (3), (1), (1), (1), (2), (NULL), (NULL)").unwrap();
+    engine.execute("INSERT INTO r VALUES (1), (1), (4), (NULL)").unwrap();
+    let vals = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|mut row| row.remove(0)).collect()
+    };
+    let i = ScalarValue::Int64;
+    let null = ScalarValue::Null;
+
+    // Set operations treat NULLs as equal and keep the left input's order.
+    assert_eq!(vals("SELECT v FROM l INTERSECT SELECT v FROM r", &mut engine), vec![i(1), null.clone()]);
+    assert_eq!(vals("SELECT v FROM l EXCEPT SELECT v FROM r", &mut engine), vec![i(3), i(2)]);
+    assert_eq!(vals("SELECT v FROM l INTERSECT ALL SELECT v FROM r", &mut engine), vec![i(1), i(1), null.clone()]);
+    assert_eq!(vals("SELECT v FROM l EXCEPT ALL SELECT v FROM r", &mut engine), vec![i(3), i(1), i(2), null]);
+    assert_eq!(vals("SELECT v FROM r EXCEPT ALL SELECT v FROM l", &mut engine), vec![i(4)]);
+}
+
+#[test]
+fn test_set_operation_columns() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE ints (n INTEGER, label TEXT)").unwrap();
+    engine.execute("CREATE TABLE floats (x DOUBLE, tag TEXT)").unwrap();
+    engine.execute("INSERT INTO ints VALUES (1, 'one')").unwrap();
+    engine.execute("INSERT INTO floats VALUES (2.5, 'two and a half')").unwrap();
+
+    // Output columns are named after the left query.
+    let r = engine.execute("SELECT n AS num, label FROM ints UNION ALL SELECT x, tag FROM floats").unwrap();
+    assert_eq!(r.columns, vec!["num", "label"]);
+    assert_eq!(r.rows[1], vec![ScalarValue::Float64(2.5), ScalarValue::Utf8("two and a half".into())]);
+    let r = engine.execute("SELECT x FROM floats EXCEPT SELECT n FROM ints").unwrap();
+    assert_eq!(r.columns, vec!["x"]);
+
+    for op in ["UNION", "UNION ALL", "INTERSECT", "EXCEPT ALL"] {
+        let err = engine.execute(&format!("SELECT n, label FROM ints {} SELECT x FROM floats", op)).unwrap_err();
+        assert!(err.to_string().contains("same number of columns"), "{}", err);
+    }
+
+    // INTEGER and DOUBLE inputs make a DOUBLE column, which a DATE column rejects.
+    engine.execute("CREATE TABLE days (d DATE)").unwrap();
+    engine.execute("INSERT INTO days SELECT n FROM ints").unwrap();
+    assert!(engine.execute("INSERT INTO days SELECT n FROM ints UNION ALL SELECT x FROM floats").is_err());
+}
+
+#[test]
+fn test_recursive_cte() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let r = engine.execute(
+        "WITH RECURSIVE t(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t WHERE n < 10) SELECT n FROM t"
+    ).unwrap();
+    assert_eq!(r.columns, vec!["n"]);
+    assert_eq!(r.rows, (1..=10).map(|n| vec![ScalarValue::Int64(n)]).collect::<Vec<_>>());
+
+    // Walk a hierarchy; UNION stops once a round finds nothing new, even on a cycle.
+    engine.execute("CREATE TABLE edges (src INTEGER, dst INTEGER)").unwrap();
+    engine.execute("INSERT INTO edges VALUES (1, 2), (2, 3), (3, 1), (3, 4), (5, 6)").unwrap();
+    let r = engine.execute(
+        "WITH RECURSIVE reach(node) AS (
+             SELECT 1
+             UNION
+             SELECT e.dst FROM edges e JOIN reach r ON e.src = r.node
+         )
+         SELECT node FROM reach ORDER BY node"
+    ).unwrap();
+    assert_eq!(r.rows, (1..=4).map(|n| vec![ScalarValue::Int64(n)]).collect::<Vec<_>>());
+
+    // Without a stopping condition the round cap turns a runaway query into an error.
+    engine.set_max_recursion(50);
+    let err = engine.execute(
+        "WITH RECURSIVE t(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t) SELECT COUNT(*) FROM t"
+    ).unwrap_err();
+    assert!(err.to_string().contains("50 rounds"), "{}", err);
+    let r = engine.execute(
+        "WITH RECURSIVE t(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t WHERE n < 50) SELECT COUNT(*) FROM t"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(50));
+
+    // A CTE that doesn't read itself runs once; column lists work on plain CTEs too.
+    let r = engine.execute(
+        "WITH RECURSIVE a(x) AS (SELECT 1 UNION ALL SELECT 2) SELECT SUM(x) FROM a"
+    ).unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(3));
+    let r = engine.execute("WITH c(k, v) AS (SELECT 1, 2) SELECT v FROM c").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(2));
+    assert!(engine.execute("WITH c(k) AS (SELECT 1, 2) SELECT k FROM c").is_err());
+}
+
+#[test]
+fn test_order_by_position_and_alias() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (name TEXT, dept TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES ('ann', 'eng', 100), ('bob', 'ops', 70), ('cy', 'eng', 120), ('di', 'ops', 90), ('ed', 'hr', 50)").unwrap();
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+    let i = ScalarValue::Int64;
+
+    let r = engine.execute("SELECT name, salary FROM emp ORDER BY 2 DESC").unwrap();
+    let names: Vec<_> = r.rows.iter().map(|row| row[0].clone()).collect();
+    assert_eq!(names, vec![s("cy"), s("ann"), s("di"), s("bob"), s("ed")]);
+
+    // Aggregate aliases and positions both name output columns.
+    let r = engine.execute("SELECT dept, SUM(salary) AS total FROM emp GROUP BY dept ORDER BY total").unwrap();
+    assert_eq!(r.rows, vec![vec![s("hr"), i(50)], vec![s("ops"), i(160)], vec![s("eng"), i(220)]]);
+    let r = engine.execute("SELECT dept, COUNT(*), SUM(salary) FROM emp GROUP BY dept ORDER BY 2 DESC, 1").unwrap();
+    assert_eq!(r.rows[0][0], s("eng"));
+    assert_eq!(r.rows[2][0], s("hr"));
+
+    // An alias wins over a base column of the same name.
+    let r = engine.execute("SELECT name, 0 - salary AS salary FROM emp ORDER BY salary LIMIT 1").unwrap();
+    assert_eq!(r.rows[0][0], s("cy"));
+
+    // Positions outside the select list are an error; other literals still sort by value.
+    assert!(engine.execute("SELECT name FROM emp ORDER BY 2").is_err());
+    assert!(engine.execute("SELECT name FROM emp ORDER BY 0").is_err());
+    assert_eq!(engine.execute("SELECT name FROM emp ORDER BY 'x'").unwrap().row_count(), 5);
+}
+
+#[test]
+fn test_having_and_group_by_select_aliases() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (name TEXT, dept TEXT, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES ('ann', 'eng', 60000), ('bob', 'ops', 70000), ('cy', 'eng', 50000), ('di', 'ops', 20000), ('ed', 'hr', 30000)").unwrap();
+    let s = |v: &str| ScalarValue::Utf8(v.into());
+    let i = ScalarValue::Int64;
+
+    let r = engine.execute(
+        "SELECT dept, SUM(salary) AS total FROM emp GROUP BY dept HAVING total > 80000 ORDER BY dept"
+    ).unwrap();
+    assert_eq!(r.rows, vec![vec![s("eng"), i(110000)], vec![s("ops"), i(90000)]]);
+    let r = engine.execute(
+        "SELECT dept, COUNT(*) AS n, MAX(salary) AS top FROM emp GROUP BY dept HAVING n > 1 AND top < 65000"
+    ).unwrap();
+    assert_eq!(r.rows, vec![vec![s("eng"), i(2), i(60000)]]);
+
+    // GROUP BY an alias of a non-aggregate expression.
+    let r = engine.execute(
+        "SELECT salary >= 50000 AS senior, COUNT(*) FROM emp GROUP BY senior ORDER BY senior"
+    ).unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Boolean(false), i(2)],
+        vec![ScalarValue::Boolean(true), i(3)],
+    ]);
+    // An input column of the same name is grouped on instead.
+    let r = engine.execute("SELECT UPPER(dept) AS dept FROM emp GROUP BY dept ORDER BY dept").unwrap();
+    assert_eq!(r.rows, vec![vec![s("ENG")], vec![s("HR")], vec![s("OPS")]]);
+    assert!(engine.execute("SELECT dept, SUM(salary) AS total FROM emp GROUP BY total").is_err());
+}
+
+#[test]
+fn test_integer_division_setting() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (a INTEGER, b INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (7, 2), (-7, 2), (7, -2), (1, 0)").unwrap();
+    let row = |sql: &str, engine: &mut SqlEngine| engine.execute(sql).unwrap().rows.remove(0);
+    let i = ScalarValue::Int64;
+    let f = ScalarValue::Float64;
+
+    // By default INTEGER / INTEGER truncates toward zero.
+    assert_eq!(row("SELECT 7 / 2, -7 / 2, 7 / -2, 7.0 / 2, 7 / 2.0", &mut engine), vec![i(3), i(-3), i(-3), f(3.5), f(3.5)]);
+    let r = engine.execute("SELECT a / b FROM t WHERE b <> 0").unwrap();
+    assert_eq!(r.rows, vec![vec![i(3)], vec![i(-3)], vec![i(-3)]]);
+    assert_eq!(row("SELECT SUM(a) / COUNT(*) FROM t WHERE b <> 0", &mut engine), vec![i(2)]);
+    assert!(engine.execute("SELECT a / b FROM t").is_err());
+
+    engine.set_integer_division(false);
+    assert_eq!(row("SELECT 7 / 2, -7 / 2, 7 / -2", &mut engine), vec![f(3.5), f(-3.5), f(-3.5)]);
+    assert!(engine.execute("SELECT a / b FROM t").is_err());
+    assert!(engine.execute("SELECT 1 / 0").is_err());
+}
+
+#[test]
+fn test_mod_and_div_operators() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let row = |sql: &str, engine: &mut SqlEngine| engine.execute(sql).unwrap().rows.remove(0);
+    let i = ScalarValue::Int64;
+    let f = ScalarValue::Float64;
+
+    // The remainder takes the sign of the dividend.
+    assert_eq!(row("SELECT 7 % 3, -7 % 3, 7 % -3, -7 % -3", &mut engine), vec![i(1), i(-1), i(1), i(-1)]);
+    assert_eq!(row("SELECT 7.5 % 2, -7.5 % 2.0", &mut engine), vec![f(1.5), f(-1.5)]);
+    for sql in ["SELECT 5 % 0", "SELECT 5.0 % 0.0", "SELECT 5 % 0.0", "SELECT MOD(5, 0)", "SELECT 5 DIV 0", "SELECT 5.0 DIV 0"] {
+        let err = engine.execute(sql).unwrap_err().to_string();
+        assert!(err.contains("Division by zero"), "{}: {}", sql, err);
+    }
+
+    // MOD() is the same operation as %.
+    assert_eq!(row("SELECT MOD(-7, 3), MOD(7.5, 2), MOD(NULL, 3)", &mut engine), vec![i(-1), f(1.5), ScalarValue::Null]);
+
+    // DIV truncates toward zero and is INTEGER whatever the operands.
+    assert_eq!(row("SELECT 7 DIV 2, -7 div 2, 7.9 DIV 2, DIV(-9, 4)", &mut engine), vec![i(3), i(-3), i(3), i(-2)]);
+    engine.set_integer_division(false);
+    assert_eq!(row("SELECT 7 DIV 2, 7 / 2", &mut engine), vec![i(3), f(3.5)]);
+}
+
+#[test]
+fn test_like_backslash_escape_over_rows() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE codes (c VARCHAR)").unwrap();
+    engine.execute("INSERT INTO codes VALUES ('a%b'), ('axb'), ('a_b'), ('a\\b'), ('100%'), ('1000')").unwrap();
+    let matches = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|mut r| r.remove(0)).collect()
+    };
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+
+    assert_eq!(matches("SELECT c FROM codes WHERE c LIKE 'a\\%b' ESCAPE '\\'", &mut engine), vec![s("a%b")]);
+    assert_eq!(matches("SELECT c FROM codes WHERE c LIKE 'a\\_b' ESCAPE '\\'", &mut engine), vec![s("a_b")]);
+    assert_eq!(matches("SELECT c FROM codes WHERE c LIKE 'a\\\\b' ESCAPE '\\'", &mut engine), vec![s("a\\b")]);
+    assert_eq!(matches("SELECT c FROM codes WHERE c LIKE '%\\%' ESCAPE '\\'", &mut engine), vec![s("100%")]);
+    // Without ESCAPE the same patterns keep their wildcards.
+    assert_eq!(matches("SELECT c FROM codes WHERE c LIKE 'a_b'", &mut engine).len(), 4);
+}
+
+#[test]
+fn test_regexp_functions_and_operators() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE contacts (name VARCHAR, phone VARCHAR)").unwrap();
+    engine.execute("INSERT INTO contacts VALUES ('ann', '555-1234'), ('bob', 'n/a'), ('cy', NULL), ('dee', 'x555-9876')").unwrap();
+    let row = |sql: &str, engine: &mut SqlEngine| engine.execute(sql).unwrap().rows.remove(0);
+    let names = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|mut r| r.remove(0)).collect()
+    };
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+    let b = ScalarValue::Boolean;
+
+    // Anchored pattern: only ann's phone starts with the digits.
+    assert_eq!(names("SELECT name FROM contacts WHERE REGEXP_LIKE(phone, '^\\d{3}-\\d{4}$')", &mut engine), vec![s("ann")]);
+    assert_eq!(names("SELECT name FROM contacts WHERE REGEXP_LIKE(phone, '\\d{3}-\\d{4}')", &mut engine), vec![s("ann"), s("dee")]);
+    assert_eq!(names("SELECT name FROM contacts WHERE phone ~ '^\\d'", &mut engine), vec![s("ann")]);
+    // NULL phones match neither ~ nor !~.
+    assert_eq!(names("SELECT name FROM contacts WHERE phone !~ '^\\d'", &mut engine), vec![s("bob"), s("dee")]);
+
+    // Capture groups in the replacement.
+    assert_eq!(
+        row("SELECT REGEXP_REPLACE('555-1234', '(\\d+)-(\\d+)', '\\2-\\1')", &mut engine),
+        vec![s("1234-555")]
+    );
+    assert_eq!(
+        row("SELECT REGEXP_SUBSTR('order 42 of 99', '\\d+'), REGEXP_SUBSTR('none', '\\d+')", &mut engine),
+        vec![s("42"), ScalarValue::Null]
+    );
+
+    // Invalid patterns give NULL instead of failing the query.
+    assert_eq!(
+        row("SELECT REGEXP_LIKE('a', '(a'), REGEXP_SUBSTR('a', '[a'), REGEXP_REPLACE('a', 'a)', 'b')", &mut engine),
+        vec![ScalarValue::Null, ScalarValue::Null, ScalarValue::Null]
+    );
+    // So do patterns over the size limit.
+    assert_eq!(
+        row("SELECT REGEXP_LIKE('a', '(((a{1,100}){1,100}){1,100}){1,100}'), 'a' ~ 'a{1001}'", &mut engine),
+        vec![ScalarValue::Null, ScalarValue::Null]
+    );
+    assert_eq!(row("SELECT 'abc' ~ 'b', 'abc' !~ 'b'", &mut engine), vec![b(true), b(false)]);
+
+    // Negated escapes work inside brackets too.
+    assert_eq!(
+        row("SELECT REGEXP_LIKE('abc', '^[\\D]+$'), REGEXP_LIKE('ab1', '^[\\D]+$'), \
+             REGEXP_LIKE('a b', '^[\\S\\s]+$'), REGEXP_LIKE('a_b', '[\\W]'), REGEXP_LIKE('é-1', '^[\\W\\d]+$')", &mut engine),
+        vec![b(true), b(false), b(true), b(false), b(true)]
+    );
+    // Deeply nested groups are an invalid pattern, not a stack overflow.
+    let deep = format!("SELECT REGEXP_LIKE('a', '{}a{}'), REGEXP_LIKE('a', 'a{}')",
+        "(".repeat(20000), ")".repeat(20000), "?".repeat(20000));
+    assert_eq!(row(&deep, &mut engine), vec![ScalarValue::Null, ScalarValue::Null]);
+}
+
+#[test]
+fn test_pivot_clause() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE sales (product VARCHAR, region VARCHAR, amount INTEGER)").unwrap();
+    engine.execute("INSERT INTO sales VALUES \
+        ('pen', 'East', 10), ('pen', 'West', 4), ('pen', 'East', 6), \
+        ('ink', 'West', 8), ('ink', 'North', 1), ('cap', 'East', NULL)").unwrap();
+    let i = ScalarValue::Int64;
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+    let null = ScalarValue::Null;
+
+    let r = engine.execute("SELECT * FROM sales PIVOT (SUM(amount) FOR region IN ('East', 'West')) ORDER BY product").unwrap();
+    assert_eq!(r.columns, vec!["product", "East", "West"]);
+    assert_eq!(r.rows, vec![
+        vec![s("cap"), null.clone(), null.clone()],
+        vec![s("ink"), null.clone(), i(8)],
+        vec![s("pen"), i(16), i(4)],
+    ]);
+
+    // The aggregate is honored; COUNT(*) reads no column, so every other
+    // source column still groups.
+    let r = engine.execute("SELECT product, e, w FROM (SELECT product, region FROM sales) \
+        PIVOT (COUNT(*) FOR region IN ('East' AS e, 'West' AS w)) p WHERE p.product = 'pen'").unwrap();
+    assert_eq!(r.rows, vec![vec![s("pen"), i(2), i(1)]]);
+    let r = engine.execute("SELECT * FROM sales PIVOT (AVG(amount) FOR region IN ('East')) WHERE product = 'pen'").unwrap();
+    assert_eq!(r.rows, vec![vec![s("pen"), ScalarValue::Float64(8.0)]]);
+
+    // Only the remaining columns group: without product there is one row.
+    let r = engine.execute("SELECT * FROM (SELECT region, amount FROM sales) t \
+        PIVOT (MAX(t.amount) FOR region IN ('East', 'West', 'South'))").unwrap();
+    assert_eq!(r.columns, vec!["East", "West", "South"]);
+    assert_eq!(r.rows, vec![vec![i(10), i(8), null.clone()]]);
+
+    assert!(engine.execute("SELECT * FROM sales PIVOT (UPPER(product) FOR region IN ('East'))").is_err());
+    assert!(engine.execute("SELECT * FROM sales PIVOT (ROUND(SUM(amount), 0) FOR region IN ('East', 'West'))").is_err());
+
+    // Scalar functions over aggregates see the aggregated values with their own types.
+    let r = engine.execute("SELECT product, COALESCE(MAX(amount), 0), GREATEST(MIN(amount), 5), ROUND(AVG(amount), 1) \
+        FROM sales GROUP BY product ORDER BY product").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![s("cap"), i(0), i(5), null],
+        vec![s("ink"), i(8), i(5), ScalarValue::Float64(4.5)],
+        vec![s("pen"), i(10), i(5), ScalarValue::Float64(6.7)],
+    ]);
+    assert!(engine.execute("SELECT * FROM sales PIVOT (SUM(amount) FOR missing IN ('East'))").is_err());
+}
+
+#[test]
+fn test_unpivot_clause() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE wide (id INTEGER, jan INTEGER, feb INTEGER, mar INTEGER)").unwrap();
+    engine.execute("INSERT INTO wide VALUES (1, 10, 20, 30), (2, 5, NULL, 7)").unwrap();
+    let i = ScalarValue::Int64;
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+
+    let r = engine.execute("SELECT * FROM wide UNPIVOT (value FOR metric IN (jan, feb, mar))").unwrap();
+    assert_eq!(r.columns, vec!["id", "metric", "value"]);
+    assert_eq!(r.rows, vec![
+        vec![i(1), s("jan"), i(10)],
+        vec![i(1), s("feb"), i(20)],
+        vec![i(1), s("mar"), i(30)],
+        vec![i(2), s("jan"), i(5)],
+        vec![i(2), s("mar"), i(7)],
+    ]);
+
+    // INCLUDE NULLS keeps the missing value; labels rename the metrics, and
+    // the result can be filtered and aggregated like any table.
+    let r = engine.execute("SELECT u.metric, u.value FROM wide \
+        UNPIVOT INCLUDE NULLS (value FOR metric IN (jan AS 'January', feb AS 'February')) u WHERE u.id = 2").unwrap();
+    assert_eq!(r.rows, vec![vec![s("January"), i(5)], vec![s("February"), ScalarValue::Null]]);
+    let r = engine.execute("SELECT metric, SUM(value) FROM wide UNPIVOT (value FOR metric IN (jan, mar)) \
+        GROUP BY metric ORDER BY metric").unwrap();
+    assert_eq!(r.rows, vec![vec![s("jan"), i(15)], vec![s("mar"), i(37)]]);
+
+    assert!(engine.execute("SELECT * FROM wide UNPIVOT (value FOR metric IN (apr))").is_err());
+    assert!(engine.execute("SELECT * FROM wide UNPIVOT (id FOR metric IN (jan))").is_err());
+    assert!(engine.execute("SELECT * FROM wide UNPIVOT (value FOR ID IN (jan))").is_err());
+    assert!(engine.execute("SELECT * FROM wide UNPIVOT (v FOR v IN (jan))").is_err());
+
+    // Mixed value types are all cast to the reported column type.
+    engine.execute("CREATE TABLE mixed (id INTEGER, n INTEGER, x DOUBLE, label VARCHAR)").unwrap();
+    engine.execute("INSERT INTO mixed VALUES (1, 2, 2.5, 'a')").unwrap();
+    let r = engine.execute("SELECT value FROM mixed UNPIVOT (value FOR k IN (n, x))").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Float64(2.0)], vec![ScalarValue::Float64(2.5)]]);
+    let r = engine.execute("SELECT value FROM mixed UNPIVOT (value FOR k IN (n, label))").unwrap();
+    assert_eq!(r.rows, vec![vec![s("2")], vec![s("a")]]);
+}
+
+#[test]
+fn test_percentile_aggregates_and_median() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE pay (dept VARCHAR, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO pay VALUES ('a', 30), ('a', 10), ('a', 20), \
+        ('b', 40), ('b', 10), ('b', 30), ('b', 20), ('b', NULL), ('c', NULL)").unwrap();
+    let f = ScalarValue::Float64;
+    let i = ScalarValue::Int64;
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+    let null = ScalarValue::Null;
+
+    // Odd group: the middle value; even group: halfway between the middle two.
+    let r = engine.execute("SELECT dept, MEDIAN(salary), PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY salary), \
+        PERCENTILE_DISC(0.5) WITHIN GROUP (ORDER BY salary) FROM pay GROUP BY dept ORDER BY dept").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![s("a"), f(20.0), f(20.0), i(20)],
+        vec![s("b"), f(25.0), f(25.0), i(20)],
+        vec![s("c"), null.clone(), null.clone(), null.clone()],
+    ]);
+
+    // Interpolation between neighbours, and DISC returning an actual value.
+    let r = engine.execute("SELECT PERCENTILE_CONT(0.25) WITHIN GROUP (ORDER BY salary), \
+        PERCENTILE_DISC(0.9) WITHIN GROUP (ORDER BY salary), \
+        PERCENTILE_CONT(0.25) WITHIN GROUP (ORDER BY salary DESC) FROM pay WHERE dept = 'b'").unwrap();
+    assert_eq!(r.rows, vec![vec![f(17.5), i(40), f(32.5)]]);
+
+    assert!(engine.execute("SELECT PERCENTILE_CONT(0.5) FROM pay").is_err());
+    assert!(engine.execute("SELECT PERCENTILE_CONT(1.5) WITHIN GROUP (ORDER BY salary) FROM pay").is_err());
+}
+
+#[test]
+fn test_string_agg_order_by_and_distinct() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE staff (name VARCHAR, dept VARCHAR, age INTEGER)").unwrap();
+    engine.execute("INSERT INTO staff VALUES ('cy', 'ops', 40), ('ann', 'dev', 30), \
+        ('bo', 'dev', 25), ('di', 'ops', NULL), ('ed', 'dev', 35)").unwrap();
+    let row = |sql: &str, engine: &mut SqlEngine| engine.execute(sql).unwrap().rows.remove(0);
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+
+    assert_eq!(row("SELECT STRING_AGG(name, ',' ORDER BY name) FROM staff", &mut engine), vec![s("ann,bo,cy,di,ed")]);
+    assert_eq!(row("SELECT STRING_AGG(name, ',' ORDER BY name DESC) FROM staff", &mut engine), vec![s("ed,di,cy,bo,ann")]);
+    // The key need not be the aggregated value; NULLs sort as in ORDER BY.
+    assert_eq!(
+        row("SELECT STRING_AGG(name, ',' ORDER BY age NULLS FIRST), ARRAY_AGG(name ORDER BY dept, age DESC) FROM staff", &mut engine),
+        vec![s("di,bo,ann,ed,cy"), ScalarValue::Array(vec![s("ed"), s("ann"), s("bo"), s("di"), s("cy")])]
+    );
+    assert_eq!(
+        row("SELECT LISTAGG(name, '/') WITHIN GROUP (ORDER BY name) FROM staff WHERE dept = 'ops'", &mut engine),
+        vec![s("cy/di")]
+    );
+
+    // DISTINCT drops repeated values before ordering.
+    assert_eq!(row("SELECT STRING_AGG(DISTINCT dept, ',') FROM staff", &mut engine), vec![s("ops,dev")]);
+    assert_eq!(row("SELECT STRING_AGG(DISTINCT dept, ',' ORDER BY dept) FROM staff", &mut engine), vec![s("dev,ops")]);
+
+    let r = engine.execute("SELECT dept, STRING_AGG(name, ';' ORDER BY age) FROM staff GROUP BY dept ORDER BY dept").unwrap();
+    assert_eq!(r.rows, vec![vec![s("dev"), s("bo;ann;ed")], vec![s("ops"), s("cy;di")]]);
+    assert!(engine.execute("SELECT STRING_AGG(name, ',' ORDER BY name) WITHIN GROUP (ORDER BY age) FROM staff").is_err());
+    // Only the functions that combine their inputs in order take one.
+    assert!(engine.execute("SELECT SUM(age ORDER BY name) FROM staff").is_err());
+    assert!(engine.execute("SELECT UPPER(name ORDER BY age) FROM staff").is_err());
+}
+
+#[test]
+fn test_aggregate_filter_clause() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE emp (dept VARCHAR, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO emp VALUES ('eng', 90000), ('eng', 70000), ('eng', 85000), \
+        ('ops', 60000), ('ops', NULL), ('hr', 50000)").unwrap();
+    let i = ScalarValue::Int64;
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+    let null = ScalarValue::Null;
+
+    let r = engine.execute("SELECT dept, COUNT(*), COUNT(*) FILTER (WHERE salary > 80000), \
+        SUM(salary) FILTER (WHERE salary < 88000), MAX(salary) FILTER (WHERE dept <> 'eng') \
+        FROM emp GROUP BY dept ORDER BY dept").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![s("eng"), i(3), i(2), i(155000), null.clone()],
+        vec![s("hr"), i(1), i(0), i(50000), i(50000)],
+        vec![s("ops"), i(2), i(0), i(60000), i(60000)],
+    ]);
+
+    // NULL predicates exclude the row; FILTER applies before DISTINCT.
+    let r = engine.execute("SELECT COUNT(*) FILTER (WHERE salary IS NULL), \
+        COUNT(DISTINCT dept) FILTER (WHERE salary >= 60000), \
+        STRING_AGG(dept, ',' ORDER BY dept) FILTER (WHERE salary > 80000) FROM emp").unwrap();
+    assert_eq!(r.rows, vec![vec![i(1), i(2), s("eng,eng")]]);
+
+    // Aggregate window functions honor it too.
+    let r = engine.execute("SELECT dept, salary, COUNT(*) FILTER (WHERE salary > 80000) OVER (PARTITION BY dept) \
+        FROM emp WHERE dept = 'eng'").unwrap();
+    assert!(r.rows.iter().all(|row| row[2] == i(2)));
+    let r = engine.execute("SELECT dept FROM emp GROUP BY dept HAVING COUNT(*) FILTER (WHERE salary > 0) > 1 ORDER BY dept").unwrap();
+    assert_eq!(r.rows, vec![vec![s("eng")]]);
+
+    // Only aggregates take FILTER, and its predicate is evaluated per row.
+    assert!(engine.execute("SELECT UPPER(dept) FILTER (WHERE salary > 2) FROM emp").is_err());
+    assert!(engine.execute("SELECT SUM(salary) FILTER (WHERE SUM(salary) > 1) FROM emp").is_err());
+    assert!(engine.execute("SELECT COUNT(*) FILTER (WHERE MAX(salary) > 1) FROM emp").is_err());
+    let r = engine.execute("SELECT COUNT(*) FILTER (WHERE salary = (SELECT MAX(salary) FROM emp)) FROM emp").unwrap();
+    assert_eq!(r.rows, vec![vec![i(1)]]);
+}
+
+#[test]
+fn test_array_agg_and_array_functions() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE addrs (city VARCHAR, street VARCHAR, n INTEGER)").unwrap();
+    engine.execute("INSERT INTO addrs VALUES ('oslo', 'Main St, 4', 1), ('oslo', 'Elm, Apt 2, Rear', 2), \
+        ('oslo', NULL, 3), ('rome', 'Via Roma', 4)").unwrap();
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+
+    // Elements containing commas stay whole; NULL elements are kept.
+    let result = engine.execute(
+        "SELECT city, ARRAY_LENGTH(ARRAY_AGG(street ORDER BY n)), ARRAY_AGG(street ORDER BY n) \
+         FROM addrs GROUP BY city ORDER BY city",
+    ).unwrap();
+    assert_eq!(result.rows[0], vec![
+        s("oslo"),
+        ScalarValue::Int64(3),
+        ScalarValue::Array(vec![s("Main St, 4"), s("Elm, Apt 2, Rear"), ScalarValue::Null]),
+    ]);
+    assert_eq!(result.rows[1], vec![s("rome"), ScalarValue::Int64(1), ScalarValue::Array(vec![s("Via Roma")])]);
+
+    let row = |sql: &str, engine: &mut SqlEngine| engine.execute(sql).unwrap().rows.remove(0);
+    // Subscripts are 1-based; out of range is NULL.
+    assert_eq!(
+        row("SELECT (ARRAY_AGG(street ORDER BY n))[2], (ARRAY_AGG(street ORDER BY n))[9] FROM addrs", &mut engine),
+        vec![s("Elm, Apt 2, Rear"), ScalarValue::Null]
+    );
+    assert_eq!(
+        row("SELECT ARRAY_CONTAINS(ARRAY_AGG(street), 'Main St, 4'), ARRAY_CONTAINS(ARRAY_AGG(street), 'Main St') FROM addrs", &mut engine),
+        vec![ScalarValue::Boolean(true), ScalarValue::Boolean(false)]
+    );
+    assert_eq!(
+        row("SELECT ARRAY[1, 2, 3][3], ARRAY_LENGTH(ARRAY[]), ARRAY[1, 2] = ARRAY[1, 2], ARRAY[1, 2] < ARRAY[1, 3]", &mut engine),
+        vec![ScalarValue::Int64(3), ScalarValue::Int64(0), ScalarValue::Boolean(true), ScalarValue::Boolean(true)]
+    );
+    assert_eq!(row("SELECT CAST(ARRAY['a', 'b'] AS VARCHAR)", &mut engine), vec![s("[a, b]")]);
+
+    // Stored into a table, an array becomes its text form.
+    engine.execute("CREATE TABLE lists (v VARCHAR)").unwrap();
+    engine.execute("INSERT INTO lists SELECT ARRAY_AGG(n ORDER BY n) FROM addrs").unwrap();
+    assert_eq!(row("SELECT v FROM lists", &mut engine), vec![s("[1, 2, 3, 4]")]);
+}
+
+#[test]
+fn test_interval_arithmetic() {
+    use pivot_engine::column::{IntervalValue, ScalarValue};
+    let mut engine = SqlEngine::new();
+    let text = |sql: &str, engine: &mut SqlEngine| {
+        engine.execute(sql).unwrap().rows.remove(0).iter().map(|v| v.to_string()).collect::<Vec<_>>()
+    };
+
+    // Adding months clamps to the last day of the target month.
+    assert_eq!(
+        text("SELECT DATE '2021-01-31' + INTERVAL '1' MONTH, DATE '2020-01-31' + INTERVAL '1' MONTH, \
+              DATE '2020-02-29' + INTERVAL '1' YEAR, DATE '2020-03-31' - INTERVAL '1' MONTH", &mut engine),
+        vec!["2021-02-28", "2020-02-29", "2021-02-28", "2020-02-29"]
+    );
+    assert_eq!(
+        text("SELECT INTERVAL '3' DAY + DATE '2021-12-30', DATE '2020-01-01' + INTERVAL '36' HOUR", &mut engine),
+        vec!["2022-01-02", "2020-01-02 12:00:00"]
+    );
+    assert_eq!(
+        text("SELECT TIMESTAMP '2020-03-31 10:00:00' - INTERVAL '1 month 1 hour', \
+              TIME '23:30:00' + INTERVAL '45' MINUTE", &mut engine),
+        vec!["2020-02-29 09:00:00", "00:15:00"]
+    );
+
+    // Month overflow folds into years.
+    let r = engine.execute("SELECT INTERVAL '10' MONTH + INTERVAL '5' MONTH, INTERVAL '1' YEAR - INTERVAL '2 days'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Interval(IntervalValue::new(1, 3, 0, 0)));
+    assert_eq!(r.rows[0][1], ScalarValue::Interval(IntervalValue::new(1, 0, -2, 0)));
+
+    // Subtracting dates gives a day count.
+    let r = engine.execute("SELECT DATE '2020-03-01' - DATE '2020-02-01', DATE '2020-01-01' - DATE '2021-01-01'").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(29), ScalarValue::Int64(-366)]);
+
+    // Results that don't fit are errors, not panics.
+    for sql in [
+        "SELECT TIMESTAMP '2020-01-01 00:00:00' + INTERVAL '1000000 years'",
+        "SELECT INTERVAL '2000000000 years' + INTERVAL '2000000000 years'",
+        "SELECT INTERVAL '-2000000000 days' - INTERVAL '2000000000 days'",
+        "SELECT TIMESTAMP '2020-01-01 00:00:00' + INTERVAL '9223000000000000000 micros'",
+    ] {
+        let err = engine.execute(sql).unwrap_err();
+        assert!(err.to_string().contains("out of range"), "{}: {}", sql, err);
+    }
+}
+
+#[test]
+fn test_interval_comparison() {
+    use pivot_engine::column::{IntervalValue, ScalarValue};
+    let mut engine = SqlEngine::new();
+    let b = ScalarValue::Boolean;
+
+    // A month counts as 30 days and a day as 24 hours.
+    let r = engine.execute("SELECT INTERVAL '1' DAY < INTERVAL '2' DAY, INTERVAL '1' MONTH > INTERVAL '29' DAY, \
+        INTERVAL '1' MONTH = INTERVAL '30' DAY, INTERVAL '25' HOUR >= INTERVAL '1' DAY, INTERVAL '1' YEAR > INTERVAL '361' DAY").unwrap();
+    assert_eq!(r.rows[0], vec![b(true), b(true), b(true), b(true), b(false)]);
+
+    engine.execute("CREATE TABLE spans (id INTEGER, t1 TIMESTAMP, t2 TIMESTAMP)").unwrap();
+    engine.execute("INSERT INTO spans VALUES (1, '2024-01-01 00:00:00', '2024-01-01 12:00:00'), \
+        (2, '2024-01-01 00:00:00', '2024-01-03 06:00:00'), (3, '2024-01-01 00:00:00', '2024-01-02 00:00:01')").unwrap();
+    let r = engine.execute("SELECT id FROM spans WHERE t2 - t1 > INTERVAL '1' DAY ORDER BY t2 - t1 DESC").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(2)], vec![ScalarValue::Int64(3)]]);
+    let r = engine.execute("SELECT MAX(t2 - t1), MIN(t2 - t1) FROM spans").unwrap();
+    assert_eq!(r.rows[0], vec![
+        ScalarValue::Interval(IntervalValue::new(0, 0, 0, 54 * 3_600_000_000)),
+        ScalarValue::Interval(IntervalValue::new(0, 0, 0, 12 * 3_600_000_000)),
+    ]);
+
+    // Hashing agrees with equality, so equal-length intervals join and group together.
+    engine.execute("CREATE TABLE terms (id INTEGER, span INTERVAL)").unwrap();
+    engine.execute("INSERT INTO terms VALUES (1, INTERVAL '1' MONTH), (2, INTERVAL '30' DAY), \
+        (3, INTERVAL '720' HOUR), (4, INTERVAL '2' DAY)").unwrap();
+    let r = engine.execute("SELECT COUNT(*) FROM terms a JOIN terms b ON a.span = b.span").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(10)]);
+    let r = engine.execute("SELECT COUNT(*) FROM terms GROUP BY span ORDER BY COUNT(*)").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Int64(1)], vec![ScalarValue::Int64(3)]]);
+    let r = engine.execute("SELECT COUNT(DISTINCT span), (SELECT COUNT(*) FROM (SELECT DISTINCT span FROM terms) d) FROM terms").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(2), ScalarValue::Int64(2)]);
+}
+
+#[test]
+fn test_date_and_timestamp_column_subtraction() {
+    use pivot_engine::column::{IntervalValue, ScalarValue};
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE stays (arrived DATE, left_at DATE, checkin TIMESTAMP, checkout TIMESTAMP)").unwrap();
+    engine.execute("INSERT INTO stays VALUES ('2024-02-27', '2024-03-02', '2024-02-27 15:00:00', '2024-03-02 10:30:00.25')").unwrap();
+
+    let r = engine.execute("SELECT left_at - arrived, arrived - left_at FROM stays").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(4), ScalarValue::Int64(-4)]);
+
+    let micros = (3 * 86_400 + 19 * 3_600 + 30 * 60) * 1_000_000 + 250_000;
+    let r = engine.execute("SELECT checkout - checkin, checkin - checkout FROM stays").unwrap();
+    assert_eq!(r.rows[0], vec![
+        ScalarValue::Interval(IntervalValue::new(0, 0, 0, micros)),
+        ScalarValue::Interval(IntervalValue::new(0, 0, 0, -micros)),
+    ]);
+
+    // A date on either side of a timestamp counts as midnight.
+    let r = engine.execute("SELECT checkin - arrived, left_at - checkout FROM stays").unwrap();
+    assert_eq!(r.rows[0], vec![
+        ScalarValue::Interval(IntervalValue::new(0, 0, 0, 15 * 3_600_000_000)),
+        ScalarValue::Interval(IntervalValue::new(0, 0, 0, -(10 * 3_600_000_000 + 30 * 60_000_000 + 250_000))),
+    ]);
+}
+
+#[test]
+fn test_fixed_clock_for_current_date_and_now() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    // 2024-02-29 13:45:30 UTC
+    let now = 1_709_214_330_000_000;
+    engine.set_now(Some(now));
+
+    let r = engine.execute("SELECT CURRENT_DATE, YEAR(NOW()), NOW(), CURRENT_TIMESTAMP, CURRENT_TIME").unwrap();
+    assert_eq!(r.rows[0][0].to_string(), "2024-02-29");
+    assert_eq!(r.rows[0][1], ScalarValue::Int64(2024));
+    assert_eq!(r.rows[0][2], ScalarValue::Timestamp(now));
+    assert_eq!(r.rows[0][3], ScalarValue::Timestamp(now));
+    assert_eq!(r.rows[0][4].to_string(), "13:45:30");
+
+    let r = engine.execute("SELECT CURRENT_DATE - DATE '2024-01-01'").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Int64(59));
+
+    // Without an override the system clock is used.
+    engine.set_now(None);
+    let r = engine.execute("SELECT YEAR(NOW()) >= 2024").unwrap();
+    assert_eq!(r.rows[0][0], ScalarValue::Boolean(true));
+
+    // The clock is read once per statement: every row sees the same instant.
+    engine.execute("CREATE TABLE ticks (n INTEGER)").unwrap();
+    let values = (0..2000).map(|i| format!("({})", i)).collect::<Vec<_>>().join(", ");
+    engine.execute(&format!("INSERT INTO ticks VALUES {}", values)).unwrap();
+    let r = engine.execute("SELECT COUNT(DISTINCT NOW()), COUNT(DISTINCT CURRENT_TIME), MIN(NOW()) = MAX(NOW()) FROM ticks").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Int64(1), ScalarValue::Int64(1), ScalarValue::Boolean(true)]);
+}
+
+#[test]
+fn test_strftime_format_specifiers() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let s = |v: &str| ScalarValue::Utf8(v.to_string());
+    let row = |sql: &str, engine: &mut SqlEngine| engine.execute(sql).unwrap().rows.remove(0);
+
+    assert_eq!(
+        row("SELECT STRFTIME('%Y/%m/%d', DATE '2024-03-05'), STRFTIME(DATE '2024-03-05', '%d.%m.%Y')", &mut engine),
+        vec![s("2024/03/05"), s("05.03.2024")]
+    );
+    assert_eq!(
+        row("SELECT STRFTIME('%H:%M', TIMESTAMP '2024-03-05 07:09:30'), \
+             STRFTIME('%I:%M:%S %p', TIMESTAMP '2024-03-05 19:09:30')", &mut engine),
+        vec![s("07:09"), s("07:09:30 PM")]
+    );
+    assert_eq!(
+        row("SELECT STRFTIME('%A %d %B, day %j', DATE '2024-12-31')", &mut engine),
+        vec![s("Tuesday 31 December, day 366")]
+    );
+    // Unknown specifiers pass through; %% is a literal percent sign.
+    assert_eq!(row("SELECT STRFTIME('%Q %Y%% %', DATE '2024-01-01')", &mut engine), vec![s("%Q 2024% %")]);
+}
+
+#[test]
+fn test_to_date_and_to_timestamp_with_format() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let row = |sql: &str, engine: &mut SqlEngine| engine.execute(sql).unwrap().rows.remove(0);
+    let text = |vals: Vec<ScalarValue>| vals.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(
+        text(row("SELECT TO_DATE('01/31/2020', 'MM/DD/YYYY'), TO_DATE('7/4/1999', 'MM/DD/YYYY'), \
+                  TO_DATE('05-mar-2021', 'DD-Mon-YYYY'), TO_DATE('9 September 2010', 'DD Month YYYY')", &mut engine)),
+        vec!["2020-01-31", "1999-07-04", "2021-03-05", "2010-09-09"]
+    );
+    assert_eq!(
+        text(row("SELECT TO_TIMESTAMP('2020-01-31 14:05:09', 'YYYY-MM-DD HH24:MI:SS'), \
+                  TO_TIMESTAMP('31/01/2020 02:05 PM', 'DD/MM/YYYY HH:MI AM'), TO_TIMESTAMP(1609459200)", &mut engine)),
+        vec!["2020-01-31 14:05:09", "2020-01-31 14:05:00", "2021-01-01 00:00:00"]
+    );
+
+    // Text that doesn't fit the format, or names no real date, is NULL.
+    assert_eq!(
+        row("SELECT TO_DATE('2020-01-31', 'MM/DD/YYYY'), TO_DATE('02/30/2020', 'MM/DD/YYYY'), \
+             TO_DATE('01/31/2020 x', 'MM/DD/YYYY'), TO_DATE('31-Foo-2020', 'DD-Mon-YYYY'), \
+             TO_TIMESTAMP('2020-01-01 25:00', 'YYYY-MM-DD HH24:MI')", &mut engine),
+        vec![ScalarValue::Null; 5]
+    );
+    // So are epoch seconds past the timestamp range.
+    assert_eq!(
+        row("SELECT TO_TIMESTAMP(92233720368547), TO_TIMESTAMP(-92233720368547), TO_TIMESTAMP(1e300)", &mut engine),
+        vec![ScalarValue::Null; 3]
+    );
+    // Without a format the text must be ISO.
+    assert_eq!(text(row("SELECT TO_DATE('2020-01-31')", &mut engine)), vec!["2020-01-31"]);
+}
+
+#[test]
+fn test_date_add_and_diff_by_calendar() {
+    let mut engine = SqlEngine::new();
+    let text = |sql: &str, engine: &mut SqlEngine| {
+        engine.execute(sql).unwrap().rows.remove(0).iter().map(|v| v.to_string()).collect::<Vec<_>>()
+    };
+
+    // Month and year steps clamp to the end of the target month.
+    assert_eq!(
+        text("SELECT DATE_ADD('month', 1, DATE '2021-01-31'), DATE_ADD('month', 1, DATE '2024-01-31'), \
+              DATE_ADD('month', 2, DATE '2023-11-30'), DATE_ADD('month', -3, DATE '2024-02-15'), \
+              DATEADD('year', 1, DATE '2024-02-29')", &mut engine),
+        vec!["2021-02-28", "2024-02-29", "2024-01-30", "2023-11-15", "2025-02-28"]
+    );
+    assert_eq!(
+        text("SELECT DATE_ADD(DATE '2024-12-31', INTERVAL '2' MONTH), DATE_ADD(DATE '2024-12-31', 1), \
+              DATE_SUB(DATE '2024-03-31', INTERVAL '1' MONTH), DATE_SUB(DATE '2025-02-28', INTERVAL '1' YEAR), \
+              DATE_ADD('hour', 30, TIMESTAMP '2024-12-31 12:00:00')", &mut engine),
+        vec!["2025-02-28", "2025-01-01", "2024-02-29", "2024-02-28", "2025-01-01 18:00:00"]
+    );
+
+    // Counts too large for the interval, or results out of range, give NULL.
+    assert_eq!(
+        text("SELECT DATE_ADD('day', 5000000000, DATE '2020-01-01'), DATE_ADD('week', 400000000, DATE '2020-01-01'), \
+              DATE_ADD(DATE '2020-01-01', 5000000000), DATE_ADD('hour', 5000000000, TIMESTAMP '2020-01-01 00:00:00'), \
+              DATE_ADD('second', 86400, TIMESTAMP '2020-01-01 00:00:00')", &mut engine),
+        vec!["NULL", "NULL", "NULL", "NULL", "2020-01-02 00:00:00"]
+    );
+
+    // Month and year differences count calendar boundaries.
+    assert_eq!(
+        text("SELECT DATEDIFF('month', DATE '2023-12-31', DATE '2024-01-01'), \
+              DATEDIFF('month', DATE '2023-01-15', DATE '2024-03-01'), \
+              DATE_DIFF('year', DATE '2023-12-31', DATE '2024-01-01'), \
+              DATEDIFF('month', DATE '2024-03-01', DATE '2024-01-31'), \
+              DATEDIFF('day', DATE '2024-02-01', DATE '2024-03-01')", &mut engine),
+        vec!["1", "14", "1", "-2", "29"]
+    );
+}
+
+#[test]
+fn test_trigonometric_functions() {
+    use pivot_engine::column::ScalarValue;
+    use std::f64::consts::PI;
+    let mut engine = SqlEngine::new();
+    let r = engine.execute(
+        "SELECT DEGREES(PI()), RADIANS(180), ATAN2(1, 1), ASIN(1), ACOS(0), ATAN(1.0), \
+         SINH(0), COSH(0), TANH(0), ATAN2(0, -1)",
+    ).unwrap();
+    let expected = [180.0, PI, PI / 4.0, PI / 2.0, PI / 2.0, PI / 4.0, 0.0, 1.0, 0.0, PI];
+    for (v, want) in r.rows[0].iter().zip(expected) {
+        match v {
+            ScalarValue::Float64(got) => assert!((got - want).abs() < 1e-12, "{} != {}", got, want),
+            other => panic!("expected a DOUBLE, got {:?}", other),
+        }
+    }
+    let r = engine.execute("SELECT ASIN(NULL), ATAN2(1, NULL), DEGREES('x')").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Null; 3]);
+}
+
+#[test]
+fn test_integer_math_and_bitwise_functions() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    let ints = |vals: &[i64]| vals.iter().map(|v| ScalarValue::Int64(*v)).collect::<Vec<_>>();
+
+    let r = engine.execute("SELECT GCD(12, 18), GCD(-12, 18), GCD(0, 0), LCM(4, 6), LCM(-3, 5), LCM(0, 7)").unwrap();
+    assert_eq!(r.rows[0], ints(&[6, 6, 0, 12, 15, 0]));
+
+    let r = engine.execute("SELECT FACTORIAL(5), FACTORIAL(0), FACTORIAL(20)").unwrap();
+    assert_eq!(r.rows[0], ints(&[120, 1, 2_432_902_008_176_640_000]));
+    let r = engine.execute("SELECT FACTORIAL(21), FACTORIAL(-1), LCM(9223372036854775807, 2)").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Null; 3]);
+
+    let r = engine.execute(
+        "SELECT BITAND(6, 3), BITOR(6, 3), BITXOR(6, 3), BITNOT(0), SHIFTLEFT(1, 10), SHIFTRIGHT(-16, 2)",
+    ).unwrap();
+    assert_eq!(r.rows[0], ints(&[2, 7, 5, -1, 1024, -4]));
+    let r = engine.execute("SELECT SHIFTLEFT(1, 64), BITAND(1, NULL), GCD(1.5, 3)").unwrap();
+    assert_eq!(r.rows[0], vec![ScalarValue::Null; 3]);
+}
+
+#[test]
+fn test_window_count_distinct() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE visits (id INTEGER, site VARCHAR, visitor VARCHAR)").unwrap();
+    engine.execute("INSERT INTO visits VALUES (1, 'a', 'ann'), (2, 'a', 'bo'), (3, 'a', 'ann'), \
+        (4, 'a', NULL), (5, 'b', 'cy'), (6, 'b', 'cy')").unwrap();
+    let ints = |vals: &[i64]| vals.iter().map(|v| ScalarValue::Int64(*v)).collect::<Vec<_>>();
+
+    let r = engine.execute(
+        "SELECT id, site, visitor, COUNT(DISTINCT visitor) OVER (PARTITION BY site), COUNT(visitor) OVER (PARTITION BY site) \
+         FROM visits ORDER BY id",
+    ).unwrap();
+    assert_eq!(r.rows.iter().map(|row| row[3].clone()).collect::<Vec<_>>(), ints(&[2, 2, 2, 2, 1, 1]));
+    assert_eq!(r.rows.iter().map(|row| row[4].clone()).collect::<Vec<_>>(), ints(&[3, 3, 3, 3, 2, 2]));
+
+    // Distinctness is taken within each row's frame.
+    let r = engine.execute(
+        "SELECT id, site, visitor, COUNT(DISTINCT visitor) OVER (PARTITION BY site ORDER BY id), \
+         COUNT(DISTINCT visitor) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) \
+         FROM visits ORDER BY id",
+    ).unwrap();
+    assert_eq!(r.rows.iter().map(|row| row[3].clone()).collect::<Vec<_>>(), ints(&[1, 2, 2, 2, 1, 1]));
+    assert_eq!(r.rows.iter().map(|row| row[4].clone()).collect::<Vec<_>>(), ints(&[1, 2, 2, 1, 1, 1]));
+}
+
+#[test]
+fn test_strict_functions_reject_unknown_names() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE words (id INTEGER, w VARCHAR)").unwrap();
+    engine.execute("INSERT INTO words VALUES (1, 'alpha'), (2, 'beta')").unwrap();
+
+    // Lenient by default: an unknown name evaluates to NULL.
+    let r = engine.execute("SELECT SUBSTRNG(w, 1, 2) FROM words ORDER BY id").unwrap();
+    assert_eq!(r.rows, vec![vec![ScalarValue::Null], vec![ScalarValue::Null]]);
+    let r = engine.execute("SELECT id, MYSTERY_RANK() OVER (ORDER BY id) FROM words ORDER BY id").unwrap();
+    assert_eq!(r.rows[0][1], ScalarValue::Null);
+
+    engine.set_strict_functions(true);
+    let err = engine.execute("SELECT SUBSTRNG(w, 1, 2) FROM words").unwrap_err();
+    assert!(err.to_string().contains("Unknown function 'SUBSTRNG'"), "{}", err);
+    let err = engine.execute("SELECT id, MYSTERY_RANK() OVER (ORDER BY id) FROM words").unwrap_err();
+    assert!(err.to_string().contains("Unknown window function 'MYSTERY_RANK'"), "{}", err);
+    // Known functions are unaffected.
+    let r = engine.execute("SELECT SUBSTRING(w, 1, 2), ROW_NUMBER() OVER (ORDER BY id) FROM words ORDER BY id").unwrap();
+    assert_eq!(r.rows[1], vec![ScalarValue::Utf8("be".to_string()), ScalarValue::Int64(2)]);
+}
+
+#[test]
+fn test_unknown_columns_in_window_and_order_keys_error() {
+    use pivot_engine::column::ScalarValue;
+    use pivot_engine::error::PivotError;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE t (id INTEGER, dept VARCHAR, sal INTEGER)").unwrap();
+    engine.execute("INSERT INTO t VALUES (1, 'a', 10), (2, 'a', 20), (3, 'b', 30), (4, 'b', 5)").unwrap();
+
+    for sql in [
+        "SELECT id FROM t ORDER BY nosuch",
+        "SELECT id, ROW_NUMBER() OVER (ORDER BY nosuch) FROM t",
+        "SELECT id, RANK() OVER (PARTITION BY nosuch ORDER BY id) FROM t",
+        "SELECT id, LAG(nosuch) OVER (ORDER BY id) FROM t",
+        "SELECT id, LAG(sal, nosuch) OVER (ORDER BY id) FROM t",
+        "SELECT id, NTH_VALUE(sal, nosuch) OVER (ORDER BY id) FROM t",
+        "SELECT COUNT(*) FROM t GROUP BY nosuch",
+    ] {
+        assert!(matches!(engine.execute(sql), Err(PivotError::ColumnNotFound(_))), "{}", sql);
+    }
+    for sql in ["SELECT id FROM t OFFSET -1", "SELECT id FROM t LIMIT 'x'", "SELECT id FROM t LIMIT 1 OFFSET 1.5"] {
+        assert!(matches!(engine.execute(sql), Err(PivotError::TypeError(_))), "{}", sql);
+    }
+    assert_eq!(engine.execute("SELECT id FROM t LIMIT NULL").unwrap().row_count(), 4);
+    assert_eq!(engine.execute("SELECT id FROM t LIMIT -1").unwrap().row_count(), 4);
+
+    // Window keys may name input columns the SELECT list drops.
+    let r = engine.execute(
+        "SELECT id, RANK() OVER (PARTITION BY dept ORDER BY sal DESC) FROM t ORDER BY id",
+    ).unwrap();
+    assert_eq!(r.rows.iter().map(|row| row[1].clone()).collect::<Vec<_>>(),
+        [2, 1, 1, 2].map(ScalarValue::Int64).to_vec());
+
+    // Over groups, windows see the group keys and the aggregates they read.
+    let r = engine.execute("SELECT SUM(sal), RANK() OVER (ORDER BY dept DESC) FROM t GROUP BY dept ORDER BY 2").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Int64(35), ScalarValue::Int64(1)],
+        vec![ScalarValue::Int64(30), ScalarValue::Int64(2)],
+    ]);
+    let r = engine.execute("SELECT dept, ROW_NUMBER() OVER (ORDER BY MAX(sal)), SUM(SUM(sal)) OVER () \
+        FROM t GROUP BY dept HAVING COUNT(*) > 1 ORDER BY RANK() OVER (ORDER BY MIN(id) DESC)").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Utf8("b".to_string()), ScalarValue::Int64(2), ScalarValue::Int64(65)],
+        vec![ScalarValue::Utf8("a".to_string()), ScalarValue::Int64(1), ScalarValue::Int64(65)],
+    ]);
+
+    // Under DISTINCT, windows are computed before duplicates are removed.
+    let r = engine.execute("SELECT DISTINCT dept, ROW_NUMBER() OVER (ORDER BY id) FROM t ORDER BY 2").unwrap();
+    assert_eq!(r.row_count(), 4);
+    let r = engine.execute("SELECT DISTINCT dept, MAX(sal) OVER (PARTITION BY dept) FROM t ORDER BY dept").unwrap();
+    assert_eq!(r.rows, vec![
+        vec![ScalarValue::Utf8("a".to_string()), ScalarValue::Int64(20)],
+        vec![ScalarValue::Utf8("b".to_string()), ScalarValue::Int64(30)],
+    ]);
+}
+
+#[test]
+fn test_window_order_by_nulls_first_and_last() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE runs (id INTEGER, grp VARCHAR, score INTEGER)").unwrap();
+    engine.execute("INSERT INTO runs VALUES (1, 'a', 10), (2, 'a', NULL), (3, 'a', 30), (4, 'a', NULL), \
+        (5, 'b', 5), (6, 'b', NULL)").unwrap();
+    let col = |r: &pivot_engine::sql::QueryResult, i: usize| r.rows.iter().map(|row| row[i].clone()).collect::<Vec<_>>();
+    let ints = |vals: &[Option<i64>]| vals.iter().map(|v| v.map_or(ScalarValue::Null, ScalarValue::Int64)).collect::<Vec<_>>();
+
+    // NULL scores tie with each other and rank ahead of the rest.
+    let r = engine.execute(
+        "SELECT id, grp, RANK() OVER (PARTITION BY grp ORDER BY score DESC NULLS FIRST), \
+         LAG(id) OVER (PARTITION BY grp ORDER BY score DESC NULLS FIRST, id) \
+         FROM runs ORDER BY id",
+    ).unwrap();
+    assert_eq!(col(&r, 2), ints(&[Some(4), Some(1), Some(3), Some(1), Some(2), Some(1)]));
+    assert_eq!(col(&r, 3), ints(&[Some(3), None, Some(4), Some(2), Some(6), None]));
+
+    // NULLS LAST puts them behind ascending values.
+    let r = engine.execute(
+        "SELECT id, ROW_NUMBER() OVER (ORDER BY score NULLS LAST, id) FROM runs ORDER BY id",
+    ).unwrap();
+    assert_eq!(col(&r, 1), ints(&[Some(2), Some(4), Some(3), Some(5), Some(1), Some(6)]));
+}
+
+#[test]
+fn test_ntile_gives_earlier_buckets_the_extra_rows() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE n (id INTEGER, grp VARCHAR)").unwrap();
+    engine.execute("INSERT INTO n VALUES (1, 'a'), (2, 'a'), (3, 'a'), (4, 'a'), (5, 'a'), \
+        (6, 'a'), (7, 'a'), (8, 'a'), (9, 'a'), (10, 'a'), (11, 'b'), (12, 'b'), (13, 'b')").unwrap();
+    let tiles = |sql: &str, engine: &mut SqlEngine| engine.execute(sql).unwrap().rows.iter()
+        .map(|row| row[1].clone()).collect::<Vec<_>>();
+    let ints = |vals: &[i64]| vals.iter().map(|v| ScalarValue::Int64(*v)).collect::<Vec<_>>();
+
+    // 10 rows into 3 tiles: sizes 4, 3, 3.
+    assert_eq!(tiles("SELECT id, NTILE(3) OVER (ORDER BY id) FROM n WHERE grp = 'a' ORDER BY id", &mut engine),
+        ints(&[1, 1, 1, 1, 2, 2, 2, 3, 3, 3]));
+    assert_eq!(tiles("SELECT id, NTILE(4) OVER (ORDER BY id) FROM n WHERE grp = 'a' ORDER BY id", &mut engine),
+        ints(&[1, 1, 1, 2, 2, 2, 3, 3, 4, 4]));
+    // More tiles than rows: one row per tile.
+    assert_eq!(tiles("SELECT id, NTILE(5) OVER (PARTITION BY grp ORDER BY id) FROM n WHERE grp = 'b' ORDER BY id", &mut engine),
+        ints(&[1, 2, 3]));
+    assert!(engine.execute("SELECT id, NTILE(0) OVER (ORDER BY id) FROM n").is_err());
+}
+
+#[test]
+fn test_range_offset_and_groups_frames() {
+    use pivot_engine::column::ScalarValue;
+    let mut engine = SqlEngine::new();
+    engine.execute("CREATE TABLE pay (id INTEGER, salary INTEGER)").unwrap();
+    engine.execute("INSERT INTO pay VALUES (1, 100), (2, 100), (3, 150), (4, 200), (5, 200), (6, 260)").unwrap();
+    let col = |sql: &str, engine: &mut SqlEngine| -> Vec<ScalarValue> {
+        engine.execute(sql).unwrap().rows.into_iter().map(|r| r.last().unwrap().clone()).collect()
+    };
+    let ints = |v: &[i64]| v.iter().map(|&n| ScalarValue::Int64(n)).collect::<Vec<_>>();
+
+    // Salaries within 50 below the current one, ties included.
+    assert_eq!(col("SELECT id, salary, SUM(salary) OVER (ORDER BY salary RANGE BETWEEN 50 PRECEDING AND CURRENT ROW) FROM pay ORDER BY id", &mut engine),
+        ints(&[200, 200, 350, 550, 550, 260]));
+    assert_eq!(col("SELECT id, salary, SUM(salary) OVER (ORDER BY salary RANGE BETWEEN 50 PRECEDING AND 50 FOLLOWING) FROM pay ORDER BY id", &mut engine),
+        ints(&[350, 350, 750, 550, 550, 260]));
+
+    // GROUPS offsets count distinct salary levels rather than rows.
+    assert_eq!(col("SELECT id, salary, COUNT(*) OVER (ORDER BY salary GROUPS BETWEEN 1 PRECEDING AND CURRENT ROW) FROM pay ORDER BY id", &mut engine),
+        ints(&[2, 2, 3, 3, 3, 3]));
+    assert_eq!(col("SELECT id, salary, SUM(salary) OVER (ORDER BY salary GROUPS BETWEEN CURRENT ROW AND 1 FOLLOWING) FROM pay ORDER BY id", &mut engine),
+        ints(&[350, 350, 550, 660, 660, 260]));
+    assert_eq!(col("SELECT id, salary, COUNT(*) OVER (ORDER BY salary GROUPS 1 PRECEDING) FROM pay ORDER BY id", &mut engine),
+        ints(&[2, 2, 3, 3, 3, 3]));
+
+    assert!(engine.execute("SELECT id, COUNT(*) OVER (ORDER BY salary GROUPS BETWEEN 1.5 PRECEDING AND CURRENT ROW) FROM pay").is_err());
+    // GROUPS is not reserved: it still names aliases, tables and columns.
+    assert_eq!(engine.execute("SELECT salary AS groups FROM pay WHERE id = 1").unwrap().columns, vec!["groups".to_string()]);
+    engine.execute("CREATE TABLE groups (id INTEGER, groups INTEGER)").unwrap();
+    engine.execute("INSERT INTO groups VALUES (1, 7)").unwrap();
+    assert_eq!(col("SELECT groups FROM groups", &mut engine), ints(&[7]));
+}